@@ -0,0 +1,486 @@
+use std::{
+    future::Future,
+    net::IpAddr,
+    pin::Pin,
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+use thiserror::Error;
+use tokio::sync::Mutex;
+
+use crate::{
+    socket::signal,
+    webrtc::{
+        self,
+        dtls::alert::{AlertDescription, AlertLevel},
+        dtls_transport::dtls_transport_state::RTCDtlsTransportState,
+        ice::candidate::{Candidate, CandidatePair},
+        ice_transport::{
+            ice_candidate::RTCIceCandidate, ice_connection_state::RTCIceConnectionState,
+        },
+        peer_connection::{peer_connection_state::RTCPeerConnectionState, RTCPeerConnection},
+        util::{ifaces::monitor::InterfaceEvent, KeyingMaterialExporterError},
+    },
+};
+
+/// Handler fired once when the DTLS connection ends
+pub type OnCloseHdlrFn = Box<
+    dyn (FnMut(CloseReason) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>) + Send + Sync,
+>;
+
+/// Handler fired with each local ICE candidate as it is gathered, and with `None`
+/// once gathering is complete
+pub type OnLocalCandidateHdlrFn = Box<
+    dyn (FnMut(Option<String>) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>) + Send + Sync,
+>;
+
+/// Handler fired when an address appears on or disappears from an interface of the host
+pub type OnNetworkChangeHdlrFn = Box<
+    dyn (FnMut(NetworkChange) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>) + Send + Sync,
+>;
+
+/// Handler fired when a candidate pair is selected, and again each time ICE moves to
+/// another pair
+pub type OnSelectedCandidatePairChangeHdlrFn = Box<
+    dyn (FnMut(SelectedCandidatePair) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>)
+        + Send
+        + Sync,
+>;
+
+/// Handler fired when the state of the connection changes
+pub type OnStateChangeHdlrFn = Box<
+    dyn (FnMut(ConnectionState) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>)
+        + Send
+        + Sync,
+>;
+
+// ConnectionHandle
+/// Handle to the connection set up by [`Socket::connect_with_handle`](crate::Socket::connect_with_handle)
+#[derive(Clone)]
+pub struct ConnectionHandle {
+    peer_connection: Arc<RTCPeerConnection>,
+    server_url: Arc<str>,
+    // Held while an ICE restart runs, so manual and automatic restarts don't overlap
+    restart_lock: Arc<Mutex<()>>,
+}
+
+impl ConnectionHandle {
+    pub(crate) fn new(peer_connection: Arc<RTCPeerConnection>, server_url: &str) -> Self {
+        ConnectionHandle {
+            peer_connection,
+            server_url: Arc::from(server_url),
+            restart_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Restarts ICE (RFC 8445 section 2.4), e.g. after switching from Wi-Fi to Ethernet:
+    /// candidates are gathered again and new credentials are exchanged with the server
+    /// through a new offer/answer. The DTLS and SCTP sessions keep running and move to
+    /// the new path once a pair is selected. This also runs on its own whenever the ICE
+    /// connection becomes disconnected.
+    pub async fn restart_ice(&self) -> Result<(), IceRestartError> {
+        restart_ice(&self.peer_connection, &self.server_url, &self.restart_lock).await
+    }
+
+    // restart_ice_on_disconnect restarts ICE each time the connection becomes
+    // disconnected. The handler only holds a weak reference, the peer connection owns it
+    pub(crate) async fn restart_ice_on_disconnect(&self) {
+        let peer_connection = Arc::downgrade(&self.peer_connection);
+        let server_url = Arc::clone(&self.server_url);
+        let restart_lock = Arc::clone(&self.restart_lock);
+        self.peer_connection
+            .on_ice_connection_state_change(Box::new(move |state| {
+                if state == RTCIceConnectionState::Disconnected {
+                    // The restart changes the state again, so it can't run inside the handler
+                    let peer_connection = Weak::clone(&peer_connection);
+                    let server_url = Arc::clone(&server_url);
+                    let restart_lock = Arc::clone(&restart_lock);
+                    tokio::spawn(async move {
+                        let peer_connection = match peer_connection.upgrade() {
+                            Some(peer_connection) => peer_connection,
+                            None => return,
+                        };
+                        log::info!("ice connection disconnected, restarting ice");
+                        if let Err(err) =
+                            restart_ice(&peer_connection, &server_url, &restart_lock).await
+                        {
+                            log::warn!("{}", err);
+                        }
+                    });
+                }
+                Box::pin(async {})
+            }))
+            .await;
+    }
+
+    /// Derives `length` bytes of keying material from the DTLS session, as defined
+    /// in RFC 5705 for DTLS 1.2 and RFC 8446 for DTLS 1.3. Both peers get the same
+    /// bytes for the same `label` and `context`, which binds application secrets
+    /// and tokens to this session. An empty `context` means no context, the
+    /// context may be at most 65535 bytes long.
+    pub async fn export_keying_material(
+        &self,
+        label: &str,
+        context: &[u8],
+        length: usize,
+    ) -> Result<Vec<u8>, KeyingMaterialError> {
+        let dtls_conn = match self.peer_connection.internal.dtls_transport.conn().await {
+            Some(dtls_conn) => dtls_conn,
+            None => return Err(KeyingMaterialError::NotConnected),
+        };
+
+        dtls_conn
+            .export_keying_material(label, context, length)
+            .await
+            .map_err(keying_material_error)
+    }
+
+    /// Sets a handler that is fired when the DTLS connection ends, e.g. when the
+    /// server closes it with a close_notify or aborts it with a fatal alert. If the
+    /// connection already ended, the handler is fired right away with the reason.
+    pub async fn on_close(&self, mut f: OnCloseHdlrFn) {
+        self.peer_connection
+            .internal
+            .dtls_transport
+            .on_state_change(Box::new(move |state, alert| {
+                let reason = match (state, alert) {
+                    (
+                        RTCDtlsTransportState::Closed | RTCDtlsTransportState::Failed,
+                        Some(alert),
+                    ) => CloseReason::Alert {
+                        level: alert.alert_level,
+                        description: alert.alert_description,
+                    },
+                    (RTCDtlsTransportState::Closed, None) => CloseReason::Closed,
+                    (RTCDtlsTransportState::Failed, None) => CloseReason::Failed,
+                    _ => return Box::pin(async {}),
+                };
+                f(reason)
+            }))
+            .await;
+    }
+
+    /// Sets a handler that trickles local ICE candidates (RFC 8838) to the server over
+    /// the application's own signaling, as `candidate:` attribute values. The offer
+    /// only carries the candidates gathered before it was sent. Candidates gathered
+    /// before the handler was set are replayed to it, so one may be reported twice.
+    /// `None` marks the end of candidates.
+    pub async fn on_local_candidate(&self, f: OnLocalCandidateHdlrFn) {
+        let f = Arc::new(Mutex::new(f));
+        self.peer_connection
+            .internal
+            .ice_gatherer
+            .on_local_candidate(Box::new(move |c: Option<RTCIceCandidate>| {
+                let f = Arc::clone(&f);
+                Box::pin(async move {
+                    let candidate = match c {
+                        Some(c) => match c.to_ice().await {
+                            Ok(c) => Some(format!("candidate:{}", c.marshal())),
+                            Err(err) => {
+                                log::warn!("cannot marshal local candidate {}: {}", c, err);
+                                return;
+                            }
+                        },
+                        None => None,
+                    };
+                    let mut f = f.lock().await;
+                    f(candidate).await;
+                })
+            }))
+            .await;
+    }
+
+    /// Sets a handler that is fired when an address appears on or disappears from an
+    /// interface of the host, e.g. when switching from Wi-Fi to Ethernet. By then host
+    /// candidates on a new address are gathered and trickled, and candidates on a removed
+    /// one are gone. ICE may take a while to notice a dead path, call
+    /// [`ConnectionHandle::restart_ice`] from here to move over right away.
+    pub async fn on_network_change(&self, mut f: OnNetworkChangeHdlrFn) {
+        let result = self
+            .peer_connection
+            .internal
+            .ice_gatherer
+            .on_network_change(Box::new(move |event| {
+                let change = match event {
+                    InterfaceEvent::AddrAdded { name, addr } => NetworkChange::AddressAdded {
+                        interface: name,
+                        addr,
+                    },
+                    InterfaceEvent::AddrRemoved { name, addr } => NetworkChange::AddressRemoved {
+                        interface: name,
+                        addr,
+                    },
+                };
+                f(change)
+            }))
+            .await;
+        if let Err(err) = result {
+            log::warn!("cannot watch network changes: {}", err);
+        }
+    }
+
+    /// Sets a handler that is fired when the state of the connection changes. A failed
+    /// connection carries the reason, e.g. [`FailureReason::ConsentExpired`] once the
+    /// server stopped answering consent checks (RFC 7675) and nothing is sent to it anymore.
+    pub async fn on_state_change(&self, f: OnStateChangeHdlrFn) {
+        let f = Arc::new(Mutex::new(f));
+        // The peer connection owns the handler
+        let peer_connection = Arc::downgrade(&self.peer_connection);
+        self.peer_connection
+            .on_peer_connection_state_change(Box::new(move |state| {
+                let f = Arc::clone(&f);
+                let peer_connection = Weak::clone(&peer_connection);
+                Box::pin(async move {
+                    let state = match state {
+                        RTCPeerConnectionState::Connected => ConnectionState::Connected,
+                        RTCPeerConnectionState::Disconnected => ConnectionState::Disconnected,
+                        RTCPeerConnectionState::Failed => {
+                            let peer_connection = match peer_connection.upgrade() {
+                                Some(peer_connection) => peer_connection,
+                                None => return,
+                            };
+                            ConnectionState::Failed(failure_reason(&peer_connection).await)
+                        }
+                        RTCPeerConnectionState::Closed => ConnectionState::Closed,
+                        _ => ConnectionState::Connecting,
+                    };
+                    let mut f = f.lock().await;
+                    f(state).await;
+                })
+            }))
+            .await;
+    }
+
+    /// Returns the candidate pair packets are sent on, `None` until ICE selected one.
+    pub async fn selected_candidate_pair(&self) -> Option<SelectedCandidatePair> {
+        selected_candidate_pair(&self.peer_connection).await
+    }
+
+    /// Sets a handler that is fired when a candidate pair is selected, and again each time
+    /// ICE nominates another one. With several paths, e.g. IPv4 and IPv6 or two network
+    /// interfaces, the pairs besides the selected one keep being checked, and one with a
+    /// clearly lower round trip time and loss is nominated in its place.
+    pub async fn on_selected_candidate_pair_change(&self, f: OnSelectedCandidatePairChangeHdlrFn) {
+        let f = Arc::new(Mutex::new(f));
+        // The peer connection owns the handler
+        let peer_connection = Arc::downgrade(&self.peer_connection);
+        self.peer_connection
+            .internal
+            .ice_transport
+            .on_selected_candidate_pair_change(Box::new(move |_| {
+                let f = Arc::clone(&f);
+                let peer_connection = Weak::clone(&peer_connection);
+                Box::pin(async move {
+                    let peer_connection = match peer_connection.upgrade() {
+                        Some(peer_connection) => peer_connection,
+                        None => return,
+                    };
+                    if let Some(pair) = selected_candidate_pair(&peer_connection).await {
+                        let mut f = f.lock().await;
+                        f(pair).await;
+                    }
+                })
+            }))
+            .await;
+    }
+
+    /// Adds an ICE candidate the server trickled (RFC 8838), as an `a=candidate:` or
+    /// `candidate:` attribute value. Connectivity checks start on the new pairs right
+    /// away. An empty string or `end-of-candidates` marks the end of the server's
+    /// candidates, the connection then fails as soon as every check failed.
+    pub async fn add_remote_candidate(&self, candidate: &str) -> Result<(), RemoteCandidateError> {
+        let candidate = candidate.trim();
+        let candidate = candidate.strip_prefix("a=").unwrap_or(candidate);
+        let candidate = if candidate == "end-of-candidates" {
+            ""
+        } else {
+            candidate
+        };
+
+        self.peer_connection
+            .add_ice_candidate(candidate.to_owned())
+            .await
+            .map_err(|err| RemoteCandidateError(err.to_string()))
+    }
+}
+
+async fn selected_candidate_pair(
+    peer_connection: &RTCPeerConnection,
+) -> Option<SelectedCandidatePair> {
+    let agent = peer_connection.internal.ice_gatherer.get_agent().await?;
+    let pair = agent.get_selected_candidate_pair().await?;
+    Some(SelectedCandidatePair::new(&pair))
+}
+
+async fn failure_reason(peer_connection: &RTCPeerConnection) -> FailureReason {
+    if peer_connection.internal.dtls_transport.state() == RTCDtlsTransportState::Failed {
+        return FailureReason::DtlsFailed;
+    }
+
+    match peer_connection.internal.ice_gatherer.get_agent().await {
+        Some(agent) if agent.consent_expired() => FailureReason::ConsentExpired,
+        _ => FailureReason::IceFailed,
+    }
+}
+
+async fn restart_ice(
+    peer_connection: &RTCPeerConnection,
+    server_url: &str,
+    restart_lock: &Mutex<()>,
+) -> Result<(), IceRestartError> {
+    let _restarting = match restart_lock.try_lock() {
+        Ok(guard) => guard,
+        Err(_) => return Err(IceRestartError::InProgress),
+    };
+
+    peer_connection
+        .restart_ice()
+        .await
+        .map_err(|err| IceRestartError::Other(err.to_string()))?;
+    signal(server_url, peer_connection).await.map_err(|err| {
+        match err.downcast_ref::<webrtc::error::Error>() {
+            Some(webrtc::error::Error::ErrRenegotiationDtlsParametersChanged) => {
+                IceRestartError::DtlsParametersChanged
+            }
+            _ => IceRestartError::Other(format!("{:#}", err)),
+        }
+    })?;
+
+    Ok(())
+}
+
+/// Error returned by [`ConnectionHandle::restart_ice`]
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum IceRestartError {
+    /// Another restart is still running
+    #[error("ice restart is already in progress")]
+    InProgress,
+    /// The server answered with another certificate or DTLS role, so the running DTLS
+    /// session can not continue over the new path
+    #[error("ice restart answer changed the dtls fingerprint or role")]
+    DtlsParametersChanged,
+    /// Restarting the agent or the offer/answer exchange with the server failed
+    #[error("ice restart failed: {0}")]
+    Other(String),
+}
+
+/// Error returned by [`ConnectionHandle::add_remote_candidate`]
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("cannot add remote candidate: {0}")]
+pub struct RemoteCandidateError(String);
+
+/// A change to the addresses of the host's interfaces, see
+/// [`ConnectionHandle::on_network_change`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkChange {
+    /// The address appeared on the interface
+    AddressAdded { interface: String, addr: IpAddr },
+    /// The address disappeared from the interface
+    AddressRemoved { interface: String, addr: IpAddr },
+}
+
+/// The candidate pair ICE sends on, see [`ConnectionHandle::selected_candidate_pair`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectedCandidatePair {
+    /// The local candidate, as a `candidate:` attribute value
+    pub local: String,
+    /// The server's candidate, as a `candidate:` attribute value
+    pub remote: String,
+    /// Smoothed round trip time of the ICE checks on the pair, `None` until one
+    /// was answered
+    pub round_trip_time: Option<Duration>,
+    /// Smoothed share of the ICE checks on the pair that were not answered, from 0 to 1
+    pub loss: f64,
+}
+
+impl SelectedCandidatePair {
+    fn new(pair: &CandidatePair) -> Self {
+        SelectedCandidatePair {
+            local: format!("candidate:{}", pair.local.marshal()),
+            remote: format!("candidate:{}", pair.remote.marshal()),
+            round_trip_time: pair.round_trip_time(),
+            loss: pair.loss(),
+        }
+    }
+}
+
+/// State of the connection, see [`ConnectionHandle::on_state_change`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// ICE checks or the DTLS handshake are running, also while ICE restarts
+    Connecting,
+    /// A candidate pair is selected and the DTLS handshake completed
+    Connected,
+    /// The selected pair stopped receiving, ICE restarts on its own
+    Disconnected,
+    /// The connection can not recover, nothing is sent anymore
+    Failed(FailureReason),
+    /// The connection was closed locally
+    Closed,
+}
+
+/// Why the connection failed, see [`ConnectionState::Failed`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureReason {
+    /// The server stopped answering consent checks on the selected pair for the
+    /// configured [`SocketConfig::consent_expiry`](crate::SocketConfig::consent_expiry),
+    /// RFC 7675
+    ConsentExpired,
+    /// ICE found no working candidate pair, or lost the selected one
+    IceFailed,
+    /// The DTLS handshake failed or the session was aborted
+    DtlsFailed,
+}
+
+/// Why the DTLS connection ended, see [`ConnectionHandle::on_close`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CloseReason {
+    /// The server sent an alert: `CloseNotify` for a graceful close, a fatal
+    /// alert such as `BadRecordMac` or `HandshakeFailure` otherwise
+    Alert {
+        level: AlertLevel,
+        description: AlertDescription,
+    },
+    /// The connection was closed locally or the transport below it went away
+    Closed,
+    /// The DTLS handshake failed without an alert from the server, e.g. it timed out
+    Failed,
+}
+
+/// Error returned by [`ConnectionHandle::export_keying_material`]
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum KeyingMaterialError {
+    /// The DTLS transport has not been started yet
+    #[error("dtls connection is not established")]
+    NotConnected,
+    /// The DTLS handshake has not completed yet
+    #[error("dtls handshake is in progress")]
+    HandshakeInProgress,
+    /// The context is longer than 65535 bytes
+    #[error("context is too long for export_keying_material")]
+    ContextUnsupported,
+    /// The label is reserved by TLS itself
+    #[error("export_keying_material can not be used with a reserved label")]
+    ReservedLabel,
+    /// No cipher suite has been negotiated
+    #[error("no cipher suite for export_keying_material")]
+    CipherSuiteUnset,
+    /// The derivation itself failed
+    #[error("export_keying_material failed: {0}")]
+    Other(String),
+}
+
+fn keying_material_error(err: KeyingMaterialExporterError) -> KeyingMaterialError {
+    match err {
+        KeyingMaterialExporterError::HandshakeInProgress => {
+            KeyingMaterialError::HandshakeInProgress
+        }
+        KeyingMaterialExporterError::ContextUnsupported => KeyingMaterialError::ContextUnsupported,
+        KeyingMaterialExporterError::ReservedExportKeyingMaterial => {
+            KeyingMaterialError::ReservedLabel
+        }
+        KeyingMaterialExporterError::CipherSuiteUnset => KeyingMaterialError::CipherSuiteUnset,
+        err => KeyingMaterialError::Other(err.to_string()),
+    }
+}
@@ -0,0 +1,556 @@
+use std::{future::Future, net::Ipv4Addr, sync::Arc, time::Duration};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    sync::{mpsc, Mutex},
+};
+
+use super::connection_handle::*;
+use crate::{
+    socket::signal,
+    socket_config::SocketConfig,
+    webrtc::{
+        dtls::{
+            alert::{Alert, AlertDescription, AlertLevel},
+            config::Config as DtlsConfig,
+            conn::DTLSConn,
+        },
+        dtls_transport::{dtls_role::DTLSRole, dtls_transport_state::RTCDtlsTransportState},
+        ice::{
+            agent::{agent_config::AgentConfig, Agent},
+            network_type::NetworkType,
+        },
+        ice_transport::{
+            ice_gatherer_state::RTCIceGathererState, ice_transport_state::RTCIceTransportState,
+        },
+        peer_connection::{certificate::RTCCertificate, RTCPeerConnection},
+        util::Conn,
+    },
+};
+
+// What the stand-in server puts in its answer's a=fingerprint and a=setup
+struct AnswerDtlsParameters {
+    fingerprint: String,
+    setup: &'static str,
+}
+
+// Stand-in for the server: answers offers posted over http with a controlled ICE agent,
+// runs a DTLS server over the first connection and restarts its agent on each later offer
+struct StandInServer {
+    url: String,
+    answer_dtls: Arc<Mutex<AnswerDtlsParameters>>,
+    agent: Arc<Agent>,
+    dtls_conn: Arc<Mutex<Option<Arc<DTLSConn>>>>,
+}
+
+impl StandInServer {
+    async fn start() -> Self {
+        let certificate = RTCCertificate::from_key_pair(
+            rcgen::KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap(),
+        )
+        .unwrap();
+        let fingerprint = certificate.get_fingerprints().unwrap().remove(0).value;
+        let answer_dtls = Arc::new(Mutex::new(AnswerDtlsParameters {
+            fingerprint,
+            setup: "passive",
+        }));
+
+        let agent = Arc::new(
+            Agent::new(AgentConfig {
+                network_types: vec![NetworkType::Udp4],
+                ..Default::default()
+            })
+            .await
+            .unwrap(),
+        );
+        let (candidate_tx, candidate_rx) = mpsc::unbounded_channel();
+        agent
+            .on_candidate(Box::new(move |candidate| {
+                if let Some(candidate) = candidate {
+                    let _ = candidate_tx.send(format!("candidate:{}", candidate.marshal()));
+                }
+                Box::pin(async {})
+            }))
+            .await;
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        let dtls_conn = Arc::new(Mutex::new(None));
+        let server_agent = Arc::clone(&agent);
+        let server_answer_dtls = Arc::clone(&answer_dtls);
+        let server_dtls_conn = Arc::clone(&dtls_conn);
+        tokio::spawn(async move {
+            let mut candidate_rx = candidate_rx;
+            let mut certificate = Some(certificate);
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let offer = read_http_body(&mut stream).await;
+                let ufrag = sdp_attribute(&offer, "ice-ufrag");
+                let pwd = sdp_attribute(&offer, "ice-pwd");
+
+                while candidate_rx.try_recv().is_ok() {}
+                match certificate.take() {
+                    Some(certificate) => {
+                        server_agent.gather_candidates().await.unwrap();
+                        // a=setup:active makes the stand-in the DTLS client
+                        let is_client = server_answer_dtls.lock().await.setup == "active";
+                        tokio::spawn(accept_dtls(
+                            Arc::clone(&server_agent),
+                            ufrag,
+                            pwd,
+                            certificate,
+                            is_client,
+                            Arc::clone(&server_dtls_conn),
+                        ));
+                    }
+                    None => {
+                        wait_for(|| async {
+                            server_agent
+                                .restart(String::new(), String::new())
+                                .await
+                                .is_ok()
+                        })
+                        .await;
+                        server_agent
+                            .set_remote_credentials(ufrag, pwd)
+                            .await
+                            .unwrap();
+                        server_agent.gather_candidates().await.unwrap();
+                    }
+                }
+                let candidate = candidate_rx.recv().await.unwrap();
+
+                let (local_ufrag, local_pwd) = server_agent.get_local_user_credentials().await;
+                let answer = {
+                    let answer_dtls = server_answer_dtls.lock().await;
+                    format!(
+                        "v=0\r\n\
+                         o=- 1 1 IN IP4 0.0.0.0\r\n\
+                         s=-\r\n\
+                         t=0 0\r\n\
+                         m=application 9 UDP/DTLS/SCTP webrtc-datachannel\r\n\
+                         c=IN IP4 0.0.0.0\r\n\
+                         a=ice-ufrag:{}\r\n\
+                         a=ice-pwd:{}\r\n\
+                         a=fingerprint:sha-256 {}\r\n\
+                         a=setup:{}\r\n\
+                         a=mid:{}\r\n\
+                         a=sctp-port:5000\r\n",
+                        local_ufrag,
+                        local_pwd,
+                        answer_dtls.fingerprint,
+                        answer_dtls.setup,
+                        sdp_attribute(&offer, "mid"),
+                    )
+                };
+                let body = format!(
+                    "{{\"answer\":{{\"sdp\":\"{}\",\"type\":\"answer\"}},\"candidate\":{{\"candidate\":\"{}\",\"sdpMLineIndex\":0,\"sdpMid\":\"0\"}}}}",
+                    answer.replace("\r\n", "\\r\\n"),
+                    candidate,
+                );
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body,
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+
+        StandInServer {
+            url,
+            answer_dtls,
+            agent,
+            dtls_conn,
+        }
+    }
+}
+
+async fn accept_dtls(
+    agent: Arc<Agent>,
+    ufrag: String,
+    pwd: String,
+    certificate: RTCCertificate,
+    is_client: bool,
+    slot: Arc<Mutex<Option<Arc<DTLSConn>>>>,
+) {
+    let (_cancel_tx, cancel_rx) = mpsc::channel(1);
+    let conn: Arc<dyn Conn + Send + Sync> = agent.accept(cancel_rx, ufrag, pwd).await.unwrap();
+    let config = DtlsConfig {
+        certificates: vec![certificate.certificate],
+        insecure_skip_verify: true,
+        ..Default::default()
+    };
+    let dtls_conn = Arc::new(DTLSConn::new(conn, config, is_client, None).await.unwrap());
+    *slot.lock().await = Some(Arc::clone(&dtls_conn));
+
+    // Keep the session running, the client's SCTP packets are dropped
+    let mut buf = vec![0u8; 8192];
+    while dtls_conn.read(&mut buf, None).await.is_ok() {}
+}
+
+async fn read_http_body(stream: &mut tokio::net::TcpStream) -> String {
+    let mut request = vec![];
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = stream.read(&mut buf).await.unwrap();
+        assert!(n > 0, "connection closed before the request was read");
+        request.extend_from_slice(&buf[..n]);
+
+        let request = String::from_utf8_lossy(&request);
+        if let Some(header_end) = request.find("\r\n\r\n") {
+            let content_length: usize = request[..header_end]
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    if name.eq_ignore_ascii_case("content-length") {
+                        value.trim().parse().ok()
+                    } else {
+                        None
+                    }
+                })
+                .unwrap_or(0);
+            let body = &request[header_end + 4..];
+            if body.len() >= content_length {
+                return body.to_owned();
+            }
+        }
+    }
+}
+
+fn sdp_attribute(sdp: &str, name: &str) -> String {
+    let prefix = format!("a={}:", name);
+    sdp.lines()
+        .find_map(|line| line.strip_prefix(prefix.as_str()))
+        .unwrap_or_else(|| panic!("offer has no a={}", name))
+        .to_owned()
+}
+
+async fn wait_for<F, Fut>(mut condition: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = bool>,
+{
+    tokio::time::timeout(Duration::from_secs(20), async {
+        while !condition().await {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("timed out");
+}
+
+async fn wait_for_connected(peer_connection: &RTCPeerConnection) {
+    wait_for(|| async {
+        peer_connection.internal.ice_transport.state() == RTCIceTransportState::Connected
+            && peer_connection.internal.dtls_transport.state() == RTCDtlsTransportState::Connected
+    })
+    .await;
+}
+
+async fn wait_for_gathering_complete(peer_connection: &RTCPeerConnection) {
+    wait_for(|| async {
+        peer_connection.internal.ice_gatherer.state() == RTCIceGathererState::Complete
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn test_restart_ice_checks_dtls_parameters() {
+    let server = StandInServer::start().await;
+
+    let peer_connection = RTCPeerConnection::new(&SocketConfig::default()).await;
+    peer_connection
+        .create_data_channel("data", "")
+        .await
+        .unwrap();
+    signal(&server.url, &peer_connection).await.unwrap();
+    wait_for_connected(&peer_connection).await;
+    wait_for_gathering_complete(&peer_connection).await;
+
+    let handle = ConnectionHandle::new(Arc::clone(&peer_connection), &server.url);
+
+    // Same certificate and role: the restart goes through and ICE connects again
+    // under the running DTLS session
+    handle.restart_ice().await.unwrap();
+    wait_for_connected(&peer_connection).await;
+    wait_for_gathering_complete(&peer_connection).await;
+    assert!(server.agent.get_selected_candidate_pair().await.is_some());
+
+    // Another certificate
+    let original_fingerprint = {
+        let mut answer_dtls = server.answer_dtls.lock().await;
+        let other = RTCCertificate::from_key_pair(
+            rcgen::KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap(),
+        )
+        .unwrap();
+        std::mem::replace(
+            &mut answer_dtls.fingerprint,
+            other.get_fingerprints().unwrap().remove(0).value,
+        )
+    };
+    assert_eq!(
+        handle.restart_ice().await,
+        Err(IceRestartError::DtlsParametersChanged)
+    );
+    wait_for_gathering_complete(&peer_connection).await;
+
+    // Same certificate, other role
+    {
+        let mut answer_dtls = server.answer_dtls.lock().await;
+        answer_dtls.fingerprint = original_fingerprint;
+        answer_dtls.setup = "active";
+    }
+    assert_eq!(
+        handle.restart_ice().await,
+        Err(IceRestartError::DtlsParametersChanged)
+    );
+    assert_eq!(
+        peer_connection.internal.dtls_transport.state(),
+        RTCDtlsTransportState::Connected
+    );
+}
+
+#[tokio::test]
+async fn test_remote_setup_active_runs_server_flights() {
+    let server = StandInServer::start().await;
+    server.answer_dtls.lock().await.setup = "active";
+
+    let peer_connection = RTCPeerConnection::new(&SocketConfig::default()).await;
+    peer_connection
+        .create_data_channel("data", "")
+        .await
+        .unwrap();
+    signal(&server.url, &peer_connection).await.unwrap();
+    wait_for_connected(&peer_connection).await;
+
+    assert_eq!(
+        peer_connection.internal.dtls_transport.role().await,
+        DTLSRole::Server
+    );
+    wait_for(|| async { server.dtls_conn.lock().await.is_some() }).await;
+    let server_conn = server.dtls_conn.lock().await.clone().unwrap();
+    let client_conn = peer_connection
+        .internal
+        .dtls_transport
+        .conn()
+        .await
+        .unwrap();
+    assert_eq!(
+        client_conn
+            .export_keying_material("EXPERIMENTAL-test", &[], 32)
+            .await
+            .unwrap(),
+        server_conn
+            .export_keying_material("EXPERIMENTAL-test", &[], 32)
+            .await
+            .unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_export_keying_material() {
+    let server = StandInServer::start().await;
+
+    let peer_connection = RTCPeerConnection::new(&SocketConfig::default()).await;
+    peer_connection
+        .create_data_channel("data", "")
+        .await
+        .unwrap();
+    let handle = ConnectionHandle::new(Arc::clone(&peer_connection), &server.url);
+    assert_eq!(
+        handle
+            .export_keying_material("EXPERIMENTAL-test", &[], 32)
+            .await,
+        Err(KeyingMaterialError::NotConnected)
+    );
+
+    signal(&server.url, &peer_connection).await.unwrap();
+    wait_for_connected(&peer_connection).await;
+    wait_for(|| async { server.dtls_conn.lock().await.is_some() }).await;
+    let server_conn = server.dtls_conn.lock().await.clone().unwrap();
+
+    // Both peers derive the same bytes for the same label and context
+    let mut exported = vec![];
+    for context in [&b""[..], b"ctx", b"other ctx"] {
+        let client_bytes = handle
+            .export_keying_material("EXPERIMENTAL-test", context, 32)
+            .await
+            .unwrap();
+        let server_bytes = server_conn
+            .export_keying_material("EXPERIMENTAL-test", context, 32)
+            .await
+            .unwrap();
+        assert_eq!(client_bytes, server_bytes);
+        assert!(!exported.contains(&client_bytes));
+        exported.push(client_bytes);
+    }
+
+    assert_eq!(
+        handle
+            .export_keying_material("EXPERIMENTAL-test", &[0u8; 65536], 32)
+            .await,
+        Err(KeyingMaterialError::ContextUnsupported)
+    );
+    assert_eq!(
+        handle
+            .export_keying_material("master secret", &[], 32)
+            .await,
+        Err(KeyingMaterialError::ReservedLabel)
+    );
+}
+
+// Connects to a fresh stand-in server and lets it end the session with the alert
+async fn end_with_alert(
+    level: AlertLevel,
+    description: AlertDescription,
+) -> (
+    Arc<RTCPeerConnection>,
+    ConnectionHandle,
+    mpsc::UnboundedReceiver<CloseReason>,
+    mpsc::UnboundedReceiver<(RTCDtlsTransportState, Option<Alert>)>,
+) {
+    let server = StandInServer::start().await;
+
+    let peer_connection = RTCPeerConnection::new(&SocketConfig::default()).await;
+    peer_connection
+        .create_data_channel("data", "")
+        .await
+        .unwrap();
+    let handle = ConnectionHandle::new(Arc::clone(&peer_connection), &server.url);
+    signal(&server.url, &peer_connection).await.unwrap();
+    wait_for_connected(&peer_connection).await;
+    wait_for(|| async { server.dtls_conn.lock().await.is_some() }).await;
+
+    let (state_tx, state_rx) = mpsc::unbounded_channel();
+    peer_connection
+        .internal
+        .dtls_transport
+        .on_state_change(Box::new(move |state, alert| {
+            let _ = state_tx.send((state, alert));
+            Box::pin(async {})
+        }))
+        .await;
+
+    let server_conn = server.dtls_conn.lock().await.clone().unwrap();
+    if description == AlertDescription::CloseNotify {
+        server_conn.close().await.unwrap();
+    } else {
+        server_conn.notify(level, description).await.unwrap();
+    }
+    wait_for(|| async {
+        peer_connection.internal.dtls_transport.state() != RTCDtlsTransportState::Connected
+    })
+    .await;
+
+    // Subscribed only after the close, the reason is replayed
+    let (close_tx, close_rx) = mpsc::unbounded_channel();
+    handle
+        .on_close(Box::new(move |reason| {
+            let _ = close_tx.send(reason);
+            Box::pin(async {})
+        }))
+        .await;
+
+    (peer_connection, handle, close_rx, state_rx)
+}
+
+#[tokio::test]
+async fn test_remote_close_notify_closes() {
+    let (peer_connection, _handle, mut close_rx, mut state_rx) =
+        end_with_alert(AlertLevel::Warning, AlertDescription::CloseNotify).await;
+
+    let alert = Alert {
+        alert_level: AlertLevel::Warning,
+        alert_description: AlertDescription::CloseNotify,
+    };
+    assert_eq!(
+        state_rx.recv().await,
+        Some((RTCDtlsTransportState::Closed, Some(alert)))
+    );
+    assert_eq!(
+        peer_connection.internal.dtls_transport.state(),
+        RTCDtlsTransportState::Closed
+    );
+    assert_eq!(
+        tokio::time::timeout(Duration::from_secs(5), close_rx.recv())
+            .await
+            .expect("close is not replayed"),
+        Some(CloseReason::Alert {
+            level: AlertLevel::Warning,
+            description: AlertDescription::CloseNotify,
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_remote_fatal_alert_fails() {
+    let (peer_connection, _handle, mut close_rx, mut state_rx) =
+        end_with_alert(AlertLevel::Fatal, AlertDescription::InternalError).await;
+
+    let alert = Alert {
+        alert_level: AlertLevel::Fatal,
+        alert_description: AlertDescription::InternalError,
+    };
+    assert_eq!(
+        state_rx.recv().await,
+        Some((RTCDtlsTransportState::Failed, Some(alert)))
+    );
+    assert_eq!(
+        peer_connection.internal.dtls_transport.state(),
+        RTCDtlsTransportState::Failed
+    );
+    assert_eq!(
+        tokio::time::timeout(Duration::from_secs(5), close_rx.recv())
+            .await
+            .expect("close is not replayed"),
+        Some(CloseReason::Alert {
+            level: AlertLevel::Fatal,
+            description: AlertDescription::InternalError,
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_consent_expiry_fails_connection() {
+    let server = StandInServer::start().await;
+
+    let config = SocketConfig {
+        consent_expiry: Some(Duration::from_secs(12)),
+        ..Default::default()
+    };
+    let peer_connection = RTCPeerConnection::new(&config).await;
+    peer_connection
+        .create_data_channel("data", "")
+        .await
+        .unwrap();
+    let handle = ConnectionHandle::new(Arc::clone(&peer_connection), &server.url);
+    let (state_tx, mut state_rx) = mpsc::unbounded_channel();
+    handle
+        .on_state_change(Box::new(move |state| {
+            let _ = state_tx.send(state);
+            Box::pin(async {})
+        }))
+        .await;
+    signal(&server.url, &peer_connection).await.unwrap();
+    wait_for_connected(&peer_connection).await;
+
+    // The server stops answering after selection
+    server.agent.close().await.unwrap();
+    let state = tokio::time::timeout(Duration::from_secs(20), async {
+        loop {
+            match state_rx.recv().await.unwrap() {
+                state @ ConnectionState::Failed(_) => return state,
+                _ => continue,
+            }
+        }
+    })
+    .await
+    .expect("consent did not expire");
+    assert_eq!(
+        state,
+        ConnectionState::Failed(FailureReason::ConsentExpired)
+    );
+}
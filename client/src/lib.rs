@@ -7,9 +7,37 @@ extern crate lazy_static;
 extern crate serde_derive;
 
 mod addr_cell;
+mod connection_handle;
+#[cfg(test)]
+mod connection_handle_test;
+mod nat_behavior;
+#[cfg(test)]
+mod nat_behavior_test;
 mod socket;
+mod socket_config;
+#[cfg(test)]
+mod socket_test;
+
+#[cfg(test)]
+mod socket_config_test;
 
 pub use addr_cell::{AddrCell, ServerAddr};
-pub use socket::Socket;
+pub use connection_handle::{
+    CloseReason, ConnectionHandle, ConnectionState, FailureReason, IceRestartError,
+    KeyingMaterialError, NetworkChange, OnCloseHdlrFn, OnLocalCandidateHdlrFn,
+    OnNetworkChangeHdlrFn, OnSelectedCandidatePairChangeHdlrFn, OnStateChangeHdlrFn,
+    RemoteCandidateError, SelectedCandidatePair,
+};
+pub use nat_behavior::{
+    discover_nat_behavior, NatBehavior, NatBehaviorError, NatFiltering, NatMapping,
+};
+pub use socket::{ConnectError, Socket};
+pub use socket_config::{
+    DtlsPolicy, DtlsPolicyError, DtlsPsk, DtlsSessionCache, IceNetworkPolicy, IceServer, IpFamily,
+    MdnsMode, Nat1To1CandidateType, SocketConfig, SocketConfigError,
+};
+pub use webrtc::dtls::alert::{AlertDescription, AlertLevel};
+pub use webrtc::dtls::cipher_suite::CipherSuiteId;
+pub use webrtc::dtls::curve::named_curve::NamedCurve;
 
 mod webrtc;
@@ -0,0 +1,281 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::net::lookup_host;
+
+use crate::webrtc::{
+    ice::url::{SchemeType, Url},
+    stun::{
+        addr::OtherAddress,
+        agent::TransactionId,
+        change_request::ChangeRequest,
+        client::round_trip,
+        message::{Getter, Message, Setter, BINDING_REQUEST, BINDING_SUCCESS},
+        xoraddr::XorMappedAddress,
+    },
+    util::{vnet::net::Net, Conn},
+};
+
+// How long each test waits for an answer. The filtering tests expect silence
+// from a filtering NAT, so this bounds the whole discovery as well.
+const TEST_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How the NAT chooses the public address of outgoing traffic, RFC 5780 Section 4.3
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NatMapping {
+    /// One public address for all destinations, or no NAT at all
+    EndpointIndependent,
+    /// A public address per destination IP
+    AddressDependent,
+    /// A public address per destination IP and port, what is usually called
+    /// a symmetric NAT
+    AddressAndPortDependent,
+}
+
+/// Which inbound traffic the NAT lets through to a public address,
+/// RFC 5780 Section 4.4
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NatFiltering {
+    /// Traffic from any address, nothing is filtered
+    EndpointIndependent,
+    /// Traffic from IPs the client has sent to
+    AddressDependent,
+    /// Traffic from IPs and ports the client has sent to
+    AddressAndPortDependent,
+}
+
+/// Result of [`discover_nat_behavior`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NatBehavior {
+    pub mapping: NatMapping,
+    pub filtering: NatFiltering,
+    /// Public address the STUN server saw the first request come from
+    pub mapped_addr: SocketAddr,
+    /// Whether traffic sent to the own public address comes back, so two
+    /// clients behind the same NAT can reach each other through it
+    pub hairpinning: bool,
+}
+
+/// Error returned by [`discover_nat_behavior`]
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum NatBehaviorError {
+    /// The server is not a `stun:` URL or its host does not resolve
+    #[error("invalid STUN server {url}: {reason}")]
+    InvalidServer { url: String, reason: String },
+    /// A test the discovery depends on was not answered
+    #[error("STUN server did not respond")]
+    NoResponse,
+    /// The server sends no OTHER-ADDRESS or ignores CHANGE-REQUEST
+    #[error("STUN server does not support NAT behavior discovery")]
+    Unsupported,
+    /// Binding a socket, sending or decoding a response failed
+    #[error("NAT behavior discovery failed: {0}")]
+    Other(String),
+}
+
+/// Runs the RFC 5780 tests against `stun_server`, a `stun:` URL of a server
+/// with a second IP address and port, and reports how the local NAT maps
+/// and filters UDP traffic.
+pub async fn discover_nat_behavior(stun_server: &str) -> Result<NatBehavior, NatBehaviorError> {
+    let invalid_server = |reason: String| NatBehaviorError::InvalidServer {
+        url: stun_server.to_owned(),
+        reason,
+    };
+
+    let url = Url::parse_url(stun_server).map_err(|err| invalid_server(err.to_string()))?;
+    if url.scheme != SchemeType::Stun {
+        return Err(invalid_server("not a stun: URL".to_owned()));
+    }
+    let server_addr = lookup_host(format!("{}:{}", url.host, url.port))
+        .await
+        .map_err(|err| invalid_server(err.to_string()))?
+        .next()
+        .ok_or_else(|| invalid_server("host has no address".to_owned()))?;
+
+    discover(&Arc::new(Net::new(None)), server_addr).await
+}
+
+// discover runs the tests from sockets of net, a virtual network behind one of
+// the vnet NAT types or the host's
+pub(crate) async fn discover(
+    net: &Arc<Net>,
+    server_addr: SocketAddr,
+) -> Result<NatBehavior, NatBehaviorError> {
+    let laddr = match server_addr.ip() {
+        IpAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+        IpAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+    };
+
+    // The filtering tests run from a fresh socket, the mapping tests open the
+    // NAT towards the server's other address
+    let mapping_conn = net.bind(laddr).await.map_err(other)?;
+    let filtering_conn = match net.bind(laddr).await {
+        Ok(conn) => conn,
+        Err(err) => {
+            let _ = mapping_conn.close().await;
+            return Err(other(err));
+        }
+    };
+
+    let result = run_tests(&mapping_conn, &filtering_conn, server_addr).await;
+    let _ = mapping_conn.close().await;
+    let _ = filtering_conn.close().await;
+    result
+}
+
+async fn run_tests(
+    mapping_conn: &Arc<dyn Conn + Send + Sync>,
+    filtering_conn: &Arc<dyn Conn + Send + Sync>,
+    server_addr: SocketAddr,
+) -> Result<NatBehavior, NatBehaviorError> {
+    let (mapped_addr, other_addr) = discover_mapping_start(mapping_conn, server_addr).await?;
+    let mapping = discover_mapping(mapping_conn, server_addr, mapped_addr, other_addr).await?;
+    let filtering = discover_filtering(filtering_conn, server_addr).await?;
+    let hairpinning = discover_hairpinning(mapping_conn, mapped_addr).await?;
+
+    Ok(NatBehavior {
+        mapping,
+        filtering,
+        mapped_addr,
+        hairpinning,
+    })
+}
+
+// Test I of RFC 5780 Section 4.3, returns the mapped address and the server's
+// other address
+async fn discover_mapping_start(
+    conn: &Arc<dyn Conn + Send + Sync>,
+    server_addr: SocketAddr,
+) -> Result<(SocketAddr, SocketAddr), NatBehaviorError> {
+    let (resp, _) = binding_request(conn, server_addr, None)
+        .await?
+        .ok_or(NatBehaviorError::NoResponse)?;
+    let mapped_addr = xor_mapped_addr(&resp)?;
+
+    let mut other_addr = OtherAddress::default();
+    if other_addr.get_from(&resp).is_err() {
+        return Err(NatBehaviorError::Unsupported);
+    }
+    let other_addr = SocketAddr::new(other_addr.0.ip, other_addr.0.port);
+    if other_addr.ip() == server_addr.ip() || other_addr.port() == server_addr.port() {
+        return Err(NatBehaviorError::Unsupported);
+    }
+
+    Ok((mapped_addr, other_addr))
+}
+
+// Tests II and III of RFC 5780 Section 4.3: the mapping is compared after
+// changing the destination IP, then the destination port as well
+async fn discover_mapping(
+    conn: &Arc<dyn Conn + Send + Sync>,
+    server_addr: SocketAddr,
+    mapped_addr: SocketAddr,
+    other_addr: SocketAddr,
+) -> Result<NatMapping, NatBehaviorError> {
+    let to = SocketAddr::new(other_addr.ip(), server_addr.port());
+    let (resp, _) = binding_request(conn, to, None)
+        .await?
+        .ok_or(NatBehaviorError::NoResponse)?;
+    let mapped_addr2 = xor_mapped_addr(&resp)?;
+    if mapped_addr2 == mapped_addr {
+        return Ok(NatMapping::EndpointIndependent);
+    }
+
+    let (resp, _) = binding_request(conn, other_addr, None)
+        .await?
+        .ok_or(NatBehaviorError::NoResponse)?;
+    if xor_mapped_addr(&resp)? == mapped_addr2 {
+        Ok(NatMapping::AddressDependent)
+    } else {
+        Ok(NatMapping::AddressAndPortDependent)
+    }
+}
+
+// Tests I to III of RFC 5780 Section 4.4: after the first request opens the
+// NAT, the server is asked to answer from its other IP and port, then from
+// its other port only
+async fn discover_filtering(
+    conn: &Arc<dyn Conn + Send + Sync>,
+    server_addr: SocketAddr,
+) -> Result<NatFiltering, NatBehaviorError> {
+    binding_request(conn, server_addr, None)
+        .await?
+        .ok_or(NatBehaviorError::NoResponse)?;
+
+    let change_ip_and_port = ChangeRequest {
+        change_ip: true,
+        change_port: true,
+    };
+    if let Some((_, from)) = binding_request(conn, server_addr, Some(change_ip_and_port)).await? {
+        if from.ip() == server_addr.ip() {
+            return Err(NatBehaviorError::Unsupported);
+        }
+        return Ok(NatFiltering::EndpointIndependent);
+    }
+
+    let change_port = ChangeRequest {
+        change_ip: false,
+        change_port: true,
+    };
+    match binding_request(conn, server_addr, Some(change_port)).await? {
+        Some((_, from)) if from == server_addr => Err(NatBehaviorError::Unsupported),
+        Some(_) => Ok(NatFiltering::AddressDependent),
+        None => Ok(NatFiltering::AddressAndPortDependent),
+    }
+}
+
+// RFC 5780 Section 4.5: a request sent to the own mapped address arrives back
+// on the socket if the NAT hairpins it
+async fn discover_hairpinning(
+    conn: &Arc<dyn Conn + Send + Sync>,
+    mapped_addr: SocketAddr,
+) -> Result<bool, NatBehaviorError> {
+    let request = build_binding_request(None)?;
+    let echo = round_trip(conn, mapped_addr, &request, TEST_TIMEOUT)
+        .await
+        .map_err(other)?;
+    Ok(echo.is_some())
+}
+
+// binding_request returns the success response to a Binding request with the
+// address it came from, None if the request went unanswered
+async fn binding_request(
+    conn: &Arc<dyn Conn + Send + Sync>,
+    to: SocketAddr,
+    change: Option<ChangeRequest>,
+) -> Result<Option<(Message, SocketAddr)>, NatBehaviorError> {
+    let request = build_binding_request(change)?;
+    match round_trip(conn, to, &request, TEST_TIMEOUT)
+        .await
+        .map_err(other)?
+    {
+        Some((resp, _)) if resp.typ != BINDING_SUCCESS => Err(NatBehaviorError::Other(format!(
+            "unexpected STUN response {}",
+            resp.typ
+        ))),
+        resp => Ok(resp),
+    }
+}
+
+fn build_binding_request(change: Option<ChangeRequest>) -> Result<Message, NatBehaviorError> {
+    let mut setters: Vec<Box<dyn Setter>> =
+        vec![Box::new(BINDING_REQUEST), Box::new(TransactionId::new())];
+    if let Some(change) = change {
+        setters.push(Box::new(change));
+    }
+
+    let mut request = Message::new();
+    request.build(&setters).map_err(other)?;
+    Ok(request)
+}
+
+fn xor_mapped_addr(resp: &Message) -> Result<SocketAddr, NatBehaviorError> {
+    let mut addr = XorMappedAddress::default();
+    addr.get_from(resp).map_err(other)?;
+    Ok(SocketAddr::new(addr.ip, addr.port))
+}
+
+fn other(err: impl std::fmt::Display) -> NatBehaviorError {
+    NatBehaviorError::Other(err.to_string())
+}
@@ -0,0 +1,230 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use super::nat_behavior::*;
+use crate::webrtc::{
+    stun::{
+        addr::{MappedAddress, OtherAddress},
+        change_request::ChangeRequest,
+        message::{Getter, Message, BINDING_REQUEST, BINDING_SUCCESS},
+        xoraddr::XorMappedAddress,
+    },
+    util::vnet::{
+        nat::{EndpointDependencyType, NatType},
+        net::{Net, NetConfig},
+        router::{Nic, Router, RouterConfig},
+    },
+};
+
+const SERVER_IPS: [Ipv4Addr; 2] = [Ipv4Addr::new(1, 2, 3, 4), Ipv4Addr::new(1, 2, 3, 5)];
+const SERVER_PORTS: [u16; 2] = [3478, 3479];
+const NAT_MAPPED_IP: Ipv4Addr = Ipv4Addr::new(1, 2, 3, 100);
+
+// A client network behind a NAT router, on a WAN with a STUN server that
+// answers from two IPs and two ports
+struct VirtualInternet {
+    wan: Arc<Mutex<Router>>,
+    client: Arc<Net>,
+}
+
+impl VirtualInternet {
+    async fn new(
+        mapping_behavior: EndpointDependencyType,
+        filtering_behavior: EndpointDependencyType,
+    ) -> Self {
+        let wan = Arc::new(Mutex::new(
+            Router::new(RouterConfig {
+                cidr: "1.2.3.0/24".to_owned(),
+                ..Default::default()
+            })
+            .unwrap(),
+        ));
+        let lan = Arc::new(Mutex::new(
+            Router::new(RouterConfig {
+                cidr: "192.168.0.0/24".to_owned(),
+                static_ips: vec![NAT_MAPPED_IP.to_string()],
+                nat_type: Some(NatType {
+                    mapping_behavior,
+                    filtering_behavior,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .unwrap(),
+        ));
+        wan.lock().await.add_router(Arc::clone(&lan)).await.unwrap();
+        lan.lock().await.set_router(Arc::clone(&wan)).await.unwrap();
+
+        let server = Net::new(Some(NetConfig {
+            static_ips: SERVER_IPS.iter().map(|ip| ip.to_string()).collect(),
+            ..Default::default()
+        }));
+        connect(&wan, &server).await;
+        start_stun_server(&server).await;
+
+        let client = Net::new(Some(NetConfig::default()));
+        connect(&lan, &client).await;
+
+        wan.lock().await.start().await.unwrap();
+
+        VirtualInternet {
+            wan,
+            client: Arc::new(client),
+        }
+    }
+
+    async fn discover(&self) -> NatBehavior {
+        let server_addr = SocketAddr::new(SERVER_IPS[0].into(), SERVER_PORTS[0]);
+        let behavior = discover(&self.client, server_addr).await.unwrap();
+        assert_eq!(behavior.mapped_addr.ip(), IpAddr::from(NAT_MAPPED_IP));
+
+        self.wan.lock().await.stop().await.unwrap();
+        behavior
+    }
+}
+
+async fn connect(router: &Arc<Mutex<Router>>, net: &Net) {
+    let nic = net.get_nic().unwrap();
+    router.lock().await.add_net(Arc::clone(&nic)).await.unwrap();
+    nic.lock()
+        .await
+        .set_router(Arc::clone(router))
+        .await
+        .unwrap();
+}
+
+// Stand-in for an RFC 5780 STUN server: each socket answers Binding requests
+// with XOR-MAPPED-ADDRESS and OTHER-ADDRESS, from the socket CHANGE-REQUEST
+// asks for
+async fn start_stun_server(net: &Net) {
+    let mut conns = vec![];
+    for ip in SERVER_IPS {
+        for port in SERVER_PORTS {
+            conns.push(net.bind(SocketAddr::new(ip.into(), port)).await.unwrap());
+        }
+    }
+    let conns = Arc::new(conns);
+
+    for i in 0..conns.len() {
+        let conns = Arc::clone(&conns);
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 1500];
+            while let Ok((n, from)) = conns[i].recv_from(&mut buf).await {
+                let mut request = Message::new();
+                request.raw = buf[..n].to_vec();
+                if request.decode().is_err() || request.typ != BINDING_REQUEST {
+                    continue;
+                }
+
+                let mut change = ChangeRequest::default();
+                let _ = change.get_from(&request);
+                let (ip_index, port_index) = (i / 2, i % 2);
+                let responder = (ip_index ^ change.change_ip as usize) * 2
+                    + (port_index ^ change.change_port as usize);
+
+                let response = binding_success(&request, from);
+                let _ = conns[responder].send_to(&response.raw, from).await;
+            }
+        });
+    }
+}
+
+fn binding_success(request: &Message, from: SocketAddr) -> Message {
+    let mut response = Message::new();
+    response
+        .build(&[
+            Box::new(request.clone()),
+            Box::new(BINDING_SUCCESS),
+            Box::new(XorMappedAddress {
+                ip: from.ip(),
+                port: from.port(),
+            }),
+            Box::new(OtherAddress(MappedAddress {
+                ip: SERVER_IPS[1].into(),
+                port: SERVER_PORTS[1],
+            })),
+        ])
+        .unwrap();
+    response
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_discover_mapping() {
+    for (mapping_behavior, mapping) in [
+        (
+            EndpointDependencyType::EndpointIndependent,
+            NatMapping::EndpointIndependent,
+        ),
+        (
+            EndpointDependencyType::EndpointAddrDependent,
+            NatMapping::AddressDependent,
+        ),
+        (
+            EndpointDependencyType::EndpointAddrPortDependent,
+            NatMapping::AddressAndPortDependent,
+        ),
+    ] {
+        let internet = VirtualInternet::new(
+            mapping_behavior,
+            EndpointDependencyType::EndpointIndependent,
+        )
+        .await;
+        assert_eq!(internet.discover().await.mapping, mapping);
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_discover_filtering() {
+    for (filtering_behavior, filtering) in [
+        (
+            EndpointDependencyType::EndpointIndependent,
+            NatFiltering::EndpointIndependent,
+        ),
+        (
+            EndpointDependencyType::EndpointAddrDependent,
+            NatFiltering::AddressDependent,
+        ),
+        (
+            EndpointDependencyType::EndpointAddrPortDependent,
+            NatFiltering::AddressAndPortDependent,
+        ),
+    ] {
+        let internet = VirtualInternet::new(
+            EndpointDependencyType::EndpointIndependent,
+            filtering_behavior,
+        )
+        .await;
+        assert_eq!(internet.discover().await.filtering, filtering);
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_discover_hairpinning() {
+    // The request sent to the own mapped address comes back through the same mapping
+    let internet = VirtualInternet::new(
+        EndpointDependencyType::EndpointIndependent,
+        EndpointDependencyType::EndpointAddrPortDependent,
+    )
+    .await;
+    assert!(internet.discover().await.hairpinning);
+
+    // The request gets a mapping of its own, so the echo arrives from another
+    // port than it was sent to and is matched by its transaction id
+    let internet = VirtualInternet::new(
+        EndpointDependencyType::EndpointAddrPortDependent,
+        EndpointDependencyType::EndpointIndependent,
+    )
+    .await;
+    assert!(internet.discover().await.hairpinning);
+
+    // The mapping only lets the STUN server's addresses in, the echo from the
+    // other mapping is filtered
+    let internet = VirtualInternet::new(
+        EndpointDependencyType::EndpointAddrPortDependent,
+        EndpointDependencyType::EndpointAddrPortDependent,
+    )
+    .await;
+    assert!(!internet.discover().await.hairpinning);
+}
@@ -1,9 +1,10 @@
 use std::{sync::Arc, time::Duration};
 
-use anyhow::{Error, Result};
+use anyhow::{Context, Error, Result};
 use bytes::Bytes;
 use log::warn;
 use reqwest::{Client as HttpClient, Response};
+use thiserror::Error as ThisError;
 use tinyjson::JsonValue;
 use tokio::{sync::mpsc, time::sleep};
 
@@ -12,17 +13,72 @@ use crate::webrtc::{
     peer_connection::{sdp::session_description::RTCSessionDescription, RTCPeerConnection},
 };
 
-use super::addr_cell::AddrCell;
+use super::{
+    addr_cell::AddrCell,
+    connection_handle::ConnectionHandle,
+    socket_config::{SocketConfig, SocketConfigError},
+};
 
 const MESSAGE_SIZE: usize = 1500;
 const CLIENT_CHANNEL_SIZE: usize = 8;
 
 pub struct Socket;
 
+/// Error returned by [`Socket::connect`] and its variants
+#[derive(Debug, ThisError, Clone, PartialEq)]
+pub enum ConnectError {
+    /// The socket config is invalid, see [`SocketConfig::validate`]
+    #[error(transparent)]
+    Config(#[from] SocketConfigError),
+    /// The data channel could not be created
+    #[error("cannot create data channel: {0}")]
+    DataChannel(String),
+    /// The offer/answer exchange with the server failed
+    #[error("cannot signal the server: {0}")]
+    Signaling(String),
+}
+
 impl Socket {
     pub async fn connect(
         server_url: &str,
-    ) -> (AddrCell, mpsc::Sender<Box<[u8]>>, mpsc::Receiver<Box<[u8]>>) {
+    ) -> Result<(AddrCell, mpsc::Sender<Box<[u8]>>, mpsc::Receiver<Box<[u8]>>), ConnectError> {
+        let (_, addr_cell, to_server_sender, to_client_receiver) =
+            Self::connect_with_handle(server_url).await?;
+        Ok((addr_cell, to_server_sender, to_client_receiver))
+    }
+
+    /// Like [`Socket::connect`], also returning a handle to the underlying connection
+    pub async fn connect_with_handle(
+        server_url: &str,
+    ) -> Result<
+        (
+            ConnectionHandle,
+            AddrCell,
+            mpsc::Sender<Box<[u8]>>,
+            mpsc::Receiver<Box<[u8]>>,
+        ),
+        ConnectError,
+    > {
+        Self::connect_with_config(server_url, SocketConfig::default()).await
+    }
+
+    /// Like [`Socket::connect_with_handle`], with the connection set up from `config`.
+    /// Fails without connecting if `config` is invalid, see [`SocketConfig::validate`],
+    /// or if the offer/answer exchange with the server fails.
+    pub async fn connect_with_config(
+        server_url: &str,
+        config: SocketConfig,
+    ) -> Result<
+        (
+            ConnectionHandle,
+            AddrCell,
+            mpsc::Sender<Box<[u8]>>,
+            mpsc::Receiver<Box<[u8]>>,
+        ),
+        ConnectError,
+    > {
+        config.validate()?;
+
         let (to_server_sender, to_server_receiver) =
             mpsc::channel::<Box<[u8]>>(CLIENT_CHANNEL_SIZE);
         let (to_client_sender, to_client_receiver) =
@@ -31,7 +87,7 @@ impl Socket {
         let addr_cell = AddrCell::default();
 
         // create a new RTCPeerConnection
-        let peer_connection = RTCPeerConnection::new().await;
+        let peer_connection = RTCPeerConnection::new(&config).await;
 
         let label = "data";
         let protocol = "";
@@ -40,7 +96,7 @@ impl Socket {
         let data_channel = peer_connection
             .create_data_channel(label, protocol)
             .await
-            .expect("cannot create data channel");
+            .map_err(|err| ConnectError::DataChannel(err.to_string()))?;
 
         // datachannel on_error callback
         data_channel
@@ -56,10 +112,13 @@ impl Socket {
             .on_open(Box::new(move || {
                 let data_channel_ref_2 = Arc::clone(&data_channel_ref);
                 Box::pin(async move {
-                    let detached_data_channel = data_channel_ref_2
-                        .detach()
-                        .await
-                        .expect("data channel detach got error");
+                    let detached_data_channel = match data_channel_ref_2.detach().await {
+                        Ok(detached_data_channel) => detached_data_channel,
+                        Err(err) => {
+                            warn!("cannot detach data channel: {}", err);
+                            return;
+                        }
+                    };
 
                     // Handle reading from the data channel
                     let detached_data_channel_1 = Arc::clone(&detached_data_channel);
@@ -80,70 +139,89 @@ impl Socket {
             }))
             .await;
 
-        // create an offer to send to the server
-        let offer = peer_connection
-            .create_offer()
-            .await
-            .expect("cannot create offer");
-
-        // sets the LocalDescription, and starts our UDP listeners
-        peer_connection
-            .set_local_description(offer)
+        // exchange the offer for the server's answer (signaling, essentially).
+        // The offer goes out without waiting for gathering to complete, it carries the
+        // candidates gathered so far and the rest trickle through
+        // `ConnectionHandle::on_local_candidate`
+        let candidate = signal(server_url, &peer_connection)
             .await
-            .expect("cannot set local description");
+            .map_err(|err| ConnectError::Signaling(format!("{:#}", err)))?;
 
-        // send a request to server to initiate connection (signaling, essentially)
-        let http_client = HttpClient::new();
+        addr_cell.receive_candidate(candidate.as_str()).await;
 
-        let sdp = peer_connection.local_description().await.unwrap().sdp;
+        let handle = ConnectionHandle::new(peer_connection, server_url);
+        handle.restart_ice_on_disconnect().await;
 
-        let sdp_len = sdp.len();
-
-        // wait to receive a response from server
-        let response: Response = loop {
-            let request = http_client
-                .post(server_url)
-                .header("Content-Length", sdp_len)
-                .body(sdp.clone());
+        Ok((handle, addr_cell, to_server_sender, to_client_receiver))
+    }
+}
 
-            match request.send().await {
-                Ok(resp) => {
-                    break resp;
-                }
-                Err(err) => {
-                    warn!("Could not send request, original error: {:?}", err);
-                    sleep(Duration::from_secs(1)).await;
-                }
-            };
+// signal creates an offer, sends it to the server, retrying until the server is
+// reachable, and applies the answer and the server candidate it responds with. It
+// returns that candidate. It runs again for each ICE restart, whose offer carries new
+// credentials.
+pub(crate) async fn signal(
+    server_url: &str,
+    peer_connection: &RTCPeerConnection,
+) -> Result<String> {
+    let offer = peer_connection
+        .create_offer()
+        .await
+        .context("cannot create offer")?;
+
+    // sets the LocalDescription, and starts our UDP listeners
+    peer_connection
+        .set_local_description(offer)
+        .await
+        .context("cannot set local description")?;
+
+    let http_client = HttpClient::new();
+
+    let sdp = peer_connection
+        .local_description()
+        .await
+        .context("no local description")?
+        .sdp;
+
+    let sdp_len = sdp.len();
+
+    // wait to receive a response from server
+    let response: Response = loop {
+        let request = http_client
+            .post(server_url)
+            .header("Content-Length", sdp_len)
+            .body(sdp.clone());
+
+        match request.send().await {
+            Ok(resp) => {
+                break resp;
+            }
+            Err(err) => {
+                warn!("Could not send request, original error: {:?}", err);
+                sleep(Duration::from_secs(1)).await;
+            }
         };
-        let response_string = response.text().await.unwrap();
-
-        // parse session from server response
-        let session_response: JsSessionResponse = get_session_response(response_string.as_str());
+    };
+    let response_string = response.text().await?;
 
-        // apply the server's response as the remote description
-        let session_description =
-            RTCSessionDescription::answer(session_response.answer.sdp).unwrap();
+    // parse session from server response
+    let session_response = get_session_response(response_string.as_str())?;
 
-        peer_connection
-            .set_remote_description(session_description)
-            .await
-            .expect("cannot set remote description");
+    // apply the server's response as the remote description
+    let session_description = RTCSessionDescription::answer(session_response.answer.sdp)?;
 
-        addr_cell
-            .receive_candidate(session_response.candidate.candidate.as_str())
-            .await;
+    peer_connection
+        .set_remote_description(session_description)
+        .await
+        .context("cannot set remote description")?;
 
-        // add ice candidate to connection
-        if let Err(error) = peer_connection
-            .add_ice_candidate(session_response.candidate.candidate)
-            .await
-        {
-            panic!("Error during add_ice_candidate: {:?}", error);
-        }
+    // add ice candidate to connection
+    peer_connection
+        .add_ice_candidate(session_response.candidate.candidate.clone())
+        .await
+        .context("cannot add ice candidate")?;
 
-        (addr_cell, to_server_sender, to_client_receiver)
-    }
+    Ok(session_response.candidate.candidate)
 }
 
 // read_loop shows how to read from the datachannel directly
@@ -203,17 +281,30 @@ pub(crate) struct JsSessionResponse {
     pub(crate) candidate: SessionCandidate,
 }
 
-fn get_session_response(input: &str) -> JsSessionResponse {
-    let json_obj: JsonValue = input.parse().unwrap();
+fn get_session_response(input: &str) -> Result<JsSessionResponse> {
+    let json_obj: JsonValue = input.parse()?;
 
-    let sdp_opt: Option<&String> = json_obj["answer"]["sdp"].get();
-    let sdp: String = sdp_opt.unwrap().clone();
+    let sdp: String = json_string(&json_obj, &["answer", "sdp"])
+        .context("no answer sdp in server response")?
+        .clone();
 
-    let candidate_opt: Option<&String> = json_obj["candidate"]["candidate"].get();
-    let candidate: String = candidate_opt.unwrap().clone();
+    let candidate: String = json_string(&json_obj, &["candidate", "candidate"])
+        .context("no candidate in server response")?
+        .clone();
 
-    JsSessionResponse {
+    Ok(JsSessionResponse {
         answer: SessionAnswer { sdp },
         candidate: SessionCandidate { candidate },
-    }
+    })
+}
+
+// json_string looks up the string under the path of object keys, indexing a
+// JsonValue panics on a missing key
+fn json_string<'a>(json: &'a JsonValue, path: &[&str]) -> Option<&'a String> {
+    path.iter()
+        .try_fold(json, |value, key| match value {
+            JsonValue::Object(object) => object.get(*key),
+            _ => None,
+        })?
+        .get()
 }
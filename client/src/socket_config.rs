@@ -0,0 +1,458 @@
+use ipnet::IpNet;
+use std::net::IpAddr;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::webrtc::{
+    dtls::{
+        cipher_suite::{cipher_suite_for_id, parse_cipher_suites, CipherSuiteId},
+        client_certificate_type::ClientCertificateType,
+        curve::named_curve::NamedCurve,
+        session::MemorySessionStore,
+    },
+    ice::{
+        candidate::CandidateType,
+        external_ip_mapper::ExternalIpMapper,
+        mdns::MulticastDnsMode,
+        url::{ProtoType, SchemeType, Url},
+    },
+};
+
+// Smallest datagram every IPv4 host must accept, RFC 791
+const MIN_DTLS_MTU: usize = 576;
+
+// Consent checks are sent up to 6 seconds apart, leave room for one to be lost
+// https://www.rfc-editor.org/rfc/rfc7675#section-5.1
+const MIN_CONSENT_EXPIRY: Duration = Duration::from_secs(12);
+
+/// Options for [`Socket::connect_with_config`](crate::Socket::connect_with_config)
+#[derive(Clone, Default)]
+pub struct SocketConfig {
+    /// STUN servers used to gather server reflexive candidates and TURN
+    /// servers used to gather relay candidates
+    pub ice_servers: Vec<IceServer>,
+    /// Authenticate the DTLS handshake with a pre-shared key instead of
+    /// certificates. No certificate is generated and the SDP carries no
+    /// fingerprint, so both sides must be configured with the same key and
+    /// identity.
+    pub dtls_psk: Option<DtlsPsk>,
+    /// Restrictions applied to the DTLS handshake
+    pub dtls_policy: DtlsPolicy,
+    /// How long the connection keeps sending without the server answering
+    /// a consent check before it fails with
+    /// [`FailureReason::ConsentExpired`](crate::FailureReason::ConsentExpired).
+    /// Defaults to 30 seconds, RFC 7675.
+    pub consent_expiry: Option<Duration>,
+    /// Restrictions on the local interfaces, addresses and ports ICE uses
+    pub ice_network: IceNetworkPolicy,
+    /// DTLS sessions kept for resumption, shared by the sockets connected
+    /// with clones of this config
+    pub dtls_session_cache: DtlsSessionCache,
+    /// Whether `.local` candidates of the server are resolved and host
+    /// candidates are signaled under an mDNS name instead of their address
+    pub mdns_mode: MdnsMode,
+}
+
+impl SocketConfig {
+    /// Checks that the configuration can produce a working DTLS handshake.
+    /// [`Socket::connect_with_config`](crate::Socket::connect_with_config)
+    /// runs the same check and returns this error without connecting.
+    pub fn validate(&self) -> Result<(), SocketConfigError> {
+        for server in &self.ice_servers {
+            for url in &server.urls {
+                let parsed = match Url::parse_url(url) {
+                    Ok(parsed) => parsed,
+                    Err(err) => {
+                        return Err(SocketConfigError::InvalidIceServerUrl {
+                            url: url.clone(),
+                            reason: err.to_string(),
+                        })
+                    }
+                };
+                let turn = parsed.scheme == SchemeType::Turn || parsed.scheme == SchemeType::Turns;
+                // The TURN client allocates over UDP only
+                if parsed.scheme == SchemeType::Turns || (turn && parsed.proto != ProtoType::Udp) {
+                    return Err(SocketConfigError::UnsupportedTurnTransport(url.clone()));
+                }
+                if turn && (server.username.is_empty() || server.credential.is_empty()) {
+                    return Err(SocketConfigError::MissingTurnCredentials(url.clone()));
+                }
+            }
+        }
+
+        if let Some(consent_expiry) = self.consent_expiry {
+            if consent_expiry < MIN_CONSENT_EXPIRY {
+                return Err(SocketConfigError::ConsentExpiryTooShort(consent_expiry));
+            }
+        }
+
+        self.ice_network.validate()?;
+        if let Some(range) = &self.ice_network.udp_port_range {
+            let sockets = self.sockets_per_address();
+            if range.len() < sockets {
+                return Err(SocketConfigError::PortRangeTooSmall {
+                    range: range.clone(),
+                    sockets,
+                });
+            }
+        }
+        // The mDNS name replaces the address the 1:1 NAT mapping would signal
+        if self.mdns_mode == MdnsMode::QueryAndGather
+            && !self.ice_network.nat_1to1_ips.is_empty()
+            && self.ice_network.nat_1to1_candidate_type == Nat1To1CandidateType::Host
+        {
+            return Err(SocketConfigError::MdnsWithNat1To1Host);
+        }
+        self.dtls_policy.validate(self.dtls_psk.is_some())?;
+
+        Ok(())
+    }
+
+    // sockets_per_address counts the UDP sockets gathering binds on one local
+    // address: the host candidate, one per stun: url over UDP and per turn: url,
+    // and the 1:1 NAT srflx candidate
+    fn sockets_per_address(&self) -> usize {
+        let urls = self
+            .ice_servers
+            .iter()
+            .flat_map(|server| &server.urls)
+            .filter_map(|url| Url::parse_url(url).ok())
+            .filter(|url| url.scheme == SchemeType::Turn || url.proto == ProtoType::Udp)
+            .count();
+        let nat_1to1_srflx = !self.ice_network.nat_1to1_ips.is_empty()
+            && self.ice_network.nat_1to1_candidate_type == Nat1To1CandidateType::ServerReflexive;
+        1 + urls + usize::from(nat_1to1_srflx)
+    }
+}
+
+/// Restrictions on the local interfaces, addresses and ports ICE gathers
+/// candidates on, e.g. for hosts behind strict firewalls. The defaults use
+/// every interface with ephemeral ports.
+#[derive(Clone, Debug, Default)]
+pub struct IceNetworkPolicy {
+    /// Only gather on these interfaces, by name such as `eth0`, empty for all
+    pub allowed_interfaces: Vec<String>,
+    /// Never gather on these interfaces, by name
+    pub denied_interfaces: Vec<String>,
+    /// Only gather on addresses in these networks, as an IP such as
+    /// `192.168.1.10` or a CIDR such as `10.0.0.0/8`, empty for all
+    pub allowed_networks: Vec<String>,
+    /// Never gather on addresses in these networks, as an IP or a CIDR
+    pub denied_networks: Vec<String>,
+    /// The IP versions to gather on
+    pub ip_family: IpFamily,
+    /// Check IPv4 paths before IPv6 ones on dual-stack hosts, IPv6 goes first
+    /// by default
+    pub prefer_ipv4: bool,
+    /// How long only paths of the preferred IP version are checked before the
+    /// other version races them, as Happy Eyeballs (RFC 8305) does. Defaults
+    /// to 250 milliseconds, zero races both from the start.
+    pub family_head_start: Option<Duration>,
+    /// Bind UDP candidates to a port in this range, ports in use are skipped.
+    /// Each local address binds one socket for its host candidate, one per
+    /// STUN and TURN url and one for a 1:1 NAT srflx candidate, the range must
+    /// hold that many ports: a range of one port binds a fixed port only
+    /// without ICE servers. `None` binds ephemeral ports.
+    pub udp_port_range: Option<RangeInclusive<u16>>,
+    /// Public addresses of a 1:1 NAT in front of the host, e.g. the elastic IP
+    /// of a cloud VM, as `public` or `public/private` to map one private
+    /// address. Saves the round trip to a STUN server.
+    pub nat_1to1_ips: Vec<String>,
+    /// How the addresses in `nat_1to1_ips` are signaled
+    pub nat_1to1_candidate_type: Nat1To1CandidateType,
+}
+
+impl IceNetworkPolicy {
+    fn validate(&self) -> Result<(), SocketConfigError> {
+        for network in self.allowed_networks.iter().chain(&self.denied_networks) {
+            if parse_network(network).is_none() {
+                return Err(SocketConfigError::InvalidNetwork(network.clone()));
+            }
+        }
+
+        if let Some(range) = &self.udp_port_range {
+            if range.is_empty() || *range.start() == 0 {
+                return Err(SocketConfigError::InvalidPortRange(range.clone()));
+            }
+        }
+
+        if let Err(err) =
+            ExternalIpMapper::new(self.nat_1to1_candidate_type.into(), &self.nat_1to1_ips)
+        {
+            return Err(SocketConfigError::InvalidNat1To1Mapping(err.to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether candidates are gathered on the interface name.
+    pub(crate) fn allows_interface(&self, name: &str) -> bool {
+        (self.allowed_interfaces.is_empty() || self.allowed_interfaces.iter().any(|n| n == name))
+            && !self.denied_interfaces.iter().any(|n| n == name)
+    }
+
+    /// Returns whether candidates are gathered on ip.
+    pub(crate) fn allows_ip(&self, ip: IpAddr) -> bool {
+        let contains = |networks: &[String]| {
+            networks
+                .iter()
+                .filter_map(|network| parse_network(network))
+                .any(|network| network.contains(&ip))
+        };
+        (self.allowed_networks.is_empty() || contains(&self.allowed_networks))
+            && !contains(&self.denied_networks)
+    }
+}
+
+// parse_network parses a CIDR, or an IP as the network of just that address
+fn parse_network(network: &str) -> Option<IpNet> {
+    network
+        .parse::<IpNet>()
+        .ok()
+        .or_else(|| network.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+/// The IP versions ICE gathers candidates on, see [`IceNetworkPolicy`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IpFamily {
+    /// IPv4 and IPv6
+    #[default]
+    Both,
+    /// IPv4 only
+    V4,
+    /// IPv6 only
+    V6,
+}
+
+/// How the public addresses of a 1:1 NAT are signaled, see
+/// [`IceNetworkPolicy::nat_1to1_ips`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Nat1To1CandidateType {
+    /// In place of the private address of host candidates
+    #[default]
+    Host,
+    /// As server reflexive candidates next to the host candidates
+    ServerReflexive,
+}
+
+impl From<Nat1To1CandidateType> for CandidateType {
+    fn from(typ: Nat1To1CandidateType) -> Self {
+        match typ {
+            Nat1To1CandidateType::Host => CandidateType::Host,
+            Nat1To1CandidateType::ServerReflexive => CandidateType::ServerReflexive,
+        }
+    }
+}
+
+/// How ICE uses mDNS (RFC 6762) for host candidates, see
+/// [`SocketConfig::mdns_mode`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MdnsMode {
+    /// `.local` candidates of the server are dropped, host candidates carry
+    /// their address
+    Disabled,
+    /// `.local` candidates of the server are resolved, host candidates carry
+    /// their address
+    #[default]
+    QueryOnly,
+    /// `.local` candidates of the server are resolved, host candidates carry
+    /// a random `.local` name answered on the local network
+    QueryAndGather,
+}
+
+impl From<MdnsMode> for MulticastDnsMode {
+    fn from(mode: MdnsMode) -> Self {
+        match mode {
+            MdnsMode::Disabled => MulticastDnsMode::Disabled,
+            MdnsMode::QueryOnly => MulticastDnsMode::QueryOnly,
+            MdnsMode::QueryAndGather => MulticastDnsMode::QueryAndGather,
+        }
+    }
+}
+
+/// A STUN or TURN server
+#[derive(Clone, Debug, Default)]
+pub struct IceServer {
+    /// Urls of the server, e.g. `stun:stun.example.org:3478` or
+    /// `turn:turn.example.org:3478?transport=udp`. TURN is supported over UDP
+    /// only, `turns:` and `?transport=tcp` urls are rejected.
+    pub urls: Vec<String>,
+    /// Long-term credential username of a TURN server
+    pub username: String,
+    /// Long-term credential password of a TURN server
+    pub credential: String,
+}
+
+/// Pre-shared key for PSK-only DTLS
+#[derive(Clone, Debug)]
+pub struct DtlsPsk {
+    /// PSK identity, configured the same on both peers. The DTLS server sends
+    /// it as its identity hint and the client as its PSK identity, a peer
+    /// announcing a different identity fails the handshake.
+    pub identity_hint: Vec<u8>,
+    /// Secret shared by both peers
+    pub key: Vec<u8>,
+}
+
+/// Cache of DTLS sessions. Reconnecting to a server whose session is cached
+/// takes the abbreviated handshake. Clones share the sessions, a new cache
+/// starts empty.
+#[derive(Clone, Default)]
+pub struct DtlsSessionCache(pub(crate) Arc<MemorySessionStore>);
+
+/// DTLS security policy, the defaults match the DTLS library defaults
+#[derive(Clone, Debug, Default)]
+pub struct DtlsPolicy {
+    /// DTLS 1.2 cipher suites in order of preference, empty for the defaults.
+    /// DTLS 1.3 always uses its own AEAD cipher suites.
+    pub cipher_suites: Vec<CipherSuiteId>,
+    /// Curves offered for the ECDHE key exchange in order of preference,
+    /// empty for the defaults
+    pub curves: Vec<NamedCurve>,
+    /// Abort DTLS 1.2 handshakes with servers that do not support the
+    /// Extended Master Secret extension (RFC 7627)
+    pub require_extended_master_secret: bool,
+    /// Size at which handshake messages are fragmented, 1200 bytes if unset
+    pub mtu: Option<usize>,
+    /// Number of records the replay protection window covers, 64 if unset
+    pub replay_window: Option<usize>,
+    /// How long the whole handshake may take, 30 seconds if unset
+    pub handshake_timeout: Option<Duration>,
+    /// Upper bound of the retransmission interval, which starts at one second
+    /// and doubles with every retransmission of a flight, 60 seconds if unset
+    pub max_flight_interval: Option<Duration>,
+    /// Offer DTLS 1.3 when acting as DTLS client with a certificate, the
+    /// server may still pick DTLS 1.2. Off by default.
+    pub enable_dtls13: bool,
+    /// File the DTLS secrets are appended to in the NSS key log format, so
+    /// that packet captures can be decrypted with Wireshark. If unset the
+    /// `SSLKEYLOGFILE` environment variable is read. Anyone reading the file
+    /// can decrypt the logged connections, for debugging only.
+    pub key_log_path: Option<PathBuf>,
+}
+
+impl DtlsPolicy {
+    /// A policy that only allows AEAD cipher suites, dropping the legacy
+    /// AES-CBC-SHA1 ones
+    pub fn aead_only() -> Self {
+        DtlsPolicy {
+            cipher_suites: vec![
+                CipherSuiteId::Tls_Ecdhe_Ecdsa_With_Aes_128_Gcm_Sha256,
+                CipherSuiteId::Tls_Ecdhe_Ecdsa_With_Chacha20_Poly1305_Sha256,
+                CipherSuiteId::Tls_Ecdhe_Ecdsa_With_Aes_128_Ccm,
+                CipherSuiteId::Tls_Psk_With_Aes_128_Gcm_Sha256,
+                CipherSuiteId::Tls_Psk_With_Chacha20_Poly1305_Sha256,
+                CipherSuiteId::Tls_Psk_With_Aes_128_Ccm,
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn validate(&self, psk: bool) -> Result<(), DtlsPolicyError> {
+        for id in &self.cipher_suites {
+            if cipher_suite_for_id(*id).is_err() {
+                return Err(DtlsPolicyError::UnsupportedCipherSuite(*id));
+            }
+        }
+
+        // Without a PSK the handshake is authenticated with an ECDSA certificate
+        let usable = parse_cipher_suites(&self.cipher_suites, !psk, psk)
+            .map(|cipher_suites| {
+                cipher_suites.iter().any(|cipher_suite| {
+                    psk || cipher_suite.certificate_type() == ClientCertificateType::EcdsaSign
+                })
+            })
+            .unwrap_or(false);
+        if !usable {
+            return Err(DtlsPolicyError::NoUsableCipherSuite);
+        }
+
+        if let Some(curve) = self.curves.iter().find(|curve| !curve.is_supported()) {
+            return Err(DtlsPolicyError::UnsupportedCurve(*curve));
+        }
+
+        if let Some(mtu) = self.mtu {
+            if mtu < MIN_DTLS_MTU {
+                return Err(DtlsPolicyError::MtuTooSmall(mtu));
+            }
+        }
+
+        if self.replay_window == Some(0) {
+            return Err(DtlsPolicyError::EmptyReplayWindow);
+        }
+
+        if self.handshake_timeout == Some(Duration::ZERO)
+            || self.max_flight_interval == Some(Duration::ZERO)
+        {
+            return Err(DtlsPolicyError::ZeroTimeout);
+        }
+
+        Ok(())
+    }
+}
+
+/// Error returned by [`SocketConfig::validate`]
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum SocketConfigError {
+    /// An ICE server url could not be parsed
+    #[error("invalid ice server url {url}: {reason}")]
+    InvalidIceServerUrl { url: String, reason: String },
+    /// A TURN server url has no username or credential
+    #[error("turn server {0} requires a username and credential")]
+    MissingTurnCredentials(String),
+    /// A TURN server url asks for TLS or TCP, only UDP is supported
+    #[error("turn server {0} is only supported over udp")]
+    UnsupportedTurnTransport(String),
+    /// Host candidates can not carry both an mDNS name and a 1:1 NAT address
+    #[error("mdns host candidates can not be combined with 1:1 nat host candidates")]
+    MdnsWithNat1To1Host,
+    /// The consent expiry leaves no room for a lost consent check
+    #[error("consent expiry {0:?} is below the minimum of {min:?}", min = MIN_CONSENT_EXPIRY)]
+    ConsentExpiryTooShort(Duration),
+    /// A network of the ICE network policy is neither an IP nor a CIDR
+    #[error("invalid network {0}, expected an ip or a cidr")]
+    InvalidNetwork(String),
+    /// The UDP port range is empty or starts at port 0
+    #[error("invalid udp port range {0:?}")]
+    InvalidPortRange(RangeInclusive<u16>),
+    /// The UDP port range holds fewer ports than the sockets bound on each
+    /// local address
+    #[error("udp port range {range:?} is too small for the {sockets} sockets bound per address")]
+    PortRangeTooSmall {
+        range: RangeInclusive<u16>,
+        sockets: usize,
+    },
+    /// The 1:1 NAT addresses can not be mapped to the local ones
+    #[error("invalid 1:1 nat mapping: {0}")]
+    InvalidNat1To1Mapping(String),
+    /// The DTLS policy can not be satisfied
+    #[error(transparent)]
+    DtlsPolicy(#[from] DtlsPolicyError),
+}
+
+/// Error in a [`DtlsPolicy`], see [`SocketConfig::validate`]
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum DtlsPolicyError {
+    /// The cipher suite is not implemented for DTLS 1.2
+    #[error("cipher suite {0} is not supported")]
+    UnsupportedCipherSuite(CipherSuiteId),
+    /// None of the cipher suites works with the authentication in use,
+    /// ECDSA certificates or a pre-shared key
+    #[error("no cipher suite is usable with the configured authentication")]
+    NoUsableCipherSuite,
+    /// No key exchange is implemented for the curve
+    #[error("curve {0:?} is not supported")]
+    UnsupportedCurve(NamedCurve),
+    /// The MTU can not fit a handshake fragment
+    #[error("mtu {0} is below the minimum of {min} bytes", min = MIN_DTLS_MTU)]
+    MtuTooSmall(usize),
+    /// A replay window of zero would drop every record
+    #[error("replay window must not be empty")]
+    EmptyReplayWindow,
+    /// A handshake timeout or flight interval of zero
+    #[error("handshake timeout and flight interval must not be zero")]
+    ZeroTimeout,
+}
@@ -0,0 +1,115 @@
+use super::socket_config::*;
+
+fn config_with_turn_url(url: &str) -> SocketConfig {
+    SocketConfig {
+        ice_servers: vec![IceServer {
+            urls: vec![url.to_owned()],
+            username: "user".to_owned(),
+            credential: "pass".to_owned(),
+        }],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_validate_turn_transport() {
+    for url in [
+        "turn:turn.example.org:3478",
+        "turn:turn.example.org:3478?transport=udp",
+    ] {
+        assert!(config_with_turn_url(url).validate().is_ok(), "{}", url);
+    }
+
+    for url in [
+        "turns:turn.example.org:5349",
+        "turns:turn.example.org:5349?transport=tcp",
+        "turn:turn.example.org:3478?transport=tcp",
+    ] {
+        assert!(
+            matches!(
+                config_with_turn_url(url).validate(),
+                Err(SocketConfigError::UnsupportedTurnTransport(_))
+            ),
+            "{}",
+            url
+        );
+    }
+
+    let mut config = config_with_turn_url("turn:turn.example.org:3478");
+    config.ice_servers[0].credential.clear();
+    assert!(matches!(
+        config.validate(),
+        Err(SocketConfigError::MissingTurnCredentials(_))
+    ));
+}
+
+#[test]
+fn test_validate_mdns_with_nat_1to1() {
+    let mut config = SocketConfig {
+        mdns_mode: MdnsMode::QueryAndGather,
+        ..Default::default()
+    };
+    config.ice_network.nat_1to1_ips = vec!["203.0.113.1".to_owned()];
+    assert!(matches!(
+        config.validate(),
+        Err(SocketConfigError::MdnsWithNat1To1Host)
+    ));
+
+    // The mDNS name only replaces the host candidates' address
+    config.ice_network.nat_1to1_candidate_type = Nat1To1CandidateType::ServerReflexive;
+    config.validate().unwrap();
+
+    config.ice_network.nat_1to1_ips.clear();
+    config.validate().unwrap();
+}
+
+#[test]
+fn test_validate_nat_1to1_srflx() {
+    let mut config = SocketConfig::default();
+    config.ice_network.nat_1to1_candidate_type = Nat1To1CandidateType::ServerReflexive;
+    config.ice_network.nat_1to1_ips = vec!["203.0.113.1/10.0.0.1".to_owned()];
+    config.validate().unwrap();
+
+    config.ice_network.nat_1to1_ips = vec!["not an ip".to_owned()];
+    assert!(matches!(
+        config.validate(),
+        Err(SocketConfigError::InvalidNat1To1Mapping(_))
+    ));
+}
+
+#[test]
+fn test_validate_port_range_size() {
+    let mut config = SocketConfig::default();
+    config.ice_network.udp_port_range = Some(50000..=50000);
+    config.validate().unwrap();
+
+    // The host and the srflx sockets can not share the port
+    config.ice_servers = vec![IceServer {
+        urls: vec!["stun:stun.example.org:3478".to_owned()],
+        ..Default::default()
+    }];
+    assert_eq!(
+        config.validate(),
+        Err(SocketConfigError::PortRangeTooSmall {
+            range: 50000..=50000,
+            sockets: 2
+        })
+    );
+    config.ice_network.udp_port_range = Some(50000..=50001);
+    config.validate().unwrap();
+
+    // The TURN socket and the 1:1 NAT srflx socket take a port each as well
+    config.ice_servers.push(IceServer {
+        urls: vec!["turn:turn.example.org:3478".to_owned()],
+        username: "user".to_owned(),
+        credential: "pass".to_owned(),
+    });
+    config.ice_network.nat_1to1_candidate_type = Nat1To1CandidateType::ServerReflexive;
+    config.ice_network.nat_1to1_ips = vec!["203.0.113.1".to_owned()];
+    assert!(matches!(
+        config.validate(),
+        Err(SocketConfigError::PortRangeTooSmall { sockets: 4, .. })
+    ));
+    config.ice_network.udp_port_range = Some(50000..=50003);
+    config.validate().unwrap();
+}
@@ -0,0 +1,64 @@
+use std::net::Ipv4Addr;
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+use super::{
+    socket::{ConnectError, Socket},
+    socket_config::{IceNetworkPolicy, SocketConfig, SocketConfigError},
+};
+
+// Serves the body as the answer to every offer and returns the url to post to
+async fn serve_answer(body: &'static str) -> String {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            // The offer fits one read, only the answer matters here
+            let mut buf = vec![0u8; 65536];
+            let _ = stream.read(&mut buf).await;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body,
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+            let _ = stream.shutdown().await;
+        }
+    });
+    url
+}
+
+#[tokio::test]
+async fn test_connect_rejects_invalid_config() {
+    let config = SocketConfig {
+        ice_network: IceNetworkPolicy {
+            udp_port_range: Some(0..=10),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let result = Socket::connect_with_config("http://127.0.0.1:1/", config).await;
+    assert_eq!(
+        result.err(),
+        Some(ConnectError::Config(SocketConfigError::InvalidPortRange(
+            0..=10
+        )))
+    );
+}
+
+#[tokio::test]
+async fn test_connect_reports_signaling_failure() {
+    for body in ["not json", "{}", "{\"answer\":\"sdp\"}"] {
+        let url = serve_answer(body).await;
+        let result = Socket::connect_with_handle(&url).await;
+        assert!(
+            matches!(result, Err(ConnectError::Signaling(_))),
+            "{}: {:?}",
+            body,
+            result.err()
+        );
+    }
+}
@@ -1,4 +1,6 @@
+use crate::socket_config::SocketConfig;
 use crate::webrtc::dtls_transport::RTCDtlsTransport;
+use crate::webrtc::ice::url::Url;
 use crate::webrtc::ice_transport::ice_gatherer::RTCIceGatherer;
 use crate::webrtc::ice_transport::RTCIceTransport;
 use crate::webrtc::peer_connection::certificate::RTCCertificate;
@@ -19,8 +21,23 @@ impl API {
     /// new_ice_gatherer creates a new ice gatherer.
     /// This constructor is part of the ORTC API. It is not
     /// meant to be used together with the basic WebRTC API.
-    pub(crate) fn new_ice_gatherer() -> Result<RTCIceGatherer> {
-        Ok(RTCIceGatherer::new())
+    pub(crate) fn new_ice_gatherer(config: &SocketConfig) -> Result<RTCIceGatherer> {
+        let mut validated_servers = vec![];
+        for server in &config.ice_servers {
+            for url in &server.urls {
+                let mut url = Url::parse_url(url)?;
+                url.username = server.username.clone();
+                url.password = server.credential.clone();
+                validated_servers.push(url);
+            }
+        }
+
+        Ok(RTCIceGatherer::new(
+            validated_servers,
+            config.consent_expiry,
+            config.ice_network.clone(),
+            config.mdns_mode.into(),
+        ))
     }
 
     /// new_ice_transport creates a new ice transport.
@@ -33,14 +50,27 @@ impl API {
     /// new_dtls_transport creates a new dtls_transport transport.
     /// This constructor is part of the ORTC API. It is not
     /// meant to be used together with the basic WebRTC API.
+    ///
+    /// With a pre-shared key no certificate is generated, the handshake is
+    /// authenticated by the key alone.
     pub(crate) fn new_dtls_transport(
         ice_transport: Arc<RTCIceTransport>,
+        config: &SocketConfig,
     ) -> Result<RTCDtlsTransport> {
-        let kp = KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256)?;
-        let cert = RTCCertificate::from_key_pair(kp)?;
-        let certificates = vec![cert];
+        let certificates = if config.dtls_psk.is_some() {
+            vec![]
+        } else {
+            let kp = KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256)?;
+            vec![RTCCertificate::from_key_pair(kp)?]
+        };
 
-        Ok(RTCDtlsTransport::new(ice_transport, certificates))
+        Ok(RTCDtlsTransport::new(
+            ice_transport,
+            certificates,
+            config.dtls_psk.clone(),
+            config.dtls_policy.clone(),
+            config.dtls_session_cache.clone(),
+        ))
     }
 
     /// new_sctp_transport creates a new SCTPTransport.
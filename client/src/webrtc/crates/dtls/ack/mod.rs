@@ -0,0 +1,69 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Write};
+
+use super::content::*;
+use super::error::*;
+
+const RECORD_NUMBER_SIZE: usize = 16;
+
+// struct {
+//     uint64 epoch;
+//     uint64 sequence_number;
+// } RecordNumber;
+// https://www.rfc-editor.org/rfc/rfc9147#section-7
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) struct RecordNumber {
+    pub(crate) epoch: u64,
+    pub(crate) sequence_number: u64,
+}
+
+// DTLS 1.3 acknowledges handshake records explicitly instead of relying on the
+// peer's next flight. The ACK lists the records received in the current flight.
+//
+// struct {
+//     RecordNumber record_numbers<0..2^16-1>;
+// } ACK;
+// https://www.rfc-editor.org/rfc/rfc9147#section-7
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct Ack {
+    pub(crate) record_numbers: Vec<RecordNumber>,
+}
+
+impl Ack {
+    pub(crate) fn content_type(&self) -> ContentType {
+        ContentType::Ack
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        2 + self.record_numbers.len() * RECORD_NUMBER_SIZE
+    }
+
+    pub(crate) fn marshal<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_u16::<BigEndian>((self.record_numbers.len() * RECORD_NUMBER_SIZE) as u16)?;
+        for r in &self.record_numbers {
+            writer.write_u64::<BigEndian>(r.epoch)?;
+            writer.write_u64::<BigEndian>(r.sequence_number)?;
+        }
+
+        Ok(writer.flush()?)
+    }
+
+    pub(crate) fn unmarshal<R: Read>(reader: &mut R) -> Result<Self> {
+        let record_numbers_len = reader.read_u16::<BigEndian>()? as usize;
+        if !record_numbers_len.is_multiple_of(RECORD_NUMBER_SIZE) {
+            return Err(Error::ErrInvalidPacketLength);
+        }
+
+        let mut record_numbers = vec![];
+        for _ in 0..record_numbers_len / RECORD_NUMBER_SIZE {
+            let epoch = reader.read_u64::<BigEndian>()?;
+            let sequence_number = reader.read_u64::<BigEndian>()?;
+            record_numbers.push(RecordNumber {
+                epoch,
+                sequence_number,
+            });
+        }
+
+        Ok(Ack { record_numbers })
+    }
+}
@@ -5,8 +5,9 @@ use byteorder::{ReadBytesExt, WriteBytesExt};
 use std::fmt;
 use std::io::{Read, Write};
 
+/// Level of a DTLS alert [RFC 5246 Section 7.2]
 #[derive(Copy, Clone, PartialEq, Debug)]
-pub(crate) enum AlertLevel {
+pub enum AlertLevel {
     Warning = 1,
     Fatal = 2,
     Invalid,
@@ -32,8 +33,9 @@ impl From<u8> for AlertLevel {
     }
 }
 
+/// Description of a DTLS alert [RFC 5246 Section 7.2]
 #[derive(Copy, Clone, PartialEq, Debug)]
-pub(crate) enum AlertDescription {
+pub enum AlertDescription {
     CloseNotify = 0,
     UnexpectedMessage = 10,
     BadRecordMac = 20,
@@ -0,0 +1,62 @@
+use super::*;
+use crate::webrtc::dtls::content::*;
+
+// The ChaCha20-Poly1305 suites of RFC 7905, with ECDHE-ECDSA and with PSK
+const CHACHA20_SUITES: [(u16, CipherSuiteId, bool); 2] = [
+    (
+        0xcca9,
+        CipherSuiteId::Tls_Ecdhe_Ecdsa_With_Chacha20_Poly1305_Sha256,
+        false,
+    ),
+    (
+        0xccab,
+        CipherSuiteId::Tls_Psk_With_Chacha20_Poly1305_Sha256,
+        true,
+    ),
+];
+
+#[test]
+fn test_chacha20_poly1305_cipher_suites_round_trip() {
+    let master_secret = [0x5au8; 48];
+    let client_random = [0x01u8; 32];
+    let server_random = [0x02u8; 32];
+
+    for (value, id, psk) in CHACHA20_SUITES {
+        assert_eq!(CipherSuiteId::from(value), id);
+
+        let mut client = cipher_suite_for_id(id).unwrap();
+        let mut server = cipher_suite_for_id(id).unwrap();
+        assert_eq!(client.id(), id);
+        assert_eq!(client.is_psk(), psk);
+        assert!(!client.is_initialized());
+
+        client
+            .init(&master_secret, &client_random, &server_random, true)
+            .unwrap();
+        server
+            .init(&master_secret, &client_random, &server_random, false)
+            .unwrap();
+        assert!(client.is_initialized());
+
+        for (sender, receiver) in [(&client, &server), (&server, &client)] {
+            let payload = b"application data";
+            let h = RecordLayerHeader {
+                content_type: ContentType::ApplicationData,
+                protocol_version: PROTOCOL_VERSION1_2,
+                epoch: 1,
+                sequence_number: 3,
+                connection_id: vec![],
+                content_len: payload.len() as u16,
+            };
+            let mut raw = vec![];
+            h.marshal(&mut raw).unwrap();
+            raw.extend_from_slice(payload);
+
+            let encrypted = sender.encrypt(&h, &raw).unwrap();
+            assert_ne!(encrypted, raw);
+            let decrypted = receiver.decrypt(&h, &encrypted).unwrap();
+            // The header keeps the length of the encrypted record
+            assert_eq!(decrypted[h.size()..], raw[h.size()..]);
+        }
+    }
+}
@@ -1,12 +1,16 @@
 pub(crate) mod cipher_suite_aes_128_ccm;
 pub(crate) mod cipher_suite_aes_128_gcm_sha256;
 pub(crate) mod cipher_suite_aes_256_cbc_sha;
+pub(crate) mod cipher_suite_chacha20_poly1305_sha256;
 pub(crate) mod cipher_suite_tls_ecdhe_ecdsa_with_aes_128_ccm;
 pub(crate) mod cipher_suite_tls_ecdhe_ecdsa_with_aes_128_ccm8;
 pub(crate) mod cipher_suite_tls_psk_with_aes_128_ccm;
 pub(crate) mod cipher_suite_tls_psk_with_aes_128_ccm8;
 pub(crate) mod cipher_suite_tls_psk_with_aes_128_gcm_sha256;
 
+#[cfg(test)]
+mod cipher_suite_test;
+
 use std::fmt;
 use std::marker::{Send, Sync};
 
@@ -16,6 +20,7 @@ use super::record_layer::record_layer_header::*;
 
 use cipher_suite_aes_128_gcm_sha256::*;
 use cipher_suite_aes_256_cbc_sha::*;
+use cipher_suite_chacha20_poly1305_sha256::*;
 use cipher_suite_tls_ecdhe_ecdsa_with_aes_128_ccm::*;
 use cipher_suite_tls_ecdhe_ecdsa_with_aes_128_ccm8::*;
 use cipher_suite_tls_psk_with_aes_128_ccm::*;
@@ -26,7 +31,7 @@ use cipher_suite_tls_psk_with_aes_128_gcm_sha256::*;
 // Supported Cipher Suites
 #[allow(non_camel_case_types)]
 #[derive(Copy, Clone, Debug, PartialEq)]
-pub(crate) enum CipherSuiteId {
+pub enum CipherSuiteId {
     // AES-128-CCM
     Tls_Ecdhe_Ecdsa_With_Aes_128_Ccm = 0xc0ac,
     Tls_Ecdhe_Ecdsa_With_Aes_128_Ccm_8 = 0xc0ae,
@@ -39,9 +44,17 @@ pub(crate) enum CipherSuiteId {
     Tls_Ecdhe_Ecdsa_With_Aes_256_Cbc_Sha = 0xc00a,
     Tls_Ecdhe_Rsa_With_Aes_256_Cbc_Sha = 0xc014,
 
+    // CHACHA20-POLY1305-SHA256
+    Tls_Ecdhe_Ecdsa_With_Chacha20_Poly1305_Sha256 = 0xcca9,
+
     Tls_Psk_With_Aes_128_Ccm = 0xc0a4,
     Tls_Psk_With_Aes_128_Ccm_8 = 0xc0a8,
     Tls_Psk_With_Aes_128_Gcm_Sha256 = 0x00a8,
+    Tls_Psk_With_Chacha20_Poly1305_Sha256 = 0xccab,
+
+    // TLS 1.3, only usable with DTLS 1.3
+    Tls_Aes_128_Gcm_Sha256 = 0x1301,
+    Tls_Chacha20_Poly1305_Sha256 = 0x1303,
 
     Unsupported,
 }
@@ -67,11 +80,21 @@ impl fmt::Display for CipherSuiteId {
             CipherSuiteId::Tls_Ecdhe_Rsa_With_Aes_256_Cbc_Sha => {
                 write!(f, "TLS_ECDHE_RSA_WITH_AES_256_CBC_SHA")
             }
+            CipherSuiteId::Tls_Ecdhe_Ecdsa_With_Chacha20_Poly1305_Sha256 => {
+                write!(f, "TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256")
+            }
             CipherSuiteId::Tls_Psk_With_Aes_128_Ccm => write!(f, "TLS_PSK_WITH_AES_128_CCM"),
             CipherSuiteId::Tls_Psk_With_Aes_128_Ccm_8 => write!(f, "TLS_PSK_WITH_AES_128_CCM_8"),
             CipherSuiteId::Tls_Psk_With_Aes_128_Gcm_Sha256 => {
                 write!(f, "TLS_PSK_WITH_AES_128_GCM_SHA256")
             }
+            CipherSuiteId::Tls_Psk_With_Chacha20_Poly1305_Sha256 => {
+                write!(f, "TLS_PSK_WITH_CHACHA20_POLY1305_SHA256")
+            }
+            CipherSuiteId::Tls_Aes_128_Gcm_Sha256 => write!(f, "TLS_AES_128_GCM_SHA256"),
+            CipherSuiteId::Tls_Chacha20_Poly1305_Sha256 => {
+                write!(f, "TLS_CHACHA20_POLY1305_SHA256")
+            }
             _ => write!(f, "Unsupported CipherSuiteID"),
         }
     }
@@ -92,9 +115,17 @@ impl From<u16> for CipherSuiteId {
             0xc00a => CipherSuiteId::Tls_Ecdhe_Ecdsa_With_Aes_256_Cbc_Sha,
             0xc014 => CipherSuiteId::Tls_Ecdhe_Rsa_With_Aes_256_Cbc_Sha,
 
+            // CHACHA20-POLY1305-SHA256
+            0xcca9 => CipherSuiteId::Tls_Ecdhe_Ecdsa_With_Chacha20_Poly1305_Sha256,
+
             0xc0a4 => CipherSuiteId::Tls_Psk_With_Aes_128_Ccm,
             0xc0a8 => CipherSuiteId::Tls_Psk_With_Aes_128_Ccm_8,
             0x00a8 => CipherSuiteId::Tls_Psk_With_Aes_128_Gcm_Sha256,
+            0xccab => CipherSuiteId::Tls_Psk_With_Chacha20_Poly1305_Sha256,
+
+            // TLS 1.3
+            0x1301 => CipherSuiteId::Tls_Aes_128_Gcm_Sha256,
+            0x1303 => CipherSuiteId::Tls_Chacha20_Poly1305_Sha256,
 
             _ => CipherSuiteId::Unsupported,
         }
@@ -132,7 +163,7 @@ pub(crate) trait CipherSuite {
     ) -> Result<()>;
 
     fn encrypt(&self, pkt_rlh: &RecordLayerHeader, raw: &[u8]) -> Result<Vec<u8>>;
-    fn decrypt(&self, input: &[u8]) -> Result<Vec<u8>>;
+    fn decrypt(&self, h: &RecordLayerHeader, input: &[u8]) -> Result<Vec<u8>>;
 }
 
 // Taken from https://www.iana.org/assignments/tls-parameters/tls-parameters.xml
@@ -158,6 +189,9 @@ pub(crate) fn cipher_suite_for_id(id: CipherSuiteId) -> Result<Box<dyn CipherSui
         CipherSuiteId::Tls_Ecdhe_Ecdsa_With_Aes_256_Cbc_Sha => {
             Ok(Box::new(CipherSuiteAes256CbcSha::new(false)))
         }
+        CipherSuiteId::Tls_Ecdhe_Ecdsa_With_Chacha20_Poly1305_Sha256 => {
+            Ok(Box::new(CipherSuiteChaCha20Poly1305Sha256::new(false)))
+        }
         CipherSuiteId::Tls_Psk_With_Aes_128_Ccm => {
             Ok(Box::new(new_cipher_suite_tls_psk_with_aes_128_ccm()))
         }
@@ -167,6 +201,9 @@ pub(crate) fn cipher_suite_for_id(id: CipherSuiteId) -> Result<Box<dyn CipherSui
         CipherSuiteId::Tls_Psk_With_Aes_128_Gcm_Sha256 => {
             Ok(Box::new(CipherSuiteTlsPskWithAes128GcmSha256::default()))
         }
+        CipherSuiteId::Tls_Psk_With_Chacha20_Poly1305_Sha256 => {
+            Ok(Box::new(CipherSuiteChaCha20Poly1305Sha256::new(true)))
+        }
         _ => Err(Error::ErrInvalidCipherSuite),
     }
 }
@@ -175,12 +212,34 @@ pub(crate) fn cipher_suite_for_id(id: CipherSuiteId) -> Result<Box<dyn CipherSui
 pub(crate) fn default_cipher_suites() -> Vec<Box<dyn CipherSuite + Send + Sync>> {
     vec![
         Box::new(CipherSuiteAes128GcmSha256::new(false)),
+        Box::new(CipherSuiteChaCha20Poly1305Sha256::new(false)),
         Box::new(CipherSuiteAes256CbcSha::new(false)),
         Box::new(CipherSuiteAes128GcmSha256::new(true)),
         Box::new(CipherSuiteAes256CbcSha::new(true)),
     ]
 }
 
+// DTLS 1.3 CipherSuites we support in order of preference. These only name the
+// AEAD and hash, key exchange and authentication are negotiated separately.
+// https://tools.ietf.org/html/rfc8446#appendix-B.4
+pub(crate) fn dtls13_cipher_suites() -> Vec<CipherSuiteId> {
+    vec![
+        CipherSuiteId::Tls_Aes_128_Gcm_Sha256,
+        CipherSuiteId::Tls_Chacha20_Poly1305_Sha256,
+    ]
+}
+
+// dtls13_cipher_suite_hash returns the hash a DTLS 1.3 CipherSuite runs the key
+// schedule and the transcript hash with
+pub(crate) fn dtls13_cipher_suite_hash(id: CipherSuiteId) -> Result<CipherSuiteHash> {
+    match id {
+        CipherSuiteId::Tls_Aes_128_Gcm_Sha256 | CipherSuiteId::Tls_Chacha20_Poly1305_Sha256 => {
+            Ok(CipherSuiteHash::Sha256)
+        }
+        _ => Err(Error::ErrInvalidCipherSuite),
+    }
+}
+
 fn cipher_suites_for_ids(ids: &[CipherSuiteId]) -> Result<Vec<Box<dyn CipherSuite + Send + Sync>>> {
     let mut cipher_suites = vec![];
     for id in ids {
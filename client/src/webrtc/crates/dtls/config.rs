@@ -1,9 +1,12 @@
 use crate::webrtc::dtls::cipher_suite::*;
 use crate::webrtc::dtls::crypto::*;
+use crate::webrtc::dtls::curve::named_curve::NamedCurve;
 use crate::webrtc::dtls::error::*;
 use crate::webrtc::dtls::extension::extension_use_srtp::SrtpProtectionProfile;
 use crate::webrtc::dtls::handshaker::VerifyPeerCertificateFn;
+use crate::webrtc::dtls::session::SessionStore;
 
+use rand::Rng;
 use std::sync::Arc;
 use tokio::time::Duration;
 
@@ -20,6 +23,10 @@ pub(crate) struct Config {
     /// If cipher_suites is nil, a default list is used
     pub(crate) cipher_suites: Vec<CipherSuiteId>,
 
+    /// elliptic_curves is the list of curves offered for the ECDHE key exchange
+    /// in order of preference. If elliptic_curves is nil, a default list is used
+    pub(crate) elliptic_curves: Vec<NamedCurve>,
+
     /// srtp_protection_profiles are the supported protection profiles
     /// Clients will send this via use_srtp and assert that the server properly responds
     /// Servers will assert that clients send one of these profiles and will respond as needed
@@ -34,9 +41,16 @@ pub(crate) struct Config {
     pub(crate) extended_master_secret: ExtendedMasterSecretType,
 
     /// flight_interval controls how often we send outbound handshake messages
-    /// defaults to time.Second
+    /// defaults to time.Second. It doubles with every retransmission of a flight
+    /// up to max_flight_interval, see RFC 6347 Section 4.2.4.1
     pub(crate) flight_interval: Duration,
 
+    /// max_flight_interval caps the retransmission backoff, defaults to 60 seconds
+    pub(crate) max_flight_interval: Duration,
+
+    /// handshake_timeout bounds the whole handshake, defaults to 30 seconds
+    pub(crate) handshake_timeout: Duration,
+
     /// psk sets the pre-shared key used by this DTLS connection
     /// If psk is non-nil only psk cipher_suites will be used
     pub(crate) psk: Option<PskCallback>,
@@ -88,6 +102,31 @@ pub(crate) struct Config {
     /// Packet with sequence number older than this value compared to the latest
     /// accepted packet will be discarded. (default is 64)
     pub(crate) replay_protection_window: usize,
+
+    /// enable_dtls13 lets a client offer DTLS 1.3 next to DTLS 1.2 and use it
+    /// when the server selects it. Servers and PSK clients always use DTLS 1.2.
+    pub(crate) enable_dtls13: bool,
+
+    /// connection_id_generator enables the DTLS Connection ID extension (RFC 9146).
+    /// It returns the CID the peer puts on records it sends us, which lets the
+    /// connection keep working when the peer address changes. An empty CID
+    /// means we only send CIDs. (default is None, no CID is negotiated)
+    pub(crate) connection_id_generator: Option<ConnectionIdGenerator>,
+
+    /// session_store lets a client cache DTLS 1.2 sessions and resume them with
+    /// the abbreviated handshake, by session id or by session ticket (RFC 5077).
+    /// (default is None, every handshake is a full handshake)
+    pub(crate) session_store: Option<Arc<dyn SessionStore + Send + Sync>>,
+
+    /// session_key identifies the server in the session_store, e.g. its certificate
+    /// fingerprint. If empty, the server address is used.
+    pub(crate) session_key: Vec<u8>,
+
+    /// key_log_path is a file the connection secrets are appended to in the NSS
+    /// key log format, so that captures can be decrypted with Wireshark. DTLS 1.2
+    /// logs the master secret, DTLS 1.3 the traffic and exporter secrets. If empty
+    /// the SSLKEYLOGFILE environment variable is used. For debugging only.
+    pub(crate) key_log_path: String,
 }
 
 impl Default for Config {
@@ -95,10 +134,13 @@ impl Default for Config {
         Config {
             certificates: vec![],
             cipher_suites: vec![],
+            elliptic_curves: vec![],
             srtp_protection_profiles: vec![],
             client_auth: ClientAuthType::default(),
             extended_master_secret: ExtendedMasterSecretType::default(),
             flight_interval: Duration::default(),
+            max_flight_interval: Duration::default(),
+            handshake_timeout: Duration::default(),
             psk: None,
             psk_identity_hint: None,
             insecure_skip_verify: false,
@@ -108,16 +150,36 @@ impl Default for Config {
             server_name: String::default(),
             mtu: 0,
             replay_protection_window: 0,
+            enable_dtls13: false,
+            connection_id_generator: None,
+            session_store: None,
+            session_key: vec![],
+            key_log_path: String::default(),
         }
     }
 }
 
 pub(crate) const DEFAULT_MTU: usize = 1200; // bytes
+pub(crate) const DEFAULT_CONNECTION_ID_LENGTH: usize = 8; // bytes
 
 // PSKCallback is called once we have the remote's psk_identity_hint.
 // If the remote provided none it will be nil
 pub(crate) type PskCallback = Arc<dyn (Fn(&[u8]) -> Result<Vec<u8>>) + Send + Sync>;
 
+// ConnectionIdGenerator is called once per connection and returns the local
+// connection id.
+pub(crate) type ConnectionIdGenerator = Arc<dyn (Fn() -> Vec<u8>) + Send + Sync>;
+
+// random_connection_id_generator returns a generator of random connection ids
+// of the given length.
+pub(crate) fn random_connection_id_generator(size: usize) -> ConnectionIdGenerator {
+    Arc::new(move || {
+        let mut cid = vec![0u8; size];
+        rand::thread_rng().fill(cid.as_mut_slice());
+        cid
+    })
+}
+
 // ClientAuthType declares the policy the server will follow for
 // TLS Client Authentication.
 #[derive(Copy, Clone, PartialEq)]
@@ -179,5 +241,13 @@ pub(crate) fn validate_config(is_client: bool, config: &Config) -> Result<()> {
         config.psk.is_some(),
     )?;
 
+    if config
+        .elliptic_curves
+        .iter()
+        .any(|curve| !curve.is_supported())
+    {
+        return Err(Error::ErrInvalidNamedCurve);
+    }
+
     Ok(())
 }
@@ -0,0 +1,1257 @@
+use super::*;
+use crate::webrtc::dtls::change_cipher_spec::ChangeCipherSpec;
+use crate::webrtc::dtls::compression_methods::CompressionMethodId;
+use crate::webrtc::dtls::crypto::{generate_certificate_verify_dtls13, Certificate};
+use crate::webrtc::dtls::extension::extension_key_share::*;
+use crate::webrtc::dtls::extension::extension_supported_versions::*;
+use crate::webrtc::dtls::extension::*;
+use crate::webrtc::dtls::handshake::handshake_header::HANDSHAKE_HEADER_LENGTH;
+use crate::webrtc::dtls::handshake::handshake_message_certificate13::HandshakeMessageCertificate13;
+use crate::webrtc::dtls::handshake::handshake_message_certificate_verify::HandshakeMessageCertificateVerify;
+use crate::webrtc::dtls::handshake::handshake_message_encrypted_extensions::HandshakeMessageEncryptedExtensions;
+use crate::webrtc::dtls::handshake::handshake_message_finished::HandshakeMessageFinished;
+use crate::webrtc::dtls::handshake::handshake_message_new_session_ticket::HandshakeMessageNewSessionTicket;
+use crate::webrtc::dtls::handshake::handshake_message_server_hello::HandshakeMessageServerHello;
+use crate::webrtc::dtls::handshake::handshake_random::HandshakeRandom;
+use crate::webrtc::dtls::prf::*;
+use crate::webrtc::dtls::session::*;
+use crate::webrtc::peer_connection::certificate::RTCCertificate;
+
+use async_trait::async_trait;
+use rcgen::KeyPair;
+use tokio::net::UdpSocket;
+
+pub(crate) fn generate_test_certificate() -> Certificate {
+    let key_pair = KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+    RTCCertificate::from_key_pair(key_pair).unwrap().certificate
+}
+
+// socket_pair returns two UDP sockets on the loopback connected to each other
+pub(crate) async fn socket_pair() -> (Arc<dyn Conn + Send + Sync>, Arc<dyn Conn + Send + Sync>) {
+    let a = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let b = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    a.connect(b.local_addr().unwrap()).await.unwrap();
+    b.connect(a.local_addr().unwrap()).await.unwrap();
+    (Arc::new(a), Arc::new(b))
+}
+
+// create_test_client_server runs the handshake of a client and a server over
+// the loopback
+pub(crate) async fn create_test_client_server(
+    client_config: Config,
+    server_config: Config,
+) -> (Result<DTLSConn>, Result<DTLSConn>) {
+    let (ca, cb) = socket_pair().await;
+    tokio::join!(
+        DTLSConn::new(ca, client_config, true, None),
+        DTLSConn::new(cb, server_config, false, None),
+    )
+}
+
+pub(crate) fn test_config(certificate: &Certificate) -> Config {
+    Config {
+        certificates: vec![certificate.clone()],
+        insecure_skip_verify: true,
+        handshake_timeout: Duration::from_secs(10),
+        ..Default::default()
+    }
+}
+
+async fn check_application_data(client: &DTLSConn, server: &DTLSConn) {
+    let mut buf = vec![0u8; 64];
+    client.write(b"ping", None).await.unwrap();
+    let n = server.read(&mut buf, None).await.unwrap();
+    assert_eq!(&buf[..n], b"ping");
+
+    server.write(b"pong", None).await.unwrap();
+    let n = client.read(&mut buf, None).await.unwrap();
+    assert_eq!(&buf[..n], b"pong");
+}
+
+#[tokio::test]
+async fn test_dtls13_client_falls_back_to_dtls12() {
+    let certificate = generate_test_certificate();
+    let client_config = Config {
+        enable_dtls13: true,
+        ..test_config(&certificate)
+    };
+
+    let (client, server) =
+        create_test_client_server(client_config, test_config(&certificate)).await;
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    // The DTLS 1.2 server ignores the supported_versions extension
+    assert_ne!(client.state.protocol_version, PROTOCOL_VERSION1_3);
+    assert!(!client.state.dtls13.lock().await.is_enabled());
+    check_application_data(&client, &server).await;
+
+    // The RFC 5705 exporter of DTLS 1.2 puts the context and its length after
+    // the randoms, an empty context leaves both out
+    let mut seed = b"EXPERIMENTAL-test".to_vec();
+    seed.extend_from_slice(&random_bytes(&client.state.local_random));
+    seed.extend_from_slice(&random_bytes(&client.state.remote_random));
+    let without_context = client
+        .export_keying_material("EXPERIMENTAL-test", &[], 16)
+        .await
+        .unwrap();
+    assert_eq!(
+        without_context,
+        prf_p_hash(
+            &client.state.master_secret,
+            &seed,
+            16,
+            CipherSuiteHash::Sha256
+        )
+        .unwrap()
+    );
+
+    seed.extend_from_slice(&[0x00, 0x03]);
+    seed.extend_from_slice(b"ctx");
+    let with_context = client
+        .export_keying_material("EXPERIMENTAL-test", b"ctx", 16)
+        .await
+        .unwrap();
+    assert_eq!(
+        with_context,
+        prf_p_hash(
+            &client.state.master_secret,
+            &seed,
+            16,
+            CipherSuiteHash::Sha256
+        )
+        .unwrap()
+    );
+    assert_eq!(
+        server
+            .export_keying_material("EXPERIMENTAL-test", b"ctx", 16)
+            .await
+            .unwrap(),
+        with_context
+    );
+    assert_eq!(
+        client
+            .export_keying_material("EXPERIMENTAL-test", &[0u8; 65536], 16)
+            .await,
+        Err(KeyingMaterialExporterError::ContextUnsupported)
+    );
+
+    client.close().await.unwrap();
+    server.close().await.unwrap();
+}
+
+// MigratingConn is a UDP conn that answers the address it last heard from,
+// like a server does once a CID record authenticated a new address. It keeps
+// the content type of every record it received and can move to a new port.
+struct MigratingConn {
+    socket: std::sync::Mutex<Arc<UdpSocket>>,
+    peer: std::sync::Mutex<SocketAddr>,
+    content_types: std::sync::Mutex<Vec<u8>>,
+}
+
+impl MigratingConn {
+    async fn pair() -> (Arc<MigratingConn>, Arc<MigratingConn>) {
+        let a = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let b = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let (a_addr, b_addr) = (a.local_addr().unwrap(), b.local_addr().unwrap());
+        (
+            Arc::new(MigratingConn {
+                socket: std::sync::Mutex::new(Arc::new(a)),
+                peer: std::sync::Mutex::new(b_addr),
+                content_types: std::sync::Mutex::new(vec![]),
+            }),
+            Arc::new(MigratingConn {
+                socket: std::sync::Mutex::new(Arc::new(b)),
+                peer: std::sync::Mutex::new(a_addr),
+                content_types: std::sync::Mutex::new(vec![]),
+            }),
+        )
+    }
+
+    fn socket(&self) -> Arc<UdpSocket> {
+        Arc::clone(&self.socket.lock().unwrap())
+    }
+
+    // migrate sends from a new port from now on
+    async fn migrate(&self) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        *self.socket.lock().unwrap() = Arc::new(socket);
+        addr
+    }
+
+    fn peer(&self) -> SocketAddr {
+        *self.peer.lock().unwrap()
+    }
+
+    fn received(&self, content_type: ContentType) -> bool {
+        self.content_types
+            .lock()
+            .unwrap()
+            .contains(&(content_type as u8))
+    }
+}
+
+#[async_trait]
+impl Conn for MigratingConn {
+    async fn connect(&self, _addr: SocketAddr) -> UtilResult<()> {
+        Err(crate::webrtc::util::Error::Other(
+            "Not applicable".to_owned(),
+        ))
+    }
+
+    async fn recv(&self, buf: &mut [u8]) -> UtilResult<usize> {
+        let (n, _) = self.recv_from(buf).await?;
+        Ok(n)
+    }
+
+    async fn recv_from(&self, buf: &mut [u8]) -> UtilResult<(usize, SocketAddr)> {
+        let (n, from) = self.socket().recv_from(buf).await?;
+        *self.peer.lock().unwrap() = from;
+        if let Ok(pkts) = unpack_datagram(&buf[..n], 8) {
+            let mut content_types = self.content_types.lock().unwrap();
+            content_types.extend(pkts.iter().map(|pkt| pkt[0]));
+        }
+        Ok((n, from))
+    }
+
+    async fn send(&self, buf: &[u8]) -> UtilResult<usize> {
+        Ok(self.socket().send_to(buf, self.peer()).await?)
+    }
+
+    async fn send_to(&self, buf: &[u8], target: SocketAddr) -> UtilResult<usize> {
+        Ok(self.socket().send_to(buf, target).await?)
+    }
+
+    async fn local_addr(&self) -> UtilResult<SocketAddr> {
+        Ok(self.socket().local_addr()?)
+    }
+
+    async fn remote_addr(&self) -> Option<SocketAddr> {
+        Some(self.peer())
+    }
+
+    async fn close(&self) -> UtilResult<()> {
+        Ok(())
+    }
+}
+
+fn connection_id_config(certificate: &Certificate) -> Config {
+    Config {
+        connection_id_generator: Some(random_connection_id_generator(8)),
+        ..test_config(certificate)
+    }
+}
+
+#[tokio::test]
+async fn test_connection_id_round_trip() {
+    let certificate = generate_test_certificate();
+    let (ca, cb) = MigratingConn::pair().await;
+    let (client, server) = tokio::join!(
+        DTLSConn::new(
+            Arc::clone(&ca) as Arc<dyn Conn + Send + Sync>,
+            connection_id_config(&certificate),
+            true,
+            None
+        ),
+        DTLSConn::new(
+            Arc::clone(&cb) as Arc<dyn Conn + Send + Sync>,
+            connection_id_config(&certificate),
+            false,
+            None
+        ),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    // Each side puts the CID the other asked for on its protected records
+    assert_eq!(
+        *client.state.remote_connection_id.lock().await,
+        server.state.local_connection_id
+    );
+    assert_eq!(
+        *server.state.remote_connection_id.lock().await,
+        client.state.local_connection_id
+    );
+    check_application_data(&client, &server).await;
+    assert!(ca.received(ContentType::Tls12Cid));
+    assert!(cb.received(ContentType::Tls12Cid));
+
+    client.close().await.unwrap();
+    server.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_connection_id_new_remote_address() {
+    let certificate = generate_test_certificate();
+    let (ca, cb) = MigratingConn::pair().await;
+    let (client, server) = tokio::join!(
+        DTLSConn::new(
+            Arc::clone(&ca) as Arc<dyn Conn + Send + Sync>,
+            connection_id_config(&certificate),
+            true,
+            None
+        ),
+        DTLSConn::new(
+            Arc::clone(&cb) as Arc<dyn Conn + Send + Sync>,
+            connection_id_config(&certificate),
+            false,
+            None
+        ),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+    check_application_data(&client, &server).await;
+
+    // The record is matched by its CID, not by the address it came from
+    let addr = ca.migrate().await;
+    client.write(b"moved", None).await.unwrap();
+    let mut buf = vec![0u8; 64];
+    let n = server.read(&mut buf, None).await.unwrap();
+    assert_eq!(&buf[..n], b"moved");
+    assert_eq!(cb.peer(), addr);
+
+    server.close().await.unwrap();
+}
+
+const TEST_SESSION_KEY: &[u8] = b"sha-256 test-server";
+
+fn test_session(cipher_suite_id: CipherSuiteId) -> Session {
+    Session {
+        id: vec![0x07; 32],
+        ticket: vec![],
+        secret: vec![0x5a; 48],
+        cipher_suite_id,
+        extended_master_secret: false,
+        peer_certificates: vec![],
+    }
+}
+
+fn resuming_config(certificate: &Certificate, store: &Arc<MemorySessionStore>) -> Config {
+    Config {
+        session_store: Some(Arc::clone(store) as Arc<dyn SessionStore + Send + Sync>),
+        session_key: TEST_SESSION_KEY.to_vec(),
+        ..test_config(certificate)
+    }
+}
+
+fn marshal_record(record: &RecordLayer) -> Vec<u8> {
+    let mut raw = vec![];
+    {
+        let mut writer = BufWriter::<&mut Vec<u8>>::new(raw.as_mut());
+        record.marshal(&mut writer).unwrap();
+    }
+    raw
+}
+
+// recv_records returns the records of the next datagram with their headers
+async fn recv_records(conn: &Arc<dyn Conn + Send + Sync>) -> Vec<(RecordLayerHeader, Vec<u8>)> {
+    let mut buf = vec![0u8; 8192];
+    let n = conn.recv(&mut buf).await.unwrap();
+    unpack_datagram(&buf[..n], 0)
+        .unwrap()
+        .into_iter()
+        .map(|pkt| {
+            let h = RecordLayerHeader::unmarshal(&mut BufReader::new(pkt.as_slice())).unwrap();
+            (h, pkt)
+        })
+        .collect()
+}
+
+// resuming_server stands in for a server that resumes every session by id,
+// it answers the first ClientHello with the abbreviated handshake for session
+// and the cipher suite in the ServerHello, then echoes one application record
+async fn resuming_server(
+    conn: Arc<dyn Conn + Send + Sync>,
+    session: Session,
+    server_cipher_suite: CipherSuiteId,
+) -> Result<()> {
+    let hash = CipherSuiteHash::Sha256;
+
+    let client_hello = loop {
+        let records = recv_records(&conn).await;
+        if let Some((_, pkt)) = records
+            .into_iter()
+            .find(|(h, _)| h.content_type == ContentType::Handshake)
+        {
+            break pkt[RECORD_LAYER_HEADER_SIZE..].to_vec();
+        }
+    };
+    let client_random = match Handshake::unmarshal(&mut BufReader::new(client_hello.as_slice()))?
+        .handshake_message
+    {
+        HandshakeMessage::ClientHello(h) => {
+            assert_eq!(h.session_id, session.id, "the cached session is offered");
+            h.random
+        }
+        _ => return Err(Error::ErrInvalidContentType),
+    };
+
+    let mut server_random = HandshakeRandom::default();
+    server_random.populate();
+    let server_hello = Handshake::new(HandshakeMessage::ServerHello(HandshakeMessageServerHello {
+        version: PROTOCOL_VERSION1_2,
+        random: server_random.clone(),
+        session_id: session.id.clone(),
+        cipher_suite: server_cipher_suite,
+        compression_method: CompressionMethodId::Null,
+        extensions: vec![],
+    }));
+    let server_hello_record = marshal_record(&RecordLayer::new(
+        PROTOCOL_VERSION1_2,
+        0,
+        Content::Handshake(server_hello),
+    ));
+    let server_hello = server_hello_record[RECORD_LAYER_HEADER_SIZE..].to_vec();
+
+    let mut randoms = (vec![], vec![]);
+    client_random.marshal(&mut randoms.0)?;
+    server_random.marshal(&mut randoms.1)?;
+    let mut cipher_suite = cipher_suite_for_id(server_cipher_suite)?;
+    cipher_suite.init(&session.secret, &randoms.0, &randoms.1, false)?;
+
+    let mut transcript = [client_hello.as_slice(), server_hello.as_slice()].concat();
+    let mut finished = Handshake::new(HandshakeMessage::Finished(HandshakeMessageFinished {
+        verify_data: prf_verify_data_server(&session.secret, &transcript, hash)?,
+    }));
+    finished.handshake_header.message_sequence = 1;
+    let mut finished_record =
+        RecordLayer::new(PROTOCOL_VERSION1_2, 1, Content::Handshake(finished));
+    let finished_raw = marshal_record(&finished_record);
+    transcript.extend_from_slice(&finished_raw[RECORD_LAYER_HEADER_SIZE..]);
+    finished_record.record_layer_header.sequence_number = 0;
+
+    let mut change_cipher_spec = RecordLayer::new(
+        PROTOCOL_VERSION1_2,
+        0,
+        Content::ChangeCipherSpec(ChangeCipherSpec {}),
+    );
+    change_cipher_spec.record_layer_header.sequence_number = 1;
+
+    let mut flight = server_hello_record;
+    flight.extend_from_slice(&marshal_record(&change_cipher_spec));
+    flight.extend_from_slice(
+        &cipher_suite.encrypt(&finished_record.record_layer_header, &finished_raw)?,
+    );
+    conn.send(&flight).await?;
+
+    // The client Finished covers the server Finished as well
+    let client_finished = loop {
+        let records = recv_records(&conn).await;
+        if let Some((h, pkt)) = records.into_iter().find(|(h, _)| h.epoch == 1) {
+            break cipher_suite.decrypt(&h, &pkt)?;
+        }
+    };
+    let expected = prf_verify_data_client(&session.secret, &transcript, hash)?;
+    match Handshake::unmarshal(&mut BufReader::new(
+        &client_finished[RECORD_LAYER_HEADER_SIZE..],
+    ))?
+    .handshake_message
+    {
+        HandshakeMessage::Finished(h) if h.verify_data == expected => {}
+        _ => return Err(Error::ErrVerifyDataMismatch),
+    }
+
+    let data = loop {
+        let records = recv_records(&conn).await;
+        if let Some((h, pkt)) = records
+            .into_iter()
+            .find(|(h, _)| h.content_type == ContentType::ApplicationData)
+        {
+            break cipher_suite.decrypt(&h, &pkt)?[RECORD_LAYER_HEADER_SIZE..].to_vec();
+        }
+    };
+    let mut echo = RecordLayer::new(
+        PROTOCOL_VERSION1_2,
+        1,
+        Content::ApplicationData(ApplicationData { data }),
+    );
+    echo.record_layer_header.sequence_number = 1;
+    let raw = marshal_record(&echo);
+    conn.send(&cipher_suite.encrypt(&echo.record_layer_header, &raw)?)
+        .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_session_resumption_abbreviated_handshake() {
+    let certificate = generate_test_certificate();
+    let cipher_suite_id = CipherSuiteId::Tls_Ecdhe_Ecdsa_With_Aes_128_Gcm_Sha256;
+    let store = Arc::new(MemorySessionStore::default());
+    store.set(TEST_SESSION_KEY, test_session(cipher_suite_id));
+
+    let (ca, cb) = socket_pair().await;
+    let server = tokio::spawn(resuming_server(
+        cb,
+        test_session(cipher_suite_id),
+        cipher_suite_id,
+    ));
+    let client = DTLSConn::new(ca, resuming_config(&certificate, &store), true, None)
+        .await
+        .unwrap();
+
+    // No certificate was exchanged, the session's secret keys the records
+    assert_eq!(
+        client.state.master_secret,
+        test_session(cipher_suite_id).secret
+    );
+    client.write(b"resumed", None).await.unwrap();
+    let mut buf = vec![0u8; 64];
+    let n = client.read(&mut buf, None).await.unwrap();
+    assert_eq!(&buf[..n], b"resumed");
+    server.await.unwrap().unwrap();
+
+    // The session stays cached for the next connection
+    assert_eq!(
+        store.get(TEST_SESSION_KEY),
+        Some(test_session(cipher_suite_id))
+    );
+    client.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_session_resumption_rejects_other_cipher_suite() {
+    let certificate = generate_test_certificate();
+    let store = Arc::new(MemorySessionStore::default());
+    let session = test_session(CipherSuiteId::Tls_Ecdhe_Ecdsa_With_Aes_128_Gcm_Sha256);
+    store.set(TEST_SESSION_KEY, session.clone());
+
+    let (ca, cb) = socket_pair().await;
+    let server = tokio::spawn(resuming_server(
+        cb,
+        session,
+        CipherSuiteId::Tls_Ecdhe_Ecdsa_With_Chacha20_Poly1305_Sha256,
+    ));
+    let result = DTLSConn::new(ca, resuming_config(&certificate, &store), true, None).await;
+    server.abort();
+
+    assert!(
+        matches!(result, Err(Error::ErrSessionMismatch)),
+        "{:?}",
+        result.err()
+    );
+}
+
+#[tokio::test]
+async fn test_session_forgotten_after_full_handshake() {
+    let certificate = generate_test_certificate();
+    let store = Arc::new(MemorySessionStore::default());
+    store.set(
+        TEST_SESSION_KEY,
+        test_session(CipherSuiteId::Tls_Ecdhe_Ecdsa_With_Aes_128_Gcm_Sha256),
+    );
+
+    // Our server issues neither session ids nor tickets, the offer is ignored
+    // and the full handshake runs
+    let (client, server) = create_test_client_server(
+        resuming_config(&certificate, &store),
+        test_config(&certificate),
+    )
+    .await;
+    let (client, server) = (client.unwrap(), server.unwrap());
+    check_application_data(&client, &server).await;
+    assert_eq!(store.get(TEST_SESSION_KEY), None);
+
+    client.close().await.unwrap();
+    server.close().await.unwrap();
+}
+
+// Dtls13Server stands in for a DTLS 1.3 server. It runs the 1-RTT handshake
+// with a certificate and no client authentication, then keeps the
+// application traffic keys so the tests can drive the connection.
+struct Dtls13Server {
+    conn: Arc<dyn Conn + Send + Sync>,
+    keys: Dtls13EpochKeys,
+    epoch: u16,
+    sequence_numbers: HashMap<u16, u64>,
+    message_sequence: u16,
+    exporter_master_secret: Vec<u8>,
+}
+
+// transcript_message returns a DTLS handshake message with the TLS four byte
+// header it has in the DTLS 1.3 transcript
+fn transcript_message(raw: &[u8]) -> Vec<u8> {
+    [&raw[..4], &raw[HANDSHAKE_HEADER_LENGTH..]].concat()
+}
+
+fn marshal_handshake(h: &Handshake) -> Vec<u8> {
+    let mut raw = vec![];
+    {
+        let mut writer = BufWriter::<&mut Vec<u8>>::new(raw.as_mut());
+        h.marshal(&mut writer).unwrap();
+    }
+    raw
+}
+
+impl Dtls13Server {
+    async fn handshake(conn: Arc<dyn Conn + Send + Sync>, certificate: &Certificate) -> Self {
+        let cipher_suite = CipherSuiteId::Tls_Aes_128_Gcm_Sha256;
+        let hash = dtls13_cipher_suite_hash(cipher_suite).unwrap();
+
+        let client_hello = loop {
+            let records = recv_records(&conn).await;
+            if let Some((_, pkt)) = records
+                .into_iter()
+                .find(|(h, _)| h.content_type == ContentType::Handshake)
+            {
+                break pkt[RECORD_LAYER_HEADER_SIZE..].to_vec();
+            }
+        };
+        let client_hello_message =
+            match Handshake::unmarshal(&mut BufReader::new(client_hello.as_slice()))
+                .unwrap()
+                .handshake_message
+            {
+                HandshakeMessage::ClientHello(h) => h,
+                _ => panic!("expected a ClientHello"),
+            };
+        let client_share = client_hello_message
+            .extensions
+            .iter()
+            .find_map(|e| match e {
+                Extension::KeyShare(ExtensionKeyShare::ClientHello(entries)) => entries
+                    .iter()
+                    .find(|entry| entry.group == DEFAULT_NAMED_CURVE)
+                    .cloned(),
+                _ => None,
+            })
+            .expect("the ClientHello carries a key share");
+        assert!(
+            !client_hello_message
+                .extensions
+                .iter()
+                .any(|e| matches!(e, Extension::ConnectionId(_))),
+            "no CID is offered along with DTLS 1.3"
+        );
+
+        let keypair = DEFAULT_NAMED_CURVE.generate_keypair().unwrap();
+        let shared_secret = prf_pre_master_secret(
+            &client_share.key_exchange,
+            &keypair.private_key,
+            keypair.curve,
+        )
+        .unwrap();
+
+        let mut random = HandshakeRandom::default();
+        random.populate();
+        let server_hello_record = marshal_record(&RecordLayer::new(
+            PROTOCOL_VERSION1_2,
+            0,
+            Content::Handshake(Handshake::new(HandshakeMessage::ServerHello(
+                HandshakeMessageServerHello {
+                    version: PROTOCOL_VERSION1_2,
+                    random,
+                    session_id: client_hello_message.session_id.clone(),
+                    cipher_suite,
+                    compression_method: CompressionMethodId::Null,
+                    extensions: vec![
+                        Extension::SupportedVersions(ExtensionSupportedVersions::ServerHello(
+                            PROTOCOL_VERSION1_3,
+                        )),
+                        Extension::KeyShare(ExtensionKeyShare::ServerHello(KeyShareEntry {
+                            group: keypair.curve,
+                            key_exchange: keypair.public_key.clone(),
+                        })),
+                    ],
+                },
+            ))),
+        ));
+        conn.send(&server_hello_record).await.unwrap();
+
+        let mut transcript = transcript_message(&client_hello);
+        transcript.extend_from_slice(&transcript_message(
+            &server_hello_record[RECORD_LAYER_HEADER_SIZE..],
+        ));
+
+        let handshake_secret = key_schedule_handshake_secret(hash, &shared_secret).unwrap();
+        let client_handshake_secret = derive_secret(
+            hash,
+            &handshake_secret,
+            KEY_SCHEDULE_CLIENT_HANDSHAKE_TRAFFIC_LABEL,
+            &transcript,
+        )
+        .unwrap();
+        let server_handshake_secret = derive_secret(
+            hash,
+            &handshake_secret,
+            KEY_SCHEDULE_SERVER_HANDSHAKE_TRAFFIC_LABEL,
+            &transcript,
+        )
+        .unwrap();
+
+        let mut keys = Dtls13EpochKeys::default();
+        keys.cipher_suite = Some(cipher_suite);
+        let mut server = Dtls13Server {
+            conn,
+            keys,
+            epoch: DTLS13_HANDSHAKE_EPOCH,
+            sequence_numbers: HashMap::new(),
+            message_sequence: 1,
+            exporter_master_secret: vec![],
+        };
+        server
+            .keys
+            .install_local(DTLS13_HANDSHAKE_EPOCH, &server_handshake_secret)
+            .unwrap();
+        server
+            .keys
+            .install_remote(DTLS13_HANDSHAKE_EPOCH, &client_handshake_secret)
+            .unwrap();
+
+        // {EncryptedExtensions}, {Certificate}, {CertificateVerify} and {Finished}
+        // in one datagram
+        let mut flight = vec![];
+        for message in [
+            HandshakeMessage::EncryptedExtensions(HandshakeMessageEncryptedExtensions {
+                extensions: vec![],
+            }),
+            HandshakeMessage::Certificate13(HandshakeMessageCertificate13 {
+                certificate_request_context: vec![],
+                certificate: certificate
+                    .certificate
+                    .iter()
+                    .map(|c| c.0.clone())
+                    .collect(),
+            }),
+        ] {
+            let raw = server.handshake_message(message);
+            transcript.extend_from_slice(&transcript_message(&raw));
+            flight.extend_from_slice(&server.seal(ContentType::Handshake, &raw));
+        }
+
+        let (algorithm, signature) = generate_certificate_verify_dtls13(
+            &certificate_verify_content_dtls13(
+                hash,
+                SERVER_CERTIFICATE_VERIFY_CONTEXT,
+                &transcript,
+            ),
+            &certificate.private_key,
+        )
+        .unwrap();
+        let raw = server.handshake_message(HandshakeMessage::CertificateVerify(
+            HandshakeMessageCertificateVerify {
+                algorithm,
+                signature,
+            },
+        ));
+        transcript.extend_from_slice(&transcript_message(&raw));
+        flight.extend_from_slice(&server.seal(ContentType::Handshake, &raw));
+
+        let raw = server.handshake_message(HandshakeMessage::Finished(HandshakeMessageFinished {
+            verify_data: key_schedule_verify_data(hash, &server_handshake_secret, &transcript)
+                .unwrap(),
+        }));
+        transcript.extend_from_slice(&transcript_message(&raw));
+        flight.extend_from_slice(&server.seal(ContentType::Handshake, &raw));
+        server.conn.send(&flight).await.unwrap();
+
+        let master_secret = key_schedule_master_secret(hash, &handshake_secret).unwrap();
+        let client_secret = derive_secret(
+            hash,
+            &master_secret,
+            KEY_SCHEDULE_CLIENT_APPLICATION_TRAFFIC_LABEL,
+            &transcript,
+        )
+        .unwrap();
+        let server_secret = derive_secret(
+            hash,
+            &master_secret,
+            KEY_SCHEDULE_SERVER_APPLICATION_TRAFFIC_LABEL,
+            &transcript,
+        )
+        .unwrap();
+        server.exporter_master_secret = derive_secret(
+            hash,
+            &master_secret,
+            KEY_SCHEDULE_EXPORTER_MASTER_LABEL,
+            &transcript,
+        )
+        .unwrap();
+
+        // The client Finished covers the whole server flight
+        let (h, finished) = server.recv_handshake().await;
+        match finished.handshake_message {
+            HandshakeMessage::Finished(f) => assert_eq!(
+                f.verify_data,
+                key_schedule_verify_data(hash, &client_handshake_secret, &transcript).unwrap()
+            ),
+            _ => panic!("expected the client Finished"),
+        }
+
+        server
+            .keys
+            .install_local(DTLS13_APPLICATION_EPOCH, &server_secret)
+            .unwrap();
+        server
+            .keys
+            .install_remote(DTLS13_APPLICATION_EPOCH, &client_secret)
+            .unwrap();
+        server.keys.local_traffic_secret = server_secret;
+        server.keys.remote_traffic_secret = client_secret;
+        server.epoch = DTLS13_APPLICATION_EPOCH;
+        server.send_ack(&h).await;
+
+        server
+    }
+
+    fn handshake_message(&mut self, message: HandshakeMessage) -> Vec<u8> {
+        let mut h = Handshake::new(message);
+        h.handshake_header.message_sequence = self.message_sequence;
+        self.message_sequence += 1;
+        marshal_handshake(&h)
+    }
+
+    // seal_record protects content with the keys of the current epoch and returns the
+    // record with its record number
+    fn seal_record(
+        &mut self,
+        content_type: ContentType,
+        content: &[u8],
+    ) -> (RecordNumber, Vec<u8>) {
+        let sequence_number = self.sequence_numbers.entry(self.epoch).or_insert(0);
+        let record_number = RecordNumber {
+            epoch: self.epoch as u64,
+            sequence_number: *sequence_number,
+        };
+        *sequence_number += 1;
+
+        let record = self
+            .keys
+            .encrypt(
+                self.epoch,
+                record_number.sequence_number,
+                content_type,
+                content,
+            )
+            .unwrap();
+        (record_number, record)
+    }
+
+    fn seal(&mut self, content_type: ContentType, content: &[u8]) -> Vec<u8> {
+        self.seal_record(content_type, content).1
+    }
+
+    async fn send(&mut self, content_type: ContentType, content: &[u8]) -> RecordNumber {
+        let (record_number, record) = self.seal_record(content_type, content);
+        self.conn.send(&record).await.unwrap();
+        record_number
+    }
+
+    async fn send_handshake(&mut self, message: HandshakeMessage) -> RecordNumber {
+        let raw = self.handshake_message(message);
+        self.send(ContentType::Handshake, &raw).await
+    }
+
+    async fn send_ack(&mut self, h: &RecordLayerHeader) {
+        let mut raw = vec![];
+        Ack {
+            record_numbers: vec![RecordNumber {
+                epoch: h.epoch as u64,
+                sequence_number: h.sequence_number,
+            }],
+        }
+        .marshal(&mut raw)
+        .unwrap();
+        self.send(ContentType::Ack, &raw).await;
+    }
+
+    // recv returns the next protected record the client sent, opened
+    async fn recv(&mut self) -> (RecordLayerHeader, Vec<u8>) {
+        let mut buf = vec![0u8; 8192];
+        loop {
+            let n = self.conn.recv(&mut buf).await.unwrap();
+            for pkt in unpack_datagram(&buf[..n], 0).unwrap() {
+                if !is_unified_header(pkt[0]) {
+                    continue;
+                }
+                if let Ok(Some(pkt)) = self.keys.decrypt(&pkt) {
+                    let h =
+                        RecordLayerHeader::unmarshal(&mut BufReader::new(pkt.as_slice())).unwrap();
+                    return (h, pkt[RECORD_LAYER_HEADER_SIZE..].to_vec());
+                }
+            }
+        }
+    }
+
+    async fn recv_content(&mut self, content_type: ContentType) -> (RecordLayerHeader, Vec<u8>) {
+        loop {
+            let (h, content) = self.recv().await;
+            if h.content_type == content_type {
+                return (h, content);
+            }
+        }
+    }
+
+    async fn recv_handshake(&mut self) -> (RecordLayerHeader, Handshake) {
+        let (h, content) = self.recv_content(ContentType::Handshake).await;
+        let handshake = Handshake::unmarshal(&mut BufReader::new(content.as_slice())).unwrap();
+        (h, handshake)
+    }
+
+    async fn recv_ack(&mut self) -> Vec<RecordNumber> {
+        let (_, content) = self.recv_content(ContentType::Ack).await;
+        Ack::unmarshal(&mut BufReader::new(content.as_slice()))
+            .unwrap()
+            .record_numbers
+    }
+
+    async fn recv_application_data(&mut self) -> (RecordLayerHeader, Vec<u8>) {
+        self.recv_content(ContentType::ApplicationData).await
+    }
+
+    // update_keys moves one direction to the next application traffic secret
+    fn update_keys(&mut self, local: bool) {
+        let hash = self.keys.hash().unwrap();
+        if local {
+            let secret =
+                key_schedule_next_traffic_secret(hash, &self.keys.local_traffic_secret).unwrap();
+            self.epoch += 1;
+            self.keys.install_local(self.epoch, &secret).unwrap();
+            self.keys.local_traffic_secret = secret;
+        } else {
+            let secret =
+                key_schedule_next_traffic_secret(hash, &self.keys.remote_traffic_secret).unwrap();
+            let epoch = self.keys.remote.keys().max().unwrap() + 1;
+            self.keys.install_remote(epoch, &secret).unwrap();
+            self.keys.remote_traffic_secret = secret;
+        }
+    }
+}
+
+async fn create_dtls13_client_server(certificate: &Certificate) -> (DTLSConn, Dtls13Server) {
+    let client_config = Config {
+        enable_dtls13: true,
+        connection_id_generator: Some(random_connection_id_generator(8)),
+        ..test_config(certificate)
+    };
+
+    let (ca, cb) = socket_pair().await;
+    let (client, server) = tokio::join!(
+        DTLSConn::new(ca, client_config, true, None),
+        Dtls13Server::handshake(cb, certificate),
+    );
+    (client.unwrap(), server)
+}
+
+#[tokio::test]
+async fn test_dtls13_handshake() {
+    let certificate = generate_test_certificate();
+    let (client, mut server) = create_dtls13_client_server(&certificate).await;
+
+    assert_eq!(client.state.protocol_version, PROTOCOL_VERSION1_3);
+    assert_eq!(client.get_local_epoch(), DTLS13_APPLICATION_EPOCH);
+    assert!(client.state.peer_certificates_verified);
+    assert_eq!(
+        client.state.peer_certificates,
+        certificate
+            .certificate
+            .iter()
+            .map(|c| c.0.clone())
+            .collect::<Vec<_>>()
+    );
+
+    client.write(b"ping", None).await.unwrap();
+    let (h, data) = server.recv_application_data().await;
+    assert_eq!(h.epoch, DTLS13_APPLICATION_EPOCH);
+    assert_eq!(data, b"ping");
+
+    server.send(ContentType::ApplicationData, b"pong").await;
+    let mut buf = vec![0u8; 64];
+    let n = client.read(&mut buf, None).await.unwrap();
+    assert_eq!(&buf[..n], b"pong");
+
+    // Both sides derive the same exporter master secret
+    let hash = server.keys.hash().unwrap();
+    assert_eq!(
+        client
+            .export_keying_material("EXTRACTOR-dtls_srtp", b"ctx", 32)
+            .await
+            .unwrap(),
+        key_schedule_export(
+            hash,
+            &server.exporter_master_secret,
+            "EXTRACTOR-dtls_srtp",
+            b"ctx",
+            32
+        )
+        .unwrap()
+    );
+
+    client.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_dtls13_post_handshake_message_acked() {
+    let certificate = generate_test_certificate();
+    let (client, mut server) = create_dtls13_client_server(&certificate).await;
+
+    // Session tickets are not used, but acknowledged so the server stops
+    // retransmitting them
+    let record_number = server
+        .send_handshake(HandshakeMessage::NewSessionTicket(
+            HandshakeMessageNewSessionTicket {
+                ticket_lifetime_hint: 7200,
+                ticket: vec![0x42; 32],
+            },
+        ))
+        .await;
+    assert_eq!(server.recv_ack().await, vec![record_number]);
+
+    client.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_dtls13_key_update() {
+    let certificate = generate_test_certificate();
+    let (client, mut server) = create_dtls13_client_server(&certificate).await;
+
+    client.update_keys(true).await.unwrap();
+
+    // The client keeps sending with the old keys until its KeyUpdate is acknowledged
+    let (h, key_update) = server.recv_handshake().await;
+    match key_update.handshake_message {
+        HandshakeMessage::KeyUpdate(k) => assert!(k.update_requested),
+        _ => panic!("expected a KeyUpdate"),
+    }
+    assert_eq!(h.epoch, DTLS13_APPLICATION_EPOCH);
+    assert_eq!(client.get_local_epoch(), DTLS13_APPLICATION_EPOCH);
+    server.update_keys(false);
+    server.send_ack(&h).await;
+
+    // The requested update of the server's keys is acknowledged as well
+    let record_number = server
+        .send_handshake(HandshakeMessage::KeyUpdate(HandshakeMessageKeyUpdate {
+            update_requested: false,
+        }))
+        .await;
+    assert_eq!(server.recv_ack().await, vec![record_number]);
+    server.update_keys(true);
+
+    let next_epoch = DTLS13_APPLICATION_EPOCH + 1;
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while client.get_local_epoch() != next_epoch {
+        assert!(tokio::time::Instant::now() < deadline, "keys not updated");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    client.write(b"ping", None).await.unwrap();
+    let (h, data) = server.recv_application_data().await;
+    assert_eq!(h.epoch, next_epoch);
+    assert_eq!(data, b"ping");
+
+    server.send(ContentType::ApplicationData, b"pong").await;
+    let mut buf = vec![0u8; 64];
+    let n = client.read(&mut buf, None).await.unwrap();
+    assert_eq!(&buf[..n], b"pong");
+
+    client.close().await.unwrap();
+}
+
+// key_log_path returns a fresh key log file for the test
+fn key_log_path(test: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("dtls-{}-{}.keylog", test, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+// read_key_log returns the lines of a key log as (label, client random, secret)
+fn read_key_log(path: &std::path::Path) -> Vec<(String, Vec<u8>, Vec<u8>)> {
+    let from_hex = |s: &str| -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    };
+
+    std::fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let fields: Vec<&str> = line.split(' ').collect();
+            assert_eq!(fields.len(), 3, "{}", line);
+            (
+                fields[0].to_owned(),
+                from_hex(fields[1]),
+                from_hex(fields[2]),
+            )
+        })
+        .collect()
+}
+
+fn random_bytes(random: &HandshakeRandom) -> Vec<u8> {
+    let mut raw = vec![];
+    random.marshal(&mut raw).unwrap();
+    raw
+}
+
+#[tokio::test]
+async fn test_key_log_client_random() {
+    let certificate = generate_test_certificate();
+    let path = key_log_path("client-random");
+    let client_config = Config {
+        key_log_path: path.to_string_lossy().into_owned(),
+        ..test_config(&certificate)
+    };
+
+    let (client, server) =
+        create_test_client_server(client_config, test_config(&certificate)).await;
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    // CLIENT_RANDOM <client_random> <master_secret>
+    assert_eq!(
+        read_key_log(&path),
+        vec![(
+            KEY_LOG_CLIENT_RANDOM.to_owned(),
+            random_bytes(&client.state.local_random),
+            client.state.master_secret.clone(),
+        )]
+    );
+    assert_eq!(client.state.master_secret, server.state.master_secret);
+    std::fs::remove_file(&path).unwrap();
+
+    client.close().await.unwrap();
+    server.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_dtls13_key_log() {
+    let certificate = generate_test_certificate();
+    let path = key_log_path("dtls13");
+    let client_config = Config {
+        enable_dtls13: true,
+        key_log_path: path.to_string_lossy().into_owned(),
+        ..test_config(&certificate)
+    };
+
+    let (ca, cb) = socket_pair().await;
+    let (client, server) = tokio::join!(
+        DTLSConn::new(ca, client_config, true, None),
+        Dtls13Server::handshake(cb, &certificate),
+    );
+    let client = client.unwrap();
+
+    let client_random = random_bytes(&client.state.local_random);
+    let expected: Vec<(String, Vec<u8>, Vec<u8>)> = [
+        (
+            KEY_LOG_CLIENT_HANDSHAKE_TRAFFIC_SECRET,
+            client.state.client_handshake_traffic_secret.clone(),
+        ),
+        (
+            KEY_LOG_SERVER_HANDSHAKE_TRAFFIC_SECRET,
+            client.state.server_handshake_traffic_secret.clone(),
+        ),
+        (
+            KEY_LOG_CLIENT_TRAFFIC_SECRET_0,
+            server.keys.remote_traffic_secret.clone(),
+        ),
+        (
+            KEY_LOG_SERVER_TRAFFIC_SECRET_0,
+            server.keys.local_traffic_secret.clone(),
+        ),
+        (
+            KEY_LOG_EXPORTER_SECRET,
+            server.exporter_master_secret.clone(),
+        ),
+    ]
+    .into_iter()
+    .map(|(label, secret)| (label.to_owned(), client_random.clone(), secret))
+    .collect();
+    assert_eq!(read_key_log(&path), expected);
+    std::fs::remove_file(&path).unwrap();
+
+    client.close().await.unwrap();
+}
+
+// SilentConn stands in for a peer that never answers, it keeps the time every
+// datagram was sent at
+#[derive(Default)]
+struct SilentConn {
+    sent: std::sync::Mutex<Vec<tokio::time::Instant>>,
+}
+
+#[async_trait]
+impl Conn for SilentConn {
+    async fn connect(&self, _addr: SocketAddr) -> UtilResult<()> {
+        Err(crate::webrtc::util::Error::Other(
+            "Not applicable".to_owned(),
+        ))
+    }
+
+    async fn recv(&self, _buf: &mut [u8]) -> UtilResult<usize> {
+        std::future::pending().await
+    }
+
+    async fn recv_from(&self, _buf: &mut [u8]) -> UtilResult<(usize, SocketAddr)> {
+        std::future::pending().await
+    }
+
+    async fn send(&self, buf: &[u8]) -> UtilResult<usize> {
+        self.sent.lock().unwrap().push(tokio::time::Instant::now());
+        Ok(buf.len())
+    }
+
+    async fn send_to(&self, buf: &[u8], _target: SocketAddr) -> UtilResult<usize> {
+        self.send(buf).await
+    }
+
+    async fn local_addr(&self) -> UtilResult<SocketAddr> {
+        Ok(SocketAddr::from(([127, 0, 0, 1], 4444)))
+    }
+
+    async fn remote_addr(&self) -> Option<SocketAddr> {
+        Some(SocketAddr::from(([127, 0, 0, 1], 5555)))
+    }
+
+    async fn close(&self) -> UtilResult<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_handshake_retransmission_backoff() {
+    let certificate = generate_test_certificate();
+
+    // The interval starts at one second and doubles up to the configured
+    // maximum, or MAX_TICKER_INTERVAL by default
+    for (max_flight_interval, handshake_timeout, expected) in [
+        (
+            Duration::from_secs(4),
+            Duration::from_secs(20),
+            vec![0, 1, 3, 7, 11, 15, 19],
+        ),
+        (
+            Duration::from_secs(0),
+            Duration::from_secs(200),
+            vec![0, 1, 3, 7, 15, 31, 63, 123, 183],
+        ),
+    ] {
+        let conn = Arc::new(SilentConn::default());
+        let config = Config {
+            max_flight_interval,
+            handshake_timeout,
+            ..test_config(&certificate)
+        };
+
+        let start = tokio::time::Instant::now();
+        let result = DTLSConn::new(
+            Arc::clone(&conn) as Arc<dyn Conn + Send + Sync>,
+            config,
+            true,
+            None,
+        )
+        .await;
+        assert!(
+            matches!(result, Err(Error::ErrHandshakeTimeout)),
+            "{:?}",
+            result.err()
+        );
+        assert_eq!(start.elapsed().as_secs(), handshake_timeout.as_secs());
+
+        let sent: Vec<u64> = conn
+            .sent
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|t| t.duration_since(start).as_secs())
+            .collect();
+        assert_eq!(sent, expected);
+    }
+}
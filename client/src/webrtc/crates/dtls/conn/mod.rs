@@ -1,26 +1,38 @@
+#[cfg(test)]
+mod conn_test;
+
+use crate::webrtc::dtls::ack::*;
 use crate::webrtc::dtls::alert::*;
 use crate::webrtc::dtls::application_data::*;
 use crate::webrtc::dtls::cipher_suite::*;
 use crate::webrtc::dtls::config::*;
 use crate::webrtc::dtls::content::*;
-use crate::webrtc::dtls::curve::named_curve::NamedCurve;
+use crate::webrtc::dtls::crypto::crypto_dtls13::*;
+use crate::webrtc::dtls::curve::named_curve::*;
 use crate::webrtc::dtls::error::*;
 use crate::webrtc::dtls::flight::flight0::*;
 use crate::webrtc::dtls::flight::flight1::*;
+use crate::webrtc::dtls::flight::flight3_dtls13::*;
 use crate::webrtc::dtls::flight::flight5::*;
 use crate::webrtc::dtls::flight::flight6::*;
 use crate::webrtc::dtls::flight::*;
 use crate::webrtc::dtls::fragment_buffer::*;
 use crate::webrtc::dtls::handshake::handshake_cache::*;
 use crate::webrtc::dtls::handshake::handshake_header::HandshakeHeader;
+use crate::webrtc::dtls::handshake::handshake_message_key_update::*;
 use crate::webrtc::dtls::handshake::*;
 use crate::webrtc::dtls::handshaker::*;
+use crate::webrtc::dtls::key_log::*;
+use crate::webrtc::dtls::prf::key_schedule::*;
 use crate::webrtc::dtls::record_layer::record_layer_header::*;
+use crate::webrtc::dtls::record_layer::unified_header::*;
 use crate::webrtc::dtls::record_layer::*;
 use crate::webrtc::dtls::signature_hash_algorithm::default_signature_schemes;
 use crate::webrtc::dtls::state::*;
 
-use crate::webrtc::util::{replay_detector::*, Conn};
+use crate::webrtc::util::{
+    replay_detector::*, Conn, KeyingMaterialExporter, KeyingMaterialExporterError,
+};
 
 use async_trait::async_trait;
 use log::*;
@@ -34,11 +46,16 @@ use tokio::sync::{mpsc, Mutex};
 use tokio::time::Duration;
 
 pub(crate) const INITIAL_TICKER_INTERVAL: Duration = Duration::from_secs(1);
+pub(crate) const MAX_TICKER_INTERVAL: Duration = Duration::from_secs(60);
+pub(crate) const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
 pub(crate) const COOKIE_LENGTH: usize = 20;
 pub(crate) const DEFAULT_NAMED_CURVE: NamedCurve = NamedCurve::X25519;
 pub(crate) const INBOUND_BUFFER_SIZE: usize = 8192;
 // Default replay protection window is specified by RFC 6347 Section 4.1.2.6
 pub(crate) const DEFAULT_REPLAY_PROTECTION_WINDOW: usize = 64;
+// Records protected with one DTLS 1.3 traffic key before it is updated, the
+// AES-GCM limit is 2^24.5 records [RFC9147 Section 4.5.3]
+pub(crate) const DTLS13_KEY_UPDATE_RECORD_LIMIT: u64 = 1 << 24;
 
 lazy_static! {
     pub(crate) static ref INVALID_KEYING_LABELS: HashMap<&'static str, bool> = {
@@ -62,10 +79,13 @@ struct ConnReaderContext {
     fragment_buffer: FragmentBuffer,
     cache: HandshakeCache,
     cipher_suite: Arc<Mutex<Option<Box<dyn CipherSuite + Send + Sync>>>>,
+    dtls13: Arc<Mutex<Dtls13EpochKeys>>,
+    local_connection_id: Option<Vec<u8>>,
     remote_epoch: Arc<AtomicU16>,
     handshake_tx: mpsc::Sender<mpsc::Sender<()>>,
     handshake_done_rx: mpsc::Receiver<()>,
     packet_tx: Arc<mpsc::Sender<PacketSendRequest>>,
+    alert_tx: mpsc::Sender<Alert>,
 }
 
 // Conn represents a DTLS connection
@@ -94,6 +114,7 @@ pub(crate) struct DTLSConn {
     pub(crate) flights: Option<Vec<Packet>>,
     pub(crate) cfg: HandshakeConfig,
     pub(crate) retransmit: bool,
+    pub(crate) retransmit_interval: Duration, // Current retransmit timer value
     pub(crate) handshake_rx: mpsc::Receiver<mpsc::Sender<()>>,
 
     pub(crate) packet_tx: Arc<mpsc::Sender<PacketSendRequest>>,
@@ -101,6 +122,7 @@ pub(crate) struct DTLSConn {
     pub(crate) handshake_done_tx: Option<mpsc::Sender<()>>,
 
     reader_close_tx: Mutex<Option<mpsc::Sender<()>>>,
+    alert_rx: Mutex<mpsc::Receiver<Alert>>, // Fatal or close_notify alert that ended the connection
 }
 
 type UtilResult<T> = std::result::Result<T, crate::webrtc::util::Error>;
@@ -171,6 +193,12 @@ impl DTLSConn {
         .map(|cs| cs.id())
         .collect();
 
+        let local_elliptic_curves = if config.elliptic_curves.is_empty() {
+            default_elliptic_curves()
+        } else {
+            config.elliptic_curves.clone()
+        };
+
         let local_signature_schemes = default_signature_schemes();
 
         let retransmit_interval = if config.flight_interval != Duration::from_secs(0) {
@@ -179,6 +207,18 @@ impl DTLSConn {
             INITIAL_TICKER_INTERVAL
         };
 
+        let max_retransmit_interval = if config.max_flight_interval != Duration::from_secs(0) {
+            config.max_flight_interval
+        } else {
+            MAX_TICKER_INTERVAL
+        };
+
+        let handshake_timeout = if config.handshake_timeout != Duration::from_secs(0) {
+            config.handshake_timeout
+        } else {
+            DEFAULT_HANDSHAKE_TIMEOUT
+        };
+
         /*
            loggerFactory := config.LoggerFactory
            if loggerFactory == nil {
@@ -211,10 +251,40 @@ impl DTLSConn {
             }
         }
 
+        // Cached sessions are looked up by the server address unless a key is provided
+        let session_key = if !config.session_key.is_empty() {
+            config.session_key.clone()
+        } else if let Some(remote_addr) = conn.remote_addr().await {
+            remote_addr.to_string().into_bytes()
+        } else {
+            server_name.clone().into_bytes()
+        };
+
+        let key_log_path = if !config.key_log_path.is_empty() {
+            config.key_log_path.clone()
+        } else {
+            std::env::var(KEY_LOG_FILE_ENV).unwrap_or_default()
+        };
+        let key_log = if !key_log_path.is_empty() {
+            match KeyLog::open(&key_log_path) {
+                Ok(key_log) => {
+                    log::warn!("logging DTLS secrets to {}", key_log_path);
+                    Some(Arc::new(key_log))
+                }
+                Err(err) => {
+                    log::warn!("failed to open key log {}: {}", key_log_path, err);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
         let cfg = HandshakeConfig {
             local_psk_callback: config.psk.take(),
             local_psk_identity_hint: config.psk_identity_hint.take(),
             local_cipher_suites,
+            local_elliptic_curves,
             local_signature_schemes,
             extended_master_secret: config.extended_master_secret,
             local_srtp_protection_profiles: config.srtp_protection_profiles.clone(),
@@ -232,8 +302,13 @@ impl DTLSConn {
                 None
             },
             retransmit_interval,
+            max_retransmit_interval,
             //log: logger,
             initial_epoch: 0,
+            enable_dtls13: config.enable_dtls13,
+            session_store: config.session_store.take(),
+            session_key,
+            key_log,
             ..Default::default()
         };
 
@@ -252,9 +327,20 @@ impl DTLSConn {
                 Box::new(Flight0 {}) as Box<dyn Flight + Send + Sync>
             };
 
+            // The CID is only negotiated for DTLS 1.2 records, the unified header
+            // CID is not implemented, so a ClientHello offering DTLS 1.3 leaves
+            // it out
+            // https://www.rfc-editor.org/rfc/rfc9147#section-9
+            let local_connection_id = if is_client && is_dtls13_offered(&cfg) {
+                None
+            } else {
+                config.connection_id_generator.as_ref().map(|g| g())
+            };
+
             (
                 State {
                     is_client,
+                    local_connection_id,
                     ..Default::default()
                 },
                 flight,
@@ -265,6 +351,7 @@ impl DTLSConn {
         let (decrypted_tx, decrypted_rx) = mpsc::channel(1);
         let (handshake_tx, handshake_rx) = mpsc::channel(1);
         let (handshake_done_tx, handshake_done_rx) = mpsc::channel(1);
+        let (alert_tx, alert_rx) = mpsc::channel(1);
         let (packet_tx, mut packet_rx) = mpsc::channel(1);
         let (handle_queue_tx, mut handle_queue_rx) = mpsc::channel(1);
         let (reader_close_tx, mut reader_close_rx) = mpsc::channel(1);
@@ -291,14 +378,18 @@ impl DTLSConn {
             flights: None,
             cfg,
             retransmit: false,
+            retransmit_interval,
             handshake_rx,
             packet_tx,
             handle_queue_tx,
             handshake_done_tx: Some(handshake_done_tx),
             reader_close_tx: Mutex::new(Some(reader_close_tx)),
+            alert_rx: Mutex::new(alert_rx),
         };
 
         let cipher_suite1 = Arc::clone(&c.state.cipher_suite);
+        let dtls13_1 = Arc::clone(&c.state.dtls13);
+        let remote_connection_id = Arc::clone(&c.state.remote_connection_id);
         let sequence_number = Arc::clone(&c.state.local_sequence_number);
 
         tokio::spawn(async move {
@@ -314,6 +405,8 @@ impl DTLSConn {
                         is_client,
                         &sequence_number,
                         &cipher_suite1,
+                        &dtls13_1,
+                        &remote_connection_id,
                         maximum_transmission_unit,
                     )
                     .await;
@@ -331,6 +424,8 @@ impl DTLSConn {
         let local_epoch = Arc::clone(&c.state.local_epoch);
         let remote_epoch = Arc::clone(&c.state.remote_epoch);
         let cipher_suite2 = Arc::clone(&c.state.cipher_suite);
+        let dtls13_2 = Arc::clone(&c.state.dtls13);
+        let local_connection_id = c.state.local_connection_id.clone();
 
         tokio::spawn(async move {
             let mut buf = vec![0u8; INBOUND_BUFFER_SIZE];
@@ -343,10 +438,13 @@ impl DTLSConn {
                 fragment_buffer: FragmentBuffer::new(),
                 cache: cache2,
                 cipher_suite: cipher_suite2,
+                dtls13: dtls13_2,
+                local_connection_id,
                 remote_epoch,
                 handshake_tx,
                 handshake_done_rx,
                 packet_tx: packet_tx2,
+                alert_tx,
             };
 
             //trace!("before enter read_and_buffer: {}] ", srv_cli_str(is_client));
@@ -373,7 +471,7 @@ impl DTLSConn {
                                 srv_cli_str(is_client),
                                 err
                             );
-                            if Error::ErrAlertFatalOrClose == err {
+                            if Error::ErrAlertFatalOrClose == err || Error::ErrConnClosed == err {
                                 trace!(
                                     "{}: read_and_buffer exit with {}",
                                     srv_cli_str(ctx.is_client),
@@ -389,7 +487,18 @@ impl DTLSConn {
         });
 
         // Do handshake
-        c.handshake(initial_fsm_state).await?;
+        match tokio::time::timeout(handshake_timeout, c.handshake(initial_fsm_state)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => {
+                // Report the alert of the peer rather than the closed channel it caused
+                let mut alert_rx = c.alert_rx.lock().await;
+                if let Ok(alert) = alert_rx.try_recv() {
+                    return Err(Error::ErrAlertReceived(alert));
+                }
+                return Err(err);
+            }
+            Err(_) => return Err(Error::ErrHandshakeTimeout),
+        };
 
         trace!("Handshake Completed");
 
@@ -469,9 +578,88 @@ impl DTLSConn {
             self.write_packets(pkts).await?;
         }
 
+        self.update_keys_if_exhausted().await?;
+
         Ok(p.len())
     }
 
+    // update_keys sends a DTLS 1.3 KeyUpdate, the new sending keys are used once
+    // the peer acknowledged it. With request_peer_update the peer updates its
+    // sending keys too.
+    // https://www.rfc-editor.org/rfc/rfc9147#section-8
+    pub(crate) async fn update_keys(&self, request_peer_update: bool) -> Result<()> {
+        if !self.is_handshake_completed_successfully() {
+            return Err(Error::ErrHandshakeInProgress);
+        }
+
+        let message_sequence = {
+            let mut keys = self.state.dtls13.lock().await;
+            if !keys.is_enabled() {
+                return Err(Error::ErrKeyUpdateNotSupported);
+            }
+            if keys.pending_key_update.is_some() {
+                return Ok(());
+            }
+
+            keys.handshake_send_sequence += 1;
+            keys.handshake_send_sequence - 1
+        };
+
+        let mut h = Handshake::new(HandshakeMessage::KeyUpdate(HandshakeMessageKeyUpdate {
+            update_requested: request_peer_update,
+        }));
+        h.handshake_header.message_sequence = message_sequence as u16;
+
+        self.write_packets(vec![Packet {
+            record: RecordLayer::new(
+                PROTOCOL_VERSION1_2,
+                self.get_local_epoch(),
+                Content::Handshake(h),
+            ),
+            should_encrypt: true,
+        }])
+        .await
+    }
+
+    // update_keys_if_exhausted updates the sending keys before too many records
+    // were protected with them
+    async fn update_keys_if_exhausted(&self) -> Result<()> {
+        if !self.state.dtls13.lock().await.is_enabled() {
+            return Ok(());
+        }
+
+        let epoch = self.get_local_epoch() as usize;
+        let sent = {
+            let lsn = self.state.local_sequence_number.lock().await;
+            lsn.get(epoch).copied().unwrap_or(0)
+        };
+        if sent >= DTLS13_KEY_UPDATE_RECORD_LIMIT {
+            self.update_keys(false).await?;
+        }
+
+        Ok(())
+    }
+
+    // export_keying_material derives keying material from the session secrets,
+    // as defined in RFC 5705 for DTLS 1.2 and RFC 8446 Section 7.5 for DTLS 1.3
+    pub(crate) async fn export_keying_material(
+        &self,
+        label: &str,
+        context: &[u8],
+        length: usize,
+    ) -> std::result::Result<Vec<u8>, KeyingMaterialExporterError> {
+        self.state
+            .export_keying_material(label, context, length)
+            .await
+    }
+
+    // remote_alert waits until the peer ends the connection with a fatal alert
+    // or a close_notify and returns it, None if the connection ended otherwise
+    pub(crate) async fn remote_alert(&self) -> Option<Alert> {
+        let mut alert_rx = self.alert_rx.lock().await;
+        alert_rx.recv().await
+    }
+
     // Close closes the connection.
     pub(crate) async fn close(&self) -> Result<()> {
         if !self.closed.load(Ordering::SeqCst) {
@@ -519,6 +707,7 @@ impl DTLSConn {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn handle_outgoing_packets(
         next_conn: &Arc<dyn crate::webrtc::util::Conn + Send + Sync>,
         mut pkts: Vec<Packet>,
@@ -526,6 +715,8 @@ impl DTLSConn {
         is_client: bool,
         local_sequence_number: &Arc<Mutex<Vec<u64>>>,
         cipher_suite: &Arc<Mutex<Option<Box<dyn CipherSuite + Send + Sync>>>>,
+        dtls13: &Arc<Mutex<Dtls13EpochKeys>>,
+        remote_connection_id: &Arc<Mutex<Option<Vec<u8>>>>,
         maximum_transmission_unit: usize,
     ) -> Result<()> {
         let mut raw_packets = vec![];
@@ -556,6 +747,8 @@ impl DTLSConn {
                 let raw_handshake_packets = DTLSConn::process_handshake_packet(
                     local_sequence_number,
                     cipher_suite,
+                    dtls13,
+                    remote_connection_id,
                     maximum_transmission_unit,
                     p,
                     h,
@@ -569,8 +762,14 @@ impl DTLSConn {
                     }
                 }*/
 
-                let raw_packet =
-                    DTLSConn::process_packet(local_sequence_number, cipher_suite, p).await?;
+                let raw_packet = DTLSConn::process_packet(
+                    local_sequence_number,
+                    cipher_suite,
+                    dtls13,
+                    remote_connection_id,
+                    p,
+                )
+                .await?;
                 raw_packets.push(raw_packet);
             }
         }
@@ -590,6 +789,8 @@ impl DTLSConn {
     async fn process_packet(
         local_sequence_number: &Arc<Mutex<Vec<u64>>>,
         cipher_suite: &Arc<Mutex<Option<Box<dyn CipherSuite + Send + Sync>>>>,
+        dtls13: &Arc<Mutex<Dtls13EpochKeys>>,
+        remote_connection_id: &Arc<Mutex<Option<Vec<u8>>>>,
         p: &mut Packet,
     ) -> Result<Vec<u8>> {
         let epoch = p.record.record_layer_header.epoch as usize;
@@ -619,18 +820,114 @@ impl DTLSConn {
         }
 
         if p.should_encrypt {
+            let keys = dtls13.lock().await;
+            if keys.local.contains_key(&p.record.record_layer_header.epoch) {
+                return keys.encrypt(
+                    p.record.record_layer_header.epoch,
+                    seq,
+                    p.record.record_layer_header.content_type,
+                    &raw_packet[RECORD_LAYER_HEADER_SIZE..],
+                );
+            }
+
             let cipher_suite = cipher_suite.lock().await;
             if let Some(cipher_suite) = &*cipher_suite {
-                raw_packet = cipher_suite.encrypt(&p.record.record_layer_header, &raw_packet)?;
+                raw_packet = if let Some(connection_id) =
+                    DTLSConn::negotiated_connection_id(remote_connection_id).await
+                {
+                    DTLSConn::encrypt_connection_id(
+                        cipher_suite.as_ref(),
+                        &p.record.record_layer_header,
+                        &connection_id,
+                        &raw_packet[RECORD_LAYER_HEADER_SIZE..],
+                    )?
+                } else {
+                    cipher_suite.encrypt(&p.record.record_layer_header, &raw_packet)?
+                };
             }
         }
 
         Ok(raw_packet)
     }
 
+    // negotiated_connection_id returns the CID to put on protected records, an
+    // empty CID from the peer means it does not want to receive one
+    async fn negotiated_connection_id(
+        remote_connection_id: &Arc<Mutex<Option<Vec<u8>>>>,
+    ) -> Option<Vec<u8>> {
+        let remote_connection_id = remote_connection_id.lock().await;
+        match &*remote_connection_id {
+            Some(connection_id) if !connection_id.is_empty() => Some(connection_id.clone()),
+            _ => None,
+        }
+    }
+
+    // encrypt_connection_id protects a record as tls12_cid, the real content type
+    // moves into the encrypted DTLSInnerPlaintext
+    // https://www.rfc-editor.org/rfc/rfc9146#section-4
+    fn encrypt_connection_id(
+        cipher_suite: &(dyn CipherSuite + Send + Sync),
+        h: &RecordLayerHeader,
+        connection_id: &[u8],
+        content: &[u8],
+    ) -> Result<Vec<u8>> {
+        let mut inner_plaintext = content.to_vec();
+        inner_plaintext.push(h.content_type as u8);
+
+        let record_layer_header = RecordLayerHeader {
+            content_type: ContentType::Tls12Cid,
+            connection_id: connection_id.to_vec(),
+            content_len: inner_plaintext.len() as u16,
+            ..h.clone()
+        };
+
+        let mut raw_packet = vec![];
+        {
+            let mut writer = BufWriter::<&mut Vec<u8>>::new(raw_packet.as_mut());
+            record_layer_header.marshal(&mut writer)?;
+        }
+        raw_packet.extend_from_slice(&inner_plaintext);
+
+        cipher_suite.encrypt(&record_layer_header, &raw_packet)
+    }
+
+    // decrypt_connection_id opens a tls12_cid record and returns it as a plain
+    // DTLSCiphertext record of the real content type
+    fn decrypt_connection_id(
+        h: &RecordLayerHeader,
+        pkt: &[u8],
+    ) -> Result<(RecordLayerHeader, Vec<u8>)> {
+        let inner_plaintext = &pkt[h.size()..];
+
+        // Zero padding follows the real content type
+        let content_type_index = match inner_plaintext.iter().rposition(|&b| b != 0) {
+            Some(index) => index,
+            None => return Err(Error::ErrInvalidContentType),
+        };
+        let content = &inner_plaintext[..content_type_index];
+
+        let record_layer_header = RecordLayerHeader {
+            content_type: ContentType::from(inner_plaintext[content_type_index]),
+            connection_id: vec![],
+            content_len: content.len() as u16,
+            ..h.clone()
+        };
+
+        let mut raw_packet = vec![];
+        {
+            let mut writer = BufWriter::<&mut Vec<u8>>::new(raw_packet.as_mut());
+            record_layer_header.marshal(&mut writer)?;
+        }
+        raw_packet.extend_from_slice(content);
+
+        Ok((record_layer_header, raw_packet))
+    }
+
     async fn process_handshake_packet(
         local_sequence_number: &Arc<Mutex<Vec<u64>>>,
         cipher_suite: &Arc<Mutex<Option<Box<dyn CipherSuite + Send + Sync>>>>,
+        dtls13: &Arc<Mutex<Dtls13EpochKeys>>,
+        remote_connection_id: &Arc<Mutex<Option<Vec<u8>>>>,
         maximum_transmission_unit: usize,
         p: &Packet,
         h: &Handshake,
@@ -662,6 +959,7 @@ impl DTLSConn {
                 content_len: handshake_fragment.len() as u16,
                 epoch: p.record.record_layer_header.epoch,
                 sequence_number: seq,
+                connection_id: vec![],
             };
 
             let mut record_layer_header_bytes = vec![];
@@ -676,9 +974,39 @@ impl DTLSConn {
             raw_packet.extend_from_slice(&record_layer_header_bytes);
             raw_packet.extend_from_slice(handshake_fragment);
             if p.should_encrypt {
+                let mut keys = dtls13.lock().await;
+                if keys.local.contains_key(&record_layer_header.epoch) {
+                    raw_packet = keys.encrypt(
+                        record_layer_header.epoch,
+                        seq,
+                        record_layer_header.content_type,
+                        handshake_fragment,
+                    )?;
+                    if let HandshakeMessage::KeyUpdate(_) = h.handshake_message {
+                        // The new sending keys are installed when this record is acknowledged
+                        keys.pending_key_update = Some(RecordNumber {
+                            epoch: record_layer_header.epoch as u64,
+                            sequence_number: seq,
+                        });
+                    }
+                    raw_packets.push(raw_packet);
+                    continue;
+                }
+
                 let cipher_suite = cipher_suite.lock().await;
                 if let Some(cipher_suite) = &*cipher_suite {
-                    raw_packet = cipher_suite.encrypt(&record_layer_header, &raw_packet)?;
+                    raw_packet = if let Some(connection_id) =
+                        DTLSConn::negotiated_connection_id(remote_connection_id).await
+                    {
+                        DTLSConn::encrypt_connection_id(
+                            cipher_suite.as_ref(),
+                            &record_layer_header,
+                            &connection_id,
+                            handshake_fragment,
+                        )?
+                    } else {
+                        cipher_suite.encrypt(&record_layer_header, &raw_packet)?
+                    };
                 }
             }
 
@@ -750,11 +1078,18 @@ impl DTLSConn {
         local_epoch: &Arc<AtomicU16>,
         handshake_completed_successfully: &Arc<AtomicBool>,
     ) -> Result<()> {
-        let n = next_conn.recv(buf).await?;
-        let pkts = unpack_datagram(&buf[..n])?;
+        // The conn below fails every read once it is closed, the reader stops
+        // rather than spin on it
+        let n = next_conn.recv(buf).await.map_err(|err| {
+            trace!("{}: recv failed: {}", srv_cli_str(ctx.is_client), err);
+            Error::ErrConnClosed
+        })?;
+        let connection_id_len = ctx.local_connection_id.as_ref().map_or(0, |c| c.len());
+        let pkts = unpack_datagram(&buf[..n], connection_id_len)?;
         let mut has_handshake = false;
         for pkt in pkts {
-            let (hs, alert, mut err) = DTLSConn::handle_incoming_packet(ctx, pkt, true).await;
+            let (hs, alert, mut err) =
+                DTLSConn::handle_incoming_packet(ctx, pkt, local_epoch, true).await;
             if let Some(alert) = alert {
                 let alert_err = ctx
                     .packet_tx
@@ -834,7 +1169,8 @@ impl DTLSConn {
         pkts: Vec<Vec<u8>>,
     ) -> Result<()> {
         for p in pkts {
-            let (_, alert, mut err) = DTLSConn::handle_incoming_packet(ctx, p, false).await; // don't re-enqueue
+            let (_, alert, mut err) =
+                DTLSConn::handle_incoming_packet(ctx, p, local_epoch, false).await; // don't re-enqueue
             if let Some(alert) = alert {
                 let alert_err = ctx
                     .packet_tx
@@ -877,26 +1213,81 @@ impl DTLSConn {
     async fn handle_incoming_packet(
         ctx: &mut ConnReaderContext,
         mut pkt: Vec<u8>,
+        local_epoch: &Arc<AtomicU16>,
         enqueue: bool,
     ) -> (bool, Option<Alert>, Option<Error>) {
+        // DTLS 1.3 protected records use the unified header, they are opened
+        // here and continue as DTLSPlaintext records
+        let is_dtls13_record = !pkt.is_empty() && is_unified_header(pkt[0]);
+        if is_dtls13_record {
+            let mut keys = ctx.dtls13.lock().await;
+            pkt = match keys.decrypt(&pkt) {
+                Ok(Some(pkt)) => pkt,
+                Ok(None) => {
+                    if enqueue {
+                        debug!(
+                            "{}: keys of the record epoch not installed, queuing packet",
+                            srv_cli_str(ctx.is_client)
+                        );
+                        ctx.encrypted_packets.push(pkt);
+                    } else {
+                        debug!(
+                            "{}: discarded DTLS 1.3 record of epoch bits {}, no keys installed",
+                            srv_cli_str(ctx.is_client),
+                            pkt[0] & UNIFIED_HEADER_EPOCH_MASK
+                        );
+                    }
+                    return (false, None, None);
+                }
+                Err(err) => {
+                    debug!(
+                        "{}: discarded DTLS 1.3 record of epoch bits {}, decrypt failed: {}",
+                        srv_cli_str(ctx.is_client),
+                        pkt[0] & UNIFIED_HEADER_EPOCH_MASK,
+                        err
+                    );
+                    return (false, None, None);
+                }
+            };
+        }
+
+        let connection_id_len = ctx.local_connection_id.as_ref().map_or(0, |c| c.len());
         let mut reader = BufReader::new(pkt.as_slice());
-        let h = match RecordLayerHeader::unmarshal(&mut reader) {
-            Ok(h) => h,
-            Err(err) => {
-                // Decode error must be silently discarded
-                // [RFC6347 Section-4.1.2.7]
-                debug!(
-                    "{}: discarded broken packet: {}",
-                    srv_cli_str(ctx.is_client),
-                    err
-                );
-                return (false, None, None);
-            }
-        };
+        let mut h =
+            match RecordLayerHeader::unmarshal_with_connection_id(&mut reader, connection_id_len) {
+                Ok(h) => h,
+                Err(err) => {
+                    // Decode error must be silently discarded
+                    // [RFC6347 Section-4.1.2.7]
+                    debug!(
+                        "{}: discarded broken packet: {}",
+                        srv_cli_str(ctx.is_client),
+                        err
+                    );
+                    return (false, None, None);
+                }
+            };
+
+        // tls12_cid records are matched by CID rather than by the address they
+        // came from, so they keep decrypting after the peer address changes
+        // https://www.rfc-editor.org/rfc/rfc9146#section-6
+        if h.content_type == ContentType::Tls12Cid
+            && (h.epoch == 0
+                || connection_id_len == 0
+                || ctx.local_connection_id.as_ref() != Some(&h.connection_id))
+        {
+            debug!(
+                "{}: discarded record with unknown connection id (epoch: {}, seq: {})",
+                srv_cli_str(ctx.is_client),
+                h.epoch,
+                h.sequence_number,
+            );
+            return (false, None, None);
+        }
 
         // Validate epoch
         let epoch = ctx.remote_epoch.load(Ordering::SeqCst);
-        if h.epoch > epoch {
+        if h.epoch > epoch && !is_dtls13_record {
             if h.epoch > epoch + 1 {
                 debug!(
                     "{}: discarded future packet (epoch: {}, seq: {})",
@@ -937,7 +1328,7 @@ impl DTLSConn {
         }
 
         // Decrypt
-        if h.epoch != 0 {
+        if h.epoch != 0 && !is_dtls13_record {
             let invalid_cipher_suite = {
                 let cipher_suite = ctx.cipher_suite.lock().await;
                 if cipher_suite.is_none() {
@@ -961,7 +1352,7 @@ impl DTLSConn {
 
             let cipher_suite = ctx.cipher_suite.lock().await;
             if let Some(cipher_suite) = &*cipher_suite {
-                pkt = match cipher_suite.decrypt(&pkt) {
+                pkt = match cipher_suite.decrypt(&h, &pkt) {
                     Ok(pkt) => pkt,
                     Err(err) => {
                         debug!("{}: decrypt failed: {}", srv_cli_str(ctx.is_client), err);
@@ -969,6 +1360,16 @@ impl DTLSConn {
                     }
                 };
             }
+
+            if h.content_type == ContentType::Tls12Cid {
+                (h, pkt) = match DTLSConn::decrypt_connection_id(&h, &pkt) {
+                    Ok(r) => r,
+                    Err(err) => {
+                        debug!("{}: decrypt failed: {}", srv_cli_str(ctx.is_client), err);
+                        return (false, None, None);
+                    }
+                };
+            }
         }
 
         let is_handshake = match ctx.fragment_buffer.push(&pkt) {
@@ -982,10 +1383,30 @@ impl DTLSConn {
         };
         if is_handshake {
             ctx.replay_detector[h.epoch as usize].accept();
+            let mut is_post_handshake = false;
             while let Ok((out, epoch)) = ctx.fragment_buffer.pop() {
                 //log::debug!("Extension Debug: out.len()={}", out.len());
+                if is_dtls13_record && epoch >= DTLS13_APPLICATION_EPOCH {
+                    is_post_handshake = true;
+                    if let Err(err) =
+                        DTLSConn::handle_post_handshake_message(ctx, &h, &out, local_epoch).await
+                    {
+                        return (
+                            false,
+                            Some(Alert {
+                                alert_level: AlertLevel::Fatal,
+                                alert_description: AlertDescription::UnexpectedMessage,
+                            }),
+                            Some(err),
+                        );
+                    }
+                    continue;
+                }
+
                 let mut reader = BufReader::new(out.as_slice());
-                let raw_handshake = match Handshake::unmarshal(&mut reader) {
+                let raw_handshake = match Handshake::unmarshal(&mut reader)
+                    .or_else(|_| Handshake::unmarshal_dtls13(&out))
+                {
                     Ok(rh) => {
                         trace!(
                             "Recv [handshake:{}] -> {} (epoch: {}, seq: {})",
@@ -1017,7 +1438,7 @@ impl DTLSConn {
                     .await;
             }
 
-            return (true, None, None);
+            return (!is_post_handshake, None, None);
         }
 
         let mut reader = BufReader::new(pkt.as_slice());
@@ -1038,6 +1459,12 @@ impl DTLSConn {
         match r.content {
             Content::Alert(mut a) => {
                 trace!("{}: <- {}", srv_cli_str(ctx.is_client), a.to_string());
+                if a.alert_level == AlertLevel::Fatal
+                    || a.alert_description == AlertDescription::CloseNotify
+                {
+                    // Keep what ended the connection for the application
+                    let _ = ctx.alert_tx.try_send(a);
+                }
                 if a.alert_description == AlertDescription::CloseNotify {
                     // Respond with a close_notify [RFC5246 Section 7.2.1]
                     a = Alert {
@@ -1101,13 +1528,73 @@ impl DTLSConn {
 
                 ctx.replay_detector[h.epoch as usize].accept();
 
+                // Application data from the server acknowledges our final flight
+                // [RFC9147 Section 5.8.1]
+                let mut implicit_ack = false;
+                if is_dtls13_record {
+                    let mut keys = ctx.dtls13.lock().await;
+                    if !keys.handshake_acked {
+                        keys.handshake_acked = true;
+                        implicit_ack = true;
+                    }
+                }
+
                 let _ = ctx.decrypted_tx.send(Ok(a.data)).await;
+                if implicit_ack {
+                    return (true, None, None);
+                }
                 //TODO
                 /*select {
                     case self.decrypted < - content.data:
                     case < -c.closed.Done():
                 }*/
             }
+            Content::Ack(a) if is_dtls13_record => {
+                ctx.replay_detector[h.epoch as usize].accept();
+
+                let mut keys = ctx.dtls13.lock().await;
+                if !keys.handshake_acked {
+                    if a.record_numbers
+                        .iter()
+                        .any(|r| r.epoch == DTLS13_HANDSHAKE_EPOCH as u64)
+                    {
+                        keys.handshake_acked = true;
+                        return (true, None, None);
+                    }
+                } else if let Some(pending) = keys.pending_key_update {
+                    if a.record_numbers.contains(&pending) {
+                        let next_epoch = pending.epoch as u16 + 1;
+                        let result = keys
+                            .hash()
+                            .and_then(|hash| {
+                                key_schedule_next_traffic_secret(hash, &keys.local_traffic_secret)
+                            })
+                            .and_then(|secret| {
+                                keys.install_local(next_epoch, &secret)?;
+                                keys.local_traffic_secret = secret;
+                                Ok(())
+                            });
+                        if let Err(err) = result {
+                            return (
+                                false,
+                                Some(Alert {
+                                    alert_level: AlertLevel::Fatal,
+                                    alert_description: AlertDescription::InternalError,
+                                }),
+                                Some(err),
+                            );
+                        }
+
+                        keys.pending_key_update = None;
+                        local_epoch.store(next_epoch, Ordering::SeqCst);
+                        trace!(
+                            "{}: sending keys updated (epoch: {})",
+                            srv_cli_str(ctx.is_client),
+                            next_epoch
+                        );
+                    }
+                }
+            }
             _ => {
                 return (
                     false,
@@ -1123,6 +1610,95 @@ impl DTLSConn {
         (false, None, None)
     }
 
+    // handle_post_handshake_message handles the DTLS 1.3 handshake messages
+    // received after the handshake, they are acknowledged but never cached.
+    // https://www.rfc-editor.org/rfc/rfc9147#section-8
+    async fn handle_post_handshake_message(
+        ctx: &mut ConnReaderContext,
+        h: &RecordLayerHeader,
+        out: &[u8],
+        local_epoch: &Arc<AtomicU16>,
+    ) -> Result<()> {
+        let mut reader = BufReader::new(out);
+        let header = HandshakeHeader::unmarshal(&mut reader)?;
+        trace!(
+            "Recv [handshake:{}] -> {} (epoch: {}, seq: {})",
+            srv_cli_str(ctx.is_client),
+            header.handshake_type,
+            h.epoch,
+            header.message_sequence
+        );
+
+        let epoch = local_epoch.load(Ordering::SeqCst);
+        let mut pkts = vec![Packet {
+            record: RecordLayer::new(
+                PROTOCOL_VERSION1_2,
+                epoch,
+                Content::Ack(Ack {
+                    record_numbers: vec![RecordNumber {
+                        epoch: h.epoch as u64,
+                        sequence_number: h.sequence_number,
+                    }],
+                }),
+            ),
+            should_encrypt: true,
+        }];
+
+        match header.handshake_type {
+            HandshakeType::KeyUpdate => {
+                let mut reader = BufReader::new(out);
+                let k = match Handshake::unmarshal(&mut reader)?.handshake_message {
+                    HandshakeMessage::KeyUpdate(k) => k,
+                    _ => return Err(Error::ErrInvalidKeyUpdate),
+                };
+
+                let mut keys = ctx.dtls13.lock().await;
+                let next_epoch = h.epoch + 1;
+                if !keys.remote.contains_key(&next_epoch) {
+                    let secret = key_schedule_next_traffic_secret(
+                        keys.hash()?,
+                        &keys.remote_traffic_secret,
+                    )?;
+                    keys.install_remote(next_epoch, &secret)?;
+                    keys.remote_traffic_secret = secret;
+                    ctx.remote_epoch.store(next_epoch, Ordering::SeqCst);
+                    trace!(
+                        "{}: receiving keys updated (epoch: {})",
+                        srv_cli_str(ctx.is_client),
+                        next_epoch
+                    );
+
+                    if k.update_requested && keys.pending_key_update.is_none() {
+                        let mut update = Handshake::new(HandshakeMessage::KeyUpdate(
+                            HandshakeMessageKeyUpdate {
+                                update_requested: false,
+                            },
+                        ));
+                        update.handshake_header.message_sequence =
+                            keys.handshake_send_sequence as u16;
+                        keys.handshake_send_sequence += 1;
+
+                        pkts.push(Packet {
+                            record: RecordLayer::new(
+                                PROTOCOL_VERSION1_2,
+                                epoch,
+                                Content::Handshake(update),
+                            ),
+                            should_encrypt: true,
+                        });
+                    }
+                }
+            }
+            // Session tickets are not used, acknowledging them stops retransmission
+            HandshakeType::NewSessionTicket => {}
+            _ => return Err(Error::ErrUnhandledContextType),
+        }
+
+        ctx.packet_tx.send((pkts, None)).await?;
+
+        Ok(())
+    }
+
     fn is_connection_closed(&self) -> bool {
         self.closed.load(Ordering::SeqCst)
     }
@@ -1,3 +1,4 @@
+use super::ack::*;
 use super::alert::*;
 use super::application_data::*;
 use super::change_cipher_spec::*;
@@ -13,6 +14,8 @@ pub(crate) enum ContentType {
     Alert = 21,
     Handshake = 22,
     ApplicationData = 23,
+    Tls12Cid = 25, // https://www.rfc-editor.org/rfc/rfc9146#section-4
+    Ack = 26,      // https://www.rfc-editor.org/rfc/rfc9147#section-7
     Invalid,
 }
 
@@ -23,6 +26,8 @@ impl From<u8> for ContentType {
             21 => ContentType::Alert,
             22 => ContentType::Handshake,
             23 => ContentType::ApplicationData,
+            25 => ContentType::Tls12Cid,
+            26 => ContentType::Ack,
             _ => ContentType::Invalid,
         }
     }
@@ -40,6 +45,7 @@ pub(crate) enum Content {
     Alert(Alert),
     Handshake(Handshake),
     ApplicationData(ApplicationData),
+    Ack(Ack),
 }
 
 impl Content {
@@ -49,6 +55,7 @@ impl Content {
             Content::Alert(c) => c.content_type(),
             Content::Handshake(c) => c.content_type(),
             Content::ApplicationData(c) => c.content_type(),
+            Content::Ack(c) => c.content_type(),
         }
     }
 
@@ -58,6 +65,7 @@ impl Content {
             Content::Alert(c) => c.size(),
             Content::Handshake(c) => c.size(),
             Content::ApplicationData(c) => c.size(),
+            Content::Ack(c) => c.size(),
         }
     }
 
@@ -67,6 +75,7 @@ impl Content {
             Content::Alert(c) => c.marshal(writer),
             Content::Handshake(c) => c.marshal(writer),
             Content::ApplicationData(c) => c.marshal(writer),
+            Content::Ack(c) => c.marshal(writer),
         }
     }
 }
@@ -8,7 +8,6 @@
 
 // https://github.com/RustCrypto/block-ciphers
 
-use std::io::Cursor;
 use std::ops::Not;
 
 use crate::webrtc::dtls::content::*;
@@ -22,6 +21,7 @@ use block_modes::{BlockMode, Cbc};
 use rand::Rng;
 use subtle::ConstantTimeEq;
 
+use super::generate_aead_additional_data;
 use super::padding::DtlsPadding;
 type Aes256Cbc = Cbc<Aes256, DtlsPadding>;
 
@@ -53,21 +53,34 @@ impl CryptoCbc {
         })
     }
 
-    pub(crate) fn encrypt(&self, pkt_rlh: &RecordLayerHeader, raw: &[u8]) -> Result<Vec<u8>> {
-        let mut payload = raw[RECORD_LAYER_HEADER_SIZE..].to_vec();
-        let raw = &raw[..RECORD_LAYER_HEADER_SIZE];
-
-        // Generate + Append MAC
-        let h = pkt_rlh;
+    // tls12_cid records authenticate the same fields as the AEAD additional data
+    // https://www.rfc-editor.org/rfc/rfc9146#section-5.1
+    fn mac(h: &RecordLayerHeader, payload: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+        if h.content_type == ContentType::Tls12Cid {
+            return prf_mac_with_additional_data(
+                &generate_aead_additional_data(h, payload.len()),
+                payload,
+                key,
+            );
+        }
 
-        let mac = prf_mac(
+        prf_mac(
             h.epoch,
             h.sequence_number,
             h.content_type,
             h.protocol_version,
-            &payload,
-            &self.write_mac,
-        )?;
+            payload,
+            key,
+        )
+    }
+
+    pub(crate) fn encrypt(&self, pkt_rlh: &RecordLayerHeader, raw: &[u8]) -> Result<Vec<u8>> {
+        let header_size = pkt_rlh.size();
+        let mut payload = raw[header_size..].to_vec();
+        let raw = &raw[..header_size];
+
+        // Generate + Append MAC
+        let mac = CryptoCbc::mac(pkt_rlh, &payload, &self.write_mac)?;
         payload.extend_from_slice(&mac);
 
         let mut iv: Vec<u8> = vec![0; Self::BLOCK_SIZE];
@@ -82,22 +95,20 @@ impl CryptoCbc {
         r.extend_from_slice(&iv);
         r.extend_from_slice(&encrypted);
 
-        let r_len = (r.len() - RECORD_LAYER_HEADER_SIZE) as u16;
-        r[RECORD_LAYER_HEADER_SIZE - 2..RECORD_LAYER_HEADER_SIZE]
-            .copy_from_slice(&r_len.to_be_bytes());
+        let r_len = (r.len() - header_size) as u16;
+        r[header_size - 2..header_size].copy_from_slice(&r_len.to_be_bytes());
 
         Ok(r)
     }
 
-    pub(crate) fn decrypt(&self, r: &[u8]) -> Result<Vec<u8>> {
-        let mut reader = Cursor::new(r);
-        let h = RecordLayerHeader::unmarshal(&mut reader)?;
+    pub(crate) fn decrypt(&self, h: &RecordLayerHeader, r: &[u8]) -> Result<Vec<u8>> {
         if h.content_type == ContentType::ChangeCipherSpec {
             // Nothing to encrypt with ChangeCipherSpec
             return Ok(r.to_vec());
         }
 
-        let body = &r[RECORD_LAYER_HEADER_SIZE..];
+        let header_size = h.size();
+        let body = &r[header_size..];
         let iv = &body[0..Self::BLOCK_SIZE];
         let body = &body[Self::BLOCK_SIZE..];
         //TODO: add body.len() check
@@ -108,21 +119,14 @@ impl CryptoCbc {
 
         let recv_mac = &decrypted[decrypted.len() - Self::MAC_SIZE..];
         let decrypted = &decrypted[0..decrypted.len() - Self::MAC_SIZE];
-        let mac = prf_mac(
-            h.epoch,
-            h.sequence_number,
-            h.content_type,
-            h.protocol_version,
-            decrypted,
-            &self.read_mac,
-        )?;
+        let mac = CryptoCbc::mac(h, decrypted, &self.read_mac)?;
 
         if recv_mac.ct_eq(&mac).not().into() {
             return Err(BlockModeError.into());
         }
 
-        let mut d = Vec::with_capacity(RECORD_LAYER_HEADER_SIZE + decrypted.len());
-        d.extend_from_slice(&r[..RECORD_LAYER_HEADER_SIZE]);
+        let mut d = Vec::with_capacity(header_size + decrypted.len());
+        d.extend_from_slice(&r[..header_size]);
         d.extend_from_slice(decrypted);
 
         Ok(d)
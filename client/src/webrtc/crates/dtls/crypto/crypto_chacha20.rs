@@ -0,0 +1,126 @@
+// ChaCha20-Poly1305
+// Stream cipher + MAC, fast in software on CPUs without AES instructions.
+// RFC 7905 year 2016 https://tools.ietf.org/html/rfc7905
+
+// https://github.com/RustCrypto/AEADs
+// https://docs.rs/chacha20poly1305/0.7.1/chacha20poly1305/
+
+use super::*;
+use crate::webrtc::dtls::content::*;
+use crate::webrtc::dtls::error::*;
+use crate::webrtc::dtls::record_layer::record_layer_header::*;
+
+use chacha20poly1305::aead::{generic_array::GenericArray, AeadInPlace, NewAead};
+use chacha20poly1305::ChaCha20Poly1305;
+
+pub(crate) const CRYPTO_CHACHA20_TAG_LENGTH: usize = 16;
+const CRYPTO_CHACHA20_NONCE_LENGTH: usize = 12;
+
+// State needed to handle encrypted input/output
+#[derive(Clone)]
+pub(crate) struct CryptoChaCha20 {
+    local_chacha: ChaCha20Poly1305,
+    remote_chacha: ChaCha20Poly1305,
+    local_write_iv: Vec<u8>,
+    remote_write_iv: Vec<u8>,
+}
+
+impl CryptoChaCha20 {
+    pub(crate) fn new(
+        local_key: &[u8],
+        local_write_iv: &[u8],
+        remote_key: &[u8],
+        remote_write_iv: &[u8],
+    ) -> Self {
+        let key = GenericArray::from_slice(local_key);
+        let local_chacha = ChaCha20Poly1305::new(key);
+
+        let key = GenericArray::from_slice(remote_key);
+        let remote_chacha = ChaCha20Poly1305::new(key);
+
+        CryptoChaCha20 {
+            local_chacha,
+            local_write_iv: local_write_iv.to_vec(),
+            remote_chacha,
+            remote_write_iv: remote_write_iv.to_vec(),
+        }
+    }
+
+    // The per-record nonce is the 64-bit epoch + sequence number, left-padded to
+    // 96 bits and XORed with the 12 byte write IV. Nothing is sent on the wire.
+    pub(crate) fn nonce(write_iv: &[u8], h: &RecordLayerHeader) -> Vec<u8> {
+        let mut seq = [0u8; 8];
+        seq.copy_from_slice(&h.sequence_number.to_be_bytes());
+        seq[..2].copy_from_slice(&h.epoch.to_be_bytes());
+
+        let mut nonce = write_iv[..CRYPTO_CHACHA20_NONCE_LENGTH].to_vec();
+        for (n, s) in nonce[CRYPTO_CHACHA20_NONCE_LENGTH - 8..]
+            .iter_mut()
+            .zip(seq.iter())
+        {
+            *n ^= s;
+        }
+        nonce
+    }
+
+    pub(crate) fn encrypt(&self, pkt_rlh: &RecordLayerHeader, raw: &[u8]) -> Result<Vec<u8>> {
+        let header_size = pkt_rlh.size();
+        let payload = &raw[header_size..];
+        let raw = &raw[..header_size];
+
+        let nonce = CryptoChaCha20::nonce(&self.local_write_iv, pkt_rlh);
+        let nonce = GenericArray::from_slice(&nonce);
+
+        let additional_data = generate_aead_additional_data(pkt_rlh, payload.len());
+
+        let mut buffer: Vec<u8> = Vec::new();
+        buffer.extend_from_slice(payload);
+
+        self.local_chacha
+            .encrypt_in_place(nonce, &additional_data, &mut buffer)
+            .map_err(|e| Error::Other(e.to_string()))?;
+
+        let mut r = Vec::with_capacity(raw.len() + buffer.len());
+        r.extend_from_slice(raw);
+        r.extend_from_slice(&buffer);
+
+        // Update recordLayer size to include the authentication tag
+        let r_len = (r.len() - header_size) as u16;
+        r[header_size - 2..header_size].copy_from_slice(&r_len.to_be_bytes());
+
+        Ok(r)
+    }
+
+    pub(crate) fn decrypt(&self, h: &RecordLayerHeader, r: &[u8]) -> Result<Vec<u8>> {
+        if h.content_type == ContentType::ChangeCipherSpec {
+            // Nothing to encrypt with ChangeCipherSpec
+            return Ok(r.to_vec());
+        }
+
+        let header_size = h.size();
+        if r.len() < (header_size + CRYPTO_CHACHA20_TAG_LENGTH) {
+            return Err(Error::ErrInvalidPacketLength);
+        }
+
+        let nonce = CryptoChaCha20::nonce(&self.remote_write_iv, h);
+        let nonce = GenericArray::from_slice(&nonce);
+
+        let out = &r[header_size..];
+
+        let additional_data =
+            generate_aead_additional_data(h, out.len() - CRYPTO_CHACHA20_TAG_LENGTH);
+
+        let mut buffer: Vec<u8> = Vec::new();
+        buffer.extend_from_slice(out);
+
+        self.remote_chacha
+            .decrypt_in_place(nonce, &additional_data, &mut buffer)
+            .map_err(|e| Error::Other(e.to_string()))?;
+
+        let mut d = Vec::with_capacity(header_size + buffer.len());
+        d.extend_from_slice(&r[..header_size]);
+        d.extend_from_slice(&buffer);
+
+        Ok(d)
+    }
+}
@@ -0,0 +1,115 @@
+use super::crypto_chacha20::*;
+use crate::webrtc::dtls::content::*;
+use crate::webrtc::dtls::record_layer::record_layer_header::*;
+
+use chacha20poly1305::aead::{generic_array::GenericArray, AeadInPlace, NewAead};
+use chacha20poly1305::ChaCha20Poly1305;
+
+fn hex(s: &str) -> Vec<u8> {
+    s.split_whitespace()
+        .map(|b| u8::from_str_radix(b, 16).unwrap())
+        .collect()
+}
+
+fn header(epoch: u16, sequence_number: u64, content_len: usize) -> RecordLayerHeader {
+    RecordLayerHeader {
+        content_type: ContentType::ApplicationData,
+        protocol_version: PROTOCOL_VERSION1_2,
+        epoch,
+        sequence_number,
+        connection_id: vec![],
+        content_len: content_len as u16,
+    }
+}
+
+fn record(h: &RecordLayerHeader, payload: &[u8]) -> Vec<u8> {
+    let mut raw = vec![];
+    h.marshal(&mut raw).unwrap();
+    raw.extend_from_slice(payload);
+    raw
+}
+
+// AEAD construction test vector, RFC 7539 Section 2.8.2. With epoch and
+// sequence number 0 the record nonce is the write IV itself.
+#[test]
+fn test_chacha20_poly1305_rfc7539_vector() {
+    let key = hex("80 81 82 83 84 85 86 87 88 89 8a 8b 8c 8d 8e 8f \
+         90 91 92 93 94 95 96 97 98 99 9a 9b 9c 9d 9e 9f");
+    let iv = hex("07 00 00 00 40 41 42 43 44 45 46 47");
+    let aad = hex("50 51 52 53 c0 c1 c2 c3 c4 c5 c6 c7");
+    let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+    let ciphertext = hex("d3 1a 8d 34 64 8e 60 db 7b 86 af bc 53 ef 7e c2 \
+         a4 ad ed 51 29 6e 08 fe a9 e2 b5 a7 36 ee 62 d6 \
+         3d be a4 5e 8c a9 67 12 82 fa fb 69 da 92 72 8b \
+         1a 71 de 0a 9e 06 0b 29 05 d6 a5 b6 7e cd 3b 36 \
+         92 dd bd 7f 2d 77 8b 8c 98 03 ae e3 28 09 1b 58 \
+         fa b3 24 e4 fa d6 75 94 55 85 80 8b 48 31 d7 bc \
+         3f f4 de f0 8e 4b 7a 9d e5 76 d2 65 86 ce c6 4b \
+         61 16");
+    let tag = hex("1a e1 0b 59 4f 09 e2 6a 7e 90 2e cb d0 60 06 91");
+
+    let nonce = CryptoChaCha20::nonce(&iv, &header(0, 0, 0));
+    assert_eq!(nonce, iv);
+
+    let chacha = ChaCha20Poly1305::new(GenericArray::from_slice(&key));
+    let mut buffer = plaintext.to_vec();
+    chacha
+        .encrypt_in_place(GenericArray::from_slice(&nonce), &aad, &mut buffer)
+        .unwrap();
+    assert_eq!(buffer[..plaintext.len()], ciphertext[..]);
+    assert_eq!(buffer[plaintext.len()..], tag[..]);
+
+    chacha
+        .decrypt_in_place(GenericArray::from_slice(&nonce), &aad, &mut buffer)
+        .unwrap();
+    assert_eq!(buffer, plaintext.to_vec());
+}
+
+// RFC 7905 Section 2: the 48-bit sequence number prefixed with the 16-bit epoch
+// is XORed into the last 8 bytes of the write IV
+#[test]
+fn test_chacha20_nonce_from_epoch_and_sequence_number() {
+    let iv = hex("00 01 02 03 04 05 06 07 08 09 0a 0b");
+    let nonce = CryptoChaCha20::nonce(&iv, &header(0x0001, 0x0a0b_0c0d_0e0f, 0));
+    assert_eq!(nonce, hex("00 01 02 03 04 04 0c 0c 04 04 04 04"));
+}
+
+#[test]
+fn test_chacha20_encrypt_decrypt_round_trip() {
+    let client_key = [0x11u8; 32];
+    let client_iv = [0x22u8; 12];
+    let server_key = [0x33u8; 32];
+    let server_iv = [0x44u8; 12];
+    let client = CryptoChaCha20::new(&client_key, &client_iv, &server_key, &server_iv);
+    let server = CryptoChaCha20::new(&server_key, &server_iv, &client_key, &client_iv);
+
+    let payload = b"hello over dtls";
+    let h = header(1, 7, payload.len());
+    let encrypted = client.encrypt(&h, &record(&h, payload)).unwrap();
+    assert_eq!(
+        encrypted.len(),
+        RECORD_LAYER_HEADER_SIZE + payload.len() + CRYPTO_CHACHA20_TAG_LENGTH
+    );
+    assert_ne!(
+        &encrypted[RECORD_LAYER_HEADER_SIZE..][..payload.len()],
+        payload
+    );
+
+    let decrypted = server.decrypt(&h, &encrypted).unwrap();
+    assert_eq!(&decrypted[RECORD_LAYER_HEADER_SIZE..], payload);
+
+    // The nonce and additional data bind the record to its epoch and sequence number
+    assert!(server
+        .decrypt(&header(1, 8, payload.len()), &encrypted)
+        .is_err());
+    assert!(server
+        .decrypt(&header(2, 7, payload.len()), &encrypted)
+        .is_err());
+
+    let mut tampered = encrypted.clone();
+    tampered[RECORD_LAYER_HEADER_SIZE] ^= 1;
+    assert!(server.decrypt(&h, &tampered).is_err());
+
+    // Each side decrypts with the peer's key only
+    assert!(client.decrypt(&h, &encrypted).is_err());
+}
@@ -0,0 +1,345 @@
+// DTLS 1.3 record protection
+// The AEAD protects DTLSInnerPlaintext (content || type), the unified header is
+// the additional data and its sequence number is then encrypted with a mask
+// derived from the ciphertext.
+// RFC 9147 year 2022 https://www.rfc-editor.org/rfc/rfc9147#section-4
+
+use std::collections::HashMap;
+use std::io::BufWriter;
+
+use crate::webrtc::dtls::ack::*;
+use crate::webrtc::dtls::cipher_suite::*;
+use crate::webrtc::dtls::content::*;
+use crate::webrtc::dtls::error::*;
+use crate::webrtc::dtls::prf::key_schedule::*;
+use crate::webrtc::dtls::record_layer::record_layer_header::*;
+use crate::webrtc::dtls::record_layer::unified_header::*;
+
+use aes::{Aes128, BlockCipher, NewBlockCipher};
+use aes_gcm::aead::{generic_array::GenericArray, AeadInPlace, NewAead};
+use aes_gcm::Aes128Gcm;
+use chacha20::cipher::{NewStreamCipher, SyncStreamCipher, SyncStreamCipherSeek};
+use chacha20::ChaCha20;
+use chacha20poly1305::ChaCha20Poly1305;
+
+const CRYPTO_DTLS13_TAG_LENGTH: usize = 16;
+const CRYPTO_DTLS13_NONCE_LENGTH: usize = 12;
+const CRYPTO_DTLS13_MASK_LENGTH: usize = 16;
+
+#[derive(Clone)]
+enum Dtls13Aead {
+    Aes128Gcm(Box<Aes128Gcm>),
+    ChaCha20Poly1305(ChaCha20Poly1305),
+}
+
+// Keys protecting the records of one direction of one epoch
+#[derive(Clone)]
+pub(crate) struct CryptoDtls13 {
+    aead: Dtls13Aead,
+    write_iv: Vec<u8>,
+    sn_key: Vec<u8>,
+}
+
+impl CryptoDtls13 {
+    pub(crate) fn new(cipher_suite: CipherSuiteId, traffic_secret: &[u8]) -> Result<Self> {
+        let key_len = match cipher_suite {
+            CipherSuiteId::Tls_Aes_128_Gcm_Sha256 => 16,
+            CipherSuiteId::Tls_Chacha20_Poly1305_Sha256 => 32,
+            _ => return Err(Error::ErrInvalidCipherSuite),
+        };
+
+        let keys = key_schedule_traffic_keys(
+            dtls13_cipher_suite_hash(cipher_suite)?,
+            traffic_secret,
+            key_len,
+            CRYPTO_DTLS13_NONCE_LENGTH,
+        )?;
+
+        let aead = match cipher_suite {
+            CipherSuiteId::Tls_Aes_128_Gcm_Sha256 => Dtls13Aead::Aes128Gcm(Box::new(
+                Aes128Gcm::new(GenericArray::from_slice(&keys.write_key)),
+            )),
+            _ => Dtls13Aead::ChaCha20Poly1305(ChaCha20Poly1305::new(GenericArray::from_slice(
+                &keys.write_key,
+            ))),
+        };
+
+        Ok(CryptoDtls13 {
+            aead,
+            write_iv: keys.write_iv,
+            sn_key: keys.sn_key,
+        })
+    }
+
+    // Unlike DTLS 1.2 the epoch is not part of the nonce, only the 64-bit
+    // sequence number is XORed with the write IV.
+    // https://tools.ietf.org/html/rfc8446#section-5.3
+    fn nonce(&self, sequence_number: u64) -> Vec<u8> {
+        let mut nonce = self.write_iv.clone();
+        for (n, s) in nonce[CRYPTO_DTLS13_NONCE_LENGTH - 8..]
+            .iter_mut()
+            .zip(sequence_number.to_be_bytes().iter())
+        {
+            *n ^= s;
+        }
+        nonce
+    }
+
+    // Mask = AES-ECB(sn_key, Ciphertext[0..15]) for AES based suites, for ChaCha20
+    // the first 4 bytes are the block counter and the next 12 the nonce.
+    // https://www.rfc-editor.org/rfc/rfc9147#section-4.2.3
+    fn sequence_number_mask(&self, ciphertext: &[u8]) -> Result<[u8; CRYPTO_DTLS13_MASK_LENGTH]> {
+        if ciphertext.len() < CRYPTO_DTLS13_MASK_LENGTH {
+            return Err(Error::ErrInvalidPacketLength);
+        }
+
+        let mut mask = [0u8; CRYPTO_DTLS13_MASK_LENGTH];
+        match self.aead {
+            Dtls13Aead::Aes128Gcm(_) => {
+                let cipher = Aes128::new(GenericArray::from_slice(&self.sn_key));
+                let mut block = GenericArray::clone_from_slice(&ciphertext[..16]);
+                cipher.encrypt_block(&mut block);
+                mask.copy_from_slice(&block);
+            }
+            Dtls13Aead::ChaCha20Poly1305(_) => {
+                let counter = u32::from_le_bytes([
+                    ciphertext[0],
+                    ciphertext[1],
+                    ciphertext[2],
+                    ciphertext[3],
+                ]);
+                let mut cipher = ChaCha20::new(
+                    GenericArray::from_slice(&self.sn_key),
+                    GenericArray::from_slice(&ciphertext[4..16]),
+                );
+                cipher.seek(counter as u64 * 64);
+                cipher.apply_keystream(&mut mask);
+            }
+        };
+
+        Ok(mask)
+    }
+
+    // encrypt returns the DTLSCiphertext record carrying content, always written
+    // with a 16 bit sequence number and an explicit length
+    pub(crate) fn encrypt(
+        &self,
+        epoch: u16,
+        sequence_number: u64,
+        content_type: ContentType,
+        content: &[u8],
+    ) -> Result<Vec<u8>> {
+        // struct {
+        //     opaque content[DTLSPlaintext.length];
+        //     ContentType type;
+        //     uint8 zeros[length_of_padding];
+        // } DTLSInnerPlaintext;
+        let mut buffer: Vec<u8> = Vec::with_capacity(content.len() + 1 + CRYPTO_DTLS13_TAG_LENGTH);
+        buffer.extend_from_slice(content);
+        buffer.push(content_type as u8);
+
+        let mut header = vec![
+            UNIFIED_HEADER_FIXED_BITS
+                | UNIFIED_HEADER_SEQUENCE_NUMBER_16_FLAG
+                | UNIFIED_HEADER_LENGTH_FLAG
+                | (epoch as u8 & UNIFIED_HEADER_EPOCH_MASK),
+        ];
+        header.extend_from_slice(&(sequence_number as u16).to_be_bytes());
+        header.extend_from_slice(&((buffer.len() + CRYPTO_DTLS13_TAG_LENGTH) as u16).to_be_bytes());
+
+        let nonce = self.nonce(sequence_number);
+        let nonce = GenericArray::from_slice(&nonce);
+        match &self.aead {
+            Dtls13Aead::Aes128Gcm(c) => c.encrypt_in_place(nonce, &header, &mut buffer),
+            Dtls13Aead::ChaCha20Poly1305(c) => c.encrypt_in_place(nonce, &header, &mut buffer),
+        }
+        .map_err(|e| Error::Other(e.to_string()))?;
+
+        let mask = self.sequence_number_mask(&buffer)?;
+        header[1] ^= mask[0];
+        header[2] ^= mask[1];
+
+        let mut r = Vec::with_capacity(header.len() + buffer.len());
+        r.extend_from_slice(&header);
+        r.extend_from_slice(&buffer);
+
+        Ok(r)
+    }
+
+    // decrypt opens a DTLSCiphertext record of the given epoch and returns it as a
+    // DTLSPlaintext record, so the rest of the record processing stays the same as
+    // for DTLS 1.2. next_sequence_number is used to reconstruct the full sequence
+    // number from the bits on the wire.
+    pub(crate) fn decrypt(
+        &self,
+        epoch: u16,
+        next_sequence_number: u64,
+        r: &[u8],
+    ) -> Result<(u64, Vec<u8>)> {
+        let header_size = unified_header_size(r[0])?;
+        if r.len() < header_size + CRYPTO_DTLS13_TAG_LENGTH {
+            return Err(Error::ErrInvalidPacketLength);
+        }
+
+        let mut header = r[..header_size].to_vec();
+        let out = &r[header_size..];
+
+        let mask = self.sequence_number_mask(out)?;
+        let sequence_number = if header[0] & UNIFIED_HEADER_SEQUENCE_NUMBER_16_FLAG != 0 {
+            header[1] ^= mask[0];
+            header[2] ^= mask[1];
+            reconstruct_sequence_number(
+                next_sequence_number,
+                u16::from_be_bytes([header[1], header[2]]) as u64,
+                16,
+            )
+        } else {
+            header[1] ^= mask[0];
+            reconstruct_sequence_number(next_sequence_number, header[1] as u64, 8)
+        };
+
+        let mut buffer: Vec<u8> = Vec::new();
+        buffer.extend_from_slice(out);
+
+        let nonce = self.nonce(sequence_number);
+        let nonce = GenericArray::from_slice(&nonce);
+        match &self.aead {
+            Dtls13Aead::Aes128Gcm(c) => c.decrypt_in_place(nonce, &header, &mut buffer),
+            Dtls13Aead::ChaCha20Poly1305(c) => c.decrypt_in_place(nonce, &header, &mut buffer),
+        }
+        .map_err(|e| Error::Other(e.to_string()))?;
+
+        // The content type is the last non-zero byte
+        while let Some(0) = buffer.last() {
+            buffer.pop();
+        }
+        let content_type: ContentType = match buffer.pop() {
+            Some(t) => t.into(),
+            None => return Err(Error::ErrInvalidContentType),
+        };
+
+        let h = RecordLayerHeader {
+            content_type,
+            protocol_version: PROTOCOL_VERSION1_2,
+            epoch,
+            sequence_number,
+            connection_id: vec![],
+            content_len: buffer.len() as u16,
+        };
+
+        let mut d = Vec::with_capacity(RECORD_LAYER_HEADER_SIZE + buffer.len());
+        {
+            let mut writer = BufWriter::<&mut Vec<u8>>::new(d.as_mut());
+            h.marshal(&mut writer)?;
+        }
+        d.extend_from_slice(&buffer);
+
+        Ok((sequence_number, d))
+    }
+}
+
+// The receiver picks the sequence number closest to one plus the highest
+// sequence number received so far that matches the low bits on the wire.
+// https://www.rfc-editor.org/rfc/rfc9147#section-4.2.2
+pub(crate) fn reconstruct_sequence_number(expected: u64, bits: u64, width: u32) -> u64 {
+    let window = 1u64 << width;
+    let candidate = (expected & !(window - 1)) | bits;
+
+    let mut best = candidate;
+    if candidate >= window && expected.abs_diff(candidate - window) < expected.abs_diff(best) {
+        best = candidate - window;
+    }
+    if candidate + window <= MAX_SEQUENCE_NUMBER
+        && expected.abs_diff(candidate + window) < expected.abs_diff(best)
+    {
+        best = candidate + window;
+    }
+
+    best
+}
+
+// Dtls13EpochKeys is the record protection state of a DTLS 1.3 connection. It is
+// shared by the handshake and the reader and writer tasks. Epoch 2 protects the
+// handshake, epoch 3 and up the application data.
+// https://www.rfc-editor.org/rfc/rfc9147#section-6.1
+#[derive(Default)]
+pub(crate) struct Dtls13EpochKeys {
+    pub(crate) cipher_suite: Option<CipherSuiteId>, // None unless DTLS 1.3 was negotiated
+    pub(crate) local: HashMap<u16, CryptoDtls13>,
+    pub(crate) remote: HashMap<u16, CryptoDtls13>,
+    remote_next_sequence_number: HashMap<u16, u64>,
+
+    // Current application traffic secrets, advanced by KeyUpdate
+    pub(crate) local_traffic_secret: Vec<u8>,
+    pub(crate) remote_traffic_secret: Vec<u8>,
+
+    // Set once the server acknowledged the client's final flight
+    pub(crate) handshake_acked: bool,
+    // KeyUpdate sent by us and waiting for its ACK before the new keys are used
+    pub(crate) pending_key_update: Option<RecordNumber>,
+    // Sequence number of the next record sent by the handshake at epoch 2
+    pub(crate) handshake_send_sequence: u64,
+}
+
+impl Dtls13EpochKeys {
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.cipher_suite.is_some()
+    }
+
+    // hash returns the hash of the negotiated CipherSuite
+    pub(crate) fn hash(&self) -> Result<CipherSuiteHash> {
+        dtls13_cipher_suite_hash(self.cipher_suite.ok_or(Error::ErrInvalidCipherSuite)?)
+    }
+
+    pub(crate) fn install_local(&mut self, epoch: u16, traffic_secret: &[u8]) -> Result<()> {
+        let cipher_suite = self.cipher_suite.ok_or(Error::ErrInvalidCipherSuite)?;
+        self.local
+            .insert(epoch, CryptoDtls13::new(cipher_suite, traffic_secret)?);
+        Ok(())
+    }
+
+    pub(crate) fn install_remote(&mut self, epoch: u16, traffic_secret: &[u8]) -> Result<()> {
+        let cipher_suite = self.cipher_suite.ok_or(Error::ErrInvalidCipherSuite)?;
+        self.remote
+            .insert(epoch, CryptoDtls13::new(cipher_suite, traffic_secret)?);
+        Ok(())
+    }
+
+    pub(crate) fn encrypt(
+        &self,
+        epoch: u16,
+        sequence_number: u64,
+        content_type: ContentType,
+        content: &[u8],
+    ) -> Result<Vec<u8>> {
+        match self.local.get(&epoch) {
+            Some(c) => c.encrypt(epoch, sequence_number, content_type, content),
+            None => Err(Error::ErrInvalidCipherSuite),
+        }
+    }
+
+    // decrypt resolves the epoch from its low bits on the wire against the
+    // installed keys. Ok(None) means the keys for the record's epoch are not
+    // known yet and the record should be queued.
+    pub(crate) fn decrypt(&mut self, r: &[u8]) -> Result<Option<Vec<u8>>> {
+        let bits = (r[0] & UNIFIED_HEADER_EPOCH_MASK) as u16;
+        let epoch = match self
+            .remote
+            .keys()
+            .filter(|e| *e & UNIFIED_HEADER_EPOCH_MASK as u16 == bits)
+            .max()
+        {
+            Some(epoch) => *epoch,
+            None => return Ok(None),
+        };
+
+        let next_sequence_number = *self.remote_next_sequence_number.get(&epoch).unwrap_or(&0);
+        let (sequence_number, d) = self.remote[&epoch].decrypt(epoch, next_sequence_number, r)?;
+        if sequence_number >= next_sequence_number {
+            self.remote_next_sequence_number
+                .insert(epoch, sequence_number + 1);
+        }
+
+        Ok(Some(d))
+    }
+}
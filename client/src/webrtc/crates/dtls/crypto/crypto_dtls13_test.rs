@@ -0,0 +1,171 @@
+use super::crypto_dtls13::*;
+use crate::webrtc::dtls::cipher_suite::*;
+use crate::webrtc::dtls::content::*;
+use crate::webrtc::dtls::record_layer::record_layer_header::*;
+use crate::webrtc::dtls::record_layer::unified_header::*;
+
+use std::io::BufReader;
+
+const TRAFFIC_SECRET: [u8; 32] = [0x42; 32];
+
+fn dtls13_cipher_suites_under_test() -> [CipherSuiteId; 2] {
+    [
+        CipherSuiteId::Tls_Aes_128_Gcm_Sha256,
+        CipherSuiteId::Tls_Chacha20_Poly1305_Sha256,
+    ]
+}
+
+fn open(d: &[u8]) -> (RecordLayerHeader, Vec<u8>) {
+    let mut reader = BufReader::new(d);
+    let h = RecordLayerHeader::unmarshal(&mut reader).unwrap();
+    (h, d[RECORD_LAYER_HEADER_SIZE..].to_vec())
+}
+
+#[test]
+fn test_crypto_dtls13_unified_header_encode() {
+    for cipher_suite in dtls13_cipher_suites_under_test() {
+        let c = CryptoDtls13::new(cipher_suite, &TRAFFIC_SECRET).unwrap();
+        let r = c
+            .encrypt(3, 0x1234, ContentType::ApplicationData, b"hello")
+            .unwrap();
+
+        // Flags, 16 bit sequence number and length, low epoch bits
+        assert_eq!(
+            r[0],
+            UNIFIED_HEADER_FIXED_BITS
+                | UNIFIED_HEADER_SEQUENCE_NUMBER_16_FLAG
+                | UNIFIED_HEADER_LENGTH_FLAG
+                | 3
+        );
+        assert_eq!(unified_header_size(r[0]).unwrap(), 5);
+        let length = u16::from_be_bytes([r[3], r[4]]) as usize;
+        // content, inner content type and the tag
+        assert_eq!(length, 5 + 1 + 16);
+        assert_eq!(unified_record_size(&r).unwrap(), r.len());
+    }
+}
+
+#[test]
+fn test_crypto_dtls13_round_trip() {
+    for cipher_suite in dtls13_cipher_suites_under_test() {
+        let local = CryptoDtls13::new(cipher_suite, &TRAFFIC_SECRET).unwrap();
+        let remote = CryptoDtls13::new(cipher_suite, &TRAFFIC_SECRET).unwrap();
+
+        for (epoch, sequence_number, next_sequence_number) in [
+            (2, 0, 0),
+            (3, 7, 5),
+            // The wire carries the low 16 bits only
+            (3, 0x1_0005, 0x1_0000),
+            (4, 0x2_0001, 0x1_fff0),
+        ] {
+            let r = local
+                .encrypt(
+                    epoch,
+                    sequence_number,
+                    ContentType::ApplicationData,
+                    b"hello",
+                )
+                .unwrap();
+            let (got_sequence_number, d) = remote.decrypt(epoch, next_sequence_number, &r).unwrap();
+            assert_eq!(got_sequence_number, sequence_number, "{}", cipher_suite);
+
+            let (h, content) = open(&d);
+            assert_eq!(h.content_type, ContentType::ApplicationData);
+            assert_eq!(h.epoch, epoch);
+            assert_eq!(h.sequence_number, sequence_number);
+            assert_eq!(content, b"hello");
+        }
+    }
+}
+
+#[test]
+fn test_crypto_dtls13_decrypt_failures() {
+    for cipher_suite in dtls13_cipher_suites_under_test() {
+        let local = CryptoDtls13::new(cipher_suite, &TRAFFIC_SECRET).unwrap();
+        let r = local
+            .encrypt(3, 9, ContentType::ApplicationData, b"hello")
+            .unwrap();
+
+        // Other keys
+        let other = CryptoDtls13::new(cipher_suite, &[0x43; 32]).unwrap();
+        assert!(other.decrypt(3, 9, &r).is_err(), "{}", cipher_suite);
+
+        // Tampered header and ciphertext
+        let remote = CryptoDtls13::new(cipher_suite, &TRAFFIC_SECRET).unwrap();
+        let mut tampered = r.clone();
+        tampered[4] ^= 0x01;
+        assert!(remote.decrypt(3, 9, &tampered).is_err());
+        let mut tampered = r.clone();
+        *tampered.last_mut().unwrap() ^= 0x01;
+        assert!(remote.decrypt(3, 9, &tampered).is_err());
+
+        // Shorter than the tag
+        assert!(remote.decrypt(3, 9, &r[..10]).is_err());
+    }
+}
+
+#[test]
+fn test_reconstruct_sequence_number() {
+    let tests = [
+        // expected, bits on the wire, width, reconstructed
+        (0, 0, 8, 0),
+        (5, 5, 16, 5),
+        (0, 0xff, 8, 0xff),
+        (0x1ff, 0x00, 8, 0x200),
+        (0x100, 0xff, 8, 0xff),
+        (0x1_0000, 0xfffe, 16, 0xfffe),
+        (0x1_fff0, 0x0001, 16, 0x2_0001),
+        (0x12_3456, 0x3457, 16, 0x12_3457),
+        // Never past the largest sequence number
+        (
+            MAX_SEQUENCE_NUMBER,
+            0x0000,
+            16,
+            MAX_SEQUENCE_NUMBER - 0xffff,
+        ),
+    ];
+    for (expected, bits, width, reconstructed) in tests {
+        assert_eq!(
+            reconstruct_sequence_number(expected, bits, width),
+            reconstructed,
+            "expected {:#x} bits {:#x}",
+            expected,
+            bits
+        );
+    }
+}
+
+#[test]
+fn test_dtls13_epoch_keys() {
+    let mut local = Dtls13EpochKeys::default();
+    local.cipher_suite = Some(CipherSuiteId::Tls_Aes_128_Gcm_Sha256);
+    let mut remote = Dtls13EpochKeys::default();
+    remote.cipher_suite = Some(CipherSuiteId::Tls_Aes_128_Gcm_Sha256);
+    local.install_local(2, &TRAFFIC_SECRET).unwrap();
+    local.install_local(3, &[0x43; 32]).unwrap();
+    remote.install_remote(2, &TRAFFIC_SECRET).unwrap();
+
+    // Records of an epoch without keys are queued
+    let r = local
+        .encrypt(3, 0, ContentType::ApplicationData, b"early")
+        .unwrap();
+    assert_eq!(remote.decrypt(&r).unwrap(), None);
+
+    // The epoch is resolved from its low bits and sequence numbers are tracked
+    for sequence_number in 0..3 {
+        let r = local
+            .encrypt(2, sequence_number, ContentType::Handshake, b"hs")
+            .unwrap();
+        let (h, _) = open(&remote.decrypt(&r).unwrap().unwrap());
+        assert_eq!(h.epoch, 2);
+        assert_eq!(h.sequence_number, sequence_number);
+    }
+
+    remote.install_remote(3, &[0x43; 32]).unwrap();
+    let (h, content) = open(&remote.decrypt(&r).unwrap().unwrap());
+    assert_eq!(h.epoch, 3);
+    assert_eq!(content, b"early");
+
+    assert!(matches!(local.hash(), Ok(CipherSuiteHash::Sha256)));
+    assert!(Dtls13EpochKeys::default().hash().is_err());
+}
@@ -1,8 +1,15 @@
 pub(crate) mod crypto_cbc;
 pub(crate) mod crypto_ccm;
+pub(crate) mod crypto_chacha20;
+#[cfg(test)]
+mod crypto_chacha20_test;
+pub(crate) mod crypto_dtls13;
+#[cfg(test)]
+mod crypto_dtls13_test;
 pub(crate) mod crypto_gcm;
 pub(crate) mod padding;
 
+use crate::webrtc::dtls::content::ContentType;
 use crate::webrtc::dtls::curve::named_curve::*;
 use crate::webrtc::dtls::error::*;
 use crate::webrtc::dtls::record_layer::record_layer_header::*;
@@ -175,6 +182,9 @@ fn verify_signature(
         SignatureAlgorithm::Rsa if hash_algorithm.hash == HashAlgorithm::Sha512 => {
             &ring::signature::RSA_PKCS1_2048_8192_SHA512
         }
+        SignatureAlgorithm::RsaPssRsaeSha256 => &ring::signature::RSA_PSS_2048_8192_SHA256,
+        SignatureAlgorithm::RsaPssRsaeSha384 => &ring::signature::RSA_PSS_2048_8192_SHA384,
+        SignatureAlgorithm::RsaPssRsaeSha512 => &ring::signature::RSA_PSS_2048_8192_SHA512,
         _ => return Err(Error::ErrKeySignatureVerifyUnimplemented),
     };
 
@@ -249,6 +259,50 @@ pub(crate) fn generate_certificate_verify(
     Ok(signature)
 }
 
+// DTLS 1.3 signs the CertificateVerify content with a fixed scheme per key type,
+// RSA keys must use RSASSA-PSS instead of PKCS #1 v1.5.
+// https://tools.ietf.org/html/rfc8446#section-4.4.3
+pub(crate) fn generate_certificate_verify_dtls13(
+    content: &[u8],
+    private_key: &CryptoPrivateKey,
+) -> Result<(SignatureHashAlgorithm, Vec<u8>)> {
+    match &private_key.kind {
+        CryptoPrivateKeyKind::Rsa256(kp) => {
+            let system_random = SystemRandom::new();
+            let mut signature = vec![0; kp.public_modulus_len()];
+            kp.sign(
+                &ring::signature::RSA_PSS_SHA256,
+                &system_random,
+                content,
+                &mut signature,
+            )
+            .map_err(|e| Error::Other(e.to_string()))?;
+
+            Ok((
+                SignatureHashAlgorithm {
+                    hash: HashAlgorithm::Ed25519,
+                    signature: SignatureAlgorithm::RsaPssRsaeSha256,
+                },
+                signature,
+            ))
+        }
+        CryptoPrivateKeyKind::Ecdsa256(_) => Ok((
+            SignatureHashAlgorithm {
+                hash: HashAlgorithm::Sha256,
+                signature: SignatureAlgorithm::Ecdsa,
+            },
+            generate_certificate_verify(content, private_key)?,
+        )),
+        CryptoPrivateKeyKind::Ed25519(_) => Ok((
+            SignatureHashAlgorithm {
+                hash: HashAlgorithm::Ed25519,
+                signature: SignatureAlgorithm::Ed25519,
+            },
+            generate_certificate_verify(content, private_key)?,
+        )),
+    }
+}
+
 pub(crate) fn verify_certificate_verify(
     handshake_bodies: &[u8],
     hash_algorithm: &SignatureHashAlgorithm,
@@ -312,6 +366,10 @@ pub(crate) fn verify_server_cert(
 }
 
 pub(crate) fn generate_aead_additional_data(h: &RecordLayerHeader, payload_len: usize) -> Vec<u8> {
+    if h.content_type == ContentType::Tls12Cid {
+        return generate_aead_additional_data_connection_id(h, payload_len);
+    }
+
     let mut additional_data = vec![0u8; 13];
     // SequenceNumber MUST be set first
     // we only want uint48, clobbering an extra 2 (using uint64, rust doesn't have uint48)
@@ -324,3 +382,24 @@ pub(crate) fn generate_aead_additional_data(h: &RecordLayerHeader, payload_len:
 
     additional_data
 }
+
+// tls12_cid records replace the sequence number prefix with a placeholder and
+// bind the CID and its length into the additional data
+// https://www.rfc-editor.org/rfc/rfc9146#section-5.3
+fn generate_aead_additional_data_connection_id(
+    h: &RecordLayerHeader,
+    payload_len: usize,
+) -> Vec<u8> {
+    let mut additional_data = vec![0xffu8; 8];
+    additional_data.push(ContentType::Tls12Cid as u8);
+    additional_data.push(h.connection_id.len() as u8);
+    additional_data.push(ContentType::Tls12Cid as u8);
+    additional_data.push(h.protocol_version.major);
+    additional_data.push(h.protocol_version.minor);
+    additional_data.extend_from_slice(&h.epoch.to_be_bytes());
+    additional_data.extend_from_slice(&h.sequence_number.to_be_bytes()[2..]);
+    additional_data.extend_from_slice(&h.connection_id);
+    additional_data.extend_from_slice(&(payload_len as u16).to_be_bytes());
+
+    additional_data
+}
@@ -4,7 +4,7 @@ use crate::webrtc::dtls::error::*;
 
 // https://www.iana.org/assignments/tls-parameters/tls-parameters.xml#tls-parameters-8
 #[derive(Copy, Clone, PartialEq, Debug)]
-pub(crate) enum NamedCurve {
+pub enum NamedCurve {
     P256 = 0x0017,
     P384 = 0x0018,
     X25519 = 0x001d,
@@ -62,7 +62,17 @@ fn elliptic_curve_keypair(curve: NamedCurve) -> Result<NamedCurveKeypair> {
     })
 }
 
+// Curves offered by a client in order of preference
+pub(crate) fn default_elliptic_curves() -> Vec<NamedCurve> {
+    vec![NamedCurve::P256, NamedCurve::X25519, NamedCurve::P384]
+}
+
 impl NamedCurve {
+    // is_supported reports whether a key pair can be generated for the curve
+    pub(crate) fn is_supported(&self) -> bool {
+        matches!(*self, NamedCurve::X25519 | NamedCurve::P256)
+    }
+
     pub(crate) fn generate_keypair(&self) -> Result<NamedCurveKeypair> {
         match *self {
             //TODO: add P384
@@ -1,5 +1,6 @@
 use thiserror::Error;
 
+use crate::webrtc::dtls::alert::Alert;
 use crate::webrtc::util::KeyingMaterialExporterError;
 use rcgen::RcgenError;
 use std::io;
@@ -19,6 +20,8 @@ pub(crate) enum Error {
     ErrBufferTooSmall,
     #[error("handshake is in progress")]
     ErrHandshakeInProgress,
+    #[error("handshake timed out")]
+    ErrHandshakeTimeout,
     #[error("invalid content type")]
     ErrInvalidContentType,
     #[error("packet length and declared length do not match")]
@@ -39,6 +42,10 @@ pub(crate) enum Error {
     ErrCookieMismatch,
     #[error("cookie must not be longer then 255 bytes")]
     ErrCookieTooLong,
+    #[error("session id must not be longer then 32 bytes")]
+    ErrSessionIdTooLong,
+    #[error("resumed session does not match the cached session")]
+    ErrSessionMismatch,
     #[error("PSK Identity Hint provided but PSK is nil")]
     ErrIdentityNoPsk,
     #[error("no certificate provided")]
@@ -73,6 +80,8 @@ pub(crate) enum Error {
     ErrPskAndCertificate,
     #[error("PSK and PSK Identity Hint must both be set for client")]
     ErrPskAndIdentityMustBeSetForClient,
+    #[error("PSK identity of the peer does not match the local identity")]
+    ErrPskIdentityMismatch,
     #[error("SRTP support was requested but server did not respond with use_srtp extension")]
     ErrRequestedButNoSrtpExtension,
     #[error("Certificate is mandatory for server")]
@@ -103,6 +112,20 @@ pub(crate) enum Error {
     ErrEmptyFragment,
     #[error("Alert is Fatal or Close Notify")]
     ErrAlertFatalOrClose,
+    #[error("alert received: {0}")]
+    ErrAlertReceived(Alert),
+    #[error("invalid KeyUpdate request")]
+    ErrInvalidKeyUpdate,
+    #[error("server selected DTLS 1.2 but the ServerHello random carries the DTLS 1.3 downgrade sentinel")]
+    ErrDowngradeDetected,
+    #[error("server did not send a key_share extension")]
+    ErrKeyShareMissing,
+    #[error("server sent a second HelloRetryRequest")]
+    ErrHelloRetryRequestRepeated,
+    #[error("KeyUpdate is only supported on DTLS 1.3 connections")]
+    ErrKeyUpdateNotSupported,
+    #[error("server asked for a connection id, which is not supported with DTLS 1.3")]
+    ErrConnectionIdNotSupported,
 
     #[error("{0}")]
     Io(#[source] IoError),
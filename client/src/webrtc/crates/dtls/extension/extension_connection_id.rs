@@ -0,0 +1,42 @@
+use super::*;
+
+// Negotiates the CID placed in records sent to the peer. Each side announces the
+// CID it wants to receive, an empty one means the peer should send without CID.
+//
+// struct {
+//     opaque cid<0..2^8-1>;
+// } ConnectionId;
+//
+// https://www.rfc-editor.org/rfc/rfc9146#section-3
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ExtensionConnectionId {
+    pub(crate) connection_id: Vec<u8>,
+}
+
+impl ExtensionConnectionId {
+    pub(crate) fn extension_value(&self) -> ExtensionValue {
+        ExtensionValue::ConnectionId
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        2 + 1 + self.connection_id.len()
+    }
+
+    pub(crate) fn marshal<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_u16::<BigEndian>(1 + self.connection_id.len() as u16)?;
+        writer.write_u8(self.connection_id.len() as u8)?;
+        writer.write_all(&self.connection_id)?;
+
+        Ok(writer.flush()?)
+    }
+
+    pub(crate) fn unmarshal<R: Read>(reader: &mut R) -> Result<Self> {
+        let _ = reader.read_u16::<BigEndian>()?;
+
+        let connection_id_len = reader.read_u8()? as usize;
+        let mut connection_id = vec![0u8; connection_id_len];
+        reader.read_exact(&mut connection_id)?;
+
+        Ok(ExtensionConnectionId { connection_id })
+    }
+}
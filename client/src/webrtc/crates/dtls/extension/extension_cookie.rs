@@ -0,0 +1,42 @@
+use super::*;
+
+// Sent by the server in a HelloRetryRequest and echoed back in the second
+// ClientHello, takes the role of the DTLS 1.2 HelloVerifyRequest cookie.
+//
+// struct {
+//     opaque cookie<1..2^16-1>;
+// } Cookie;
+//
+// https://tools.ietf.org/html/rfc8446#section-4.2.2
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ExtensionCookie {
+    pub(crate) cookie: Vec<u8>,
+}
+
+impl ExtensionCookie {
+    pub(crate) fn extension_value(&self) -> ExtensionValue {
+        ExtensionValue::Cookie
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        2 + 2 + self.cookie.len()
+    }
+
+    pub(crate) fn marshal<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_u16::<BigEndian>(2 + self.cookie.len() as u16)?;
+        writer.write_u16::<BigEndian>(self.cookie.len() as u16)?;
+        writer.write_all(&self.cookie)?;
+
+        Ok(writer.flush()?)
+    }
+
+    pub(crate) fn unmarshal<R: Read>(reader: &mut R) -> Result<Self> {
+        let _ = reader.read_u16::<BigEndian>()?;
+
+        let cookie_len = reader.read_u16::<BigEndian>()? as usize;
+        let mut cookie = vec![0u8; cookie_len];
+        reader.read_exact(&mut cookie)?;
+
+        Ok(ExtensionCookie { cookie })
+    }
+}
@@ -0,0 +1,126 @@
+use super::*;
+use crate::webrtc::dtls::curve::named_curve::*;
+
+// struct {
+//     NamedGroup group;
+//     opaque key_exchange<1..2^16-1>;
+// } KeyShareEntry;
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct KeyShareEntry {
+    pub(crate) group: NamedCurve,
+    pub(crate) key_exchange: Vec<u8>,
+}
+
+impl KeyShareEntry {
+    fn size(&self) -> usize {
+        2 + 2 + self.key_exchange.len()
+    }
+
+    fn marshal<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_u16::<BigEndian>(self.group as u16)?;
+        writer.write_u16::<BigEndian>(self.key_exchange.len() as u16)?;
+        writer.write_all(&self.key_exchange)?;
+        Ok(())
+    }
+
+    fn unmarshal<R: Read>(reader: &mut R) -> Result<Self> {
+        let group = reader.read_u16::<BigEndian>()?.into();
+        let key_exchange_len = reader.read_u16::<BigEndian>()? as usize;
+        let mut key_exchange = vec![0u8; key_exchange_len];
+        reader.read_exact(&mut key_exchange)?;
+
+        Ok(KeyShareEntry {
+            group,
+            key_exchange,
+        })
+    }
+}
+
+// The content depends on the message carrying the extension, the client offers
+// a list of shares, the server answers with one share or, in a
+// HelloRetryRequest, only names the group it wants a share for.
+//
+// struct {
+//     KeyShareEntry client_shares<0..2^16-1>;
+// } KeyShareClientHello;
+//
+// struct {
+//     NamedGroup selected_group;
+// } KeyShareHelloRetryRequest;
+//
+// struct {
+//     KeyShareEntry server_share;
+// } KeyShareServerHello;
+//
+// https://tools.ietf.org/html/rfc8446#section-4.2.8
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum ExtensionKeyShare {
+    ClientHello(Vec<KeyShareEntry>),
+    ServerHello(KeyShareEntry),
+    HelloRetryRequest(NamedCurve),
+}
+
+impl ExtensionKeyShare {
+    pub(crate) fn extension_value(&self) -> ExtensionValue {
+        ExtensionValue::KeyShare
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        2 + match self {
+            ExtensionKeyShare::ClientHello(entries) => {
+                2 + entries.iter().map(|e| e.size()).sum::<usize>()
+            }
+            ExtensionKeyShare::ServerHello(entry) => entry.size(),
+            ExtensionKeyShare::HelloRetryRequest(_) => 2,
+        }
+    }
+
+    pub(crate) fn marshal<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_u16::<BigEndian>((self.size() - 2) as u16)?;
+        match self {
+            ExtensionKeyShare::ClientHello(entries) => {
+                writer.write_u16::<BigEndian>(
+                    entries.iter().map(|e| e.size()).sum::<usize>() as u16
+                )?;
+                for entry in entries {
+                    entry.marshal(writer)?;
+                }
+            }
+            ExtensionKeyShare::ServerHello(entry) => entry.marshal(writer)?,
+            ExtensionKeyShare::HelloRetryRequest(group) => {
+                writer.write_u16::<BigEndian>(*group as u16)?
+            }
+        }
+
+        Ok(writer.flush()?)
+    }
+
+    pub(crate) fn unmarshal<R: Read>(reader: &mut R) -> Result<Self> {
+        let length = reader.read_u16::<BigEndian>()? as usize;
+        let mut data = vec![0u8; length];
+        reader.read_exact(&mut data)?;
+
+        if length == 2 {
+            let group = u16::from_be_bytes([data[0], data[1]]).into();
+            return Ok(ExtensionKeyShare::HelloRetryRequest(group));
+        }
+        if length < 4 {
+            return Err(Error::ErrBufferTooSmall);
+        }
+
+        let mut reader = std::io::Cursor::new(&data);
+        let first = u16::from_be_bytes([data[0], data[1]]) as usize;
+        if first == length - 2 {
+            reader.set_position(2);
+            let mut entries = vec![];
+            while (reader.position() as usize) < length {
+                entries.push(KeyShareEntry::unmarshal(&mut reader)?);
+            }
+            Ok(ExtensionKeyShare::ClientHello(entries))
+        } else {
+            Ok(ExtensionKeyShare::ServerHello(KeyShareEntry::unmarshal(
+                &mut reader,
+            )?))
+        }
+    }
+}
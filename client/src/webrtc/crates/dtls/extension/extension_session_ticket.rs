@@ -0,0 +1,36 @@
+use super::*;
+
+// Carries an RFC 5077 session ticket. An empty ticket in the ClientHello asks
+// for a new one, an empty extension in the ServerHello announces that the server
+// will send a NewSessionTicket message.
+//
+// https://tools.ietf.org/html/rfc5077#section-3.2
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ExtensionSessionTicket {
+    pub(crate) ticket: Vec<u8>,
+}
+
+impl ExtensionSessionTicket {
+    pub(crate) fn extension_value(&self) -> ExtensionValue {
+        ExtensionValue::SessionTicket
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        2 + self.ticket.len()
+    }
+
+    pub(crate) fn marshal<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_u16::<BigEndian>(self.ticket.len() as u16)?;
+        writer.write_all(&self.ticket)?;
+
+        Ok(writer.flush()?)
+    }
+
+    pub(crate) fn unmarshal<R: Read>(reader: &mut R) -> Result<Self> {
+        let ticket_len = reader.read_u16::<BigEndian>()? as usize;
+        let mut ticket = vec![0u8; ticket_len];
+        reader.read_exact(&mut ticket)?;
+
+        Ok(ExtensionSessionTicket { ticket })
+    }
+}
@@ -0,0 +1,77 @@
+use super::*;
+use crate::webrtc::dtls::record_layer::record_layer_header::*;
+
+// The ClientHello lists every version it supports, the ServerHello (and
+// HelloRetryRequest) carries the single selected version.
+//
+// struct {
+//     select (Handshake.msg_type) {
+//         case client_hello:
+//              ProtocolVersion versions<2..254>;
+//
+//         case server_hello: /* and HelloRetryRequest */
+//              ProtocolVersion selected_version;
+//     };
+// } SupportedVersions;
+//
+// https://tools.ietf.org/html/rfc8446#section-4.2.1
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum ExtensionSupportedVersions {
+    ClientHello(Vec<ProtocolVersion>),
+    ServerHello(ProtocolVersion),
+}
+
+impl ExtensionSupportedVersions {
+    pub(crate) fn extension_value(&self) -> ExtensionValue {
+        ExtensionValue::SupportedVersions
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        match self {
+            ExtensionSupportedVersions::ClientHello(versions) => 2 + 1 + versions.len() * 2,
+            ExtensionSupportedVersions::ServerHello(_) => 2 + 2,
+        }
+    }
+
+    pub(crate) fn marshal<W: Write>(&self, writer: &mut W) -> Result<()> {
+        match self {
+            ExtensionSupportedVersions::ClientHello(versions) => {
+                writer.write_u16::<BigEndian>(1 + 2 * versions.len() as u16)?;
+                writer.write_u8(2 * versions.len() as u8)?;
+                for v in versions {
+                    writer.write_u8(v.major)?;
+                    writer.write_u8(v.minor)?;
+                }
+            }
+            ExtensionSupportedVersions::ServerHello(v) => {
+                writer.write_u16::<BigEndian>(2)?;
+                writer.write_u8(v.major)?;
+                writer.write_u8(v.minor)?;
+            }
+        }
+
+        Ok(writer.flush()?)
+    }
+
+    pub(crate) fn unmarshal<R: Read>(reader: &mut R) -> Result<Self> {
+        let length = reader.read_u16::<BigEndian>()? as usize;
+        if length == 2 {
+            let major = reader.read_u8()?;
+            let minor = reader.read_u8()?;
+            return Ok(ExtensionSupportedVersions::ServerHello(ProtocolVersion {
+                major,
+                minor,
+            }));
+        }
+
+        let version_count = reader.read_u8()? as usize / 2;
+        let mut versions = vec![];
+        for _ in 0..version_count {
+            let major = reader.read_u8()?;
+            let minor = reader.read_u8()?;
+            versions.push(ProtocolVersion { major, minor });
+        }
+
+        Ok(ExtensionSupportedVersions::ClientHello(versions))
+    }
+}
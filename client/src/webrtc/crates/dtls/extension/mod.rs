@@ -1,15 +1,25 @@
+pub(crate) mod extension_connection_id;
+pub(crate) mod extension_cookie;
+pub(crate) mod extension_key_share;
 pub(crate) mod extension_server_name;
+pub(crate) mod extension_session_ticket;
 pub(crate) mod extension_supported_elliptic_curves;
 pub(crate) mod extension_supported_point_formats;
 pub(crate) mod extension_supported_signature_algorithms;
+pub(crate) mod extension_supported_versions;
 pub(crate) mod extension_use_extended_master_secret;
 pub(crate) mod extension_use_srtp;
 pub(crate) mod renegotiation_info;
 
+use extension_connection_id::*;
+use extension_cookie::*;
+use extension_key_share::*;
 use extension_server_name::*;
+use extension_session_ticket::*;
 use extension_supported_elliptic_curves::*;
 use extension_supported_point_formats::*;
 use extension_supported_signature_algorithms::*;
+use extension_supported_versions::*;
 use extension_use_extended_master_secret::*;
 use extension_use_srtp::*;
 
@@ -28,6 +38,11 @@ pub(crate) enum ExtensionValue {
     SupportedSignatureAlgorithms = 13,
     UseSrtp = 14,
     UseExtendedMasterSecret = 23,
+    SessionTicket = 35,
+    SupportedVersions = 43,
+    Cookie = 44,
+    KeyShare = 51,
+    ConnectionId = 54,
     RenegotiationInfo = 65281,
     Unsupported,
 }
@@ -41,6 +56,11 @@ impl From<u16> for ExtensionValue {
             13 => ExtensionValue::SupportedSignatureAlgorithms,
             14 => ExtensionValue::UseSrtp,
             23 => ExtensionValue::UseExtendedMasterSecret,
+            35 => ExtensionValue::SessionTicket,
+            43 => ExtensionValue::SupportedVersions,
+            44 => ExtensionValue::Cookie,
+            51 => ExtensionValue::KeyShare,
+            54 => ExtensionValue::ConnectionId,
             65281 => ExtensionValue::RenegotiationInfo,
             _ => ExtensionValue::Unsupported,
         }
@@ -55,6 +75,11 @@ pub(crate) enum Extension {
     SupportedSignatureAlgorithms(ExtensionSupportedSignatureAlgorithms),
     UseSrtp(ExtensionUseSrtp),
     UseExtendedMasterSecret(ExtensionUseExtendedMasterSecret),
+    SessionTicket(ExtensionSessionTicket),
+    SupportedVersions(ExtensionSupportedVersions),
+    Cookie(ExtensionCookie),
+    KeyShare(ExtensionKeyShare),
+    ConnectionId(ExtensionConnectionId),
     RenegotiationInfo(ExtensionRenegotiationInfo),
 }
 
@@ -67,6 +92,11 @@ impl Extension {
             Extension::SupportedSignatureAlgorithms(ext) => ext.extension_value(),
             Extension::UseSrtp(ext) => ext.extension_value(),
             Extension::UseExtendedMasterSecret(ext) => ext.extension_value(),
+            Extension::SessionTicket(ext) => ext.extension_value(),
+            Extension::SupportedVersions(ext) => ext.extension_value(),
+            Extension::Cookie(ext) => ext.extension_value(),
+            Extension::KeyShare(ext) => ext.extension_value(),
+            Extension::ConnectionId(ext) => ext.extension_value(),
             Extension::RenegotiationInfo(ext) => ext.extension_value(),
         }
     }
@@ -81,6 +111,11 @@ impl Extension {
             Extension::SupportedSignatureAlgorithms(ext) => ext.size(),
             Extension::UseSrtp(ext) => ext.size(),
             Extension::UseExtendedMasterSecret(ext) => ext.size(),
+            Extension::SessionTicket(ext) => ext.size(),
+            Extension::SupportedVersions(ext) => ext.size(),
+            Extension::Cookie(ext) => ext.size(),
+            Extension::KeyShare(ext) => ext.size(),
+            Extension::ConnectionId(ext) => ext.size(),
             Extension::RenegotiationInfo(ext) => ext.size(),
         };
 
@@ -96,6 +131,11 @@ impl Extension {
             Extension::SupportedSignatureAlgorithms(ext) => ext.marshal(writer),
             Extension::UseSrtp(ext) => ext.marshal(writer),
             Extension::UseExtendedMasterSecret(ext) => ext.marshal(writer),
+            Extension::SessionTicket(ext) => ext.marshal(writer),
+            Extension::SupportedVersions(ext) => ext.marshal(writer),
+            Extension::Cookie(ext) => ext.marshal(writer),
+            Extension::KeyShare(ext) => ext.marshal(writer),
+            Extension::ConnectionId(ext) => ext.marshal(writer),
             Extension::RenegotiationInfo(ext) => ext.marshal(writer),
         }
     }
@@ -121,6 +161,19 @@ impl Extension {
             ExtensionValue::UseExtendedMasterSecret => Ok(Extension::UseExtendedMasterSecret(
                 ExtensionUseExtendedMasterSecret::unmarshal(reader)?,
             )),
+            ExtensionValue::SessionTicket => Ok(Extension::SessionTicket(
+                ExtensionSessionTicket::unmarshal(reader)?,
+            )),
+            ExtensionValue::SupportedVersions => Ok(Extension::SupportedVersions(
+                ExtensionSupportedVersions::unmarshal(reader)?,
+            )),
+            ExtensionValue::Cookie => Ok(Extension::Cookie(ExtensionCookie::unmarshal(reader)?)),
+            ExtensionValue::KeyShare => {
+                Ok(Extension::KeyShare(ExtensionKeyShare::unmarshal(reader)?))
+            }
+            ExtensionValue::ConnectionId => Ok(Extension::ConnectionId(
+                ExtensionConnectionId::unmarshal(reader)?,
+            )),
             ExtensionValue::RenegotiationInfo => Ok(Extension::RenegotiationInfo(
                 ExtensionRenegotiationInfo::unmarshal(reader)?,
             )),
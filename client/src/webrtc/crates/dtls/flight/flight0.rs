@@ -110,7 +110,23 @@ impl Flight for Flight0 {
                                 Some(Error::ErrNoSupportedEllipticCurves),
                             ));
                         }
-                        state.named_curve = e.elliptic_curves[0];
+                        // Pick the client's most preferred curve we also allow
+                        state.named_curve = match e
+                            .elliptic_curves
+                            .iter()
+                            .find(|curve| cfg.local_elliptic_curves.contains(curve))
+                        {
+                            Some(curve) => *curve,
+                            None => {
+                                return Err((
+                                    Some(Alert {
+                                        alert_level: AlertLevel::Fatal,
+                                        alert_description: AlertDescription::InsufficientSecurity,
+                                    }),
+                                    Some(Error::ErrNoSupportedEllipticCurves),
+                                ))
+                            }
+                        };
                     }
                     Extension::UseSrtp(e) => {
                         if let Ok(profile) = find_matching_srtp_profile(
@@ -136,6 +152,10 @@ impl Flight for Flight0 {
                     Extension::ServerName(e) => {
                         state.server_name = e.server_name.clone(); // remote server name
                     }
+                    Extension::ConnectionId(e) if state.local_connection_id.is_some() => {
+                        let mut remote_connection_id = state.remote_connection_id.lock().await;
+                        *remote_connection_id = Some(e.connection_id.clone());
+                    }
                     _ => {}
                 }
             }
@@ -183,7 +203,7 @@ impl Flight for Flight0 {
         &self,
         state: &mut State,
         _cache: &HandshakeCache,
-        _cfg: &HandshakeConfig,
+        cfg: &HandshakeConfig,
     ) -> Result<Vec<Packet>, (Option<Alert>, Option<Error>)> {
         // Initialize
         state.cookie = vec![0; COOKIE_LENGTH];
@@ -194,7 +214,12 @@ impl Flight for Flight0 {
         state.local_epoch.store(zero_epoch, Ordering::SeqCst);
         state.remote_epoch.store(zero_epoch, Ordering::SeqCst);
 
-        state.named_curve = DEFAULT_NAMED_CURVE;
+        // Used when the client sends no supported_groups extension
+        state.named_curve = if cfg.local_elliptic_curves.contains(&DEFAULT_NAMED_CURVE) {
+            DEFAULT_NAMED_CURVE
+        } else {
+            cfg.local_elliptic_curves[0]
+        };
         state.local_random.populate();
 
         Ok(vec![])
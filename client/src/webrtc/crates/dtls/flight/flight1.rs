@@ -1,11 +1,13 @@
 use super::flight3::*;
+use super::flight3_dtls13::*;
+use super::flight5b::*;
 use super::*;
 use crate::webrtc::dtls::compression_methods::*;
 use crate::webrtc::dtls::config::*;
 use crate::webrtc::dtls::conn::*;
 use crate::webrtc::dtls::content::*;
-use crate::webrtc::dtls::curve::named_curve::*;
 use crate::webrtc::dtls::error::Error;
+use crate::webrtc::dtls::extension::extension_connection_id::*;
 use crate::webrtc::dtls::extension::extension_server_name::*;
 use crate::webrtc::dtls::extension::extension_supported_elliptic_curves::*;
 use crate::webrtc::dtls::extension::extension_supported_point_formats::*;
@@ -41,6 +43,13 @@ impl Flight for Flight1 {
         cache: &HandshakeCache,
         cfg: &HandshakeConfig,
     ) -> Result<Box<dyn Flight + Send + Sync>, (Option<Alert>, Option<Error>)> {
+        if state.protocol_version == PROTOCOL_VERSION1_3 {
+            // The DTLS 1.3 ServerHello was handled while the rest of the
+            // server flight was still on its way.
+            let flight3 = Flight3 {};
+            return flight3.parse(tx, state, cache, cfg).await;
+        }
+
         // HelloVerifyRequest can be skipped by the server,
         // so allow ServerHello during flight1 also
         let (seq, msgs) = match cache
@@ -128,6 +137,15 @@ impl Flight for Flight1 {
         state.named_curve = DEFAULT_NAMED_CURVE;
         state.cookie = vec![];
         state.local_random.populate();
+        offer_session(state, cfg);
+
+        state.protocol_version = ProtocolVersion::default();
+        state.hello_retry_cookie = vec![];
+        state.hello_retry_transcript = vec![];
+        state.server_handshake_traffic_secret = vec![];
+        if is_dtls13_offered(cfg) {
+            generate_keypair_dtls13(state)?;
+        }
 
         let mut extensions = vec![
             Extension::SupportedSignatureAlgorithms(ExtensionSupportedSignatureAlgorithms {
@@ -141,7 +159,7 @@ impl Flight for Flight1 {
         if cfg.local_psk_callback.is_none() {
             extensions.extend_from_slice(&[
                 Extension::SupportedEllipticCurves(ExtensionSupportedEllipticCurves {
-                    elliptic_curves: vec![NamedCurve::P256, NamedCurve::X25519, NamedCurve::P384],
+                    elliptic_curves: cfg.local_elliptic_curves.clone(),
                 }),
                 Extension::SupportedPointFormats(ExtensionSupportedPointFormats {
                     point_formats: vec![ELLIPTIC_CURVE_POINT_FORMAT_UNCOMPRESSED],
@@ -149,6 +167,13 @@ impl Flight for Flight1 {
             ]);
         }
 
+        let cipher_suites = if is_dtls13_offered(cfg) {
+            extensions.extend(client_hello_extensions_dtls13(state));
+            client_hello_cipher_suites_dtls13(cfg)
+        } else {
+            cfg.local_cipher_suites.clone()
+        };
+
         if !cfg.local_srtp_protection_profiles.is_empty() {
             extensions.push(Extension::UseSrtp(ExtensionUseSrtp {
                 protection_profiles: cfg.local_srtp_protection_profiles.clone(),
@@ -169,6 +194,14 @@ impl Flight for Flight1 {
             }));
         }
 
+        if let Some(connection_id) = &state.local_connection_id {
+            extensions.push(Extension::ConnectionId(ExtensionConnectionId {
+                connection_id: connection_id.clone(),
+            }));
+        }
+
+        extensions.extend(client_hello_extensions_session(state, cfg));
+
         Ok(vec![Packet {
             record: RecordLayer::new(
                 PROTOCOL_VERSION1_2,
@@ -177,9 +210,10 @@ impl Flight for Flight1 {
                     HandshakeMessageClientHello {
                         version: PROTOCOL_VERSION1_2,
                         random: state.local_random.clone(),
+                        session_id: state.session_id.clone(),
                         cookie: state.cookie.clone(),
 
-                        cipher_suites: cfg.local_cipher_suites.clone(),
+                        cipher_suites,
                         compression_methods: default_compression_methods(),
                         extensions,
                     },
@@ -1,10 +1,12 @@
+use super::flight3_dtls13::*;
 use super::flight5::*;
+use super::flight5b::*;
 use super::*;
 use crate::webrtc::dtls::compression_methods::*;
 use crate::webrtc::dtls::config::*;
 use crate::webrtc::dtls::content::*;
-use crate::webrtc::dtls::curve::named_curve::*;
 use crate::webrtc::dtls::error::Error;
+use crate::webrtc::dtls::extension::extension_connection_id::*;
 use crate::webrtc::dtls::extension::extension_server_name::*;
 use crate::webrtc::dtls::extension::extension_supported_elliptic_curves::*;
 use crate::webrtc::dtls::extension::extension_supported_point_formats::*;
@@ -13,6 +15,7 @@ use crate::webrtc::dtls::extension::extension_use_extended_master_secret::*;
 use crate::webrtc::dtls::extension::extension_use_srtp::*;
 use crate::webrtc::dtls::extension::*;
 use crate::webrtc::dtls::handshake::handshake_message_client_hello::*;
+use crate::webrtc::dtls::handshake::handshake_message_server_hello::*;
 use crate::webrtc::dtls::handshake::handshake_message_server_key_exchange::*;
 use crate::webrtc::dtls::handshake::*;
 use crate::webrtc::dtls::record_layer::record_layer_header::*;
@@ -40,11 +43,15 @@ impl fmt::Display for Flight3 {
 impl Flight for Flight3 {
     async fn parse(
         &self,
-        _tx: &mut mpsc::Sender<mpsc::Sender<()>>,
+        tx: &mut mpsc::Sender<mpsc::Sender<()>>,
         state: &mut State,
         cache: &HandshakeCache,
         cfg: &HandshakeConfig,
     ) -> Result<Box<dyn Flight + Send + Sync>, (Option<Alert>, Option<Error>)> {
+        if state.protocol_version == PROTOCOL_VERSION1_3 {
+            return parse_server_flight_dtls13(state, cache, cfg).await;
+        }
+
         // Clients may receive multiple HelloVerifyRequest messages with different cookies.
         // Clients SHOULD handle this by sending a new ClientHello with a cookie in response
         // to the new HelloVerifyRequest. RFC 6347 Section 4.2.1
@@ -94,6 +101,60 @@ impl Flight for Flight3 {
             }
         }
 
+        if is_dtls13_offered(cfg) {
+            // The ServerHello decides between DTLS 1.3, a HelloRetryRequest and DTLS 1.2
+            if let Ok((seq, msgs)) = cache
+                .full_pull_map(
+                    state.handshake_recv_sequence,
+                    &[HandshakeCachePullRule {
+                        typ: HandshakeType::ServerHello,
+                        epoch: cfg.initial_epoch,
+                        is_client: false,
+                        optional: false,
+                    }],
+                )
+                .await
+            {
+                if let Some(HandshakeMessage::ServerHello(h)) =
+                    msgs.get(&HandshakeType::ServerHello)
+                {
+                    if is_hello_retry_request(h) {
+                        return handle_hello_retry_request(state, cache, cfg, h, seq).await;
+                    }
+                    if is_server_hello_dtls13(h) {
+                        handle_server_hello_dtls13(tx, state, cache, cfg, h, seq).await?;
+                        return parse_server_flight_dtls13(state, cache, cfg).await;
+                    }
+                    check_downgrade_dtls13(cfg, h)?;
+                }
+            }
+        }
+
+        if state.offered_session.is_some() {
+            // The server echoes the offered session id when it resumes the session
+            // https://tools.ietf.org/html/rfc5246#section-7.4.1.3
+            if let Ok((_, msgs)) = cache
+                .full_pull_map(
+                    state.handshake_recv_sequence,
+                    &[HandshakeCachePullRule {
+                        typ: HandshakeType::ServerHello,
+                        epoch: cfg.initial_epoch,
+                        is_client: false,
+                        optional: false,
+                    }],
+                )
+                .await
+            {
+                if let Some(HandshakeMessage::ServerHello(h)) =
+                    msgs.get(&HandshakeType::ServerHello)
+                {
+                    if is_session_resumed(state, h) {
+                        return parse_server_flight_resumption(tx, state, cache, cfg, h).await;
+                    }
+                }
+            }
+        }
+
         let result = if cfg.local_psk_callback.is_some() {
             cache
                 .full_pull_map(
@@ -181,112 +242,7 @@ impl Flight for Flight3 {
                 }
             };
 
-            if h.version != PROTOCOL_VERSION1_2 {
-                return Err((
-                    Some(Alert {
-                        alert_level: AlertLevel::Fatal,
-                        alert_description: AlertDescription::ProtocolVersion,
-                    }),
-                    Some(Error::ErrUnsupportedProtocolVersion),
-                ));
-            }
-
-            for extension in &h.extensions {
-                match extension {
-                    Extension::UseSrtp(e) => {
-                        let profile = match find_matching_srtp_profile(
-                            &e.protection_profiles,
-                            &cfg.local_srtp_protection_profiles,
-                        ) {
-                            Ok(profile) => profile,
-                            Err(_) => {
-                                return Err((
-                                    Some(Alert {
-                                        alert_level: AlertLevel::Fatal,
-                                        alert_description: AlertDescription::IllegalParameter,
-                                    }),
-                                    Some(Error::ErrClientNoMatchingSrtpProfile),
-                                ))
-                            }
-                        };
-                        state.srtp_protection_profile = profile;
-                    }
-                    Extension::UseExtendedMasterSecret(_) => {
-                        if cfg.extended_master_secret != ExtendedMasterSecretType::Disable {
-                            state.extended_master_secret = true;
-                        }
-                    }
-                    _ => {}
-                };
-            }
-
-            if cfg.extended_master_secret == ExtendedMasterSecretType::Require
-                && !state.extended_master_secret
-            {
-                return Err((
-                    Some(Alert {
-                        alert_level: AlertLevel::Fatal,
-                        alert_description: AlertDescription::InsufficientSecurity,
-                    }),
-                    Some(Error::ErrClientRequiredButNoServerEms),
-                ));
-            }
-            if !cfg.local_srtp_protection_profiles.is_empty()
-                && state.srtp_protection_profile == SrtpProtectionProfile::Unsupported
-            {
-                return Err((
-                    Some(Alert {
-                        alert_level: AlertLevel::Fatal,
-                        alert_description: AlertDescription::InsufficientSecurity,
-                    }),
-                    Some(Error::ErrRequestedButNoSrtpExtension),
-                ));
-            }
-            if find_matching_cipher_suite(&[h.cipher_suite], &cfg.local_cipher_suites).is_err() {
-                debug!(
-                    "[handshake:{}] use cipher suite: {}",
-                    srv_cli_str(state.is_client),
-                    h.cipher_suite
-                );
-
-                return Err((
-                    Some(Alert {
-                        alert_level: AlertLevel::Fatal,
-                        alert_description: AlertDescription::InsufficientSecurity,
-                    }),
-                    Some(Error::ErrCipherSuiteNoIntersection),
-                ));
-            }
-
-            let cipher_suite = match cipher_suite_for_id(h.cipher_suite) {
-                Ok(cipher_suite) => cipher_suite,
-                Err(_) => {
-                    debug!(
-                        "[handshake:{}] use cipher suite: {}",
-                        srv_cli_str(state.is_client),
-                        h.cipher_suite
-                    );
-
-                    return Err((
-                        Some(Alert {
-                            alert_level: AlertLevel::Fatal,
-                            alert_description: AlertDescription::InsufficientSecurity,
-                        }),
-                        Some(Error::ErrInvalidCipherSuite),
-                    ));
-                }
-            };
-
-            trace!(
-                "[handshake:{}] use cipher suite: {}",
-                srv_cli_str(state.is_client),
-                cipher_suite.to_string()
-            );
-            {
-                let mut cs = state.cipher_suite.lock().await;
-                *cs = Some(cipher_suite);
-            }
-            state.remote_random = h.random.clone();
+            handle_server_hello(state, cfg, h).await?;
         }
 
         if let Some(message) = msgs.get(&HandshakeType::Certificate) {
@@ -361,7 +317,7 @@ impl Flight for Flight3 {
         if cfg.local_psk_callback.is_none() {
             extensions.extend_from_slice(&[
                 Extension::SupportedEllipticCurves(ExtensionSupportedEllipticCurves {
-                    elliptic_curves: vec![NamedCurve::P256, NamedCurve::X25519, NamedCurve::P384],
+                    elliptic_curves: cfg.local_elliptic_curves.clone(),
                 }),
                 Extension::SupportedPointFormats(ExtensionSupportedPointFormats {
                     point_formats: vec![ELLIPTIC_CURVE_POINT_FORMAT_UNCOMPRESSED],
@@ -369,6 +325,13 @@ impl Flight for Flight3 {
             ]);
         }
 
+        let cipher_suites = if is_dtls13_offered(cfg) {
+            extensions.extend(client_hello_extensions_dtls13(state));
+            client_hello_cipher_suites_dtls13(cfg)
+        } else {
+            cfg.local_cipher_suites.clone()
+        };
+
         if !cfg.local_srtp_protection_profiles.is_empty() {
             extensions.push(Extension::UseSrtp(ExtensionUseSrtp {
                 protection_profiles: cfg.local_srtp_protection_profiles.clone(),
@@ -389,6 +352,14 @@ impl Flight for Flight3 {
             }));
         }
 
+        if let Some(connection_id) = &state.local_connection_id {
+            extensions.push(Extension::ConnectionId(ExtensionConnectionId {
+                connection_id: connection_id.clone(),
+            }));
+        }
+
+        extensions.extend(client_hello_extensions_session(state, cfg));
+
         Ok(vec![Packet {
             record: RecordLayer::new(
                 PROTOCOL_VERSION1_2,
@@ -397,9 +368,10 @@ impl Flight for Flight3 {
                     HandshakeMessageClientHello {
                         version: PROTOCOL_VERSION1_2,
                         random: state.local_random.clone(),
+                        session_id: state.session_id.clone(),
                         cookie: state.cookie.clone(),
 
-                        cipher_suites: cfg.local_cipher_suites.clone(),
+                        cipher_suites,
                         compression_methods: default_compression_methods(),
                         extensions,
                     },
@@ -410,6 +382,130 @@ impl Flight for Flight3 {
     }
 }
 
+// handle_server_hello applies a DTLS 1.2 ServerHello, it is shared by the full
+// and the abbreviated handshake
+pub(crate) async fn handle_server_hello(
+    state: &mut State,
+    cfg: &HandshakeConfig,
+    h: &HandshakeMessageServerHello,
+) -> Result<(), (Option<Alert>, Option<Error>)> {
+    if h.version != PROTOCOL_VERSION1_2 {
+        return Err((
+            Some(Alert {
+                alert_level: AlertLevel::Fatal,
+                alert_description: AlertDescription::ProtocolVersion,
+            }),
+            Some(Error::ErrUnsupportedProtocolVersion),
+        ));
+    }
+
+    for extension in &h.extensions {
+        match extension {
+            Extension::UseSrtp(e) => {
+                let profile = match find_matching_srtp_profile(
+                    &e.protection_profiles,
+                    &cfg.local_srtp_protection_profiles,
+                ) {
+                    Ok(profile) => profile,
+                    Err(_) => {
+                        return Err((
+                            Some(Alert {
+                                alert_level: AlertLevel::Fatal,
+                                alert_description: AlertDescription::IllegalParameter,
+                            }),
+                            Some(Error::ErrClientNoMatchingSrtpProfile),
+                        ))
+                    }
+                };
+                state.srtp_protection_profile = profile;
+            }
+            Extension::UseExtendedMasterSecret(_) => {
+                if cfg.extended_master_secret != ExtendedMasterSecretType::Disable {
+                    state.extended_master_secret = true;
+                }
+            }
+            Extension::ConnectionId(e) if state.local_connection_id.is_some() => {
+                // Only honored when we offered a CID ourselves
+                // https://www.rfc-editor.org/rfc/rfc9146#section-3
+                let mut remote_connection_id = state.remote_connection_id.lock().await;
+                *remote_connection_id = Some(e.connection_id.clone());
+            }
+            _ => {}
+        };
+    }
+
+    if cfg.extended_master_secret == ExtendedMasterSecretType::Require
+        && !state.extended_master_secret
+    {
+        return Err((
+            Some(Alert {
+                alert_level: AlertLevel::Fatal,
+                alert_description: AlertDescription::InsufficientSecurity,
+            }),
+            Some(Error::ErrClientRequiredButNoServerEms),
+        ));
+    }
+    if !cfg.local_srtp_protection_profiles.is_empty()
+        && state.srtp_protection_profile == SrtpProtectionProfile::Unsupported
+    {
+        return Err((
+            Some(Alert {
+                alert_level: AlertLevel::Fatal,
+                alert_description: AlertDescription::InsufficientSecurity,
+            }),
+            Some(Error::ErrRequestedButNoSrtpExtension),
+        ));
+    }
+    if find_matching_cipher_suite(&[h.cipher_suite], &cfg.local_cipher_suites).is_err() {
+        debug!(
+            "[handshake:{}] use cipher suite: {}",
+            srv_cli_str(state.is_client),
+            h.cipher_suite
+        );
+
+        return Err((
+            Some(Alert {
+                alert_level: AlertLevel::Fatal,
+                alert_description: AlertDescription::InsufficientSecurity,
+            }),
+            Some(Error::ErrCipherSuiteNoIntersection),
+        ));
+    }
+
+    let cipher_suite = match cipher_suite_for_id(h.cipher_suite) {
+        Ok(cipher_suite) => cipher_suite,
+        Err(_) => {
+            debug!(
+                "[handshake:{}] use cipher suite: {}",
+                srv_cli_str(state.is_client),
+                h.cipher_suite
+            );
+
+            return Err((
+                Some(Alert {
+                    alert_level: AlertLevel::Fatal,
+                    alert_description: AlertDescription::InsufficientSecurity,
+                }),
+                Some(Error::ErrInvalidCipherSuite),
+            ));
+        }
+    };
+
+    trace!(
+        "[handshake:{}] use cipher suite: {}",
+        srv_cli_str(state.is_client),
+        cipher_suite.to_string()
+    );
+    {
+        let mut cs = state.cipher_suite.lock().await;
+        *cs = Some(cipher_suite);
+    }
+    state.remote_random = h.random.clone();
+    state.session_id = h.session_id.clone();
+
+    Ok(())
+}
+
 pub(crate) fn handle_server_key_exchange(
     state: &mut State,
     cfg: &HandshakeConfig,
@@ -432,6 +528,16 @@ pub(crate) fn handle_server_key_exchange(
         state.identity_hint = h.identity_hint.clone();
         state.pre_master_secret = prf_psk_pre_master_secret(&psk);
     } else {
+        if !cfg.local_elliptic_curves.contains(&h.named_curve) {
+            return Err((
+                Some(Alert {
+                    alert_level: AlertLevel::Fatal,
+                    alert_description: AlertDescription::IllegalParameter,
+                }),
+                Some(Error::ErrInvalidNamedCurve),
+            ));
+        }
+
         let local_keypair = match h.named_curve.generate_keypair() {
             Ok(local_keypair) => local_keypair,
             Err(err) => {
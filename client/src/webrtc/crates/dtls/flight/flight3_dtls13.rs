@@ -0,0 +1,709 @@
+use super::flight3::*;
+use super::flight5_dtls13::*;
+use super::*;
+use crate::webrtc::dtls::cipher_suite::*;
+use crate::webrtc::dtls::conn::*;
+use crate::webrtc::dtls::crypto::*;
+use crate::webrtc::dtls::error::Error;
+use crate::webrtc::dtls::extension::extension_cookie::*;
+use crate::webrtc::dtls::extension::extension_key_share::*;
+use crate::webrtc::dtls::extension::extension_supported_versions::*;
+use crate::webrtc::dtls::extension::extension_use_srtp::*;
+use crate::webrtc::dtls::extension::*;
+use crate::webrtc::dtls::find_matching_srtp_profile;
+use crate::webrtc::dtls::handshake::handshake_message_server_hello::*;
+use crate::webrtc::dtls::handshake::handshake_random::*;
+use crate::webrtc::dtls::handshake::*;
+use crate::webrtc::dtls::key_log::*;
+use crate::webrtc::dtls::prf::key_schedule::*;
+use crate::webrtc::dtls::prf::prf_pre_master_secret;
+use crate::webrtc::dtls::record_layer::record_layer_header::*;
+
+use log::*;
+use std::io::BufWriter;
+use std::sync::atomic::Ordering;
+
+// DTLS 1.3 handshake, client side
+// https://www.rfc-editor.org/rfc/rfc9147#section-5
+//
+//  Client                                             Server
+//
+//  ClientHello
+//  + key_share
+//  + supported_versions     -------->
+//                                                ServerHello
+//                                                + key_share
+//                                      {EncryptedExtensions}
+//                                      {CertificateRequest*}
+//                                             {Certificate}
+//                                       {CertificateVerify}
+//                           <--------             {Finished}
+//  {Certificate*}
+//  {CertificateVerify*}
+//  {Finished}               -------->
+//                           <--------                  [ACK]
+//
+// {} are protected with the handshake traffic keys (epoch 2), [] with the
+// application traffic keys (epoch 3). The server may answer the first
+// ClientHello with a HelloRetryRequest asking for a cookie or another key share.
+
+pub(crate) const DTLS13_HANDSHAKE_EPOCH: u16 = 2;
+pub(crate) const DTLS13_APPLICATION_EPOCH: u16 = 3;
+
+// A ServerHello with this random is a HelloRetryRequest, it is SHA-256("HelloRetryRequest")
+// https://tools.ietf.org/html/rfc8446#section-4.1.3
+const HELLO_RETRY_REQUEST_RANDOM: [u8; HANDSHAKE_RANDOM_LENGTH] = [
+    0xcf, 0x21, 0xad, 0x74, 0xe5, 0x9a, 0x61, 0x11, 0xbe, 0x1d, 0x8c, 0x02, 0x1e, 0x65, 0xb8, 0x91,
+    0xc2, 0xa2, 0x11, 0x16, 0x7a, 0xbb, 0x8c, 0x5e, 0x07, 0x9e, 0x09, 0xe2, 0xc8, 0xa8, 0x33, 0x9c,
+];
+
+// A server that supports DTLS 1.3 but negotiates DTLS 1.2 puts "DOWNGRD\x01" in the
+// last 8 bytes of its random, finding it means an attacker removed DTLS 1.3
+// from our ClientHello.
+// https://tools.ietf.org/html/rfc8446#section-4.1.3
+const DOWNGRADE_SENTINEL_DTLS12: [u8; 8] = [0x44, 0x4f, 0x57, 0x4e, 0x47, 0x52, 0x44, 0x01];
+
+pub(crate) const SERVER_CERTIFICATE_VERIFY_CONTEXT: &[u8] = b"TLS 1.3, server CertificateVerify";
+pub(crate) const CLIENT_CERTIFICATE_VERIFY_CONTEXT: &[u8] = b"TLS 1.3, client CertificateVerify";
+
+fn fatal(
+    alert_description: AlertDescription,
+    err: Option<Error>,
+) -> (Option<Alert>, Option<Error>) {
+    (
+        Some(Alert {
+            alert_level: AlertLevel::Fatal,
+            alert_description,
+        }),
+        err,
+    )
+}
+
+// is_dtls13_offered reports whether the ClientHello offers DTLS 1.3, PSK
+// clients keep using the DTLS 1.2 PSK cipher suites. The key share is always
+// made on DEFAULT_NAMED_CURVE, so DTLS 1.3 is not offered without it.
+pub(crate) fn is_dtls13_offered(cfg: &HandshakeConfig) -> bool {
+    cfg.enable_dtls13
+        && cfg.local_psk_callback.is_none()
+        && cfg.local_elliptic_curves.contains(&DEFAULT_NAMED_CURVE)
+}
+
+// client_hello_cipher_suites_dtls13 lists the DTLS 1.3 cipher suites in front of
+// the configured DTLS 1.2 ones
+pub(crate) fn client_hello_cipher_suites_dtls13(cfg: &HandshakeConfig) -> Vec<CipherSuiteId> {
+    let mut cipher_suites = dtls13_cipher_suites();
+    cipher_suites.extend_from_slice(&cfg.local_cipher_suites);
+    cipher_suites
+}
+
+// client_hello_extensions_dtls13 returns the extensions added to the ClientHello
+// when DTLS 1.3 is offered
+pub(crate) fn client_hello_extensions_dtls13(state: &State) -> Vec<Extension> {
+    let mut extensions = vec![Extension::SupportedVersions(
+        ExtensionSupportedVersions::ClientHello(vec![PROTOCOL_VERSION1_3, PROTOCOL_VERSION1_2]),
+    )];
+
+    if let Some(local_keypair) = &state.local_keypair {
+        extensions.push(Extension::KeyShare(ExtensionKeyShare::ClientHello(vec![
+            KeyShareEntry {
+                group: local_keypair.curve,
+                key_exchange: local_keypair.public_key.clone(),
+            },
+        ])));
+    }
+
+    if !state.hello_retry_cookie.is_empty() {
+        extensions.push(Extension::Cookie(ExtensionCookie {
+            cookie: state.hello_retry_cookie.clone(),
+        }));
+    }
+
+    extensions
+}
+
+fn random_bytes(random: &HandshakeRandom) -> Vec<u8> {
+    let mut raw = vec![];
+    {
+        let mut writer = BufWriter::<&mut Vec<u8>>::new(raw.as_mut());
+        let _ = random.marshal(&mut writer);
+    }
+    raw
+}
+
+pub(crate) fn is_hello_retry_request(h: &HandshakeMessageServerHello) -> bool {
+    random_bytes(&h.random) == HELLO_RETRY_REQUEST_RANDOM
+}
+
+pub(crate) fn is_server_hello_dtls13(h: &HandshakeMessageServerHello) -> bool {
+    h.extensions.iter().any(|e| {
+        matches!(
+            e,
+            Extension::SupportedVersions(ExtensionSupportedVersions::ServerHello(v)) if *v == PROTOCOL_VERSION1_3
+        )
+    })
+}
+
+pub(crate) fn has_downgrade_sentinel(h: &HandshakeMessageServerHello) -> bool {
+    random_bytes(&h.random).ends_with(&DOWNGRADE_SENTINEL_DTLS12)
+}
+
+// transcript_dtls13 returns the handshake messages covered by the transcript hash,
+// prefixed with the synthetic message_hash message after a HelloRetryRequest
+async fn transcript_dtls13(
+    state: &State,
+    cache: &HandshakeCache,
+    rules: &[HandshakeCachePullRule],
+) -> Vec<u8> {
+    let mut transcript = state.hello_retry_transcript.clone();
+    transcript.extend_from_slice(&cache.pull_and_merge_dtls13(rules).await);
+    transcript
+}
+
+// transcript_rules_dtls13 lists the messages of the 1-RTT handshake in transcript
+// order, up to and including last
+pub(crate) fn transcript_rules_dtls13(
+    cfg: &HandshakeConfig,
+    last: HandshakeType,
+    last_is_client: bool,
+) -> Vec<HandshakeCachePullRule> {
+    let order = [
+        (HandshakeType::ClientHello, cfg.initial_epoch, true),
+        (HandshakeType::ServerHello, cfg.initial_epoch, false),
+        (
+            HandshakeType::EncryptedExtensions,
+            DTLS13_HANDSHAKE_EPOCH,
+            false,
+        ),
+        (
+            HandshakeType::CertificateRequest,
+            DTLS13_HANDSHAKE_EPOCH,
+            false,
+        ),
+        (HandshakeType::Certificate, DTLS13_HANDSHAKE_EPOCH, false),
+        (
+            HandshakeType::CertificateVerify,
+            DTLS13_HANDSHAKE_EPOCH,
+            false,
+        ),
+        (HandshakeType::Finished, DTLS13_HANDSHAKE_EPOCH, false),
+        (HandshakeType::Certificate, DTLS13_HANDSHAKE_EPOCH, true),
+        (
+            HandshakeType::CertificateVerify,
+            DTLS13_HANDSHAKE_EPOCH,
+            true,
+        ),
+        (HandshakeType::Finished, DTLS13_HANDSHAKE_EPOCH, true),
+    ];
+
+    let mut rules = vec![];
+    for (typ, epoch, is_client) in order {
+        rules.push(HandshakeCachePullRule {
+            typ,
+            epoch,
+            is_client,
+            optional: true,
+        });
+        if typ == last && is_client == last_is_client {
+            break;
+        }
+    }
+
+    rules
+}
+
+// certificate_verify_content_dtls13 builds the signed content of a CertificateVerify,
+// 64 spaces, the context string, a zero byte and the transcript hash
+// https://tools.ietf.org/html/rfc8446#section-4.4.3
+pub(crate) fn certificate_verify_content_dtls13(
+    hash: CipherSuiteHash,
+    context: &[u8],
+    transcript: &[u8],
+) -> Vec<u8> {
+    let mut content = vec![0x20u8; 64];
+    content.extend_from_slice(context);
+    content.push(0x00);
+    content.extend_from_slice(&transcript_hash(hash, transcript));
+    content
+}
+
+// handle_hello_retry_request remembers the cookie and the requested group and
+// replaces ClientHello1 in the transcript with its hash
+// https://tools.ietf.org/html/rfc8446#section-4.4.1
+pub(crate) async fn handle_hello_retry_request(
+    state: &mut State,
+    cache: &HandshakeCache,
+    cfg: &HandshakeConfig,
+    h: &HandshakeMessageServerHello,
+    seq: isize,
+) -> Result<Box<dyn Flight + Send + Sync>, (Option<Alert>, Option<Error>)> {
+    if !state.hello_retry_transcript.is_empty() {
+        return Err(fatal(
+            AlertDescription::UnexpectedMessage,
+            Some(Error::ErrHelloRetryRequestRepeated),
+        ));
+    }
+    if !dtls13_cipher_suites().contains(&h.cipher_suite) {
+        return Err(fatal(
+            AlertDescription::IllegalParameter,
+            Some(Error::ErrCipherSuiteNoIntersection),
+        ));
+    }
+    let hash = match dtls13_cipher_suite_hash(h.cipher_suite) {
+        Ok(hash) => hash,
+        Err(err) => return Err(fatal(AlertDescription::InternalError, Some(err))),
+    };
+
+    let client_hello = cache
+        .pull_and_merge_dtls13(&[HandshakeCachePullRule {
+            typ: HandshakeType::ClientHello,
+            epoch: cfg.initial_epoch,
+            is_client: true,
+            optional: false,
+        }])
+        .await;
+    let hello_retry_request = cache
+        .pull_and_merge_dtls13(&[HandshakeCachePullRule {
+            typ: HandshakeType::ServerHello,
+            epoch: cfg.initial_epoch,
+            is_client: false,
+            optional: false,
+        }])
+        .await;
+
+    let client_hello_hash = transcript_hash(hash, &client_hello);
+    let mut transcript = vec![HandshakeType::MessageHash as u8, 0x00, 0x00];
+    transcript.push(client_hello_hash.len() as u8);
+    transcript.extend_from_slice(&client_hello_hash);
+    transcript.extend_from_slice(&hello_retry_request);
+
+    for extension in &h.extensions {
+        match extension {
+            Extension::Cookie(e) => state.hello_retry_cookie = e.cookie.clone(),
+            Extension::KeyShare(ExtensionKeyShare::HelloRetryRequest(group)) => {
+                let offered = match &state.local_keypair {
+                    Some(local_keypair) => local_keypair.curve == *group,
+                    None => false,
+                };
+                if offered {
+                    // A HelloRetryRequest must not ask for the share we already sent
+                    return Err(fatal(AlertDescription::IllegalParameter, None));
+                }
+
+                state.local_keypair = match group.generate_keypair() {
+                    Ok(local_keypair) => Some(local_keypair),
+                    Err(err) => return Err(fatal(AlertDescription::IllegalParameter, Some(err))),
+                };
+            }
+            _ => {}
+        }
+    }
+
+    debug!(
+        "[handshake:{}] received HelloRetryRequest",
+        srv_cli_str(state.is_client)
+    );
+
+    state.hello_retry_transcript = transcript;
+    state.handshake_recv_sequence = seq;
+    Ok(Box::new(Flight3 {}))
+}
+
+// handle_server_hello_dtls13 runs the (EC)DHE exchange and switches the
+// connection to the handshake traffic keys
+pub(crate) async fn handle_server_hello_dtls13(
+    tx: &mut mpsc::Sender<mpsc::Sender<()>>,
+    state: &mut State,
+    cache: &HandshakeCache,
+    cfg: &HandshakeConfig,
+    h: &HandshakeMessageServerHello,
+    seq: isize,
+) -> Result<(), (Option<Alert>, Option<Error>)> {
+    if !dtls13_cipher_suites().contains(&h.cipher_suite) {
+        return Err(fatal(
+            AlertDescription::IllegalParameter,
+            Some(Error::ErrCipherSuiteNoIntersection),
+        ));
+    }
+    let hash = match dtls13_cipher_suite_hash(h.cipher_suite) {
+        Ok(hash) => hash,
+        Err(err) => return Err(fatal(AlertDescription::InternalError, Some(err))),
+    };
+
+    let server_share = h.extensions.iter().find_map(|e| match e {
+        Extension::KeyShare(ExtensionKeyShare::ServerHello(entry)) => Some(entry),
+        _ => None,
+    });
+    let server_share = match server_share {
+        Some(server_share) => server_share,
+        None => {
+            return Err(fatal(
+                AlertDescription::IllegalParameter,
+                Some(Error::ErrKeyShareMissing),
+            ))
+        }
+    };
+
+    let shared_secret = match &state.local_keypair {
+        Some(local_keypair) if local_keypair.curve == server_share.group => {
+            match prf_pre_master_secret(
+                &server_share.key_exchange,
+                &local_keypair.private_key,
+                local_keypair.curve,
+            ) {
+                Ok(shared_secret) => shared_secret,
+                Err(err) => return Err(fatal(AlertDescription::IllegalParameter, Some(err))),
+            }
+        }
+        _ => {
+            return Err(fatal(
+                AlertDescription::IllegalParameter,
+                Some(Error::ErrInvalidNamedCurve),
+            ))
+        }
+    };
+
+    state.protocol_version = PROTOCOL_VERSION1_3;
+    state.remote_random = h.random.clone();
+    state.handshake_recv_sequence = seq;
+
+    let transcript = transcript_dtls13(
+        state,
+        cache,
+        &transcript_rules_dtls13(cfg, HandshakeType::ServerHello, false),
+    )
+    .await;
+
+    let secrets = key_schedule_handshake_secret(hash, &shared_secret).and_then(|hs| {
+        let c = derive_secret(
+            hash,
+            &hs,
+            KEY_SCHEDULE_CLIENT_HANDSHAKE_TRAFFIC_LABEL,
+            &transcript,
+        )?;
+        let s = derive_secret(
+            hash,
+            &hs,
+            KEY_SCHEDULE_SERVER_HANDSHAKE_TRAFFIC_LABEL,
+            &transcript,
+        )?;
+        Ok((hs, c, s))
+    });
+    let (handshake_secret, client_secret, server_secret) = match secrets {
+        Ok(secrets) => secrets,
+        Err(err) => return Err(fatal(AlertDescription::InternalError, Some(err))),
+    };
+
+    {
+        let mut keys = state.dtls13.lock().await;
+        keys.cipher_suite = Some(h.cipher_suite);
+        if let Err(err) = keys
+            .install_local(DTLS13_HANDSHAKE_EPOCH, &client_secret)
+            .and_then(|_| keys.install_remote(DTLS13_HANDSHAKE_EPOCH, &server_secret))
+        {
+            return Err(fatal(AlertDescription::InternalError, Some(err)));
+        }
+    }
+
+    trace!(
+        "[handshake:{}] use cipher suite: {} (DTLS 1.3)",
+        srv_cli_str(state.is_client),
+        h.cipher_suite
+    );
+
+    state.handshake_secret = handshake_secret;
+    state.client_handshake_traffic_secret = client_secret;
+    state.server_handshake_traffic_secret = server_secret;
+    if let Some(key_log) = &cfg.key_log {
+        key_log.write_secret(
+            state,
+            KEY_LOG_CLIENT_HANDSHAKE_TRAFFIC_SECRET,
+            &state.client_handshake_traffic_secret,
+        );
+        key_log.write_secret(
+            state,
+            KEY_LOG_SERVER_HANDSHAKE_TRAFFIC_SECRET,
+            &state.server_handshake_traffic_secret,
+        );
+    }
+    state
+        .remote_epoch
+        .store(DTLS13_HANDSHAKE_EPOCH, Ordering::SeqCst);
+
+    // Now, encrypted packets can be handled
+    let (done_tx, mut done_rx) = mpsc::channel(1);
+    if let Err(err) = tx.send(done_tx).await {
+        return Err(fatal(
+            AlertDescription::InternalError,
+            Some(Error::Other(err.to_string())),
+        ));
+    }
+
+    done_rx.recv().await;
+
+    Ok(())
+}
+
+// parse_server_flight_dtls13 handles the encrypted part of the server flight,
+// from EncryptedExtensions to Finished
+pub(crate) async fn parse_server_flight_dtls13(
+    state: &mut State,
+    cache: &HandshakeCache,
+    cfg: &HandshakeConfig,
+) -> Result<Box<dyn Flight + Send + Sync>, (Option<Alert>, Option<Error>)> {
+    let (seq, msgs) = match cache
+        .full_pull_map_dtls13(
+            state.handshake_recv_sequence,
+            &[
+                HandshakeCachePullRule {
+                    typ: HandshakeType::EncryptedExtensions,
+                    epoch: DTLS13_HANDSHAKE_EPOCH,
+                    is_client: false,
+                    optional: false,
+                },
+                HandshakeCachePullRule {
+                    typ: HandshakeType::CertificateRequest,
+                    epoch: DTLS13_HANDSHAKE_EPOCH,
+                    is_client: false,
+                    optional: true,
+                },
+                HandshakeCachePullRule {
+                    typ: HandshakeType::Certificate,
+                    epoch: DTLS13_HANDSHAKE_EPOCH,
+                    is_client: false,
+                    optional: false,
+                },
+                HandshakeCachePullRule {
+                    typ: HandshakeType::CertificateVerify,
+                    epoch: DTLS13_HANDSHAKE_EPOCH,
+                    is_client: false,
+                    optional: false,
+                },
+                HandshakeCachePullRule {
+                    typ: HandshakeType::Finished,
+                    epoch: DTLS13_HANDSHAKE_EPOCH,
+                    is_client: false,
+                    optional: false,
+                },
+            ],
+        )
+        .await
+    {
+        // No valid message received. Keep reading
+        Ok((seq, msgs)) => (seq, msgs),
+        Err(_) => return Err((None, None)),
+    };
+
+    if let Some(HandshakeMessage::EncryptedExtensions(h)) =
+        msgs.get(&HandshakeType::EncryptedExtensions)
+    {
+        for extension in &h.extensions {
+            match extension {
+                Extension::UseSrtp(e) => {
+                    state.srtp_protection_profile = match find_matching_srtp_profile(
+                        &e.protection_profiles,
+                        &cfg.local_srtp_protection_profiles,
+                    ) {
+                        Ok(profile) => profile,
+                        Err(_) => {
+                            return Err(fatal(
+                                AlertDescription::IllegalParameter,
+                                Some(Error::ErrClientNoMatchingSrtpProfile),
+                            ))
+                        }
+                    };
+                }
+                // A ClientHello offering DTLS 1.3 carries no CID, so the server
+                // must not send one
+                // https://www.rfc-editor.org/rfc/rfc9146#section-3
+                Extension::ConnectionId(e) if !e.connection_id.is_empty() => {
+                    return Err(fatal(
+                        AlertDescription::UnsupportedExtension,
+                        Some(Error::ErrConnectionIdNotSupported),
+                    ));
+                }
+                _ => {}
+            }
+        }
+    } else {
+        return Err(fatal(AlertDescription::InternalError, None));
+    }
+
+    if !cfg.local_srtp_protection_profiles.is_empty()
+        && state.srtp_protection_profile == SrtpProtectionProfile::Unsupported
+    {
+        return Err(fatal(
+            AlertDescription::InsufficientSecurity,
+            Some(Error::ErrRequestedButNoSrtpExtension),
+        ));
+    }
+
+    state.remote_requested_certificate = msgs.contains_key(&HandshakeType::CertificateRequest);
+
+    match msgs.get(&HandshakeType::Certificate) {
+        Some(HandshakeMessage::Certificate13(h)) => state.peer_certificates = h.certificate.clone(),
+        _ => return Err(fatal(AlertDescription::InternalError, None)),
+    };
+
+    // Verify the signature over the transcript up to the server Certificate
+    let certificate_verify = match msgs.get(&HandshakeType::CertificateVerify) {
+        Some(HandshakeMessage::CertificateVerify(h)) => h,
+        _ => return Err(fatal(AlertDescription::InternalError, None)),
+    };
+    if !cfg
+        .local_signature_schemes
+        .contains(&certificate_verify.algorithm)
+    {
+        return Err(fatal(
+            AlertDescription::InsufficientSecurity,
+            Some(Error::ErrNoAvailableSignatureSchemes),
+        ));
+    }
+
+    let hash = match state.dtls13.lock().await.hash() {
+        Ok(hash) => hash,
+        Err(err) => return Err(fatal(AlertDescription::InternalError, Some(err))),
+    };
+    let transcript = transcript_dtls13(
+        state,
+        cache,
+        &transcript_rules_dtls13(cfg, HandshakeType::Certificate, false),
+    )
+    .await;
+    if let Err(err) = verify_certificate_verify(
+        &certificate_verify_content_dtls13(hash, SERVER_CERTIFICATE_VERIFY_CONTEXT, &transcript),
+        &certificate_verify.algorithm,
+        &certificate_verify.signature,
+        &state.peer_certificates,
+    ) {
+        return Err(fatal(AlertDescription::DecryptError, Some(err)));
+    }
+
+    let mut chains = vec![];
+    if !cfg.insecure_skip_verify {
+        chains = match verify_server_cert(
+            &state.peer_certificates,
+            &cfg.server_cert_verifier,
+            &cfg.roots_cas,
+            &cfg.server_name,
+        ) {
+            Ok(chains) => chains,
+            Err(err) => return Err(fatal(AlertDescription::BadCertificate, Some(err))),
+        };
+    }
+    if let Some(verify_peer_certificate) = &cfg.verify_peer_certificate {
+        if let Err(err) = verify_peer_certificate(&state.peer_certificates, &chains) {
+            return Err(fatal(AlertDescription::BadCertificate, Some(err)));
+        }
+    }
+    state.peer_certificates_verified = true;
+
+    // Verify the server Finished over the transcript up to the server CertificateVerify
+    let finished = match msgs.get(&HandshakeType::Finished) {
+        Some(HandshakeMessage::Finished(h)) => h,
+        _ => return Err(fatal(AlertDescription::InternalError, None)),
+    };
+
+    let transcript = transcript_dtls13(
+        state,
+        cache,
+        &transcript_rules_dtls13(cfg, HandshakeType::CertificateVerify, false),
+    )
+    .await;
+    let expected_verify_data =
+        match key_schedule_verify_data(hash, &state.server_handshake_traffic_secret, &transcript) {
+            Ok(d) => d,
+            Err(err) => return Err(fatal(AlertDescription::InternalError, Some(err))),
+        };
+    if expected_verify_data != finished.verify_data {
+        return Err(fatal(
+            AlertDescription::DecryptError,
+            Some(Error::ErrVerifyDataMismatch),
+        ));
+    }
+
+    // The application traffic secrets cover the transcript up to the server Finished
+    let transcript = transcript_dtls13(
+        state,
+        cache,
+        &transcript_rules_dtls13(cfg, HandshakeType::Finished, false),
+    )
+    .await;
+    let secrets = key_schedule_master_secret(hash, &state.handshake_secret).and_then(|master| {
+        let c = derive_secret(
+            hash,
+            &master,
+            KEY_SCHEDULE_CLIENT_APPLICATION_TRAFFIC_LABEL,
+            &transcript,
+        )?;
+        let s = derive_secret(
+            hash,
+            &master,
+            KEY_SCHEDULE_SERVER_APPLICATION_TRAFFIC_LABEL,
+            &transcript,
+        )?;
+        let e = derive_secret(
+            hash,
+            &master,
+            KEY_SCHEDULE_EXPORTER_MASTER_LABEL,
+            &transcript,
+        )?;
+        Ok((c, s, e))
+    });
+    let (client_secret, server_secret, exporter_master_secret) = match secrets {
+        Ok(secrets) => secrets,
+        Err(err) => return Err(fatal(AlertDescription::InternalError, Some(err))),
+    };
+    if let Some(key_log) = &cfg.key_log {
+        key_log.write_secret(state, KEY_LOG_CLIENT_TRAFFIC_SECRET_0, &client_secret);
+        key_log.write_secret(state, KEY_LOG_SERVER_TRAFFIC_SECRET_0, &server_secret);
+        key_log.write_secret(state, KEY_LOG_EXPORTER_SECRET, &exporter_master_secret);
+    }
+
+    {
+        let mut keys = state.dtls13.lock().await;
+        if let Err(err) = keys
+            .install_local(DTLS13_APPLICATION_EPOCH, &client_secret)
+            .and_then(|_| keys.install_remote(DTLS13_APPLICATION_EPOCH, &server_secret))
+        {
+            return Err(fatal(AlertDescription::InternalError, Some(err)));
+        }
+        keys.local_traffic_secret = client_secret;
+        keys.remote_traffic_secret = server_secret;
+    }
+
+    state.exporter_master_secret = exporter_master_secret;
+    state
+        .remote_epoch
+        .store(DTLS13_APPLICATION_EPOCH, Ordering::SeqCst);
+    state.handshake_recv_sequence = seq;
+
+    Ok(Box::new(Flight5Dtls13 {}))
+}
+
+// check_downgrade_dtls13 rejects a DTLS 1.2 ServerHello carrying the downgrade
+// sentinel when DTLS 1.3 was offered
+pub(crate) fn check_downgrade_dtls13(
+    cfg: &HandshakeConfig,
+    h: &HandshakeMessageServerHello,
+) -> Result<(), (Option<Alert>, Option<Error>)> {
+    if is_dtls13_offered(cfg) && has_downgrade_sentinel(h) {
+        return Err(fatal(
+            AlertDescription::IllegalParameter,
+            Some(Error::ErrDowngradeDetected),
+        ));
+    }
+
+    Ok(())
+}
+
+// generate_keypair_dtls13 creates the key share sent in the first ClientHello
+pub(crate) fn generate_keypair_dtls13(
+    state: &mut State,
+) -> Result<(), (Option<Alert>, Option<Error>)> {
+    state.local_keypair = match DEFAULT_NAMED_CURVE.generate_keypair() {
+        Ok(local_keypair) => Some(local_keypair),
+        Err(err) => return Err(fatal(AlertDescription::InternalError, Some(err))),
+    };
+
+    Ok(())
+}
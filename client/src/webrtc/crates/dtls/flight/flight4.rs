@@ -9,6 +9,7 @@ use crate::webrtc::dtls::crypto::*;
 use crate::webrtc::dtls::curve::named_curve::*;
 use crate::webrtc::dtls::curve::*;
 use crate::webrtc::dtls::error::Error;
+use crate::webrtc::dtls::extension::extension_connection_id::*;
 use crate::webrtc::dtls::extension::extension_supported_elliptic_curves::*;
 use crate::webrtc::dtls::extension::extension_supported_point_formats::*;
 use crate::webrtc::dtls::extension::extension_use_extended_master_secret::*;
@@ -370,6 +371,10 @@ impl Flight for Flight4 {
                         };
                     }
 
+                    if let Some(key_log) = &cfg.key_log {
+                        key_log.write_state(state);
+                    }
+
                     if let Err(err) = cipher_suite.init(
                         &state.master_secret,
                         &client_random,
@@ -493,10 +498,20 @@ impl Flight for Flight4 {
             }));
         }
 
+        // The server only sends its CID when the client offered one
+        // https://www.rfc-editor.org/rfc/rfc9146#section-3
+        if let Some(connection_id) = &state.local_connection_id {
+            if state.remote_connection_id.lock().await.is_some() {
+                extensions.push(Extension::ConnectionId(ExtensionConnectionId {
+                    connection_id: connection_id.clone(),
+                }));
+            }
+        }
+
         if cfg.local_psk_callback.is_none() {
             extensions.extend_from_slice(&[
                 Extension::SupportedEllipticCurves(ExtensionSupportedEllipticCurves {
-                    elliptic_curves: vec![NamedCurve::P256, NamedCurve::X25519, NamedCurve::P384],
+                    elliptic_curves: cfg.local_elliptic_curves.clone(),
                 }),
                 Extension::SupportedPointFormats(ExtensionSupportedPointFormats {
                     point_formats: vec![ELLIPTIC_CURVE_POINT_FORMAT_UNCOMPRESSED],
@@ -512,6 +527,7 @@ impl Flight for Flight4 {
                     HandshakeMessageServerHello {
                         version: PROTOCOL_VERSION1_2,
                         random: state.local_random.clone(),
+                        session_id: vec![],
                         cipher_suite: {
                             let cipher_suite = state.cipher_suite.lock().await;
                             if let Some(cipher_suite) = &*cipher_suite {
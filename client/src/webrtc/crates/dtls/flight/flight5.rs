@@ -1,4 +1,5 @@
 use super::flight3::*;
+use super::flight5b::*;
 use super::*;
 use crate::webrtc::dtls::change_cipher_spec::ChangeCipherSpec;
 use crate::webrtc::dtls::content::*;
@@ -46,12 +47,20 @@ impl Flight for Flight5 {
         let (_seq, msgs) = match cache
             .full_pull_map(
                 state.handshake_recv_sequence,
-                &[HandshakeCachePullRule {
-                    typ: HandshakeType::Finished,
-                    epoch: cfg.initial_epoch + 1,
-                    is_client: false,
-                    optional: false,
-                }],
+                &[
+                    HandshakeCachePullRule {
+                        typ: HandshakeType::NewSessionTicket,
+                        epoch: cfg.initial_epoch,
+                        is_client: false,
+                        optional: true,
+                    },
+                    HandshakeCachePullRule {
+                        typ: HandshakeType::Finished,
+                        epoch: cfg.initial_epoch + 1,
+                        is_client: false,
+                        optional: false,
+                    },
+                ],
             )
             .await
         {
@@ -134,6 +143,12 @@ impl Flight for Flight5 {
                     is_client: true,
                     optional: false,
                 },
+                HandshakeCachePullRule {
+                    typ: HandshakeType::NewSessionTicket,
+                    epoch: cfg.initial_epoch,
+                    is_client: false,
+                    optional: true,
+                },
             ])
             .await;
 
@@ -169,6 +184,15 @@ impl Flight for Flight5 {
             }
         }
 
+        // The server sends NewSessionTicket right before its ChangeCipherSpec
+        // https://tools.ietf.org/html/rfc5077#section-3.3
+        if let Some(HandshakeMessage::NewSessionTicket(h)) =
+            msgs.get(&HandshakeType::NewSessionTicket)
+        {
+            state.session_ticket = h.ticket.clone();
+        }
+        store_session(state, cfg).await;
+
         Ok(Box::new(Flight5 {}))
     }
 
@@ -678,6 +702,10 @@ async fn initalize_cipher_suite(
         }
     }
 
+    if let Some(key_log) = &cfg.key_log {
+        key_log.write_state(state);
+    }
+
     if cfg.local_psk_callback.is_none() {
         // Verify that the pair of hash algorithm and signiture is listed.
         let mut valid_signature_scheme = false;
@@ -0,0 +1,227 @@
+use super::flight3_dtls13::*;
+use super::*;
+use crate::webrtc::dtls::content::*;
+use crate::webrtc::dtls::crypto::*;
+use crate::webrtc::dtls::error::Error;
+use crate::webrtc::dtls::handshake::handshake_header::*;
+use crate::webrtc::dtls::handshake::handshake_message_certificate13::*;
+use crate::webrtc::dtls::handshake::handshake_message_certificate_verify::*;
+use crate::webrtc::dtls::handshake::handshake_message_finished::*;
+use crate::webrtc::dtls::handshake::*;
+use crate::webrtc::dtls::prf::key_schedule::*;
+use crate::webrtc::dtls::record_layer::record_layer_header::*;
+use crate::webrtc::dtls::record_layer::*;
+
+use async_trait::async_trait;
+use std::fmt;
+use std::io::BufWriter;
+use std::sync::atomic::Ordering;
+
+// Flight5Dtls13 sends the client authentication messages protected with the
+// handshake traffic keys, the handshake is over once the server acknowledged them.
+// https://www.rfc-editor.org/rfc/rfc9147#section-5.8.1
+#[derive(Debug, PartialEq)]
+pub(crate) struct Flight5Dtls13;
+
+impl fmt::Display for Flight5Dtls13 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Flight 5 (DTLS 1.3)")
+    }
+}
+
+// transcript_bytes returns a handshake message the way it enters the DTLS 1.3
+// transcript, with the TLS four byte header
+fn transcript_bytes(h: &Handshake) -> Result<Vec<u8>, (Option<Alert>, Option<Error>)> {
+    let mut raw = vec![];
+    {
+        let mut writer = BufWriter::<&mut Vec<u8>>::new(raw.as_mut());
+        if let Err(err) = h.marshal(&mut writer) {
+            return Err((
+                Some(Alert {
+                    alert_level: AlertLevel::Fatal,
+                    alert_description: AlertDescription::InternalError,
+                }),
+                Some(err),
+            ));
+        }
+    }
+
+    let mut out = raw[..4].to_vec();
+    out.extend_from_slice(&raw[HANDSHAKE_HEADER_LENGTH..]);
+    Ok(out)
+}
+
+#[async_trait]
+impl Flight for Flight5Dtls13 {
+    fn is_last_recv_flight(&self) -> bool {
+        true
+    }
+
+    async fn parse(
+        &self,
+        _tx: &mut mpsc::Sender<mpsc::Sender<()>>,
+        state: &mut State,
+        _cache: &HandshakeCache,
+        _cfg: &HandshakeConfig,
+    ) -> Result<Box<dyn Flight + Send + Sync>, (Option<Alert>, Option<Error>)> {
+        {
+            let mut keys = state.dtls13.lock().await;
+            if !keys.handshake_acked {
+                // Keep retransmitting until the server acknowledges our Finished
+                return Err((None, None));
+            }
+            keys.handshake_send_sequence = state.handshake_send_sequence as u64;
+        }
+
+        state
+            .local_epoch
+            .store(DTLS13_APPLICATION_EPOCH, Ordering::SeqCst);
+
+        Ok(Box::new(Flight5Dtls13 {}))
+    }
+
+    async fn generate(
+        &self,
+        state: &mut State,
+        cache: &HandshakeCache,
+        cfg: &HandshakeConfig,
+    ) -> Result<Vec<Packet>, (Option<Alert>, Option<Error>)> {
+        let certificate = if !cfg.local_certificates.is_empty() {
+            let cert = match cfg.get_certificate(&cfg.server_name) {
+                Ok(cert) => cert,
+                Err(err) => {
+                    return Err((
+                        Some(Alert {
+                            alert_level: AlertLevel::Fatal,
+                            alert_description: AlertDescription::HandshakeFailure,
+                        }),
+                        Some(err),
+                    ))
+                }
+            };
+            Some(cert)
+        } else {
+            None
+        };
+
+        let hash = match state.dtls13.lock().await.hash() {
+            Ok(hash) => hash,
+            Err(err) => {
+                return Err((
+                    Some(Alert {
+                        alert_level: AlertLevel::Fatal,
+                        alert_description: AlertDescription::InternalError,
+                    }),
+                    Some(err),
+                ))
+            }
+        };
+
+        let mut transcript = state.hello_retry_transcript.clone();
+        transcript.extend_from_slice(
+            &cache
+                .pull_and_merge_dtls13(&transcript_rules_dtls13(
+                    cfg,
+                    HandshakeType::Finished,
+                    false,
+                ))
+                .await,
+        );
+
+        let mut pkts = vec![];
+
+        if state.remote_requested_certificate {
+            let h = Handshake::new(HandshakeMessage::Certificate13(
+                HandshakeMessageCertificate13 {
+                    certificate_request_context: vec![],
+                    certificate: if let Some(cert) = &certificate {
+                        cert.certificate.iter().map(|x| x.0.clone()).collect()
+                    } else {
+                        vec![]
+                    },
+                },
+            ));
+            transcript.extend_from_slice(&transcript_bytes(&h)?);
+
+            pkts.push(Packet {
+                record: RecordLayer::new(
+                    PROTOCOL_VERSION1_2,
+                    DTLS13_HANDSHAKE_EPOCH,
+                    Content::Handshake(h),
+                ),
+                should_encrypt: true,
+            });
+
+            if let Some(cert) = &certificate {
+                let content = certificate_verify_content_dtls13(
+                    hash,
+                    CLIENT_CERTIFICATE_VERIFY_CONTEXT,
+                    &transcript,
+                );
+                let (algorithm, signature) =
+                    match generate_certificate_verify_dtls13(&content, &cert.private_key) {
+                        Ok(s) => s,
+                        Err(err) => {
+                            return Err((
+                                Some(Alert {
+                                    alert_level: AlertLevel::Fatal,
+                                    alert_description: AlertDescription::InternalError,
+                                }),
+                                Some(err),
+                            ))
+                        }
+                    };
+                state.local_certificates_verify = signature.clone();
+
+                let h = Handshake::new(HandshakeMessage::CertificateVerify(
+                    HandshakeMessageCertificateVerify {
+                        algorithm,
+                        signature,
+                    },
+                ));
+                transcript.extend_from_slice(&transcript_bytes(&h)?);
+
+                pkts.push(Packet {
+                    record: RecordLayer::new(
+                        PROTOCOL_VERSION1_2,
+                        DTLS13_HANDSHAKE_EPOCH,
+                        Content::Handshake(h),
+                    ),
+                    should_encrypt: true,
+                });
+            }
+        }
+
+        state.local_verify_data = match key_schedule_verify_data(
+            hash,
+            &state.client_handshake_traffic_secret,
+            &transcript,
+        ) {
+            Ok(d) => d,
+            Err(err) => {
+                return Err((
+                    Some(Alert {
+                        alert_level: AlertLevel::Fatal,
+                        alert_description: AlertDescription::InternalError,
+                    }),
+                    Some(err),
+                ))
+            }
+        };
+
+        pkts.push(Packet {
+            record: RecordLayer::new(
+                PROTOCOL_VERSION1_2,
+                DTLS13_HANDSHAKE_EPOCH,
+                Content::Handshake(Handshake::new(HandshakeMessage::Finished(
+                    HandshakeMessageFinished {
+                        verify_data: state.local_verify_data.clone(),
+                    },
+                ))),
+            ),
+            should_encrypt: true,
+        });
+
+        Ok(pkts)
+    }
+}
@@ -0,0 +1,421 @@
+use super::flight3::*;
+use super::*;
+use crate::webrtc::dtls::change_cipher_spec::ChangeCipherSpec;
+use crate::webrtc::dtls::content::*;
+use crate::webrtc::dtls::error::Error;
+use crate::webrtc::dtls::extension::extension_session_ticket::*;
+use crate::webrtc::dtls::extension::*;
+use crate::webrtc::dtls::handshake::handshake_message_finished::*;
+use crate::webrtc::dtls::handshake::handshake_message_server_hello::*;
+use crate::webrtc::dtls::handshake::*;
+use crate::webrtc::dtls::prf::*;
+use crate::webrtc::dtls::record_layer::record_layer_header::*;
+use crate::webrtc::dtls::record_layer::*;
+use crate::webrtc::dtls::session::*;
+
+use async_trait::async_trait;
+use rand::Rng;
+use std::fmt;
+use std::io::BufWriter;
+
+// Flight5b is the client flight of the abbreviated handshake, it answers the
+// server Finished of a resumed session
+// https://tools.ietf.org/html/rfc5246#section-7.3
+#[derive(Debug, PartialEq)]
+pub(crate) struct Flight5b;
+
+impl fmt::Display for Flight5b {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Flight 5b")
+    }
+}
+
+#[async_trait]
+impl Flight for Flight5b {
+    fn is_last_send_flight(&self) -> bool {
+        true
+    }
+
+    async fn parse(
+        &self,
+        _tx: &mut mpsc::Sender<mpsc::Sender<()>>,
+        state: &mut State,
+        cache: &HandshakeCache,
+        cfg: &HandshakeConfig,
+    ) -> Result<Box<dyn Flight + Send + Sync>, (Option<Alert>, Option<Error>)> {
+        let (_, msgs) = match cache
+            .full_pull_map(
+                state.handshake_recv_sequence - 1,
+                &[HandshakeCachePullRule {
+                    typ: HandshakeType::Finished,
+                    epoch: cfg.initial_epoch + 1,
+                    is_client: false,
+                    optional: false,
+                }],
+            )
+            .await
+        {
+            Ok((seq, msgs)) => (seq, msgs),
+            // No valid message received. Keep reading
+            Err(_) => return Err((None, None)),
+        };
+
+        if let Some(message) = msgs.get(&HandshakeType::Finished) {
+            match message {
+                HandshakeMessage::Finished(_) => {}
+                _ => {
+                    return Err((
+                        Some(Alert {
+                            alert_level: AlertLevel::Fatal,
+                            alert_description: AlertDescription::InternalError,
+                        }),
+                        None,
+                    ))
+                }
+            };
+        }
+
+        // Other party retransmitted the last flight.
+        Ok(Box::new(Flight5b {}))
+    }
+
+    async fn generate(
+        &self,
+        state: &mut State,
+        cache: &HandshakeCache,
+        cfg: &HandshakeConfig,
+    ) -> Result<Vec<Packet>, (Option<Alert>, Option<Error>)> {
+        let mut pkts = vec![Packet {
+            record: RecordLayer::new(
+                PROTOCOL_VERSION1_2,
+                0,
+                Content::ChangeCipherSpec(ChangeCipherSpec {}),
+            ),
+            should_encrypt: false,
+        }];
+
+        if state.local_verify_data.is_empty() {
+            let plain_text = cache
+                .pull_and_merge(&abbreviated_handshake_rules(cfg, true))
+                .await;
+
+            let cipher_suite = state.cipher_suite.lock().await;
+            if let Some(cipher_suite) = &*cipher_suite {
+                state.local_verify_data = match prf_verify_data_client(
+                    &state.master_secret,
+                    &plain_text,
+                    cipher_suite.hash_func(),
+                ) {
+                    Ok(data) => data,
+                    Err(err) => {
+                        return Err((
+                            Some(Alert {
+                                alert_level: AlertLevel::Fatal,
+                                alert_description: AlertDescription::InternalError,
+                            }),
+                            Some(err),
+                        ))
+                    }
+                };
+            }
+        }
+
+        pkts.push(Packet {
+            record: RecordLayer::new(
+                PROTOCOL_VERSION1_2,
+                1,
+                Content::Handshake(Handshake::new(HandshakeMessage::Finished(
+                    HandshakeMessageFinished {
+                        verify_data: state.local_verify_data.clone(),
+                    },
+                ))),
+            ),
+            should_encrypt: true,
+        });
+
+        // The session may carry a fresh ticket now
+        store_session(state, cfg).await;
+
+        Ok(pkts)
+    }
+}
+
+// abbreviated_handshake_rules returns the messages of the abbreviated handshake,
+// up to the server Finished if with_server_finished is set
+fn abbreviated_handshake_rules(
+    cfg: &HandshakeConfig,
+    with_server_finished: bool,
+) -> Vec<HandshakeCachePullRule> {
+    let mut rules = vec![
+        HandshakeCachePullRule {
+            typ: HandshakeType::ClientHello,
+            epoch: cfg.initial_epoch,
+            is_client: true,
+            optional: false,
+        },
+        HandshakeCachePullRule {
+            typ: HandshakeType::ServerHello,
+            epoch: cfg.initial_epoch,
+            is_client: false,
+            optional: false,
+        },
+        HandshakeCachePullRule {
+            typ: HandshakeType::NewSessionTicket,
+            epoch: cfg.initial_epoch,
+            is_client: false,
+            optional: true,
+        },
+    ];
+    if with_server_finished {
+        rules.push(HandshakeCachePullRule {
+            typ: HandshakeType::Finished,
+            epoch: cfg.initial_epoch + 1,
+            is_client: false,
+            optional: false,
+        });
+    }
+    rules
+}
+
+// offer_session picks the cached session of the server, if any, to be offered
+// in the ClientHello
+pub(crate) fn offer_session(state: &mut State, cfg: &HandshakeConfig) {
+    state.session_id = vec![];
+    state.session_ticket = vec![];
+    state.offered_session = None;
+
+    if let Some(session_store) = &cfg.session_store {
+        if let Some(session) = session_store.get(&cfg.session_key) {
+            // A ticket is offered with a random session id, the server echoes it
+            // when it accepts the ticket
+            // https://tools.ietf.org/html/rfc5077#section-3.4
+            state.session_id = if session.id.is_empty() {
+                let mut session_id = vec![0u8; 32];
+                rand::thread_rng().fill(session_id.as_mut_slice());
+                session_id
+            } else {
+                session.id.clone()
+            };
+            state.offered_session = Some(session);
+        }
+    }
+}
+
+// client_hello_extensions_session returns the SessionTicket extension, an empty
+// ticket asks the server for a new one
+// https://tools.ietf.org/html/rfc5077#section-3.2
+pub(crate) fn client_hello_extensions_session(
+    state: &State,
+    cfg: &HandshakeConfig,
+) -> Vec<Extension> {
+    if cfg.session_store.is_none() {
+        return vec![];
+    }
+
+    vec![Extension::SessionTicket(ExtensionSessionTicket {
+        ticket: if let Some(session) = &state.offered_session {
+            session.ticket.clone()
+        } else {
+            vec![]
+        },
+    })]
+}
+
+// is_session_resumed returns true if the ServerHello echoes the session id we offered
+pub(crate) fn is_session_resumed(state: &State, h: &HandshakeMessageServerHello) -> bool {
+    state.offered_session.is_some() && !h.session_id.is_empty() && h.session_id == state.session_id
+}
+
+// parse_server_flight_resumption handles the server flight of the abbreviated
+// handshake: ServerHello, NewSessionTicket*, ChangeCipherSpec and Finished
+pub(crate) async fn parse_server_flight_resumption(
+    tx: &mut mpsc::Sender<mpsc::Sender<()>>,
+    state: &mut State,
+    cache: &HandshakeCache,
+    cfg: &HandshakeConfig,
+    h: &HandshakeMessageServerHello,
+) -> Result<Box<dyn Flight + Send + Sync>, (Option<Alert>, Option<Error>)> {
+    let session = match &state.offered_session {
+        Some(session) => session.clone(),
+        None => {
+            return Err((
+                Some(Alert {
+                    alert_level: AlertLevel::Fatal,
+                    alert_description: AlertDescription::InternalError,
+                }),
+                None,
+            ))
+        }
+    };
+
+    let initialized = {
+        let cipher_suite = state.cipher_suite.lock().await;
+        matches!(&*cipher_suite, Some(cipher_suite) if cipher_suite.is_initialized())
+    };
+    if !initialized {
+        handle_server_hello(state, cfg, h).await?;
+
+        // The server must resume the session with its original parameters
+        // https://tools.ietf.org/html/rfc7627#section-5.3
+        if h.cipher_suite != session.cipher_suite_id
+            || state.extended_master_secret != session.extended_master_secret
+        {
+            return Err((
+                Some(Alert {
+                    alert_level: AlertLevel::Fatal,
+                    alert_description: AlertDescription::HandshakeFailure,
+                }),
+                Some(Error::ErrSessionMismatch),
+            ));
+        }
+
+        state.master_secret = session.secret.clone();
+        state.peer_certificates = session.peer_certificates.clone();
+        state.session_ticket = session.ticket.clone();
+        if let Some(key_log) = &cfg.key_log {
+            key_log.write_state(state);
+        }
+
+        let mut client_random = vec![];
+        {
+            let mut writer = BufWriter::<&mut Vec<u8>>::new(client_random.as_mut());
+            let _ = state.local_random.marshal(&mut writer);
+        }
+        let mut server_random = vec![];
+        {
+            let mut writer = BufWriter::<&mut Vec<u8>>::new(server_random.as_mut());
+            let _ = state.remote_random.marshal(&mut writer);
+        }
+
+        {
+            let mut cipher_suite = state.cipher_suite.lock().await;
+            if let Some(cipher_suite) = &mut *cipher_suite {
+                if let Err(err) =
+                    cipher_suite.init(&state.master_secret, &client_random, &server_random, true)
+                {
+                    return Err((
+                        Some(Alert {
+                            alert_level: AlertLevel::Fatal,
+                            alert_description: AlertDescription::InternalError,
+                        }),
+                        Some(err),
+                    ));
+                }
+            }
+        }
+
+        // Now, the server ChangeCipherSpec and Finished can be handled
+        let (done_tx, mut done_rx) = mpsc::channel(1);
+        if let Err(err) = tx.send(done_tx).await {
+            return Err((
+                Some(Alert {
+                    alert_level: AlertLevel::Fatal,
+                    alert_description: AlertDescription::InternalError,
+                }),
+                Some(Error::Other(err.to_string())),
+            ));
+        }
+        done_rx.recv().await;
+    }
+
+    let (seq, msgs) = match cache
+        .full_pull_map(
+            state.handshake_recv_sequence,
+            &abbreviated_handshake_rules(cfg, true)[1..],
+        )
+        .await
+    {
+        Ok((seq, msgs)) => (seq, msgs),
+        Err(_) => return Err((None, None)),
+    };
+
+    let finished = if let Some(HandshakeMessage::Finished(h)) = msgs.get(&HandshakeType::Finished) {
+        h
+    } else {
+        return Err((
+            Some(Alert {
+                alert_level: AlertLevel::Fatal,
+                alert_description: AlertDescription::InternalError,
+            }),
+            None,
+        ));
+    };
+
+    let plain_text = cache
+        .pull_and_merge(&abbreviated_handshake_rules(cfg, false))
+        .await;
+
+    {
+        let cipher_suite = state.cipher_suite.lock().await;
+        if let Some(cipher_suite) = &*cipher_suite {
+            let expected_verify_data = match prf_verify_data_server(
+                &state.master_secret,
+                &plain_text,
+                cipher_suite.hash_func(),
+            ) {
+                Ok(d) => d,
+                Err(err) => {
+                    return Err((
+                        Some(Alert {
+                            alert_level: AlertLevel::Fatal,
+                            alert_description: AlertDescription::InsufficientSecurity,
+                        }),
+                        Some(err),
+                    ))
+                }
+            };
+
+            if expected_verify_data != finished.verify_data {
+                return Err((
+                    Some(Alert {
+                        alert_level: AlertLevel::Fatal,
+                        alert_description: AlertDescription::HandshakeFailure,
+                    }),
+                    Some(Error::ErrVerifyDataMismatch),
+                ));
+            }
+        }
+    }
+
+    if let Some(HandshakeMessage::NewSessionTicket(h)) = msgs.get(&HandshakeType::NewSessionTicket)
+    {
+        state.session_ticket = h.ticket.clone();
+    }
+    state.handshake_recv_sequence = seq;
+
+    Ok(Box::new(Flight5b {}))
+}
+
+// store_session caches the session of a completed handshake, or forgets the
+// server when it supports no resumption at all
+pub(crate) async fn store_session(state: &State, cfg: &HandshakeConfig) {
+    let session_store = match &cfg.session_store {
+        Some(session_store) => session_store,
+        None => return,
+    };
+
+    if state.session_id.is_empty() && state.session_ticket.is_empty() {
+        session_store.del(&cfg.session_key);
+        return;
+    }
+
+    let cipher_suite_id = {
+        let cipher_suite = state.cipher_suite.lock().await;
+        match &*cipher_suite {
+            Some(cipher_suite) => cipher_suite.id(),
+            None => return,
+        }
+    };
+
+    session_store.set(
+        &cfg.session_key,
+        Session {
+            id: state.session_id.clone(),
+            ticket: state.session_ticket.clone(),
+            secret: state.master_secret.clone(),
+            cipher_suite_id,
+            extended_master_secret: state.extended_master_secret,
+            peer_certificates: state.peer_certificates.clone(),
+        },
+    );
+}
@@ -2,8 +2,11 @@ pub(crate) mod flight0;
 pub(crate) mod flight1;
 pub(crate) mod flight2;
 pub(crate) mod flight3;
+pub(crate) mod flight3_dtls13;
 pub(crate) mod flight4;
 pub(crate) mod flight5;
+pub(crate) mod flight5_dtls13;
+pub(crate) mod flight5b;
 pub(crate) mod flight6;
 
 use crate::webrtc::dtls::alert::*;
@@ -48,6 +51,18 @@ use tokio::sync::mpsc;
                                       [ChangeCipherSpec]    \ Flight 6
                           <--------             Finished    /
 
+  When the server resumes a session offered in Flight 1 or Flight 3 the
+  handshake is abbreviated, the server sends its Finished first.
+  https://tools.ietf.org/html/rfc5246#section-7.3
+
+                                             ServerHello    \
+                                       NewSessionTicket*     \ Flight 4
+                                      [ChangeCipherSpec]     /
+                          <--------             Finished    /
+
+  [ChangeCipherSpec]                                        \ Flight 5b
+  Finished                -------->                         /
+
 */
 
 #[derive(Clone, Debug)]
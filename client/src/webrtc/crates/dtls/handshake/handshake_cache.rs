@@ -98,6 +98,24 @@ impl HandshakeCache {
         &self,
         start_seq: isize,
         rules: &[HandshakeCachePullRule],
+    ) -> Result<(isize, HashMap<HandshakeType, HandshakeMessage>)> {
+        self.full_pull_map_internal(start_seq, rules, false).await
+    }
+
+    // full_pull_map_dtls13 is full_pull_map for messages using the DTLS 1.3 layouts
+    pub(crate) async fn full_pull_map_dtls13(
+        &self,
+        start_seq: isize,
+        rules: &[HandshakeCachePullRule],
+    ) -> Result<(isize, HashMap<HandshakeType, HandshakeMessage>)> {
+        self.full_pull_map_internal(start_seq, rules, true).await
+    }
+
+    async fn full_pull_map_internal(
+        &self,
+        start_seq: isize,
+        rules: &[HandshakeCachePullRule],
+        dtls13: bool,
     ) -> Result<(isize, HashMap<HandshakeType, HandshakeMessage>)> {
         let cache = self.cache.lock().await;
 
@@ -130,8 +148,12 @@ impl HandshakeCache {
         for r in rules {
             let t = r.typ;
             if let Some(i) = ci.get(&t) {
-                let mut reader = BufReader::new(i.data.as_slice());
-                let raw_handshake = Handshake::unmarshal(&mut reader)?;
+                let raw_handshake = if dtls13 {
+                    Handshake::unmarshal_dtls13(&i.data)?
+                } else {
+                    let mut reader = BufReader::new(i.data.as_slice());
+                    Handshake::unmarshal(&mut reader)?
+                };
                 if seq as u16 != raw_handshake.handshake_header.message_sequence {
                     // There is a gap. Some messages are not arrived.
                     return Err(Error::Other(
@@ -157,6 +179,24 @@ impl HandshakeCache {
         merged
     }
 
+    // pull_and_merge_dtls13 is pull_and_merge for the DTLS 1.3 transcript, which
+    // hashes the messages with the TLS 1.3 four byte header, leaving out the
+    // message_seq and fragment fields.
+    // https://www.rfc-editor.org/rfc/rfc9147#section-5.2
+    pub(crate) async fn pull_and_merge_dtls13(&self, rules: &[HandshakeCachePullRule]) -> Vec<u8> {
+        let mut merged = vec![];
+
+        for p in &self.pull(rules).await {
+            if p.data.len() < HANDSHAKE_HEADER_LENGTH {
+                continue;
+            }
+            merged.extend_from_slice(&p.data[..4]);
+            merged.extend_from_slice(&p.data[HANDSHAKE_HEADER_LENGTH..]);
+        }
+
+        merged
+    }
+
     // session_hash returns the session hash for Extended Master Secret support
     // https://tools.ietf.org/html/draft-ietf-tls-session-hash-06#section-4
     pub(crate) async fn session_hash(
@@ -0,0 +1,89 @@
+use super::*;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Write};
+
+const HANDSHAKE_MESSAGE_CERTIFICATE13_LENGTH_FIELD_SIZE: usize = 3;
+const HANDSHAKE_MESSAGE_CERTIFICATE13_EXTENSIONS_FIELD_SIZE: usize = 2;
+
+// DTLS 1.3 adds a request context and per certificate extensions, no
+// certificate extensions are sent and received ones are ignored.
+//
+// struct {
+//     opaque certificate_request_context<0..2^8-1>;
+//     CertificateEntry certificate_list<0..2^24-1>;
+// } Certificate;
+//
+// https://tools.ietf.org/html/rfc8446#section-4.4.2
+#[derive(PartialEq, Debug, Clone)]
+pub(crate) struct HandshakeMessageCertificate13 {
+    pub(crate) certificate_request_context: Vec<u8>,
+    pub(crate) certificate: Vec<Vec<u8>>,
+}
+
+impl HandshakeMessageCertificate13 {
+    pub(crate) fn handshake_type(&self) -> HandshakeType {
+        HandshakeType::Certificate
+    }
+
+    fn payload_size(&self) -> usize {
+        self.certificate
+            .iter()
+            .map(|r| {
+                HANDSHAKE_MESSAGE_CERTIFICATE13_LENGTH_FIELD_SIZE
+                    + r.len()
+                    + HANDSHAKE_MESSAGE_CERTIFICATE13_EXTENSIONS_FIELD_SIZE
+            })
+            .sum()
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        1 + self.certificate_request_context.len() + 3 + self.payload_size()
+    }
+
+    pub(crate) fn marshal<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_u8(self.certificate_request_context.len() as u8)?;
+        writer.write_all(&self.certificate_request_context)?;
+
+        writer.write_u24::<BigEndian>(self.payload_size() as u32)?;
+        for r in &self.certificate {
+            writer.write_u24::<BigEndian>(r.len() as u32)?;
+            writer.write_all(r)?;
+
+            // Extensions
+            writer.write_u16::<BigEndian>(0)?;
+        }
+
+        Ok(writer.flush()?)
+    }
+
+    pub(crate) fn unmarshal<R: Read>(reader: &mut R) -> Result<Self> {
+        let context_len = reader.read_u8()? as usize;
+        let mut certificate_request_context = vec![0u8; context_len];
+        reader.read_exact(&mut certificate_request_context)?;
+
+        let mut certificate: Vec<Vec<u8>> = vec![];
+        let payload_size = reader.read_u24::<BigEndian>()? as usize;
+        let mut offset = 0;
+        while offset < payload_size {
+            let certificate_len = reader.read_u24::<BigEndian>()? as usize;
+            offset += HANDSHAKE_MESSAGE_CERTIFICATE13_LENGTH_FIELD_SIZE;
+
+            let mut buf = vec![0; certificate_len];
+            reader.read_exact(&mut buf)?;
+            offset += certificate_len;
+
+            let extensions_len = reader.read_u16::<BigEndian>()? as usize;
+            let mut extensions = vec![0; extensions_len];
+            reader.read_exact(&mut extensions)?;
+            offset += HANDSHAKE_MESSAGE_CERTIFICATE13_EXTENSIONS_FIELD_SIZE + extensions_len;
+
+            certificate.push(buf);
+        }
+
+        Ok(HandshakeMessageCertificate13 {
+            certificate_request_context,
+            certificate,
+        })
+    }
+}
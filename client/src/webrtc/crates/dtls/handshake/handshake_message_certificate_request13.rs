@@ -0,0 +1,89 @@
+use super::*;
+use crate::webrtc::dtls::extension::*;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{BufReader, BufWriter};
+
+// The accepted signature algorithms moved into the signature_algorithms
+// extension, the certificate types are gone.
+//
+// struct {
+//     opaque certificate_request_context<0..2^8-1>;
+//     Extension extensions<2..2^16-1>;
+// } CertificateRequest;
+//
+// https://tools.ietf.org/html/rfc8446#section-4.3.2
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct HandshakeMessageCertificateRequest13 {
+    pub(crate) certificate_request_context: Vec<u8>,
+    pub(crate) extensions: Vec<Extension>,
+}
+
+impl HandshakeMessageCertificateRequest13 {
+    pub(crate) fn handshake_type(&self) -> HandshakeType {
+        HandshakeType::CertificateRequest
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        let mut len = 1 + self.certificate_request_context.len() + 2;
+        for extension in &self.extensions {
+            len += extension.size();
+        }
+
+        len
+    }
+
+    pub(crate) fn marshal<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_u8(self.certificate_request_context.len() as u8)?;
+        writer.write_all(&self.certificate_request_context)?;
+
+        let mut extension_buffer = vec![];
+        {
+            let mut extension_writer = BufWriter::<&mut Vec<u8>>::new(extension_buffer.as_mut());
+            for extension in &self.extensions {
+                extension.marshal(&mut extension_writer)?;
+            }
+        }
+
+        writer.write_u16::<BigEndian>(extension_buffer.len() as u16)?;
+        writer.write_all(&extension_buffer)?;
+
+        Ok(writer.flush()?)
+    }
+
+    pub(crate) fn unmarshal<R: Read>(reader: &mut R) -> Result<Self> {
+        let context_len = reader.read_u8()? as usize;
+        let mut certificate_request_context = vec![0u8; context_len];
+        reader.read_exact(&mut certificate_request_context)?;
+
+        let mut extensions = vec![];
+
+        let extension_buffer_len = reader.read_u16::<BigEndian>()? as usize;
+        let mut extension_buffer = vec![0u8; extension_buffer_len];
+        reader.read_exact(&mut extension_buffer)?;
+
+        let mut offset = 0;
+        while offset + 4 <= extension_buffer_len {
+            let mut extension_reader = BufReader::new(&extension_buffer[offset..]);
+            if let Ok(extension) = Extension::unmarshal(&mut extension_reader) {
+                extensions.push(extension);
+            } else {
+                log::warn!(
+                    "Unsupported Extension Type {} {}",
+                    extension_buffer[offset],
+                    extension_buffer[offset + 1]
+                );
+            }
+
+            let extension_len =
+                u16::from_be_bytes([extension_buffer[offset + 2], extension_buffer[offset + 3]])
+                    as usize;
+            offset += 4 + extension_len;
+        }
+
+        Ok(HandshakeMessageCertificateRequest13 {
+            certificate_request_context,
+            extensions,
+        })
+    }
+}
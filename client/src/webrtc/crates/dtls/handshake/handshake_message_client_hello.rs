@@ -20,6 +20,7 @@ existing connection.
 pub(crate) struct HandshakeMessageClientHello {
     pub(crate) version: ProtocolVersion,
     pub(crate) random: HandshakeRandom,
+    pub(crate) session_id: Vec<u8>,
     pub(crate) cookie: Vec<u8>,
 
     pub(crate) cipher_suites: Vec<CipherSuiteId>,
@@ -31,6 +32,7 @@ impl PartialEq for HandshakeMessageClientHello {
     fn eq(&self, other: &Self) -> bool {
         if !(self.version == other.version
             && self.random == other.random
+            && self.session_id == other.session_id
             && self.cookie == other.cookie
             && self.compression_methods == other.compression_methods
             && self.extensions == other.extensions
@@ -58,6 +60,7 @@ impl fmt::Debug for HandshakeMessageClientHello {
         }
         let s = vec![
             format!("version: {:?} random: {:?}", self.version, self.random),
+            format!("session_id: {:?}", self.session_id),
             format!("cookie: {:?}", self.cookie),
             format!("cipher_suites: {:?}", cipher_suites_str),
             format!("compression_methods: {:?}", self.compression_methods),
@@ -79,7 +82,7 @@ impl HandshakeMessageClientHello {
         len += self.random.size();
 
         // SessionID
-        len += 1;
+        len += 1 + self.session_id.len();
 
         len += 1 + self.cookie.len();
 
@@ -99,13 +102,17 @@ impl HandshakeMessageClientHello {
         if self.cookie.len() > 255 {
             return Err(Error::ErrCookieTooLong);
         }
+        if self.session_id.len() > 32 {
+            return Err(Error::ErrSessionIdTooLong);
+        }
 
         writer.write_u8(self.version.major)?;
         writer.write_u8(self.version.minor)?;
         self.random.marshal(writer)?;
 
         // SessionID
-        writer.write_u8(0x00)?;
+        writer.write_u8(self.session_id.len() as u8)?;
+        writer.write_all(&self.session_id)?;
 
         writer.write_u8(self.cookie.len() as u8)?;
         writer.write_all(&self.cookie)?;
@@ -137,7 +144,9 @@ impl HandshakeMessageClientHello {
         let random = HandshakeRandom::unmarshal(reader)?;
 
         // Session ID
-        reader.read_u8()?;
+        let session_id_len = reader.read_u8()? as usize;
+        let mut session_id = vec![0; session_id_len];
+        reader.read_exact(&mut session_id)?;
 
         let cookie_len = reader.read_u8()? as usize;
         let mut cookie = vec![0; cookie_len];
@@ -180,6 +189,7 @@ impl HandshakeMessageClientHello {
         Ok(HandshakeMessageClientHello {
             version: ProtocolVersion { major, minor },
             random,
+            session_id,
             cookie,
 
             cipher_suites,
@@ -0,0 +1,43 @@
+use super::*;
+
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Write};
+
+// Signals that the sender switched to the next generation of traffic keys,
+// update_requested asks the peer to do the same.
+//
+// enum {
+//     update_not_requested(0), update_requested(1), (255)
+// } KeyUpdateRequest;
+//
+// https://tools.ietf.org/html/rfc8446#section-4.6.3
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct HandshakeMessageKeyUpdate {
+    pub(crate) update_requested: bool,
+}
+
+impl HandshakeMessageKeyUpdate {
+    pub(crate) fn handshake_type(&self) -> HandshakeType {
+        HandshakeType::KeyUpdate
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        1
+    }
+
+    pub(crate) fn marshal<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_u8(self.update_requested as u8)?;
+
+        Ok(writer.flush()?)
+    }
+
+    pub(crate) fn unmarshal<R: Read>(reader: &mut R) -> Result<Self> {
+        let update_requested = match reader.read_u8()? {
+            0 => false,
+            1 => true,
+            _ => return Err(Error::ErrInvalidKeyUpdate),
+        };
+
+        Ok(HandshakeMessageKeyUpdate { update_requested })
+    }
+}
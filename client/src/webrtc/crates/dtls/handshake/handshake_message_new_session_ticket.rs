@@ -0,0 +1,49 @@
+use super::*;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Write};
+
+// Sent by the server before its ChangeCipherSpec, the ticket lets the client
+// resume the session with a later ClientHello.
+//
+// struct {
+//     uint32 ticket_lifetime_hint;
+//     opaque ticket<0..2^16-1>;
+// } NewSessionTicket;
+//
+// https://tools.ietf.org/html/rfc5077#section-3.3
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct HandshakeMessageNewSessionTicket {
+    pub(crate) ticket_lifetime_hint: u32,
+    pub(crate) ticket: Vec<u8>,
+}
+
+impl HandshakeMessageNewSessionTicket {
+    pub(crate) fn handshake_type(&self) -> HandshakeType {
+        HandshakeType::NewSessionTicket
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        4 + 2 + self.ticket.len()
+    }
+
+    pub(crate) fn marshal<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_u32::<BigEndian>(self.ticket_lifetime_hint)?;
+        writer.write_u16::<BigEndian>(self.ticket.len() as u16)?;
+        writer.write_all(&self.ticket)?;
+
+        Ok(writer.flush()?)
+    }
+
+    pub(crate) fn unmarshal<R: Read>(reader: &mut R) -> Result<Self> {
+        let ticket_lifetime_hint = reader.read_u32::<BigEndian>()?;
+        let ticket_len = reader.read_u16::<BigEndian>()? as usize;
+        let mut ticket = vec![0u8; ticket_len];
+        reader.read_exact(&mut ticket)?;
+
+        Ok(HandshakeMessageNewSessionTicket {
+            ticket_lifetime_hint,
+            ticket,
+        })
+    }
+}
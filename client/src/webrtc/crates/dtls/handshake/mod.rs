@@ -1,31 +1,41 @@
 pub(crate) mod handshake_cache;
 pub(crate) mod handshake_header;
 pub(crate) mod handshake_message_certificate;
+pub(crate) mod handshake_message_certificate13;
 pub(crate) mod handshake_message_certificate_request;
+pub(crate) mod handshake_message_certificate_request13;
 pub(crate) mod handshake_message_certificate_verify;
 pub(crate) mod handshake_message_client_hello;
 pub(crate) mod handshake_message_client_key_exchange;
+pub(crate) mod handshake_message_encrypted_extensions;
 pub(crate) mod handshake_message_finished;
 pub(crate) mod handshake_message_hello_verify_request;
+pub(crate) mod handshake_message_key_update;
+pub(crate) mod handshake_message_new_session_ticket;
 pub(crate) mod handshake_message_server_hello;
 pub(crate) mod handshake_message_server_hello_done;
 pub(crate) mod handshake_message_server_key_exchange;
 pub(crate) mod handshake_random;
 
 use std::fmt;
-use std::io::{Read, Write};
+use std::io::{BufReader, Read, Write};
 
 use super::content::*;
 use super::error::*;
 
 use handshake_header::*;
 use handshake_message_certificate::*;
+use handshake_message_certificate13::*;
 use handshake_message_certificate_request::*;
+use handshake_message_certificate_request13::*;
 use handshake_message_certificate_verify::*;
 use handshake_message_client_hello::*;
 use handshake_message_client_key_exchange::*;
+use handshake_message_encrypted_extensions::*;
 use handshake_message_finished::*;
 use handshake_message_hello_verify_request::*;
+use handshake_message_key_update::*;
+use handshake_message_new_session_ticket::*;
 use handshake_message_server_hello::*;
 use handshake_message_server_hello_done::*;
 use handshake_message_server_key_exchange::*;
@@ -37,6 +47,8 @@ pub(crate) enum HandshakeType {
     ClientHello = 1,
     ServerHello = 2,
     HelloVerifyRequest = 3,
+    NewSessionTicket = 4,
+    EncryptedExtensions = 8,
     Certificate = 11,
     ServerKeyExchange = 12,
     CertificateRequest = 13,
@@ -44,6 +56,8 @@ pub(crate) enum HandshakeType {
     CertificateVerify = 15,
     ClientKeyExchange = 16,
     Finished = 20,
+    KeyUpdate = 24,
+    MessageHash = 254,
     Invalid,
 }
 
@@ -54,6 +68,8 @@ impl fmt::Display for HandshakeType {
             HandshakeType::ClientHello => write!(f, "ClientHello"),
             HandshakeType::ServerHello => write!(f, "ServerHello"),
             HandshakeType::HelloVerifyRequest => write!(f, "HelloVerifyRequest"),
+            HandshakeType::NewSessionTicket => write!(f, "NewSessionTicket"),
+            HandshakeType::EncryptedExtensions => write!(f, "EncryptedExtensions"),
             HandshakeType::Certificate => write!(f, "Certificate"),
             HandshakeType::ServerKeyExchange => write!(f, "ServerKeyExchange"),
             HandshakeType::CertificateRequest => write!(f, "CertificateRequest"),
@@ -61,6 +77,8 @@ impl fmt::Display for HandshakeType {
             HandshakeType::CertificateVerify => write!(f, "CertificateVerify"),
             HandshakeType::ClientKeyExchange => write!(f, "ClientKeyExchange"),
             HandshakeType::Finished => write!(f, "Finished"),
+            HandshakeType::KeyUpdate => write!(f, "KeyUpdate"),
+            HandshakeType::MessageHash => write!(f, "MessageHash"),
             HandshakeType::Invalid => write!(f, "Invalid"),
         }
     }
@@ -73,6 +91,8 @@ impl From<u8> for HandshakeType {
             1 => HandshakeType::ClientHello,
             2 => HandshakeType::ServerHello,
             3 => HandshakeType::HelloVerifyRequest,
+            4 => HandshakeType::NewSessionTicket,
+            8 => HandshakeType::EncryptedExtensions,
             11 => HandshakeType::Certificate,
             12 => HandshakeType::ServerKeyExchange,
             13 => HandshakeType::CertificateRequest,
@@ -80,6 +100,8 @@ impl From<u8> for HandshakeType {
             15 => HandshakeType::CertificateVerify,
             16 => HandshakeType::ClientKeyExchange,
             20 => HandshakeType::Finished,
+            24 => HandshakeType::KeyUpdate,
+            254 => HandshakeType::MessageHash,
             _ => HandshakeType::Invalid,
         }
     }
@@ -104,6 +126,11 @@ pub(crate) enum HandshakeMessage {
     CertificateVerify(HandshakeMessageCertificateVerify),
     ClientKeyExchange(HandshakeMessageClientKeyExchange),
     Finished(HandshakeMessageFinished),
+    EncryptedExtensions(HandshakeMessageEncryptedExtensions),
+    Certificate13(HandshakeMessageCertificate13),
+    CertificateRequest13(HandshakeMessageCertificateRequest13),
+    KeyUpdate(HandshakeMessageKeyUpdate),
+    NewSessionTicket(HandshakeMessageNewSessionTicket),
 }
 
 impl HandshakeMessage {
@@ -119,6 +146,11 @@ impl HandshakeMessage {
             HandshakeMessage::CertificateVerify(msg) => msg.handshake_type(),
             HandshakeMessage::ClientKeyExchange(msg) => msg.handshake_type(),
             HandshakeMessage::Finished(msg) => msg.handshake_type(),
+            HandshakeMessage::EncryptedExtensions(msg) => msg.handshake_type(),
+            HandshakeMessage::Certificate13(msg) => msg.handshake_type(),
+            HandshakeMessage::CertificateRequest13(msg) => msg.handshake_type(),
+            HandshakeMessage::KeyUpdate(msg) => msg.handshake_type(),
+            HandshakeMessage::NewSessionTicket(msg) => msg.handshake_type(),
         }
     }
 
@@ -134,6 +166,11 @@ impl HandshakeMessage {
             HandshakeMessage::CertificateVerify(msg) => msg.size(),
             HandshakeMessage::ClientKeyExchange(msg) => msg.size(),
             HandshakeMessage::Finished(msg) => msg.size(),
+            HandshakeMessage::EncryptedExtensions(msg) => msg.size(),
+            HandshakeMessage::Certificate13(msg) => msg.size(),
+            HandshakeMessage::CertificateRequest13(msg) => msg.size(),
+            HandshakeMessage::KeyUpdate(msg) => msg.size(),
+            HandshakeMessage::NewSessionTicket(msg) => msg.size(),
         }
     }
 
@@ -149,6 +186,11 @@ impl HandshakeMessage {
             HandshakeMessage::CertificateVerify(msg) => msg.marshal(writer)?,
             HandshakeMessage::ClientKeyExchange(msg) => msg.marshal(writer)?,
             HandshakeMessage::Finished(msg) => msg.marshal(writer)?,
+            HandshakeMessage::EncryptedExtensions(msg) => msg.marshal(writer)?,
+            HandshakeMessage::Certificate13(msg) => msg.marshal(writer)?,
+            HandshakeMessage::CertificateRequest13(msg) => msg.marshal(writer)?,
+            HandshakeMessage::KeyUpdate(msg) => msg.marshal(writer)?,
+            HandshakeMessage::NewSessionTicket(msg) => msg.marshal(writer)?,
         }
 
         Ok(())
@@ -229,6 +271,15 @@ impl Handshake {
             HandshakeType::Finished => {
                 HandshakeMessage::Finished(HandshakeMessageFinished::unmarshal(reader)?)
             }
+            HandshakeType::EncryptedExtensions => HandshakeMessage::EncryptedExtensions(
+                HandshakeMessageEncryptedExtensions::unmarshal(reader)?,
+            ),
+            HandshakeType::KeyUpdate => {
+                HandshakeMessage::KeyUpdate(HandshakeMessageKeyUpdate::unmarshal(reader)?)
+            }
+            HandshakeType::NewSessionTicket => HandshakeMessage::NewSessionTicket(
+                HandshakeMessageNewSessionTicket::unmarshal(reader)?,
+            ),
             _ => return Err(Error::ErrNotImplemented),
         };
 
@@ -237,4 +288,26 @@ impl Handshake {
             handshake_message,
         })
     }
+
+    // unmarshal_dtls13 parses the messages whose layout changed in DTLS 1.3
+    // https://tools.ietf.org/html/rfc8446#section-4
+    pub(crate) fn unmarshal_dtls13(raw_handshake: &[u8]) -> Result<Self> {
+        let mut reader = BufReader::new(raw_handshake);
+        let handshake_header = HandshakeHeader::unmarshal(&mut reader)?;
+
+        let handshake_message = match handshake_header.handshake_type {
+            HandshakeType::Certificate => HandshakeMessage::Certificate13(
+                HandshakeMessageCertificate13::unmarshal(&mut reader)?,
+            ),
+            HandshakeType::CertificateRequest => HandshakeMessage::CertificateRequest13(
+                HandshakeMessageCertificateRequest13::unmarshal(&mut reader)?,
+            ),
+            _ => return Handshake::unmarshal(&mut BufReader::new(raw_handshake)),
+        };
+
+        Ok(Handshake {
+            handshake_header,
+            handshake_message,
+        })
+    }
 }
@@ -3,8 +3,11 @@ use crate::webrtc::dtls::config::*;
 use crate::webrtc::dtls::conn::*;
 use crate::webrtc::dtls::content::*;
 use crate::webrtc::dtls::crypto::*;
+use crate::webrtc::dtls::curve::named_curve::*;
 use crate::webrtc::dtls::error::*;
 use crate::webrtc::dtls::extension::extension_use_srtp::*;
+use crate::webrtc::dtls::key_log::*;
+use crate::webrtc::dtls::session::*;
 use crate::webrtc::dtls::signature_hash_algorithm::*;
 
 use log::*;
@@ -75,6 +78,7 @@ pub(crate) struct HandshakeConfig {
     pub(crate) local_psk_callback: Option<PskCallback>,
     pub(crate) local_psk_identity_hint: Option<Vec<u8>>,
     pub(crate) local_cipher_suites: Vec<CipherSuiteId>, // Available CipherSuites
+    pub(crate) local_elliptic_curves: Vec<NamedCurve>,  // Available curves for ECDHE
     pub(crate) local_signature_schemes: Vec<SignatureHashAlgorithm>, // Available signature schemes
     pub(crate) extended_master_secret: ExtendedMasterSecretType, // Policy for the Extended Master Support extension
     pub(crate) local_srtp_protection_profiles: Vec<SrtpProtectionProfile>, // Available SRTPProtectionProfiles, if empty no SRTP support
@@ -87,8 +91,14 @@ pub(crate) struct HandshakeConfig {
     pub(crate) roots_cas: rustls::RootCertStore,
     pub(crate) server_cert_verifier: Arc<dyn rustls::ServerCertVerifier>,
     pub(crate) client_cert_verifier: Option<Arc<dyn rustls::ClientCertVerifier>>,
-    pub(crate) retransmit_interval: tokio::time::Duration,
+    pub(crate) retransmit_interval: tokio::time::Duration, // Initial retransmit timer value
+    pub(crate) max_retransmit_interval: tokio::time::Duration, // Upper bound of the backoff
     pub(crate) initial_epoch: u16,
+    pub(crate) enable_dtls13: bool,
+    pub(crate) session_store: Option<Arc<dyn SessionStore + Send + Sync>>,
+    pub(crate) session_key: Vec<u8>, // Key of the server in the session_store
+    // Writer of the secrets for debugging, if enabled
+    pub(crate) key_log: Option<Arc<KeyLog>>,
     //log           logging.LeveledLogger
     //mu sync.Mutex
 }
@@ -99,6 +109,7 @@ impl Default for HandshakeConfig {
             local_psk_callback: None,
             local_psk_identity_hint: None,
             local_cipher_suites: vec![],
+            local_elliptic_curves: vec![],
             local_signature_schemes: vec![],
             extended_master_secret: ExtendedMasterSecretType::Disable,
             local_srtp_protection_profiles: vec![],
@@ -112,7 +123,12 @@ impl Default for HandshakeConfig {
             server_cert_verifier: Arc::new(rustls::WebPKIVerifier::new()),
             client_cert_verifier: None,
             retransmit_interval: tokio::time::Duration::from_secs(0),
+            max_retransmit_interval: tokio::time::Duration::from_secs(0),
             initial_epoch: 0,
+            enable_dtls13: false,
+            session_store: None,
+            session_key: vec![],
+            key_log: None,
         }
     }
 }
@@ -299,7 +315,7 @@ impl DTLSConn {
         }
     }
     async fn wait(&mut self) -> Result<HandshakeState> {
-        let retransmit_timer = tokio::time::sleep(self.cfg.retransmit_interval);
+        let retransmit_timer = tokio::time::sleep(self.retransmit_interval);
         tokio::pin!(retransmit_timer);
 
         loop {
@@ -340,6 +356,7 @@ impl DTLSConn {
                                 return Ok(HandshakeState::Finished);
                             }
                             self.current_flight = next_flight;
+                            self.retransmit_interval = self.cfg.retransmit_interval;
                             return Ok(HandshakeState::Preparing);
                         }
                     };
@@ -351,6 +368,12 @@ impl DTLSConn {
                     if !self.retransmit {
                         return Ok(HandshakeState::Waiting);
                     }
+
+                    // Double the timer value on each retransmission, RFC 6347 Section 4.2.4.1
+                    self.retransmit_interval = std::cmp::min(
+                        self.retransmit_interval * 2,
+                        self.cfg.max_retransmit_interval,
+                    );
                     return Ok(HandshakeState::Sending);
                 }
 
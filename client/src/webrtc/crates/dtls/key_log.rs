@@ -0,0 +1,73 @@
+use super::error::*;
+use super::state::State;
+
+use log::*;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::sync::Mutex;
+
+// KEY_LOG_FILE_ENV names the environment variable read when no key log path is configured
+pub(crate) const KEY_LOG_FILE_ENV: &str = "SSLKEYLOGFILE";
+
+// Labels of the logged secrets, DTLS 1.2 logs the master secret and DTLS 1.3
+// the traffic secrets of the handshake and of the first application epoch
+pub(crate) const KEY_LOG_CLIENT_RANDOM: &str = "CLIENT_RANDOM";
+pub(crate) const KEY_LOG_CLIENT_HANDSHAKE_TRAFFIC_SECRET: &str = "CLIENT_HANDSHAKE_TRAFFIC_SECRET";
+pub(crate) const KEY_LOG_SERVER_HANDSHAKE_TRAFFIC_SECRET: &str = "SERVER_HANDSHAKE_TRAFFIC_SECRET";
+pub(crate) const KEY_LOG_CLIENT_TRAFFIC_SECRET_0: &str = "CLIENT_TRAFFIC_SECRET_0";
+pub(crate) const KEY_LOG_SERVER_TRAFFIC_SECRET_0: &str = "SERVER_TRAFFIC_SECRET_0";
+pub(crate) const KEY_LOG_EXPORTER_SECRET: &str = "EXPORTER_SECRET";
+
+// KeyLog appends connection secrets in the NSS key log format, which lets
+// Wireshark decrypt captured traffic. It defeats the security of every logged
+// connection and is meant for debugging only.
+// https://developer.mozilla.org/en-US/docs/Mozilla/Projects/NSS/Key_Log_Format
+pub(crate) struct KeyLog {
+    file: Mutex<File>,
+}
+
+impl KeyLog {
+    pub(crate) fn open(path: &str) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(KeyLog {
+            file: Mutex::new(file),
+        })
+    }
+
+    // write_state logs the master secret of a DTLS 1.2 connection as
+    // CLIENT_RANDOM <client_random> <master_secret>
+    pub(crate) fn write_state(&self, state: &State) {
+        self.write_secret(state, KEY_LOG_CLIENT_RANDOM, &state.master_secret);
+    }
+
+    // write_secret logs a secret of the connection as
+    // <label> <client_random> <secret>
+    pub(crate) fn write_secret(&self, state: &State, label: &str, secret: &[u8]) {
+        if let Err(err) = self.write_line(state, label, secret) {
+            warn!("failed to write key log: {}", err);
+        }
+    }
+
+    fn write_line(&self, state: &State, label: &str, secret: &[u8]) -> Result<()> {
+        let client_random = if state.is_client {
+            &state.local_random
+        } else {
+            &state.remote_random
+        };
+        let mut raw = vec![];
+        {
+            let mut writer = BufWriter::<&mut Vec<u8>>::new(raw.as_mut());
+            client_random.marshal(&mut writer)?;
+        }
+
+        let line = format!("{} {} {}\n", label, to_hex(&raw), to_hex(secret));
+
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+fn to_hex(b: &[u8]) -> String {
+    b.iter().map(|x| format!("{:02x}", x)).collect()
+}
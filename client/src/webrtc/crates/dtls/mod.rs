@@ -1,3 +1,4 @@
+pub(crate) mod ack;
 pub(crate) mod alert;
 pub(crate) mod application_data;
 pub(crate) mod change_cipher_spec;
@@ -15,9 +16,11 @@ pub(crate) mod flight;
 pub(crate) mod fragment_buffer;
 pub(crate) mod handshake;
 pub(crate) mod handshaker;
+pub(crate) mod key_log;
 pub(crate) mod listener;
 pub(crate) mod prf;
 pub(crate) mod record_layer;
+pub(crate) mod session;
 pub(crate) mod signature_hash_algorithm;
 pub(crate) mod state;
 
@@ -0,0 +1,222 @@
+use super::*;
+
+use byteorder::{BigEndian, WriteBytesExt};
+
+// DTLS 1.3 reuses the TLS 1.3 key schedule but swaps the "tls13 " label prefix
+// https://www.rfc-editor.org/rfc/rfc9147#section-5.9
+pub(crate) const DTLS13_LABEL_PREFIX: &str = "dtls13";
+
+pub(crate) const KEY_SCHEDULE_DERIVED_LABEL: &str = "derived";
+pub(crate) const KEY_SCHEDULE_CLIENT_HANDSHAKE_TRAFFIC_LABEL: &str = "c hs traffic";
+pub(crate) const KEY_SCHEDULE_SERVER_HANDSHAKE_TRAFFIC_LABEL: &str = "s hs traffic";
+pub(crate) const KEY_SCHEDULE_CLIENT_APPLICATION_TRAFFIC_LABEL: &str = "c ap traffic";
+pub(crate) const KEY_SCHEDULE_SERVER_APPLICATION_TRAFFIC_LABEL: &str = "s ap traffic";
+pub(crate) const KEY_SCHEDULE_EXPORTER_MASTER_LABEL: &str = "exp master";
+pub(crate) const KEY_SCHEDULE_FINISHED_LABEL: &str = "finished";
+pub(crate) const KEY_SCHEDULE_TRAFFIC_UPDATE_LABEL: &str = "traffic upd";
+pub(crate) const KEY_SCHEDULE_KEY_LABEL: &str = "key";
+pub(crate) const KEY_SCHEDULE_IV_LABEL: &str = "iv";
+pub(crate) const KEY_SCHEDULE_SN_LABEL: &str = "sn";
+pub(crate) const KEY_SCHEDULE_EXPORTER_LABEL: &str = "exporter";
+
+// HKDF-Extract(salt, IKM), a zero-length salt is replaced by HashLen zero bytes
+// https://tools.ietf.org/html/rfc5869#section-2.2
+pub(crate) fn hkdf_extract(h: CipherSuiteHash, salt: &[u8], ikm: &[u8]) -> Result<Vec<u8>> {
+    if salt.is_empty() {
+        hmac_sha(h, &vec![0u8; h.size()], ikm)
+    } else {
+        hmac_sha(h, salt, ikm)
+    }
+}
+
+// HKDF-Expand(PRK, info, L)
+// https://tools.ietf.org/html/rfc5869#section-2.3
+pub(crate) fn hkdf_expand(
+    h: CipherSuiteHash,
+    prk: &[u8],
+    info: &[u8],
+    length: usize,
+) -> Result<Vec<u8>> {
+    if length > 255 * h.size() {
+        return Err(Error::Other("hkdf: requested length too large".to_owned()));
+    }
+
+    let mut out = vec![];
+    let mut last_round = vec![];
+    let mut counter = 1u8;
+    while out.len() < length {
+        let mut data = last_round.clone();
+        data.extend_from_slice(info);
+        data.push(counter);
+
+        last_round = hmac_sha(h, prk, &data)?;
+        out.extend_from_slice(&last_round);
+        counter += 1;
+    }
+
+    Ok(out[..length].to_vec())
+}
+
+// HKDF-Expand-Label(Secret, Label, Context, Length)
+//
+// struct {
+//     uint16 length = Length;
+//     opaque label<6..255> = "dtls13" + Label;
+//     opaque context<0..255> = Context;
+// } HkdfLabel;
+//
+// https://tools.ietf.org/html/rfc8446#section-7.1
+pub(crate) fn hkdf_expand_label(
+    h: CipherSuiteHash,
+    secret: &[u8],
+    label: &str,
+    context: &[u8],
+    length: usize,
+) -> Result<Vec<u8>> {
+    hkdf_expand_label_with_prefix(h, DTLS13_LABEL_PREFIX, secret, label, context, length)
+}
+
+// hkdf_expand_label_with_prefix is HKDF-Expand-Label with the TLS 1.3 "tls13 "
+// or the DTLS 1.3 "dtls13" prefix
+pub(crate) fn hkdf_expand_label_with_prefix(
+    h: CipherSuiteHash,
+    prefix: &str,
+    secret: &[u8],
+    label: &str,
+    context: &[u8],
+    length: usize,
+) -> Result<Vec<u8>> {
+    let full_label = format!("{}{}", prefix, label);
+
+    let mut info = vec![];
+    info.write_u16::<BigEndian>(length as u16)?;
+    info.write_u8(full_label.len() as u8)?;
+    info.extend_from_slice(full_label.as_bytes());
+    info.write_u8(context.len() as u8)?;
+    info.extend_from_slice(context);
+
+    hkdf_expand(h, secret, &info, length)
+}
+
+// Derive-Secret(Secret, Label, Messages) =
+//     HKDF-Expand-Label(Secret, Label, Transcript-Hash(Messages), Hash.length)
+pub(crate) fn derive_secret(
+    h: CipherSuiteHash,
+    secret: &[u8],
+    label: &str,
+    transcript: &[u8],
+) -> Result<Vec<u8>> {
+    let transcript_hash = transcript_hash(h, transcript);
+    hkdf_expand_label(h, secret, label, &transcript_hash, h.size())
+}
+
+pub(crate) fn transcript_hash(h: CipherSuiteHash, transcript: &[u8]) -> Vec<u8> {
+    let mut hasher = match h {
+        CipherSuiteHash::Sha256 => Sha256::new(),
+    };
+    hasher.update(transcript);
+    hasher.finalize().as_slice().to_vec()
+}
+
+// Early Secret without a PSK, followed by the Handshake Secret for the (EC)DHE shared secret
+//
+//              0
+//              |
+//              v
+//    PSK ->  HKDF-Extract = Early Secret
+//              |
+//              v
+//        Derive-Secret(., "derived", "")
+//              |
+//              v
+//    (EC)DHE -> HKDF-Extract = Handshake Secret
+pub(crate) fn key_schedule_handshake_secret(
+    h: CipherSuiteHash,
+    shared_secret: &[u8],
+) -> Result<Vec<u8>> {
+    let early_secret = hkdf_extract(h, &[], &vec![0u8; h.size()])?;
+    let derived = derive_secret(h, &early_secret, KEY_SCHEDULE_DERIVED_LABEL, &[])?;
+    hkdf_extract(h, &derived, shared_secret)
+}
+
+//        Derive-Secret(., "derived", "")
+//              |
+//              v
+//    0 -> HKDF-Extract = Master Secret
+pub(crate) fn key_schedule_master_secret(
+    h: CipherSuiteHash,
+    handshake_secret: &[u8],
+) -> Result<Vec<u8>> {
+    let derived = derive_secret(h, handshake_secret, KEY_SCHEDULE_DERIVED_LABEL, &[])?;
+    hkdf_extract(h, &derived, &vec![0u8; h.size()])
+}
+
+// verify_data = HMAC(finished_key, Transcript-Hash(Handshake Context, Certificate*, CertificateVerify*))
+// https://tools.ietf.org/html/rfc8446#section-4.4.4
+pub(crate) fn key_schedule_verify_data(
+    h: CipherSuiteHash,
+    base_key: &[u8],
+    transcript: &[u8],
+) -> Result<Vec<u8>> {
+    let finished_key = hkdf_expand_label(h, base_key, KEY_SCHEDULE_FINISHED_LABEL, &[], h.size())?;
+    hmac_sha(h, &finished_key, &transcript_hash(h, transcript))
+}
+
+// application_traffic_secret_N+1 =
+//     HKDF-Expand-Label(application_traffic_secret_N, "traffic upd", "", Hash.length)
+// https://tools.ietf.org/html/rfc8446#section-7.2
+pub(crate) fn key_schedule_next_traffic_secret(
+    h: CipherSuiteHash,
+    traffic_secret: &[u8],
+) -> Result<Vec<u8>> {
+    hkdf_expand_label(
+        h,
+        traffic_secret,
+        KEY_SCHEDULE_TRAFFIC_UPDATE_LABEL,
+        &[],
+        h.size(),
+    )
+}
+
+// TLS-Exporter(label, context_value, key_length) =
+//     HKDF-Expand-Label(Derive-Secret(Secret, label, ""), "exporter", Hash(context_value), key_length)
+// https://tools.ietf.org/html/rfc8446#section-7.5
+pub(crate) fn key_schedule_export(
+    h: CipherSuiteHash,
+    exporter_master_secret: &[u8],
+    label: &str,
+    context: &[u8],
+    length: usize,
+) -> Result<Vec<u8>> {
+    let secret = derive_secret(h, exporter_master_secret, label, &[])?;
+    hkdf_expand_label(
+        h,
+        &secret,
+        KEY_SCHEDULE_EXPORTER_LABEL,
+        &transcript_hash(h, context),
+        length,
+    )
+}
+
+// Traffic keys for one direction of one epoch. The "sn" key protects the record
+// sequence number of the unified header.
+// https://www.rfc-editor.org/rfc/rfc9147#section-4.2.3
+#[derive(PartialEq, Debug, Clone)]
+pub(crate) struct TrafficKeys {
+    pub(crate) write_key: Vec<u8>,
+    pub(crate) write_iv: Vec<u8>,
+    pub(crate) sn_key: Vec<u8>,
+}
+
+pub(crate) fn key_schedule_traffic_keys(
+    h: CipherSuiteHash,
+    traffic_secret: &[u8],
+    key_len: usize,
+    iv_len: usize,
+) -> Result<TrafficKeys> {
+    Ok(TrafficKeys {
+        write_key: hkdf_expand_label(h, traffic_secret, KEY_SCHEDULE_KEY_LABEL, &[], key_len)?,
+        write_iv: hkdf_expand_label(h, traffic_secret, KEY_SCHEDULE_IV_LABEL, &[], iv_len)?,
+        sn_key: hkdf_expand_label(h, traffic_secret, KEY_SCHEDULE_SN_LABEL, &[], key_len)?,
+    })
+}
@@ -0,0 +1,187 @@
+use super::key_schedule::*;
+use crate::webrtc::dtls::cipher_suite::CipherSuiteHash;
+
+// RFC 8448 only has TLS 1.3 traces, so the steps are checked with the "tls13 "
+// prefix and the DTLS 1.3 schedule against the same steps with "dtls13"
+const TLS13_LABEL_PREFIX: &str = "tls13 ";
+
+const HASH: CipherSuiteHash = CipherSuiteHash::Sha256;
+
+fn from_hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+// Simple 1-RTT Handshake, https://www.rfc-editor.org/rfc/rfc8448#section-3
+const RFC8448_EARLY_SECRET: &str =
+    "33ad0a1c607ec03b09e6cd9893680ce210adf300aa1f2660e1b22e10f170f92a";
+const RFC8448_DERIVED_EARLY: &str =
+    "6f2615a108c702c5678f54fc9dbab69716c076189c48250cebeac3576c3611ba";
+const RFC8448_SHARED_SECRET: &str =
+    "8bd4054fb55b9d63fdfbacf9f04b9f0d35e6d63f537563efd46272900f89492d";
+const RFC8448_HANDSHAKE_SECRET: &str =
+    "1dc826e93606aa6fdc0aadc12f741b01046aa6b99f691ed221a9f0ca043fbeac";
+const RFC8448_MASTER_SECRET: &str =
+    "18df06843d13a08bf2a449844c5f8a478001bc4d4c627984d5a41da8d0402919";
+// Transcript-Hash(ClientHello, ServerHello)
+const RFC8448_HELLO_HASH: &str = "860c06edc07858ee8e78f0e7428c58edd6b43f2ca3e6e95f02ed063cf0e1cad8";
+const RFC8448_CLIENT_HANDSHAKE_TRAFFIC: &str =
+    "b3eddb126e067f35a780b3abf45e2d8f3b1a950738f52e9600746a0e27a55a21";
+const RFC8448_SERVER_HANDSHAKE_TRAFFIC: &str =
+    "b67b7d690cc16c4e75e54213cb2d37b4e9c912bcded9105d42befd59d391ad38";
+const RFC8448_SERVER_HANDSHAKE_KEY: &str = "3fce516009c21727d0f2e4e86ee403bc";
+const RFC8448_SERVER_HANDSHAKE_IV: &str = "5d313eb2671276ee13000b30";
+
+// derive_secret_with_prefix is Derive-Secret over an empty transcript
+fn derive_secret_with_prefix(prefix: &str, secret: &[u8], label: &str) -> Vec<u8> {
+    hkdf_expand_label_with_prefix(
+        HASH,
+        prefix,
+        secret,
+        label,
+        &transcript_hash(HASH, &[]),
+        HASH.size(),
+    )
+    .unwrap()
+}
+
+fn handshake_secret_with_prefix(prefix: &str, shared_secret: &[u8]) -> Vec<u8> {
+    let early_secret = hkdf_extract(HASH, &[], &[0u8; 32]).unwrap();
+    let derived = derive_secret_with_prefix(prefix, &early_secret, KEY_SCHEDULE_DERIVED_LABEL);
+    hkdf_extract(HASH, &derived, shared_secret).unwrap()
+}
+
+fn master_secret_with_prefix(prefix: &str, handshake_secret: &[u8]) -> Vec<u8> {
+    let derived = derive_secret_with_prefix(prefix, handshake_secret, KEY_SCHEDULE_DERIVED_LABEL);
+    hkdf_extract(HASH, &derived, &[0u8; 32]).unwrap()
+}
+
+#[test]
+fn test_key_schedule_rfc8448_early_secret() {
+    let early_secret = hkdf_extract(HASH, &[], &[0u8; 32]).unwrap();
+    assert_eq!(early_secret, from_hex(RFC8448_EARLY_SECRET));
+
+    let derived = derive_secret_with_prefix(
+        TLS13_LABEL_PREFIX,
+        &early_secret,
+        KEY_SCHEDULE_DERIVED_LABEL,
+    );
+    assert_eq!(derived, from_hex(RFC8448_DERIVED_EARLY));
+}
+
+#[test]
+fn test_key_schedule_rfc8448_secrets() {
+    let shared_secret = from_hex(RFC8448_SHARED_SECRET);
+
+    let handshake_secret = handshake_secret_with_prefix(TLS13_LABEL_PREFIX, &shared_secret);
+    assert_eq!(handshake_secret, from_hex(RFC8448_HANDSHAKE_SECRET));
+
+    let master_secret = master_secret_with_prefix(TLS13_LABEL_PREFIX, &handshake_secret);
+    assert_eq!(master_secret, from_hex(RFC8448_MASTER_SECRET));
+
+    let hello_hash = from_hex(RFC8448_HELLO_HASH);
+    for (label, expected) in [
+        (
+            KEY_SCHEDULE_CLIENT_HANDSHAKE_TRAFFIC_LABEL,
+            RFC8448_CLIENT_HANDSHAKE_TRAFFIC,
+        ),
+        (
+            KEY_SCHEDULE_SERVER_HANDSHAKE_TRAFFIC_LABEL,
+            RFC8448_SERVER_HANDSHAKE_TRAFFIC,
+        ),
+    ] {
+        let secret = hkdf_expand_label_with_prefix(
+            HASH,
+            TLS13_LABEL_PREFIX,
+            &handshake_secret,
+            label,
+            &hello_hash,
+            HASH.size(),
+        )
+        .unwrap();
+        assert_eq!(secret, from_hex(expected), "{}", label);
+    }
+}
+
+#[test]
+fn test_key_schedule_rfc8448_traffic_keys() {
+    let secret = from_hex(RFC8448_SERVER_HANDSHAKE_TRAFFIC);
+
+    let key = hkdf_expand_label_with_prefix(
+        HASH,
+        TLS13_LABEL_PREFIX,
+        &secret,
+        KEY_SCHEDULE_KEY_LABEL,
+        &[],
+        16,
+    )
+    .unwrap();
+    assert_eq!(key, from_hex(RFC8448_SERVER_HANDSHAKE_KEY));
+
+    let iv = hkdf_expand_label_with_prefix(
+        HASH,
+        TLS13_LABEL_PREFIX,
+        &secret,
+        KEY_SCHEDULE_IV_LABEL,
+        &[],
+        12,
+    )
+    .unwrap();
+    assert_eq!(iv, from_hex(RFC8448_SERVER_HANDSHAKE_IV));
+}
+
+#[test]
+fn test_key_schedule_dtls13_prefix() {
+    let shared_secret = from_hex(RFC8448_SHARED_SECRET);
+
+    // Same steps as RFC 8448, only the label prefix differs
+    let handshake_secret = key_schedule_handshake_secret(HASH, &shared_secret).unwrap();
+    assert_eq!(
+        handshake_secret,
+        handshake_secret_with_prefix(DTLS13_LABEL_PREFIX, &shared_secret)
+    );
+    assert_ne!(handshake_secret, from_hex(RFC8448_HANDSHAKE_SECRET));
+
+    let master_secret = key_schedule_master_secret(HASH, &handshake_secret).unwrap();
+    assert_eq!(
+        master_secret,
+        master_secret_with_prefix(DTLS13_LABEL_PREFIX, &handshake_secret)
+    );
+
+    let secret = from_hex(RFC8448_SERVER_HANDSHAKE_TRAFFIC);
+    let keys = key_schedule_traffic_keys(HASH, &secret, 16, 12).unwrap();
+    for (got, label, len) in [
+        (&keys.write_key, KEY_SCHEDULE_KEY_LABEL, 16),
+        (&keys.write_iv, KEY_SCHEDULE_IV_LABEL, 12),
+        (&keys.sn_key, KEY_SCHEDULE_SN_LABEL, 16),
+    ] {
+        let expected =
+            hkdf_expand_label_with_prefix(HASH, DTLS13_LABEL_PREFIX, &secret, label, &[], len)
+                .unwrap();
+        assert_eq!(got, &expected, "{}", label);
+    }
+    assert_ne!(keys.write_key, from_hex(RFC8448_SERVER_HANDSHAKE_KEY));
+}
+
+#[test]
+fn test_key_schedule_export_context() {
+    let secret = from_hex(RFC8448_MASTER_SECRET);
+
+    let without_context = key_schedule_export(HASH, &secret, "EXPERIMENTAL", &[], 32).unwrap();
+    let with_context = key_schedule_export(HASH, &secret, "EXPERIMENTAL", b"ctx", 32).unwrap();
+    assert_eq!(without_context.len(), 32);
+    assert_ne!(without_context, with_context);
+
+    let derived = derive_secret(HASH, &secret, "EXPERIMENTAL", &[]).unwrap();
+    let expected = hkdf_expand_label(
+        HASH,
+        &derived,
+        KEY_SCHEDULE_EXPORTER_LABEL,
+        &transcript_hash(HASH, b"ctx"),
+        32,
+    )
+    .unwrap();
+    assert_eq!(with_context, expected);
+}
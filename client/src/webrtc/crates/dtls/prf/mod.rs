@@ -1,3 +1,8 @@
+pub(crate) mod key_schedule;
+
+#[cfg(test)]
+mod key_schedule_test;
+
 use std::convert::TryInto;
 use std::fmt;
 
@@ -304,3 +309,20 @@ pub(crate) fn prf_mac(
 
     Ok(result.into_bytes().to_vec())
 }
+
+// compute the MAC using HMAC-SHA1 over already serialized record fields, used
+// for tls12_cid records whose MAC input matches the AEAD additional data
+// https://www.rfc-editor.org/rfc/rfc9146#section-5.1
+pub(crate) fn prf_mac_with_additional_data(
+    additional_data: &[u8],
+    payload: &[u8],
+    key: &[u8],
+) -> Result<Vec<u8>> {
+    let mut hmac = HmacSha1::new_varkey(key).map_err(|e| Error::Other(e.to_string()))?;
+
+    hmac.update(additional_data);
+    hmac.update(payload);
+    let result = hmac.finalize();
+
+    Ok(result.into_bytes().to_vec())
+}
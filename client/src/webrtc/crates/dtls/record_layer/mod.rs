@@ -1,5 +1,10 @@
 pub(crate) mod record_layer_header;
+pub(crate) mod unified_header;
 
+#[cfg(test)]
+mod unified_header_test;
+
+use super::ack::Ack;
 use super::content::*;
 use super::error::*;
 use crate::webrtc::dtls::alert::Alert;
@@ -7,6 +12,7 @@ use crate::webrtc::dtls::application_data::ApplicationData;
 use crate::webrtc::dtls::change_cipher_spec::ChangeCipherSpec;
 use crate::webrtc::dtls::handshake::Handshake;
 use record_layer_header::*;
+use unified_header::*;
 
 use std::io::{Read, Write};
 
@@ -40,6 +46,7 @@ impl RecordLayer {
                 protocol_version,
                 epoch,
                 sequence_number: 0,
+                connection_id: vec![],
                 content_len: content.size() as u16,
             },
             content,
@@ -63,6 +70,7 @@ impl RecordLayer {
                 Content::ChangeCipherSpec(ChangeCipherSpec::unmarshal(reader)?)
             }
             ContentType::Handshake => Content::Handshake(Handshake::unmarshal(reader)?),
+            ContentType::Ack => Content::Ack(Ack::unmarshal(reader)?),
             _ => return Err(Error::Other("Invalid Content Type".to_owned())),
         };
 
@@ -79,18 +87,38 @@ impl RecordLayer {
 // two DTLS messages into the same datagram: in the same record or in
 // separate records.
 // https://tools.ietf.org/html/rfc6347#section-4.2.3
-pub(crate) fn unpack_datagram(buf: &[u8]) -> Result<Vec<Vec<u8>>> {
+//
+// DTLS 1.3 protected records use the unified header instead and may share the
+// datagram with DTLSPlaintext records.
+// https://www.rfc-editor.org/rfc/rfc9147#section-4.2
+//
+// tls12_cid records move the length field behind the CID, connection_id_len is
+// the length of the CID we asked the peer to use.
+// https://www.rfc-editor.org/rfc/rfc9146#section-4
+pub(crate) fn unpack_datagram(buf: &[u8], connection_id_len: usize) -> Result<Vec<Vec<u8>>> {
     let mut out = vec![];
 
     let mut offset = 0;
     while buf.len() != offset {
-        if buf.len() - offset <= RECORD_LAYER_HEADER_SIZE {
+        if is_unified_header(buf[offset]) {
+            let pkt_len = unified_record_size(&buf[offset..])?;
+            out.push(buf[offset..offset + pkt_len].to_vec());
+            offset += pkt_len;
+            continue;
+        }
+
+        let header_size = if buf[offset] == ContentType::Tls12Cid as u8 {
+            RECORD_LAYER_HEADER_SIZE + connection_id_len
+        } else {
+            RECORD_LAYER_HEADER_SIZE
+        };
+        if buf.len() - offset <= header_size {
             return Err(Error::ErrInvalidPacketLength);
         }
 
-        let pkt_len = RECORD_LAYER_HEADER_SIZE
-            + (((buf[offset + RECORD_LAYER_HEADER_SIZE - 2] as usize) << 8)
-                | buf[offset + RECORD_LAYER_HEADER_SIZE - 1] as usize);
+        let pkt_len = header_size
+            + (((buf[offset + header_size - 2] as usize) << 8)
+                | buf[offset + header_size - 1] as usize);
         if offset + pkt_len > buf.len() {
             return Err(Error::ErrInvalidPacketLength);
         }
@@ -11,6 +11,9 @@ pub(crate) const MAX_SEQUENCE_NUMBER: u64 = 0x0000FFFFFFFFFFFF;
 pub(crate) const DTLS1_2MAJOR: u8 = 0xfe;
 pub(crate) const DTLS1_2MINOR: u8 = 0xfd;
 
+pub(crate) const DTLS1_3MAJOR: u8 = 0xfe;
+pub(crate) const DTLS1_3MINOR: u8 = 0xfc;
+
 pub(crate) const DTLS1_0MAJOR: u8 = 0xfe;
 pub(crate) const DTLS1_0MINOR: u8 = 0xff;
 
@@ -23,6 +26,14 @@ pub(crate) const PROTOCOL_VERSION1_2: ProtocolVersion = ProtocolVersion {
     minor: DTLS1_2MINOR,
 };
 
+// DTLS 1.3 is only ever negotiated through the supported_versions extension,
+// records keep using the DTLS 1.2 version number.
+// https://www.rfc-editor.org/rfc/rfc9147#section-5.3
+pub(crate) const PROTOCOL_VERSION1_3: ProtocolVersion = ProtocolVersion {
+    major: DTLS1_3MAJOR,
+    minor: DTLS1_3MINOR,
+};
+
 // https://tools.ietf.org/html/rfc4346#section-6.2.1
 #[derive(Copy, Clone, PartialEq, Debug, Default)]
 pub(crate) struct ProtocolVersion {
@@ -30,16 +41,25 @@ pub(crate) struct ProtocolVersion {
     pub(crate) minor: u8,
 }
 
-#[derive(Copy, Clone, PartialEq, Debug, Default)]
+#[derive(Clone, PartialEq, Debug, Default)]
 pub(crate) struct RecordLayerHeader {
     pub(crate) content_type: ContentType,
     pub(crate) protocol_version: ProtocolVersion,
     pub(crate) epoch: u16,
-    pub(crate) sequence_number: u64, // uint48 in spec
+    pub(crate) sequence_number: u64,   // uint48 in spec
+    pub(crate) connection_id: Vec<u8>, // tls12_cid records only
     pub(crate) content_len: u16,
 }
 
 impl RecordLayerHeader {
+    pub(crate) fn size(&self) -> usize {
+        if self.content_type == ContentType::Tls12Cid {
+            RECORD_LAYER_HEADER_SIZE + self.connection_id.len()
+        } else {
+            RECORD_LAYER_HEADER_SIZE
+        }
+    }
+
     pub(crate) fn marshal<W: Write>(&self, writer: &mut W) -> Result<()> {
         if self.sequence_number > MAX_SEQUENCE_NUMBER {
             return Err(Error::ErrSequenceNumberOverflow);
@@ -53,12 +73,28 @@ impl RecordLayerHeader {
         let be: [u8; 8] = self.sequence_number.to_be_bytes();
         writer.write_all(&be[2..])?; // uint48 in spec
 
+        // The CID sits between the sequence number and the length, its length is
+        // not on the wire but known from the negotiation
+        // https://www.rfc-editor.org/rfc/rfc9146#section-4
+        if self.content_type == ContentType::Tls12Cid {
+            writer.write_all(&self.connection_id)?;
+        }
+
         writer.write_u16::<BigEndian>(self.content_len)?;
 
         Ok(writer.flush()?)
     }
 
     pub(crate) fn unmarshal<R: Read>(reader: &mut R) -> Result<Self> {
+        RecordLayerHeader::unmarshal_with_connection_id(reader, 0)
+    }
+
+    // unmarshal_with_connection_id reads a header that may belong to a tls12_cid
+    // record carrying a CID of connection_id_len bytes
+    pub(crate) fn unmarshal_with_connection_id<R: Read>(
+        reader: &mut R,
+        connection_id_len: usize,
+    ) -> Result<Self> {
         let content_type = reader.read_u8()?.into();
         let major = reader.read_u8()?;
         let minor = reader.read_u8()?;
@@ -73,6 +109,12 @@ impl RecordLayerHeader {
         if protocol_version != PROTOCOL_VERSION1_0 && protocol_version != PROTOCOL_VERSION1_2 {
             return Err(Error::ErrUnsupportedProtocolVersion);
         }
+
+        let mut connection_id = vec![];
+        if content_type == ContentType::Tls12Cid {
+            connection_id = vec![0u8; connection_id_len];
+            reader.read_exact(&mut connection_id)?;
+        }
         let content_len = reader.read_u16::<BigEndian>()?;
 
         Ok(RecordLayerHeader {
@@ -80,6 +122,7 @@ impl RecordLayerHeader {
             protocol_version,
             epoch,
             sequence_number,
+            connection_id,
             content_len,
         })
     }
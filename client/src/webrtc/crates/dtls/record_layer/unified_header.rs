@@ -0,0 +1,71 @@
+use crate::webrtc::dtls::error::*;
+
+// DTLS 1.3 protected records replace the 13 byte header with a variable length
+// unified header, the first byte carries the flags and the low bits of the epoch.
+//
+//  0 1 2 3 4 5 6 7
+// +-+-+-+-+-+-+-+-+
+// |0|0|1|C|S|L|E E|
+// +-+-+-+-+-+-+-+-+
+// | Connection ID |   Legend:
+// | (if any,      |
+// /  length as    /   C   - Connection ID (CID) present
+// |  negotiated)  |   S   - Sequence number length
+// +-+-+-+-+-+-+-+-+   L   - Length present
+// |  8 or 16 bit  |   E   - Epoch
+// |Sequence Number|
+// +-+-+-+-+-+-+-+-+
+// | 16 bit Length |
+// | (if present)  |
+// +-+-+-+-+-+-+-+-+
+// https://www.rfc-editor.org/rfc/rfc9147#section-4
+pub(crate) const UNIFIED_HEADER_FIXED_BITS_MASK: u8 = 0b1110_0000;
+pub(crate) const UNIFIED_HEADER_FIXED_BITS: u8 = 0b0010_0000;
+pub(crate) const UNIFIED_HEADER_CID_FLAG: u8 = 0b0001_0000;
+pub(crate) const UNIFIED_HEADER_SEQUENCE_NUMBER_16_FLAG: u8 = 0b0000_1000;
+pub(crate) const UNIFIED_HEADER_LENGTH_FLAG: u8 = 0b0000_0100;
+pub(crate) const UNIFIED_HEADER_EPOCH_MASK: u8 = 0b0000_0011;
+
+pub(crate) fn is_unified_header(first_byte: u8) -> bool {
+    first_byte & UNIFIED_HEADER_FIXED_BITS_MASK == UNIFIED_HEADER_FIXED_BITS
+}
+
+// unified_header_size returns the size of the header described by the flags in its first byte
+pub(crate) fn unified_header_size(flags: u8) -> Result<usize> {
+    if flags & UNIFIED_HEADER_CID_FLAG != 0 {
+        // We never negotiate a connection id for DTLS 1.3
+        return Err(Error::ErrInvalidPacketLength);
+    }
+
+    let mut size = 1;
+    size += if flags & UNIFIED_HEADER_SEQUENCE_NUMBER_16_FLAG != 0 {
+        2
+    } else {
+        1
+    };
+    if flags & UNIFIED_HEADER_LENGTH_FLAG != 0 {
+        size += 2;
+    }
+
+    Ok(size)
+}
+
+// unified_record_size returns the size of the record at the start of buf. Without
+// the length field the record extends to the end of the datagram.
+pub(crate) fn unified_record_size(buf: &[u8]) -> Result<usize> {
+    let header_size = unified_header_size(buf[0])?;
+    if buf.len() < header_size {
+        return Err(Error::ErrInvalidPacketLength);
+    }
+
+    if buf[0] & UNIFIED_HEADER_LENGTH_FLAG == 0 {
+        return Ok(buf.len());
+    }
+
+    let length = ((buf[header_size - 2] as usize) << 8) | buf[header_size - 1] as usize;
+    if header_size + length > buf.len() {
+        return Err(Error::ErrInvalidPacketLength);
+    }
+
+    Ok(header_size + length)
+}
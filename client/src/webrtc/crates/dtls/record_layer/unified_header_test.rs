@@ -0,0 +1,67 @@
+use super::unified_header::*;
+use crate::webrtc::dtls::error::Error;
+
+#[test]
+fn test_is_unified_header() {
+    // Only the 001 prefix is a unified header, content types 20 to 26 are not
+    for first_byte in [0x20, 0x2c, 0x2f, 0x3f] {
+        assert!(is_unified_header(first_byte), "{:#04x}", first_byte);
+    }
+    for first_byte in [0x14, 0x16, 0x17, 0x19, 0x1a, 0x40, 0x00] {
+        assert!(!is_unified_header(first_byte), "{:#04x}", first_byte);
+    }
+}
+
+#[test]
+fn test_unified_header_size() {
+    let tests = [
+        (UNIFIED_HEADER_FIXED_BITS, 2),
+        (UNIFIED_HEADER_FIXED_BITS | UNIFIED_HEADER_LENGTH_FLAG, 4),
+        (
+            UNIFIED_HEADER_FIXED_BITS | UNIFIED_HEADER_SEQUENCE_NUMBER_16_FLAG,
+            3,
+        ),
+        (
+            UNIFIED_HEADER_FIXED_BITS
+                | UNIFIED_HEADER_SEQUENCE_NUMBER_16_FLAG
+                | UNIFIED_HEADER_LENGTH_FLAG
+                | UNIFIED_HEADER_EPOCH_MASK,
+            5,
+        ),
+    ];
+    for (flags, size) in tests {
+        assert_eq!(unified_header_size(flags).unwrap(), size, "{:#010b}", flags);
+    }
+
+    assert_eq!(
+        unified_header_size(UNIFIED_HEADER_FIXED_BITS | UNIFIED_HEADER_CID_FLAG),
+        Err(Error::ErrInvalidPacketLength)
+    );
+}
+
+#[test]
+fn test_unified_record_size() {
+    let flags = UNIFIED_HEADER_FIXED_BITS
+        | UNIFIED_HEADER_SEQUENCE_NUMBER_16_FLAG
+        | UNIFIED_HEADER_LENGTH_FLAG;
+
+    // The length field delimits the record, a second one may follow
+    let mut datagram = vec![flags, 0x12, 0x34, 0x00, 0x03, 0xaa, 0xbb, 0xcc];
+    datagram.extend_from_slice(&[flags, 0x00, 0x01, 0x00, 0x01, 0xdd]);
+    assert_eq!(unified_record_size(&datagram).unwrap(), 8);
+    assert_eq!(unified_record_size(&datagram[8..]).unwrap(), 6);
+
+    // Without the length field the record extends to the end of the datagram
+    let datagram = [UNIFIED_HEADER_FIXED_BITS, 0x01, 0xaa, 0xbb, 0xcc];
+    assert_eq!(unified_record_size(&datagram).unwrap(), 5);
+
+    // Truncated header and truncated record
+    assert_eq!(
+        unified_record_size(&[flags, 0x00, 0x01]),
+        Err(Error::ErrInvalidPacketLength)
+    );
+    assert_eq!(
+        unified_record_size(&[flags, 0x00, 0x01, 0x00, 0x04, 0xaa]),
+        Err(Error::ErrInvalidPacketLength)
+    );
+}
@@ -0,0 +1,49 @@
+use super::cipher_suite::*;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// Session holds what a client needs to resume a DTLS 1.2 session with the
+// abbreviated handshake, either by session id or by session ticket
+// https://tools.ietf.org/html/rfc5246#section-7.3
+// https://tools.ietf.org/html/rfc5077#section-3.1
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Session {
+    pub(crate) id: Vec<u8>,
+    pub(crate) ticket: Vec<u8>, // empty if the server did not issue a ticket
+    pub(crate) secret: Vec<u8>, // master secret
+    pub(crate) cipher_suite_id: CipherSuiteId,
+    pub(crate) extended_master_secret: bool,
+    pub(crate) peer_certificates: Vec<Vec<u8>>,
+}
+
+// SessionStore caches sessions on the client, keyed by something identifying
+// the server such as its certificate fingerprint or its address
+pub(crate) trait SessionStore {
+    fn set(&self, key: &[u8], session: Session);
+    fn get(&self, key: &[u8]) -> Option<Session>;
+    fn del(&self, key: &[u8]);
+}
+
+// MemorySessionStore keeps sessions in memory for as long as it is referenced
+#[derive(Default)]
+pub(crate) struct MemorySessionStore {
+    sessions: Mutex<HashMap<Vec<u8>, Session>>,
+}
+
+impl SessionStore for MemorySessionStore {
+    fn set(&self, key: &[u8], session: Session) {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.insert(key.to_vec(), session);
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Session> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions.get(key).cloned()
+    }
+
+    fn del(&self, key: &[u8]) {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.remove(key);
+    }
+}
@@ -56,6 +56,12 @@ impl fmt::Display for HashAlgorithm {
 pub(crate) enum SignatureAlgorithm {
     Rsa = 1,
     Ecdsa = 3,
+    // RSASSA-PSS with the rsaEncryption key OID, the hash is part of the
+    // algorithm and the hash byte is always 8
+    // https://tools.ietf.org/html/rfc8446#section-4.2.3
+    RsaPssRsaeSha256 = 4,
+    RsaPssRsaeSha384 = 5,
+    RsaPssRsaeSha512 = 6,
     Ed25519 = 7,
     Unsupported,
 }
@@ -65,6 +71,9 @@ impl From<u8> for SignatureAlgorithm {
         match val {
             1 => SignatureAlgorithm::Rsa,
             3 => SignatureAlgorithm::Ecdsa,
+            4 => SignatureAlgorithm::RsaPssRsaeSha256,
+            5 => SignatureAlgorithm::RsaPssRsaeSha384,
+            6 => SignatureAlgorithm::RsaPssRsaeSha512,
             7 => SignatureAlgorithm::Ed25519,
             _ => SignatureAlgorithm::Unsupported,
         }
@@ -118,6 +127,18 @@ pub(crate) fn default_signature_schemes() -> Vec<SignatureHashAlgorithm> {
             hash: HashAlgorithm::Ed25519,
             signature: SignatureAlgorithm::Ed25519,
         },
+        SignatureHashAlgorithm {
+            hash: HashAlgorithm::Ed25519,
+            signature: SignatureAlgorithm::RsaPssRsaeSha256,
+        },
+        SignatureHashAlgorithm {
+            hash: HashAlgorithm::Ed25519,
+            signature: SignatureAlgorithm::RsaPssRsaeSha384,
+        },
+        SignatureHashAlgorithm {
+            hash: HashAlgorithm::Ed25519,
+            signature: SignatureAlgorithm::RsaPssRsaeSha512,
+        },
     ]
 }
 
@@ -1,9 +1,13 @@
 use super::cipher_suite::*;
 use super::conn::*;
+use super::crypto::crypto_dtls13::*;
 use super::curve::named_curve::*;
 use super::extension::extension_use_srtp::SrtpProtectionProfile;
 use super::handshake::handshake_random::*;
+use super::prf::key_schedule::*;
 use super::prf::*;
+use super::record_layer::record_layer_header::*;
+use super::session::*;
 
 use crate::webrtc::util::KeyingMaterialExporter;
 use crate::webrtc::util::KeyingMaterialExporterError;
@@ -45,6 +49,25 @@ pub(crate) struct State {
     pub(crate) local_key_signature: Vec<u8>,       // cached keySignature
     pub(crate) peer_certificates_verified: bool,
     //pub(crate) replay_detector: Vec<Box<dyn ReplayDetector + Send + Sync>>,
+
+    // DTLS 1.3, https://www.rfc-editor.org/rfc/rfc9147
+    pub(crate) protocol_version: ProtocolVersion, // Negotiated version, zero until the ServerHello
+    pub(crate) hello_retry_cookie: Vec<u8>,
+    pub(crate) hello_retry_transcript: Vec<u8>, // message_hash || HelloRetryRequest
+    pub(crate) handshake_secret: Vec<u8>,
+    pub(crate) client_handshake_traffic_secret: Vec<u8>,
+    pub(crate) server_handshake_traffic_secret: Vec<u8>,
+    pub(crate) exporter_master_secret: Vec<u8>,
+    pub(crate) dtls13: Arc<Mutex<Dtls13EpochKeys>>,
+
+    // DTLS Connection ID, https://www.rfc-editor.org/rfc/rfc9146
+    pub(crate) local_connection_id: Option<Vec<u8>>, // CID we asked the peer to use, None if not offered
+    pub(crate) remote_connection_id: Arc<Mutex<Option<Vec<u8>>>>, // CID the peer asked us to use
+
+    // Session resumption, https://tools.ietf.org/html/rfc5077
+    pub(crate) session_id: Vec<u8>, // offered in the ClientHello, then the one the server assigned
+    pub(crate) session_ticket: Vec<u8>, // ticket of the last NewSessionTicket
+    pub(crate) offered_session: Option<Session>, // cached session offered in the ClientHello
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
@@ -94,6 +117,19 @@ impl Default for State {
             local_key_signature: vec![],         // cached keySignature
             peer_certificates_verified: false,
             //replay_detector: vec![],
+            protocol_version: ProtocolVersion::default(),
+            hello_retry_cookie: vec![],
+            hello_retry_transcript: vec![],
+            handshake_secret: vec![],
+            client_handshake_traffic_secret: vec![],
+            server_handshake_traffic_secret: vec![],
+            exporter_master_secret: vec![],
+            dtls13: Arc::new(Mutex::new(Dtls13EpochKeys::default())),
+            local_connection_id: None,
+            remote_connection_id: Arc::new(Mutex::new(None)),
+            session_id: vec![],
+            session_ticket: vec![],
+            offered_session: None,
         }
     }
 }
@@ -114,12 +150,25 @@ impl KeyingMaterialExporter for State {
 
         if self.local_epoch.load(Ordering::SeqCst) == 0 {
             return Err(HandshakeInProgress);
-        } else if !context.is_empty() {
-            return Err(ContextUnsupported);
         } else if INVALID_KEYING_LABELS.contains_key(label) {
             return Err(ReservedExportKeyingMaterial);
         }
 
+        // The TLS 1.3 exporter hashes the context into the output
+        // https://tools.ietf.org/html/rfc8446#section-7.5
+        if self.protocol_version == PROTOCOL_VERSION1_3 {
+            let hash = self
+                .dtls13
+                .lock()
+                .await
+                .hash()
+                .map_err(|_| CipherSuiteUnset)?;
+            return key_schedule_export(hash, &self.exporter_master_secret, label, context, length)
+                .map_err(|err| Hash(err.to_string()));
+        } else if context.len() > u16::MAX as usize {
+            return Err(ContextUnsupported);
+        }
+
         let mut local_random = vec![];
         {
             let mut writer = BufWriter::<&mut Vec<u8>>::new(local_random.as_mut());
@@ -140,6 +189,14 @@ impl KeyingMaterialExporter for State {
             seed.extend_from_slice(&local_random);
         }
 
+        // RFC 5705 appends the context with its 16 bit length. An empty context
+        // is taken as no context at all, as DTLS-SRTP uses it.
+        // https://tools.ietf.org/html/rfc5705#section-4
+        if !context.is_empty() {
+            seed.extend_from_slice(&(context.len() as u16).to_be_bytes());
+            seed.extend_from_slice(context);
+        }
+
         let cipher_suite = self.cipher_suite.lock().await;
         if let Some(cipher_suite) = &*cipher_suite {
             match prf_p_hash(&self.master_secret, &seed, length, cipher_suite.hash_func()) {
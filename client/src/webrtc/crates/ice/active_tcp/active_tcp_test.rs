@@ -0,0 +1,138 @@
+use super::*;
+use crate::webrtc::ice::candidate::candidate_base::unmarshal_candidate;
+use crate::webrtc::ice::candidate::{Candidate, CandidateType};
+use crate::webrtc::ice::tcp_type::TcpType;
+use crate::webrtc::stun::agent::TransactionId;
+use crate::webrtc::stun::message::{Message, BINDING_REQUEST, BINDING_SUCCESS};
+
+use std::net::Ipv4Addr;
+use tokio::net::TcpListener;
+
+fn stun_message(typ: crate::webrtc::stun::message::MessageType) -> Vec<u8> {
+    let mut m = Message::new();
+    m.build(&[Box::new(typ), Box::new(TransactionId::new())])
+        .unwrap();
+    m.raw
+}
+
+fn frame(packet: &[u8]) -> Vec<u8> {
+    let mut frame = (packet.len() as u16).to_be_bytes().to_vec();
+    frame.extend_from_slice(packet);
+    frame
+}
+
+async fn read_frame(stream: &mut TcpStream) -> Vec<u8> {
+    let mut header = [0u8; FRAME_HEADER_SIZE];
+    stream.read_exact(&mut header).await.unwrap();
+    let mut packet = vec![0u8; usize::from(u16::from_be_bytes(header))];
+    stream.read_exact(&mut packet).await.unwrap();
+    packet
+}
+
+async fn recv(conn: &ActiveTcpConn) -> (Vec<u8>, SocketAddr) {
+    let mut buf = vec![0u8; 1500];
+    let (n, from) = tokio::time::timeout(Duration::from_secs(5), conn.recv_from(&mut buf))
+        .await
+        .expect("no packet")
+        .unwrap();
+    (buf[..n].to_vec(), from)
+}
+
+#[tokio::test]
+async fn test_first_send_dials_and_writes_frame() {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let target = listener.local_addr().unwrap();
+    let conn = ActiveTcpConn::new(Ipv4Addr::LOCALHOST.into());
+    assert_eq!(
+        conn.local_addr().await.unwrap(),
+        SocketAddr::new(Ipv4Addr::LOCALHOST.into(), ACTIVE_TCP_PORT)
+    );
+
+    // The first packet opens the connection and goes out as its first frame
+    let request = stun_message(BINDING_REQUEST);
+    assert_eq!(conn.send_to(&request, target).await.unwrap(), request.len());
+    let (mut stream, from) = listener.accept().await.unwrap();
+    assert_eq!(from.ip(), IpAddr::from(Ipv4Addr::LOCALHOST));
+    assert_eq!(read_frame(&mut stream).await, request);
+
+    conn.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_framing_in_both_directions() {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let target = listener.local_addr().unwrap();
+    let conn = ActiveTcpConn::new(Ipv4Addr::LOCALHOST.into());
+
+    let request = stun_message(BINDING_REQUEST);
+    conn.send_to(&request, target).await.unwrap();
+    let (mut stream, _) = listener.accept().await.unwrap();
+    assert_eq!(read_frame(&mut stream).await, request);
+
+    // Two frames in one write
+    let responses = [stun_message(BINDING_SUCCESS), stun_message(BINDING_SUCCESS)];
+    let mut both = frame(&responses[0]);
+    both.extend_from_slice(&frame(&responses[1]));
+    stream.write_all(&both).await.unwrap();
+    assert_eq!(recv(&conn).await, (responses[0].clone(), target));
+    assert_eq!(recv(&conn).await, (responses[1].clone(), target));
+
+    // A frame split across reads, inside its header and inside its packet
+    let response = stun_message(BINDING_SUCCESS);
+    let split = frame(&response);
+    for part in [&split[..1], &split[1..10], &split[10..]] {
+        stream.write_all(part).await.unwrap();
+        stream.flush().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(recv(&conn).await, (response, target));
+
+    // Later packets reuse the connection
+    let request = stun_message(BINDING_REQUEST);
+    conn.send_to(&request, target).await.unwrap();
+    assert_eq!(read_frame(&mut stream).await, request);
+
+    conn.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_unmarshal_tcptype() {
+    for (raw, tcp_type) in [
+        (
+            "1 1 tcp 1518280447 192.168.1.2 9 typ host tcptype active",
+            TcpType::Active,
+        ),
+        (
+            "1 1 tcp 1518280447 192.168.1.2 5000 typ host tcptype passive",
+            TcpType::Passive,
+        ),
+        (
+            "1 1 tcp 1518280447 192.168.1.2 5000 typ host tcptype so",
+            TcpType::SimultaneousOpen,
+        ),
+        (
+            "1 1 tcp 1518280447 1.2.3.4 5000 typ srflx raddr 192.168.1.2 rport 5000 tcptype passive",
+            TcpType::Passive,
+        ),
+        (
+            "1 1 udp 2130706431 192.168.1.2 5000 typ host",
+            TcpType::Unspecified,
+        ),
+    ] {
+        let candidate = unmarshal_candidate(raw).await.unwrap();
+        assert_eq!(candidate.tcp_type(), tcp_type, "{}", raw);
+        assert_eq!(candidate.marshal(), raw);
+    }
+
+    let candidate = unmarshal_candidate(
+        "1 1 tcp 1518280447 1.2.3.4 5000 typ srflx raddr 192.168.1.2 rport 5000 tcptype passive",
+    )
+    .await
+    .unwrap();
+    assert_eq!(candidate.candidate_type(), CandidateType::ServerReflexive);
+
+    assert!(matches!(
+        unmarshal_candidate("1 1 tcp 1518280447 192.168.1.2 9 typ host tcptype").await,
+        Err(crate::webrtc::ice::Error::ErrParseTcpType)
+    ));
+}
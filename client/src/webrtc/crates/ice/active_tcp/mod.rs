@@ -0,0 +1,235 @@
+use crate::webrtc::util::{self, Conn};
+
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpSocket, TcpStream};
+use tokio::sync::{broadcast, mpsc, Mutex};
+use tokio::time::Duration;
+
+#[cfg(test)]
+mod active_tcp_test;
+
+type UtilResult<T> = std::result::Result<T, util::Error>;
+
+/// The port an active TCP candidate is signaled with, it never accepts connections.
+///
+/// <https://www.rfc-editor.org/rfc/rfc6544#section-4.5>
+pub(crate) const ACTIVE_TCP_PORT: u16 = 9;
+
+// Each packet is prefixed with its length as a 16 bit integer
+// https://www.rfc-editor.org/rfc/rfc4571#section-2
+const FRAME_HEADER_SIZE: usize = 2;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_READ_QUEUE_SIZE: usize = 1024;
+
+type ReceivedPacket = (Vec<u8>, SocketAddr);
+
+enum Stream {
+    Connecting,
+    Connected(Arc<Mutex<OwnedWriteHalf>>),
+}
+
+/// ActiveTcpConn is the connection of an active TCP candidate. It opens one
+/// TCP connection from its local IP to every remote address it sends to, and
+/// frames packets over it as RFC 4571 describes, so the agent reads and writes
+/// STUN and DTLS as it does over UDP.
+pub(crate) struct ActiveTcpConn {
+    local_ip: IpAddr,
+    streams: Arc<Mutex<HashMap<SocketAddr, Stream>>>,
+    read_tx: Mutex<Option<mpsc::Sender<ReceivedPacket>>>,
+    read_rx: Mutex<mpsc::Receiver<ReceivedPacket>>,
+    close_tx: Mutex<Option<broadcast::Sender<()>>>,
+}
+
+impl ActiveTcpConn {
+    pub(crate) fn new(local_ip: IpAddr) -> Self {
+        let (read_tx, read_rx) = mpsc::channel(MAX_READ_QUEUE_SIZE);
+        let (close_tx, _) = broadcast::channel(1);
+
+        ActiveTcpConn {
+            local_ip,
+            streams: Arc::new(Mutex::new(HashMap::new())),
+            read_tx: Mutex::new(Some(read_tx)),
+            read_rx: Mutex::new(read_rx),
+            close_tx: Mutex::new(Some(close_tx)),
+        }
+    }
+
+    async fn dial(local_ip: IpAddr, target: SocketAddr) -> io::Result<TcpStream> {
+        let socket = if target.is_ipv4() {
+            TcpSocket::new_v4()?
+        } else {
+            TcpSocket::new_v6()?
+        };
+        socket.bind(SocketAddr::new(local_ip, 0))?;
+
+        match tokio::time::timeout(CONNECT_TIMEOUT, socket.connect(target)).await {
+            Ok(result) => result,
+            Err(_) => Err(io::ErrorKind::TimedOut.into()),
+        }
+    }
+
+    async fn read_frame(reader: &mut OwnedReadHalf) -> io::Result<Vec<u8>> {
+        let mut header = [0_u8; FRAME_HEADER_SIZE];
+        reader.read_exact(&mut header).await?;
+
+        let mut packet = vec![0_u8; usize::from(u16::from_be_bytes(header))];
+        reader.read_exact(&mut packet).await?;
+
+        Ok(packet)
+    }
+
+    // connect_loop opens the connection to target, writes the packet that
+    // triggered it and then reads from it until it fails or the conn is closed
+    async fn connect_loop(
+        local_ip: IpAddr,
+        target: SocketAddr,
+        first_frame: Vec<u8>,
+        streams: Arc<Mutex<HashMap<SocketAddr, Stream>>>,
+        read_tx: mpsc::Sender<ReceivedPacket>,
+        mut close_rx: broadcast::Receiver<()>,
+    ) {
+        let result = tokio::select! {
+            result = ActiveTcpConn::dial(local_ip, target) => result,
+            _ = close_rx.recv() => return,
+        };
+        let (mut reader, mut writer) = match result {
+            Ok(stream) => stream.into_split(),
+            Err(err) => {
+                log::debug!("ice: failed to connect to {}: {}", target, err);
+                streams.lock().await.remove(&target);
+                return;
+            }
+        };
+
+        if let Err(err) = writer.write_all(&first_frame).await {
+            log::debug!("ice: failed to write to {}: {}", target, err);
+            streams.lock().await.remove(&target);
+            return;
+        }
+        {
+            let mut streams = streams.lock().await;
+            streams.insert(target, Stream::Connected(Arc::new(Mutex::new(writer))));
+        }
+
+        loop {
+            tokio::select! {
+                result = ActiveTcpConn::read_frame(&mut reader) => match result {
+                    Ok(packet) => {
+                        if read_tx.send((packet, target)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        log::debug!("ice: connection to {} closed: {}", target, err);
+                        break;
+                    }
+                },
+                _ = close_rx.recv() => break,
+            }
+        }
+
+        streams.lock().await.remove(&target);
+    }
+}
+
+#[async_trait]
+impl Conn for ActiveTcpConn {
+    async fn connect(&self, _addr: SocketAddr) -> UtilResult<()> {
+        Err(util::Error::Other("Not applicable".to_owned()))
+    }
+
+    async fn recv(&self, buf: &mut [u8]) -> UtilResult<usize> {
+        let (n, _) = self.recv_from(buf).await?;
+        Ok(n)
+    }
+
+    async fn recv_from(&self, buf: &mut [u8]) -> UtilResult<(usize, SocketAddr)> {
+        let mut read_rx = self.read_rx.lock().await;
+        match read_rx.recv().await {
+            Some((data, from)) => {
+                let n = std::cmp::min(buf.len(), data.len());
+                buf[..n].copy_from_slice(&data[..n]);
+                Ok((n, from))
+            }
+            None => Err(util::Error::ErrUseClosedNetworkConn),
+        }
+    }
+
+    async fn send(&self, _buf: &[u8]) -> UtilResult<usize> {
+        Err(util::Error::ErrNoRemAddr)
+    }
+
+    // send_to writes buf to the connection to target. While that connection
+    // is being opened the packet is dropped, as a lost datagram would be, and
+    // the agent retransmits its checks.
+    async fn send_to(&self, buf: &[u8], target: SocketAddr) -> UtilResult<usize> {
+        let len = u16::try_from(buf.len()).map_err(|_| util::Error::ErrPacketTooBig)?;
+        let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + buf.len());
+        frame.extend_from_slice(&len.to_be_bytes());
+        frame.extend_from_slice(buf);
+
+        let writer = {
+            let mut streams = self.streams.lock().await;
+            match streams.get(&target) {
+                Some(Stream::Connected(writer)) => Arc::clone(writer),
+                Some(Stream::Connecting) => return Ok(buf.len()),
+                None => {
+                    let close_rx = match &*self.close_tx.lock().await {
+                        Some(close_tx) => close_tx.subscribe(),
+                        None => return Err(util::Error::ErrUseClosedNetworkConn),
+                    };
+                    let read_tx = match &*self.read_tx.lock().await {
+                        Some(read_tx) => read_tx.clone(),
+                        None => return Err(util::Error::ErrUseClosedNetworkConn),
+                    };
+
+                    streams.insert(target, Stream::Connecting);
+                    let (local_ip, streams) = (self.local_ip, Arc::clone(&self.streams));
+                    tokio::spawn(async move {
+                        ActiveTcpConn::connect_loop(
+                            local_ip, target, frame, streams, read_tx, close_rx,
+                        )
+                        .await;
+                    });
+                    return Ok(buf.len());
+                }
+            }
+        };
+
+        let mut writer = writer.lock().await;
+        if let Err(err) = writer.write_all(&frame).await {
+            self.streams.lock().await.remove(&target);
+            return Err(err.into());
+        }
+
+        Ok(buf.len())
+    }
+
+    async fn local_addr(&self) -> UtilResult<SocketAddr> {
+        Ok(SocketAddr::new(self.local_ip, ACTIVE_TCP_PORT))
+    }
+
+    async fn remote_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    async fn close(&self) -> UtilResult<()> {
+        {
+            let mut close_tx = self.close_tx.lock().await;
+            if close_tx.take().is_none() {
+                return Err(util::Error::ErrAlreadyClosed);
+            }
+        }
+        self.read_tx.lock().await.take();
+        self.streams.lock().await.clear();
+
+        Ok(())
+    }
+}
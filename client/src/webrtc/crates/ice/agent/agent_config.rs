@@ -6,6 +6,7 @@ use crate::webrtc::ice::url::*;
 
 use crate::webrtc::util::vnet::net::*;
 
+use std::net::IpAddr;
 use std::time::Duration;
 
 /// The interval at which the agent performs candidate checks in the connecting phase.
@@ -20,6 +21,38 @@ pub(crate) const DEFAULT_DISCONNECTED_TIMEOUT: Duration = Duration::from_secs(5)
 /// The default time till an Agent transitions to failed after disconnected.
 pub(crate) const DEFAULT_FAILED_TIMEOUT: Duration = Duration::from_secs(25);
 
+/// How long the selected pair may go without an authenticated binding response before consent
+/// to send on it expires.
+/// <https://www.rfc-editor.org/rfc/rfc7675#section-5.1>
+pub(crate) const DEFAULT_CONSENT_EXPIRY: Duration = Duration::from_secs(30);
+
+/// The base interval of consent checks, each one is randomized to 0.8 to 1.2 times of it.
+/// <https://www.rfc-editor.org/rfc/rfc7675#section-5.1>
+pub(crate) const CONSENT_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long checks on pairs of the preferred IP family run before the other family joins in.
+/// <https://www.rfc-editor.org/rfc/rfc8305#section-5>
+pub(crate) const DEFAULT_FAMILY_HEAD_START: Duration = Duration::from_millis(250);
+
+/// The gap between two checks sent on one tick, shrunk when they would not fit into the check
+/// interval otherwise.
+/// <https://www.rfc-editor.org/rfc/rfc8445#section-14.2>
+pub(crate) const CHECK_PACING: Duration = Duration::from_millis(50);
+
+/// How often the valid pairs besides the selected one are checked to measure their round trip
+/// time.
+pub(crate) const RTT_PROBE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Minimum time between two nominations, so the selected pair doesn't flap between paths of
+/// similar quality.
+pub(crate) const RENOMINATION_HOLD_TIME: Duration = Duration::from_secs(10);
+
+/// A pair needs to be this much faster than the selected pair to be nominated in its place.
+pub(crate) const RENOMINATION_MIN_GAIN: Duration = Duration::from_millis(10);
+
+/// The responses a pair needs before its round trip time is trusted for renomination.
+pub(crate) const RENOMINATION_MIN_SAMPLES: u32 = 3;
+
 /// Wait time before nominating a host candidate.
 pub(crate) const DEFAULT_HOST_ACCEPTANCE_MIN_WAIT: Duration = Duration::from_secs(0);
 
@@ -32,6 +65,9 @@ pub(crate) const DEFAULT_PRFLX_ACCEPTANCE_MIN_WAIT: Duration = Duration::from_mi
 /// Wait time before nominating a relay candidate.
 pub(crate) const DEFAULT_RELAY_ACCEPTANCE_MIN_WAIT: Duration = Duration::from_millis(2000);
 
+/// How long to wait for a STUN server to answer while gathering srflx candidates.
+pub(crate) const STUN_GATHER_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Max binding request before considering a pair failed.
 pub(crate) const DEFAULT_MAX_BINDING_REQUESTS: u16 = 7;
 
@@ -42,10 +78,15 @@ pub(crate) const MAX_BUFFER_SIZE: usize = 1000 * 1000; // 1MB
 pub(crate) const MAX_BINDING_REQUEST_TIMEOUT: Duration = Duration::from_millis(4000);
 
 pub(crate) fn default_candidate_types() -> Vec<CandidateType> {
-    vec![CandidateType::Host]
+    vec![
+        CandidateType::Host,
+        CandidateType::ServerReflexive,
+        CandidateType::Relay,
+    ]
 }
 
 pub(crate) type InterfaceFilterFn = Box<dyn (Fn(&str) -> bool) + Send + Sync>;
+pub(crate) type IpFilterFn = Box<dyn (Fn(IpAddr) -> bool) + Send + Sync>;
 
 /// Collects the arguments to `ice::Agent` construction into a single structure, for
 /// future-proofness of the interface.
@@ -81,9 +122,22 @@ pub(crate) struct AgentConfig {
     /// A keepalive interval of 0 means we never send keepalive packets
     pub(crate) keepalive_interval: Option<Duration>,
 
+    /// Defaults to 30 seconds when this property is nil.
+    /// How long the selected pair may go without an authenticated binding response before the
+    /// agent stops sending on it and goes to failed.
+    pub(crate) consent_expiry: Option<Duration>,
+
     /// An optional configuration for disabling or enabling support for specific network types.
     pub(crate) network_types: Vec<NetworkType>,
 
+    /// Check IPv4 pairs before IPv6 ones on dual-stack hosts, IPv6 goes first by default.
+    pub(crate) prefer_ipv4: bool,
+
+    /// Defaults to 250 milliseconds when this property is nil.
+    /// How long only pairs of the preferred IP family are checked before the pairs of the
+    /// other family join in. A head start of 0 races both families from the start.
+    pub(crate) family_head_start: Option<Duration>,
+
     /// An optional configuration for disabling or enabling support for specific candidate types.
     pub(crate) candidate_types: Vec<CandidateType>,
 
@@ -130,6 +184,17 @@ pub(crate) struct AgentConfig {
     /// A function that you can use in order to whitelist or blacklist the interfaces which are
     /// used to gather ICE candidates.
     pub(crate) interface_filter: Arc<Option<InterfaceFilterFn>>,
+
+    /// A function that you can use in order to whitelist or blacklist the local IP addresses
+    /// which are used to gather ICE candidates.
+    pub(crate) ip_filter: Arc<Option<IpFilterFn>>,
+
+    /// The lowest UDP port candidates are bound to, 0 for no lower bound.
+    pub(crate) port_min: u16,
+
+    /// The highest UDP port candidates are bound to, 0 for no upper bound. With both bounds 0
+    /// the system picks ephemeral ports.
+    pub(crate) port_max: u16,
 }
 
 impl AgentConfig {
@@ -183,6 +248,19 @@ impl AgentConfig {
             a.keepalive_interval = DEFAULT_KEEPALIVE_INTERVAL;
         }
 
+        if let Some(consent_expiry) = self.consent_expiry {
+            a.consent_expiry = consent_expiry;
+        } else {
+            a.consent_expiry = DEFAULT_CONSENT_EXPIRY;
+        }
+
+        a.prefer_ipv4 = self.prefer_ipv4;
+        if let Some(family_head_start) = self.family_head_start {
+            a.family_head_start = family_head_start;
+        } else {
+            a.family_head_start = DEFAULT_FAMILY_HEAD_START;
+        }
+
         if self.check_interval == Duration::from_secs(0) {
             a.check_interval = DEFAULT_CHECK_INTERVAL;
         } else {
@@ -212,6 +290,10 @@ impl AgentConfig {
                 if !candi_host_enabled {
                     return Err(Error::ErrIneffectiveNat1to1IpMappingHost);
                 }
+            } else if ext_ip_mapper.candidate_type == CandidateType::ServerReflexive
+                && !candidate_types.contains(&CandidateType::ServerReflexive)
+            {
+                return Err(Error::ErrIneffectiveNat1to1IpMappingSrflx);
             }
 
             Ok(Some(ext_ip_mapper))
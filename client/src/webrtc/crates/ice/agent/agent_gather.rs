@@ -1,33 +1,72 @@
 use super::*;
+use crate::webrtc::ice::active_tcp::ActiveTcpConn;
 use crate::webrtc::ice::network_type::*;
+use crate::webrtc::ice::tcp_type::TcpType;
+use crate::webrtc::ice::url::{ProtoType, SchemeType, Url};
 use crate::webrtc::ice::util::*;
+use crate::webrtc::stun::credentials::LongTermCredentials;
 
 use crate::webrtc::util::{vnet::net::*, Conn};
 
 use crate::webrtc::ice::candidate::candidate_base::CandidateBaseConfig;
 use crate::webrtc::ice::candidate::candidate_host::CandidateHostConfig;
+use crate::webrtc::ice::candidate::candidate_relay::CandidateRelayConfig;
+use crate::webrtc::ice::candidate::candidate_server_reflexive::CandidateServerReflexiveConfig;
 use crate::webrtc::ice::candidate::*;
+use crate::webrtc::turn::client::{Client, ClientConfig};
+use std::net::IpAddr;
 use std::sync::Arc;
+use tokio::net::lookup_host;
 use waitgroup::WaitGroup;
 
 pub(crate) struct GatherCandidatesInternalParams {
     pub(crate) candidate_types: Vec<CandidateType>,
+    pub(crate) urls: Vec<Url>,
     pub(crate) network_types: Vec<NetworkType>,
     pub(crate) mdns_mode: MulticastDnsMode,
     pub(crate) mdns_name: String,
     pub(crate) net: Arc<Net>,
     pub(crate) interface_filter: Arc<Option<InterfaceFilterFn>>,
+    pub(crate) ip_filter: Arc<Option<IpFilterFn>>,
+    pub(crate) port_min: u16,
+    pub(crate) port_max: u16,
     pub(crate) ext_ip_mapper: Arc<Option<ExternalIpMapper>>,
     pub(crate) agent_internal: Arc<AgentInternal>,
     pub(crate) gathering_state: Arc<AtomicU8>,
     pub(crate) chan_candidate_tx: ChanCandidateTx,
 }
 
+struct GatherCandidatesSrflxParams {
+    urls: Vec<Url>,
+    network_types: Vec<NetworkType>,
+    interface_filter: Arc<Option<InterfaceFilterFn>>,
+    ip_filter: Arc<Option<IpFilterFn>>,
+    port_min: u16,
+    port_max: u16,
+    ext_ip_mapper: Arc<Option<ExternalIpMapper>>,
+    net: Arc<Net>,
+    agent_internal: Arc<AgentInternal>,
+}
+
+struct GatherCandidatesRelayParams {
+    urls: Vec<Url>,
+    network_types: Vec<NetworkType>,
+    interface_filter: Arc<Option<InterfaceFilterFn>>,
+    ip_filter: Arc<Option<IpFilterFn>>,
+    port_min: u16,
+    port_max: u16,
+    net: Arc<Net>,
+    agent_internal: Arc<AgentInternal>,
+}
+
 struct GatherCandidatesLocalParams {
     network_types: Vec<NetworkType>,
     mdns_mode: MulticastDnsMode,
     mdns_name: String,
     interface_filter: Arc<Option<InterfaceFilterFn>>,
+    ip_filter: Arc<Option<IpFilterFn>>,
+    port_min: u16,
+    port_max: u16,
     ext_ip_mapper: Arc<Option<ExternalIpMapper>>,
     net: Arc<Net>,
     agent_internal: Arc<AgentInternal>,
@@ -52,6 +91,9 @@ impl Agent {
                         mdns_mode: params.mdns_mode,
                         mdns_name: params.mdns_name.clone(),
                         interface_filter: Arc::clone(&params.interface_filter),
+                        ip_filter: Arc::clone(&params.ip_filter),
+                        port_min: params.port_min,
+                        port_max: params.port_max,
                         ext_ip_mapper: Arc::clone(&params.ext_ip_mapper),
                         net: Arc::clone(&params.net),
                         agent_internal: Arc::clone(&params.agent_internal),
@@ -64,6 +106,45 @@ impl Agent {
                         Self::gather_candidates_local(local_params).await;
                     });
                 }
+                CandidateType::ServerReflexive => {
+                    let srflx_params = GatherCandidatesSrflxParams {
+                        urls: params.urls.clone(),
+                        network_types: params.network_types.clone(),
+                        interface_filter: Arc::clone(&params.interface_filter),
+                        ip_filter: Arc::clone(&params.ip_filter),
+                        port_min: params.port_min,
+                        port_max: params.port_max,
+                        ext_ip_mapper: Arc::clone(&params.ext_ip_mapper),
+                        net: Arc::clone(&params.net),
+                        agent_internal: Arc::clone(&params.agent_internal),
+                    };
+
+                    let w = wg.worker();
+                    tokio::spawn(async move {
+                        let _d = w;
+
+                        Self::gather_candidates_srflx(srflx_params).await;
+                    });
+                }
+                CandidateType::Relay => {
+                    let relay_params = GatherCandidatesRelayParams {
+                        urls: params.urls.clone(),
+                        network_types: params.network_types.clone(),
+                        interface_filter: Arc::clone(&params.interface_filter),
+                        ip_filter: Arc::clone(&params.ip_filter),
+                        port_min: params.port_min,
+                        port_max: params.port_max,
+                        net: Arc::clone(&params.net),
+                        agent_internal: Arc::clone(&params.agent_internal),
+                    };
+
+                    let w = wg.worker();
+                    tokio::spawn(async move {
+                        let _d = w;
+
+                        Self::gather_candidates_relay(relay_params).await;
+                    });
+                }
                 _ => {}
             }
         }
@@ -96,56 +177,148 @@ impl Agent {
         gathering_state.store(new_state as u8, Ordering::SeqCst);
     }
 
+    // watch_interfaces gathers host candidates on the addresses that appear on
+    // the host's interfaces and deletes the candidates on the ones that go
+    // away, then reports the change to the on_network_change handler
+    pub(crate) fn watch_interfaces(&self, mut events_rx: broadcast::Receiver<InterfaceEvent>) {
+        let gather_host = self.candidate_types.contains(&CandidateType::Host);
+        let gathering_state = Arc::clone(&self.gathering_state);
+        let params = GatherCandidatesLocalParams {
+            network_types: self.network_types.clone(),
+            mdns_mode: self.mdns_mode,
+            mdns_name: self.mdns_name.clone(),
+            interface_filter: Arc::clone(&self.interface_filter),
+            ip_filter: Arc::clone(&self.ip_filter),
+            port_min: self.port_min,
+            port_max: self.port_max,
+            ext_ip_mapper: Arc::clone(&self.ext_ip_mapper),
+            net: Arc::clone(&self.net),
+            agent_internal: Arc::clone(&self.internal),
+        };
+
+        tokio::spawn(async move {
+            loop {
+                let event = match events_rx.recv().await {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        log::warn!(
+                            "[{}]: missed {} interface changes",
+                            params.agent_internal.get_name(),
+                            n
+                        );
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+
+                params.net.update_interfaces().await;
+
+                match &event {
+                    InterfaceEvent::AddrAdded { name, addr } => {
+                        // A gathering that has yet to start finds the address itself
+                        if gather_host
+                            && gathering_state.load(Ordering::SeqCst) != GatheringState::New as u8
+                            && is_usable_interface_addr(
+                                name,
+                                *addr,
+                                &params.interface_filter,
+                                &params.ip_filter,
+                                &params.network_types,
+                            )
+                        {
+                            Self::gather_candidates_local_ip(&params, *addr).await;
+                        }
+                    }
+                    InterfaceEvent::AddrRemoved { addr, .. } => {
+                        params.agent_internal.delete_candidates_on(*addr).await;
+                    }
+                }
+
+                let mut on_network_change_hdlr =
+                    params.agent_internal.on_network_change_hdlr.lock().await;
+                if let Some(f) = &mut *on_network_change_hdlr {
+                    f(event).await;
+                }
+            }
+        });
+    }
+
     async fn gather_candidates_local(params: GatherCandidatesLocalParams) {
-        let (
-            network_types,
-            mdns_mode,
-            mdns_name,
-            interface_filter,
-            ext_ip_mapper,
-            net,
-            agent_internal,
-        ) = (
-            params.network_types,
+        let ips = local_interfaces(
+            &params.net,
+            &params.interface_filter,
+            &params.ip_filter,
+            &params.network_types,
+        )
+        .await;
+        for ip in ips {
+            Self::gather_candidates_local_ip(&params, ip).await;
+        }
+    }
+
+    // gather_candidates_local_ip creates the host candidates on ip, one for
+    // each network
+    async fn gather_candidates_local_ip(params: &GatherCandidatesLocalParams, ip: IpAddr) {
+        let (network_types, mdns_mode, mdns_name, ext_ip_mapper, net, agent_internal) = (
+            &params.network_types,
             params.mdns_mode,
-            params.mdns_name,
-            params.interface_filter,
-            params.ext_ip_mapper,
-            params.net,
-            params.agent_internal,
+            &params.mdns_name,
+            &params.ext_ip_mapper,
+            &params.net,
+            &params.agent_internal,
         );
 
-        let ips = local_interfaces(&net, &*interface_filter, &network_types).await;
-        for ip in ips {
-            let mut mapped_ip = ip;
-
-            if mdns_mode != MulticastDnsMode::QueryAndGather && ext_ip_mapper.is_some() {
-                if let Some(ext_ip_mapper2) = ext_ip_mapper.as_ref() {
-                    if ext_ip_mapper2.candidate_type == CandidateType::Host {
-                        if let Ok(mi) = ext_ip_mapper2.find_external_ip(&ip.to_string()) {
-                            mapped_ip = mi;
-                        } else {
-                            log::warn!(
-                                "[{}]: 1:1 NAT mapping is enabled but no external IP is found for {}",
-                                agent_internal.get_name(),
-                                ip
-                            );
-                        }
+        let mut mapped_ip = ip;
+
+        if mdns_mode != MulticastDnsMode::QueryAndGather && ext_ip_mapper.is_some() {
+            if let Some(ext_ip_mapper2) = ext_ip_mapper.as_ref() {
+                if ext_ip_mapper2.candidate_type == CandidateType::Host {
+                    if let Ok(mi) = ext_ip_mapper2.find_external_ip(&ip.to_string()) {
+                        mapped_ip = mi;
+                    } else {
+                        log::warn!(
+                            "[{}]: 1:1 NAT mapping is enabled but no external IP is found for {}",
+                            agent_internal.get_name(),
+                            ip
+                        );
                     }
                 }
             }
+        }
 
-            let address = if mdns_mode == MulticastDnsMode::QueryAndGather {
-                mdns_name.clone()
-            } else {
-                mapped_ip.to_string()
-            };
+        let address = if mdns_mode == MulticastDnsMode::QueryAndGather {
+            mdns_name.clone()
+        } else {
+            mapped_ip.to_string()
+        };
 
-            //TODO: for network in networks
-            let network = UDP.to_owned();
+        let mut networks = vec![];
+        for typ in network_types {
+            // TCP is only dialed with the host network stack
+            if typ.is_ipv4() != ip.is_ipv4() || (typ.is_tcp() && net.is_virtual()) {
+                continue;
+            }
+            let network = typ.network_short();
+            if !networks.contains(&network) {
+                networks.push(network);
+            }
+        }
 
-            let conn: Arc<dyn Conn + Send + Sync> =
-                match listen_udp_in_port_range(&net, SocketAddr::new(ip, 0)).await {
+        for network in networks {
+            let conn: Arc<dyn Conn + Send + Sync> = if network == TCP {
+                // An active candidate connects out to passive remote
+                // candidates and is signaled with the discard port
+                // https://www.rfc-editor.org/rfc/rfc6544#section-4.5
+                Arc::new(ActiveTcpConn::new(ip))
+            } else {
+                match listen_udp_in_port_range(
+                    net,
+                    params.port_max,
+                    params.port_min,
+                    SocketAddr::new(ip, 0),
+                )
+                .await
+                {
                     Ok(conn) => conn,
                     Err(err) => {
                         log::warn!(
@@ -157,7 +330,8 @@ impl Agent {
                         );
                         continue;
                     }
-                };
+                }
+            };
 
             let port = match conn.local_addr().await {
                 Ok(addr) => addr.port(),
@@ -171,12 +345,19 @@ impl Agent {
                 }
             };
 
+            let tcp_type = if network == TCP {
+                TcpType::Active
+            } else {
+                TcpType::Unspecified
+            };
+
             let host_config = CandidateHostConfig {
                 base_config: CandidateBaseConfig {
                     network: network.clone(),
-                    address,
+                    address: address.clone(),
                     port,
                     component: COMPONENT_RTP,
+                    tcp_type,
                     conn: Some(conn),
                     ..CandidateBaseConfig::default()
                 },
@@ -232,4 +413,536 @@ impl Agent {
             }
         }
     }
+
+    // gather_candidates_srflx sends a STUN Binding request to every stun: url from
+    // a socket on each local interface, the mapped address of the response becomes
+    // a srflx candidate that keeps using that socket. With a 1:1 NAT mapping for
+    // srflx candidates, the public address of each interface is signaled as well.
+    async fn gather_candidates_srflx(params: GatherCandidatesSrflxParams) {
+        let wg = WaitGroup::new();
+
+        let ips = local_interfaces(
+            &params.net,
+            &params.interface_filter,
+            &params.ip_filter,
+            &params.network_types,
+        )
+        .await;
+
+        let srflx_mapping = params
+            .ext_ip_mapper
+            .as_ref()
+            .as_ref()
+            .is_some_and(|ext_ip_mapper| {
+                ext_ip_mapper.candidate_type == CandidateType::ServerReflexive
+            });
+        if srflx_mapping {
+            for ip in &ips {
+                let ip = *ip;
+                let (port_max, port_min) = (params.port_max, params.port_min);
+                let ext_ip_mapper = Arc::clone(&params.ext_ip_mapper);
+                let net = Arc::clone(&params.net);
+                let agent_internal = Arc::clone(&params.agent_internal);
+
+                let w = wg.worker();
+                tokio::spawn(async move {
+                    let _d = w;
+
+                    Self::gather_candidate_srflx_mapped(
+                        ip,
+                        port_max,
+                        port_min,
+                        ext_ip_mapper,
+                        net,
+                        agent_internal,
+                    )
+                    .await;
+                });
+            }
+        }
+        for url in params.urls {
+            if url.scheme != SchemeType::Stun || url.proto != ProtoType::Udp {
+                continue;
+            }
+
+            for ip in &ips {
+                let (url, ip) = (url.clone(), *ip);
+                let (port_max, port_min) = (params.port_max, params.port_min);
+                let net = Arc::clone(&params.net);
+                let agent_internal = Arc::clone(&params.agent_internal);
+
+                let w = wg.worker();
+                tokio::spawn(async move {
+                    let _d = w;
+
+                    Self::gather_candidate_srflx(url, ip, port_max, port_min, net, agent_internal)
+                        .await;
+                });
+            }
+        }
+
+        wg.wait().await;
+    }
+
+    async fn gather_candidate_srflx(
+        url: Url,
+        ip: IpAddr,
+        port_max: u16,
+        port_min: u16,
+        net: Arc<Net>,
+        agent_internal: Arc<AgentInternal>,
+    ) {
+        let host_port = format!("{}:{}", url.host, url.port);
+        let server_addr = match lookup_host(host_port.as_str()).await {
+            Ok(mut addrs) => match addrs.find(|addr| addr.is_ipv4() == ip.is_ipv4()) {
+                Some(addr) => addr,
+                None => {
+                    log::debug!(
+                        "[{}]: no {} address for STUN server {}",
+                        agent_internal.get_name(),
+                        if ip.is_ipv4() { "IPv4" } else { "IPv6" },
+                        host_port
+                    );
+                    return;
+                }
+            },
+            Err(err) => {
+                log::warn!(
+                    "[{}]: failed to resolve STUN host: {}: {}",
+                    agent_internal.get_name(),
+                    host_port,
+                    err
+                );
+                return;
+            }
+        };
+
+        let conn: Arc<dyn Conn + Send + Sync> = match listen_udp_in_port_range(
+            &net,
+            port_max,
+            port_min,
+            SocketAddr::new(ip, 0),
+        )
+        .await
+        {
+            Ok(conn) => conn,
+            Err(err) => {
+                log::warn!(
+                    "[{}]: failed to listen for {}: {}",
+                    agent_internal.get_name(),
+                    server_addr,
+                    err
+                );
+                return;
+            }
+        };
+
+        // Credentials are sent once the server challenges, the realm and nonce
+        // it hands out are kept for the next gathering
+        let mut credentials = if url.username.is_empty() {
+            None
+        } else {
+            let cached = {
+                let stun_credentials = agent_internal.stun_credentials.lock().await;
+                stun_credentials.get(&server_addr).cloned()
+            };
+            Some(cached.unwrap_or_else(|| {
+                LongTermCredentials::new(url.username.clone(), url.password.clone())
+            }))
+        };
+
+        let xoraddr = match get_xormapped_addr(
+            &conn,
+            server_addr,
+            credentials.as_mut(),
+            STUN_GATHER_TIMEOUT,
+        )
+        .await
+        {
+            Ok(xoraddr) => xoraddr,
+            Err(err) => {
+                log::warn!(
+                    "[{}]: could not get server reflexive address {} {}: {}",
+                    agent_internal.get_name(),
+                    UDP,
+                    url,
+                    err
+                );
+                let _ = conn.close().await;
+                return;
+            }
+        };
+
+        if let Some(credentials) = credentials {
+            let mut stun_credentials = agent_internal.stun_credentials.lock().await;
+            stun_credentials.insert(server_addr, credentials);
+        }
+
+        let laddr = match conn.local_addr().await {
+            Ok(laddr) => laddr,
+            Err(err) => {
+                log::warn!(
+                    "[{}]: could not get local addr: {}",
+                    agent_internal.get_name(),
+                    err
+                );
+                let _ = conn.close().await;
+                return;
+            }
+        };
+
+        let srflx_config = CandidateServerReflexiveConfig {
+            base_config: CandidateBaseConfig {
+                network: UDP.to_owned(),
+                address: xoraddr.ip.to_string(),
+                port: xoraddr.port,
+                component: COMPONENT_RTP,
+                conn: Some(conn),
+                ..CandidateBaseConfig::default()
+            },
+            rel_addr: laddr.ip().to_string(),
+            rel_port: laddr.port(),
+        };
+
+        let candidate: Arc<dyn Candidate + Send + Sync> =
+            match srflx_config.new_candidate_server_reflexive().await {
+                Ok(candidate) => Arc::new(candidate),
+                Err(err) => {
+                    log::warn!(
+                        "[{}]: Failed to create server reflexive candidate: {} {} {}: {}",
+                        agent_internal.get_name(),
+                        UDP,
+                        xoraddr.ip,
+                        xoraddr.port,
+                        err
+                    );
+                    return;
+                }
+            };
+
+        if let Err(err) = agent_internal.add_candidate(&candidate).await {
+            if let Err(close_err) = candidate.close().await {
+                log::warn!(
+                    "[{}]: Failed to close candidate: {}",
+                    agent_internal.get_name(),
+                    close_err
+                );
+            }
+            log::warn!(
+                "[{}]: Failed to append to localCandidates and run onCandidateHdlr: {}",
+                agent_internal.get_name(),
+                err
+            );
+        }
+    }
+
+    // gather_candidate_srflx_mapped signals the public address the 1:1 NAT maps
+    // ip to as a srflx candidate, on a socket of ip and without asking a STUN
+    // server
+    async fn gather_candidate_srflx_mapped(
+        ip: IpAddr,
+        port_max: u16,
+        port_min: u16,
+        ext_ip_mapper: Arc<Option<ExternalIpMapper>>,
+        net: Arc<Net>,
+        agent_internal: Arc<AgentInternal>,
+    ) {
+        let mapped_ip = match ext_ip_mapper
+            .as_ref()
+            .as_ref()
+            .map(|ext_ip_mapper| ext_ip_mapper.find_external_ip(&ip.to_string()))
+        {
+            Some(Ok(mapped_ip)) => mapped_ip,
+            _ => {
+                log::warn!(
+                    "[{}]: 1:1 NAT mapping is enabled but no external IP is found for {}",
+                    agent_internal.get_name(),
+                    ip
+                );
+                return;
+            }
+        };
+
+        let conn: Arc<dyn Conn + Send + Sync> = match listen_udp_in_port_range(
+            &net,
+            port_max,
+            port_min,
+            SocketAddr::new(ip, 0),
+        )
+        .await
+        {
+            Ok(conn) => conn,
+            Err(err) => {
+                log::warn!(
+                    "[{}]: could not listen {} {}: {}",
+                    agent_internal.get_name(),
+                    UDP,
+                    ip,
+                    err
+                );
+                return;
+            }
+        };
+
+        let laddr = match conn.local_addr().await {
+            Ok(laddr) => laddr,
+            Err(err) => {
+                log::warn!(
+                    "[{}]: could not get local addr: {}",
+                    agent_internal.get_name(),
+                    err
+                );
+                let _ = conn.close().await;
+                return;
+            }
+        };
+
+        let srflx_config = CandidateServerReflexiveConfig {
+            base_config: CandidateBaseConfig {
+                network: UDP.to_owned(),
+                address: mapped_ip.to_string(),
+                port: laddr.port(),
+                component: COMPONENT_RTP,
+                conn: Some(conn),
+                ..CandidateBaseConfig::default()
+            },
+            rel_addr: laddr.ip().to_string(),
+            rel_port: laddr.port(),
+        };
+
+        let candidate: Arc<dyn Candidate + Send + Sync> =
+            match srflx_config.new_candidate_server_reflexive().await {
+                Ok(candidate) => Arc::new(candidate),
+                Err(err) => {
+                    log::warn!(
+                        "[{}]: Failed to create server reflexive candidate: {} {} {}: {}",
+                        agent_internal.get_name(),
+                        UDP,
+                        mapped_ip,
+                        laddr.port(),
+                        err
+                    );
+                    return;
+                }
+            };
+
+        if let Err(err) = agent_internal.add_candidate(&candidate).await {
+            if let Err(close_err) = candidate.close().await {
+                log::warn!(
+                    "[{}]: Failed to close candidate: {}",
+                    agent_internal.get_name(),
+                    close_err
+                );
+            }
+            log::warn!(
+                "[{}]: Failed to append to localCandidates and run onCandidateHdlr: {}",
+                agent_internal.get_name(),
+                err
+            );
+        }
+    }
+
+    // gather_candidates_relay allocates a relayed address on every turn: url,
+    // the relay candidate sends and receives through the TURN server so it
+    // works when the peer can not be reached directly
+    async fn gather_candidates_relay(params: GatherCandidatesRelayParams) {
+        let wg = WaitGroup::new();
+
+        // The allocation is made from a socket on one of the interfaces the
+        // policy allows, the lowest address first
+        let mut ips: Vec<IpAddr> = local_interfaces(
+            &params.net,
+            &params.interface_filter,
+            &params.ip_filter,
+            &params.network_types,
+        )
+        .await
+        .into_iter()
+        .collect();
+        ips.sort();
+
+        for url in params.urls {
+            if url.scheme != SchemeType::Turn && url.scheme != SchemeType::Turns {
+                continue;
+            }
+            if url.scheme == SchemeType::Turns || url.proto != ProtoType::Udp {
+                log::warn!(
+                    "[{}]: TURN over {} is not supported: {}",
+                    params.agent_internal.get_name(),
+                    if url.scheme == SchemeType::Turns {
+                        "TLS"
+                    } else {
+                        "TCP"
+                    },
+                    url
+                );
+                continue;
+            }
+
+            let ips = ips.clone();
+            let (port_max, port_min) = (params.port_max, params.port_min);
+            let net = Arc::clone(&params.net);
+            let agent_internal = Arc::clone(&params.agent_internal);
+
+            let w = wg.worker();
+            tokio::spawn(async move {
+                let _d = w;
+
+                Self::gather_candidate_relay(url, ips, port_max, port_min, net, agent_internal)
+                    .await;
+            });
+        }
+
+        wg.wait().await;
+    }
+
+    async fn gather_candidate_relay(
+        url: Url,
+        ips: Vec<IpAddr>,
+        port_max: u16,
+        port_min: u16,
+        net: Arc<Net>,
+        agent_internal: Arc<AgentInternal>,
+    ) {
+        let host_port = format!("{}:{}", url.host, url.port);
+        let turn_serv_addr = match lookup_host(host_port.as_str()).await {
+            Ok(mut addrs) => match addrs.next() {
+                Some(addr) => addr,
+                None => {
+                    log::warn!(
+                        "[{}]: no address for TURN server {}",
+                        agent_internal.get_name(),
+                        host_port
+                    );
+                    return;
+                }
+            },
+            Err(err) => {
+                log::warn!(
+                    "[{}]: failed to resolve TURN host: {}: {}",
+                    agent_internal.get_name(),
+                    host_port,
+                    err
+                );
+                return;
+            }
+        };
+
+        // Try the allowed interfaces of the server's family until one gets an
+        // allocation
+        let mut relay_conn = None;
+        for ip in ips
+            .iter()
+            .filter(|ip| ip.is_ipv4() == turn_serv_addr.is_ipv4())
+        {
+            let conn =
+                match listen_udp_in_port_range(&net, port_max, port_min, SocketAddr::new(*ip, 0))
+                    .await
+                {
+                    Ok(conn) => conn,
+                    Err(err) => {
+                        log::warn!(
+                            "[{}]: failed to listen on {} for {}: {}",
+                            agent_internal.get_name(),
+                            ip,
+                            turn_serv_addr,
+                            err
+                        );
+                        continue;
+                    }
+                };
+
+            let client = Client::new(ClientConfig {
+                turn_serv_addr,
+                username: url.username.clone(),
+                password: url.password.clone(),
+                conn,
+            })
+            .await;
+
+            match client.allocate().await {
+                Ok(conn) => {
+                    relay_conn = Some(conn);
+                    break;
+                }
+                Err(err) => {
+                    log::warn!(
+                        "[{}]: failed to allocate on TURN server {} from {}: {}",
+                        agent_internal.get_name(),
+                        url,
+                        ip,
+                        err
+                    );
+                    client.close().await;
+                }
+            }
+        }
+        let relay_conn = match relay_conn {
+            Some(relay_conn) => relay_conn,
+            None => {
+                log::debug!(
+                    "[{}]: no relay candidate from TURN server {}",
+                    agent_internal.get_name(),
+                    url
+                );
+                return;
+            }
+        };
+
+        let (raddr, mapped_addr) = match relay_conn.local_addr().await {
+            Ok(raddr) => (raddr, relay_conn.mapped_addr()),
+            Err(err) => {
+                log::warn!(
+                    "[{}]: could not get relayed addr: {}",
+                    agent_internal.get_name(),
+                    err
+                );
+                let _ = relay_conn.close().await;
+                return;
+            }
+        };
+
+        let relay_config = CandidateRelayConfig {
+            base_config: CandidateBaseConfig {
+                network: UDP.to_owned(),
+                address: raddr.ip().to_string(),
+                port: raddr.port(),
+                component: COMPONENT_RTP,
+                conn: Some(Arc::new(relay_conn)),
+                ..CandidateBaseConfig::default()
+            },
+            rel_addr: mapped_addr.ip().to_string(),
+            rel_port: mapped_addr.port(),
+        };
+
+        let candidate: Arc<dyn Candidate + Send + Sync> =
+            match relay_config.new_candidate_relay().await {
+                Ok(candidate) => Arc::new(candidate),
+                Err(err) => {
+                    log::warn!(
+                        "[{}]: Failed to create relay candidate: {} {}: {}",
+                        agent_internal.get_name(),
+                        UDP,
+                        raddr,
+                        err
+                    );
+                    return;
+                }
+            };
+
+        if let Err(err) = agent_internal.add_candidate(&candidate).await {
+            if let Err(close_err) = candidate.close().await {
+                log::warn!(
+                    "[{}]: Failed to close candidate: {}",
+                    agent_internal.get_name(),
+                    close_err
+                );
+            }
+            log::warn!(
+                "[{}]: Failed to append to localCandidates and run onCandidateHdlr: {}",
+                agent_internal.get_name(),
+                err
+            );
+        }
+    }
 }
@@ -1,7 +1,14 @@
 use super::agent_transport::*;
 use super::*;
+use crate::webrtc::ice::candidate::candidate_base::CandidateBaseConfig;
+use crate::webrtc::ice::candidate::candidate_peer_reflexive::CandidatePeerReflexiveConfig;
+use crate::webrtc::ice::priority::PriorityAttr;
+use crate::webrtc::ice::tcp_type::TcpType;
 use crate::webrtc::ice::util::*;
-use std::sync::atomic::{AtomicBool, AtomicU64};
+use crate::webrtc::stun::credentials::LongTermCredentials;
+use rand::Rng;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64};
 
 pub(crate) type ChanCandidateTx =
     Arc<Mutex<Option<mpsc::Sender<Option<Arc<dyn Candidate + Send + Sync>>>>>>;
@@ -34,6 +41,7 @@ pub(crate) struct AgentInternal {
     pub(crate) on_selected_candidate_pair_change_hdlr:
         Mutex<Option<OnSelectedCandidatePairChangeHdlrFn>>,
     pub(crate) on_candidate_hdlr: Mutex<Option<OnCandidateHdlrFn>>,
+    pub(crate) on_network_change_hdlr: Mutex<Option<OnNetworkChangeHdlrFn>>,
 
     pub(crate) tie_breaker: AtomicU64,
     pub(crate) is_controlling: AtomicBool,
@@ -43,6 +51,7 @@ pub(crate) struct AgentInternal {
     pub(crate) nominated_pair: Mutex<Option<Arc<CandidatePair>>>,
 
     pub(crate) connection_state: AtomicU8, //ConnectionState,
+    pub(crate) gathering_state: Arc<AtomicU8>, //GatheringState,
 
     pub(crate) started_ch_tx: Mutex<Option<broadcast::Sender<()>>>,
 
@@ -55,6 +64,40 @@ pub(crate) struct AgentInternal {
     // LRU of outbound Binding request Transaction IDs
     pub(crate) pending_binding_requests: Mutex<Vec<BindingRequest>>,
 
+    // Candidates keep trickling in while checking, each new pair gives the
+    // checks the full timeout again, until failed_timeout after checking began
+    pub(crate) last_pair_added: Mutex<Instant>,
+    // The peer signaled end-of-candidates
+    pub(crate) remote_candidates_complete: AtomicBool,
+
+    // Consent to send on the selected pair, refreshed by every authenticated
+    // binding response on it
+    // https://www.rfc-editor.org/rfc/rfc7675#section-5.1
+    pub(crate) consent_granted_at: Mutex<Instant>,
+    pub(crate) next_consent_check: Mutex<Instant>,
+    pub(crate) consent_expired: AtomicBool,
+
+    // Pairs of the preferred IP family are checked first
+    // https://www.rfc-editor.org/rfc/rfc8305#section-4
+    pub(crate) checks_started_at: Mutex<Instant>,
+
+    // The controlling agent keeps measuring the other valid pairs and
+    // nominates a clearly better one
+    pub(crate) next_rtt_probe: Mutex<Instant>,
+    pub(crate) last_nomination: Mutex<Instant>,
+
+    // Renomination is only used once the peer signaled ice-options:renomination.
+    // The controlling agent raises the NOMINATION value for every pair it
+    // nominates, the controlled agent follows the highest one it received
+    // https://datatracker.ietf.org/doc/html/draft-thatcher-ice-renomination-01#section-3
+    pub(crate) remote_renomination: AtomicBool,
+    pub(crate) nomination: AtomicU32,
+    pub(crate) remote_nomination: AtomicU32,
+
+    // Long-term credentials per STUN server, keeping the realm and nonce of
+    // its last challenge for the next gathering
+    pub(crate) stun_credentials: Mutex<HashMap<SocketAddr, LongTermCredentials>>,
+
     pub(crate) agent_conn: Arc<AgentConn>,
 
     // the following variables won't be changed after init_with_defaults()
@@ -72,6 +115,13 @@ pub(crate) struct AgentInternal {
     // How often should we send keepalive packets?
     // 0 means never
     pub(crate) keepalive_interval: Duration,
+    // How long the selected pair may go without an authenticated binding
+    // response before consent expires
+    pub(crate) consent_expiry: Duration,
+    // Whether IPv4 pairs are checked before IPv6 ones
+    pub(crate) prefer_ipv4: bool,
+    // How long only pairs of the preferred IP family are checked
+    pub(crate) family_head_start: Duration,
     // How often should we run our internal taskLoop to check for state changes when connecting
     pub(crate) check_interval: Duration,
 }
@@ -104,6 +154,7 @@ impl AgentInternal {
             on_connection_state_change_hdlr: Mutex::new(None),
             on_selected_candidate_pair_change_hdlr: Mutex::new(None),
             on_candidate_hdlr: Mutex::new(None),
+            on_network_change_hdlr: Mutex::new(None),
 
             tie_breaker: AtomicU64::new(rand::random::<u64>()),
             is_controlling: AtomicBool::new(config.is_controlling),
@@ -113,6 +164,7 @@ impl AgentInternal {
             nominated_pair: Mutex::new(None),
 
             connection_state: AtomicU8::new(ConnectionState::New as u8),
+            gathering_state: Arc::new(AtomicU8::new(GatheringState::New as u8)),
 
             started_ch_tx: Mutex::new(Some(started_ch_tx)),
 
@@ -135,6 +187,16 @@ impl AgentInternal {
             // 0 means never
             keepalive_interval: Duration::from_secs(0),
 
+            // How long the selected pair may go without an authenticated binding
+            // response before consent expires
+            consent_expiry: Duration::from_secs(0),
+
+            // Whether IPv4 pairs are checked before IPv6 ones
+            prefer_ipv4: false,
+
+            // How long only pairs of the preferred IP family are checked
+            family_head_start: Duration::from_secs(0),
+
             // How often should we run our internal taskLoop to check for state changes when connecting
             check_interval: Duration::from_secs(0),
 
@@ -146,6 +208,24 @@ impl AgentInternal {
             // LRU of outbound Binding request Transaction IDs
             pending_binding_requests: Mutex::new(vec![]),
 
+            last_pair_added: Mutex::new(Instant::now()),
+            remote_candidates_complete: AtomicBool::new(false),
+
+            consent_granted_at: Mutex::new(Instant::now()),
+            next_consent_check: Mutex::new(Instant::now()),
+            consent_expired: AtomicBool::new(false),
+
+            checks_started_at: Mutex::new(Instant::now()),
+
+            next_rtt_probe: Mutex::new(Instant::now()),
+            last_nomination: Mutex::new(Instant::now()),
+
+            remote_renomination: AtomicBool::new(false),
+            nomination: AtomicU32::new(0),
+            remote_nomination: AtomicU32::new(0),
+
+            stun_credentials: Mutex::new(HashMap::new()),
+
             // AgentConn
             agent_conn: Arc::new(AgentConn::new()),
         };
@@ -212,11 +292,18 @@ impl AgentInternal {
                 *checking_duration = Instant::now();
             }
 
-            // We have been in checking longer then Disconnect+Failed timeout, set the connection to Failed
+            // We have been in checking longer then Disconnect+Failed timeout, set the connection to Failed.
+            // A pair added later restarts that wait, up to failed_timeout after checking
+            // began, so a peer that keeps trickling candidates can not hold off Failed
+            let checking_since = std::cmp::min(
+                std::cmp::max(*checking_duration, *self.last_pair_added.lock().await),
+                *checking_duration + self.failed_timeout,
+            );
             if Instant::now()
-                .checked_duration_since(*checking_duration)
+                .checked_duration_since(checking_since)
                 .unwrap_or_else(|| Duration::from_secs(0))
                 > self.disconnected_timeout + self.failed_timeout
+                || self.all_checks_failed().await
             {
                 self.update_connection_state(ConnectionState::Failed).await;
                 *last_connection_state = self.connection_state.load(Ordering::SeqCst).into();
@@ -229,6 +316,23 @@ impl AgentInternal {
         *last_connection_state = self.connection_state.load(Ordering::SeqCst).into();
     }
 
+    // all_checks_failed returns whether no pair can succeed anymore: every
+    // check failed, and neither agent has candidates left to trickle
+    // https://www.rfc-editor.org/rfc/rfc8838#section-8
+    async fn all_checks_failed(&self) -> bool {
+        if !self.remote_candidates_complete.load(Ordering::SeqCst)
+            || self.gathering_state.load(Ordering::SeqCst) != GatheringState::Complete as u8
+        {
+            return false;
+        }
+
+        let checklist = self.agent_conn.checklist.lock().await;
+        !checklist.is_empty()
+            && checklist
+                .iter()
+                .all(|p| p.state.load(Ordering::SeqCst) == CandidatePairState::Failed as u8)
+    }
+
     async fn connectivity_checks(self: &Arc<Self>) {
         const ZERO_DURATION: Duration = Duration::from_secs(0);
         let mut last_connection_state = ConnectionState::Unspecified;
@@ -253,6 +357,8 @@ impl AgentInternal {
             tokio::spawn(async move {
                 loop {
                     let mut interval = DEFAULT_CHECK_INTERVAL;
+                    let consent_check_due_in = ai.consent_check_due_in().await;
+                    let family_head_start_left = ai.family_head_start_left().await;
 
                     let mut update_interval = |x: Duration| {
                         if x != ZERO_DURATION && (interval == ZERO_DURATION || interval > x) {
@@ -264,9 +370,12 @@ impl AgentInternal {
                         ConnectionState::New | ConnectionState::Checking => {
                             // While connecting, check candidates more frequently
                             update_interval(check_interval);
+                            // and start on the other IP family as soon as its turn comes
+                            update_interval(family_head_start_left);
                         }
                         ConnectionState::Connected | ConnectionState::Disconnected => {
                             update_interval(keepalive_interval);
+                            update_interval(consent_check_due_in);
                         }
                         _ => {}
                     };
@@ -328,9 +437,21 @@ impl AgentInternal {
 
         if let Some(p) = p {
             p.nominated.store(true, Ordering::SeqCst);
+            // Nominating the pair took an authenticated response on it
+            {
+                let now = Instant::now();
+                *self.consent_granted_at.lock().await = now;
+                *self.next_consent_check.lock().await = now + consent_check_interval();
+                *self.last_nomination.lock().await = now;
+            }
             {
                 let mut selected_pair = self.agent_conn.selected_pair.lock().await;
-                *selected_pair = Some(p);
+                // Another pair was nominated in place of the selected one
+                if let Some(previous) = selected_pair.replace(Arc::clone(&p)) {
+                    if !Arc::ptr_eq(&previous, &p) {
+                        previous.nominated.store(false, Ordering::SeqCst);
+                    }
+                }
             }
 
             self.update_connection_state(ConnectionState::Connected)
@@ -355,13 +476,16 @@ impl AgentInternal {
         }
     }
 
+    /// Checks the pairs that are waiting or in progress. Pairs of the two IP families are
+    /// interleaved, preferred family first, and the other family is held back during the head
+    /// start of the preferred one, as Happy Eyeballs does. The checks are paced so that this
+    /// order holds on the wire.
+    /// <https://www.rfc-editor.org/rfc/rfc8305#section-4>
     pub(crate) async fn ping_all_candidates(&self) {
         log::trace!("[{}]: pinging all candidates", self.get_name(),);
 
-        let mut pairs: Vec<(
-            Arc<dyn Candidate + Send + Sync>,
-            Arc<dyn Candidate + Send + Sync>,
-        )> = vec![];
+        let mut pairs: Vec<Arc<CandidatePair>> = vec![];
+        let head_start_over = self.family_head_start_left().await == Duration::from_secs(0);
 
         {
             let mut checklist = self.agent_conn.checklist.lock().await;
@@ -371,7 +495,20 @@ impl AgentInternal {
                     self.get_name(),
                 );
             }
+
+            // Without a pending pair of the preferred family there is nothing to wait for
+            let preferred_pending = checklist.iter().any(|p| {
+                let p_state = p.state.load(Ordering::SeqCst);
+                self.is_preferred_family(p)
+                    && (p_state == CandidatePairState::Waiting as u8
+                        || p_state == CandidatePairState::InProgress as u8)
+            });
+
             for p in &mut *checklist {
+                if !head_start_over && preferred_pending && !self.is_preferred_family(p) {
+                    continue;
+                }
+
                 let p_state = p.state.load(Ordering::SeqCst);
                 if p_state == CandidatePairState::Waiting as u8 {
                     p.state
@@ -390,30 +527,78 @@ impl AgentInternal {
                         .store(CandidatePairState::Failed as u8, Ordering::SeqCst);
                 } else {
                     p.binding_request_count.fetch_add(1, Ordering::SeqCst);
-                    let local = p.local.clone();
-                    let remote = p.remote.clone();
-                    pairs.push((local, remote));
+                    pairs.push(Arc::clone(p));
                 }
             }
         }
 
-        for (local, remote) in pairs {
-            self.ping_candidate(&local, &remote).await;
+        let pairs = self.interleave_families(pairs);
+        // Spread the checks, but never beyond the next tick
+        let pacing = match u32::try_from(pairs.len()) {
+            Ok(n) if n > 0 => std::cmp::min(CHECK_PACING, self.check_interval / n),
+            _ => CHECK_PACING,
+        };
+        for (i, p) in pairs.iter().enumerate() {
+            if i > 0 {
+                tokio::time::sleep(pacing).await;
+            }
+            self.ping_candidate(&p.local, &p.remote).await;
         }
     }
 
+    // is_preferred_family returns whether p is of the IP family checked first
+    fn is_preferred_family(&self, p: &CandidatePair) -> bool {
+        p.local.network_type().is_ipv4() == self.prefer_ipv4
+    }
+
+    // interleave_families orders pairs by priority and alternates between the
+    // IP families, starting with the preferred one
+    // https://www.rfc-editor.org/rfc/rfc8305#section-4
+    fn interleave_families(&self, mut pairs: Vec<Arc<CandidatePair>>) -> Vec<Arc<CandidatePair>> {
+        pairs.sort_by_key(|p| std::cmp::Reverse(p.priority()));
+        let (preferred, other): (Vec<_>, Vec<_>) =
+            pairs.into_iter().partition(|p| self.is_preferred_family(p));
+
+        let mut interleaved = Vec::with_capacity(preferred.len() + other.len());
+        let (mut preferred, mut other) = (preferred.into_iter(), other.into_iter());
+        loop {
+            match (preferred.next(), other.next()) {
+                (None, None) => break,
+                (p, o) => interleaved.extend(p.into_iter().chain(o)),
+            }
+        }
+        interleaved
+    }
+
+    // family_head_start_left returns how long only pairs of the preferred IP
+    // family are still checked. The head start counts from start(), which an
+    // ICE restart calls again, so pairs trickled in later get none of their own
+    async fn family_head_start_left(&self) -> Duration {
+        let checks_started_at = *self.checks_started_at.lock().await;
+        (checks_started_at + self.family_head_start).saturating_duration_since(Instant::now())
+    }
+
     pub(crate) async fn add_pair(
         &self,
         local: Arc<dyn Candidate + Send + Sync>,
         remote: Arc<dyn Candidate + Send + Sync>,
     ) {
+        // An active TCP candidate can only connect to a passive one
+        // https://www.rfc-editor.org/rfc/rfc6544#section-6.2
+        if local.tcp_type() == TcpType::Active && remote.tcp_type() != TcpType::Passive {
+            return;
+        }
+
         let p = Arc::new(CandidatePair::new(
             local,
             remote,
             self.is_controlling.load(Ordering::SeqCst),
         ));
-        let mut checklist = self.agent_conn.checklist.lock().await;
-        checklist.push(p);
+        {
+            let mut checklist = self.agent_conn.checklist.lock().await;
+            checklist.push(p);
+        }
+        *self.last_pair_added.lock().await = Instant::now();
     }
 
     pub(crate) async fn find_pair(
@@ -507,6 +692,65 @@ impl AgentInternal {
         }
     }
 
+    /// Sends a consent check on the selected pair when one is due, and goes to failed once the
+    /// pair went consent_expiry without an authenticated response. Returns whether consent is
+    /// still granted.
+    /// <https://www.rfc-editor.org/rfc/rfc7675#section-5.1>
+    pub(crate) async fn check_consent(&self) -> bool {
+        let selected_pair = match self.agent_conn.get_selected_pair().await {
+            Some(selected_pair) => selected_pair,
+            None => return true,
+        };
+
+        let now = Instant::now();
+        let granted_at = *self.consent_granted_at.lock().await;
+        if now.saturating_duration_since(granted_at) > self.consent_expiry {
+            log::warn!(
+                "[{}]: consent to send to {} expired",
+                self.get_name(),
+                selected_pair.remote
+            );
+            self.consent_expired.store(true, Ordering::SeqCst);
+            self.update_connection_state(ConnectionState::Failed).await;
+            return false;
+        }
+
+        let check_due = {
+            let mut next_consent_check = self.next_consent_check.lock().await;
+            if now >= *next_consent_check {
+                *next_consent_check = now + consent_check_interval();
+                true
+            } else {
+                false
+            }
+        };
+        if check_due {
+            self.ping_candidate(&selected_pair.local, &selected_pair.remote)
+                .await;
+        }
+
+        true
+    }
+
+    /// Renews consent when p, validated by an authenticated binding response, is the selected
+    /// pair.
+    pub(crate) async fn refresh_consent(&self, p: &Arc<CandidatePair>) {
+        if let Some(selected_pair) = self.agent_conn.get_selected_pair().await {
+            if Arc::ptr_eq(&selected_pair, p) {
+                *self.consent_granted_at.lock().await = Instant::now();
+            }
+        }
+    }
+
+    // consent_check_due_in returns how long until the next consent check on
+    // the selected pair
+    async fn consent_check_due_in(&self) -> Duration {
+        self.next_consent_check
+            .lock()
+            .await
+            .saturating_duration_since(Instant::now())
+    }
+
     fn request_connectivity_check(&self) {
         let _ = self.force_candidate_contact_tx.try_send(true);
     }
@@ -683,6 +927,69 @@ impl AgentInternal {
         }
     }
 
+    /// Closes the local candidates whose socket is bound to ip, after ip went away from the
+    /// host's interfaces, and drops their pairs. If the selected pair was one of them the
+    /// connection is disconnected until another pair is selected.
+    pub(crate) async fn delete_candidates_on(&self, ip: IpAddr) {
+        let mut deleted = vec![];
+        {
+            let mut local_candidates = self.local_candidates.lock().await;
+            for cs in local_candidates.values_mut() {
+                let mut kept = vec![];
+                for c in cs.drain(..) {
+                    let bound_to_ip = match c.get_conn() {
+                        Some(conn) => {
+                            matches!(conn.local_addr().await, Ok(addr) if addr.ip() == ip)
+                        }
+                        None => false,
+                    };
+                    if bound_to_ip {
+                        deleted.push(c);
+                    } else {
+                        kept.push(c);
+                    }
+                }
+                *cs = kept;
+            }
+        }
+        if deleted.is_empty() {
+            return;
+        }
+
+        for c in &deleted {
+            log::debug!("[{}]: Deleting candidate {}", self.get_name(), c);
+            if let Err(err) = c.close().await {
+                log::warn!(
+                    "[{}]: Failed to close candidate {}: {}",
+                    self.get_name(),
+                    c,
+                    err
+                );
+            }
+        }
+
+        let is_deleted = |p: &Option<Arc<CandidatePair>>| match p {
+            Some(p) => deleted.iter().any(|c| c.equal(&*p.local)),
+            None => false,
+        };
+        {
+            let mut checklist = self.agent_conn.checklist.lock().await;
+            checklist.retain(|p| !deleted.iter().any(|c| c.equal(&*p.local)));
+        }
+        {
+            let mut nominated_pair = self.nominated_pair.lock().await;
+            if is_deleted(&nominated_pair) {
+                *nominated_pair = None;
+            }
+        }
+        let selected_deleted = is_deleted(&*self.agent_conn.selected_pair.lock().await);
+        if selected_deleted {
+            self.set_selected_pair(None).await;
+            self.update_connection_state(ConnectionState::Disconnected)
+                .await;
+        }
+    }
+
     pub(crate) async fn find_remote_candidate(
         &self,
         network_type: NetworkType,
@@ -716,6 +1023,7 @@ impl AgentInternal {
 
         self.invalidate_pending_binding_requests(Instant::now())
             .await;
+        let pair = self.find_pair(local, remote).await;
         {
             let mut pending_binding_requests = self.pending_binding_requests.lock().await;
             pending_binding_requests.push(BindingRequest {
@@ -723,6 +1031,7 @@ impl AgentInternal {
                 transaction_id: m.transaction_id,
                 destination: remote.addr().await,
                 is_use_candidate: m.contains(ATTR_USE_CANDIDATE),
+                pair,
             });
         }
 
@@ -783,6 +1092,8 @@ impl AgentInternal {
                 .unwrap_or(true)
             {
                 temp.push(binding_request);
+            } else if let Some(pair) = &binding_request.pair {
+                pair.record_timeout();
             }
         }
 
@@ -861,7 +1172,7 @@ impl AgentInternal {
             return;
         }
 
-        let remote_candidate = self
+        let mut remote_candidate = self
             .find_remote_candidate(local.network_type(), remote)
             .await;
         if m.typ.class == CLASS_SUCCESS_RESPONSE {
@@ -917,8 +1228,53 @@ impl AgentInternal {
             }
 
             if remote_candidate.is_none() {
-                log::error!("[{}]: No remote candidate!", self.get_name(),);
-                return;
+                // A check from an address the peer did not signal, e.g. its NAT
+                // or load balancer rewrote the source. It becomes a peer reflexive
+                // candidate with the priority carried in the check.
+                // https://www.rfc-editor.org/rfc/rfc8445#section-7.3.1.3
+                let mut priority = PriorityAttr::default();
+                if let Err(err) = priority.get_from(m) {
+                    log::warn!(
+                        "[{}]: discard message from ({}), {}",
+                        self.get_name(),
+                        remote,
+                        err
+                    );
+                    return;
+                }
+
+                let prflx_config = CandidatePeerReflexiveConfig {
+                    base_config: CandidateBaseConfig {
+                        network: local.network_type().network_short(),
+                        address: remote.ip().to_string(),
+                        port: remote.port(),
+                        component: local.component(),
+                        priority: priority.0,
+                        ..CandidateBaseConfig::default()
+                    },
+                    ..CandidatePeerReflexiveConfig::default()
+                };
+
+                let rc: Arc<dyn Candidate + Send + Sync> =
+                    match prflx_config.new_candidate_peer_reflexive().await {
+                        Ok(rc) => Arc::new(rc),
+                        Err(err) => {
+                            log::error!(
+                                "[{}]: Failed to create new remote prflx candidate ({})",
+                                self.get_name(),
+                                err
+                            );
+                            return;
+                        }
+                    };
+
+                log::debug!(
+                    "[{}]: adding a new peer-reflexive candidate: {}",
+                    self.get_name(),
+                    remote
+                );
+                self.add_remote_candidate(&rc).await;
+                remote_candidate = Some(rc);
             }
 
             log::trace!(
@@ -1166,3 +1522,10 @@ impl AgentInternal {
         }
     }
 }
+
+// consent_check_interval returns the time until the next consent check,
+// randomized so that the checks of many agents don't synchronize
+// https://www.rfc-editor.org/rfc/rfc7675#section-5.1
+fn consent_check_interval() -> Duration {
+    CONSENT_CHECK_INTERVAL.mul_f64(rand::thread_rng().gen_range(0.8..1.2))
+}
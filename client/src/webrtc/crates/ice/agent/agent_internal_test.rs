@@ -0,0 +1,132 @@
+use super::agent_config::AgentConfig;
+use super::Agent;
+use crate::webrtc::ice::candidate::candidate_base::unmarshal_candidate;
+use crate::webrtc::ice::candidate::Candidate;
+use crate::webrtc::ice::network_type::NetworkType;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::time::{Duration, Instant};
+
+const HEAD_START: Duration = Duration::from_millis(250);
+
+// An agent that prefers IPv6, its checks are only recorded since its
+// candidates have no socket
+async fn dual_stack_agent() -> Agent {
+    Agent::new(AgentConfig {
+        network_types: vec![NetworkType::Udp4, NetworkType::Udp6],
+        family_head_start: Some(HEAD_START),
+        ..Default::default()
+    })
+    .await
+    .unwrap()
+}
+
+async fn host_candidate(ip: &str, port: u16, priority: u32) -> Arc<dyn Candidate + Send + Sync> {
+    Arc::new(
+        unmarshal_candidate(&format!("1 1 udp {} {} {} typ host", priority, ip, port))
+            .await
+            .unwrap(),
+    )
+}
+
+// add_pair adds a pair to remote, the priority of which orders the pairs
+async fn add_pair(agent: &Agent, remote: &str, priority: u32) -> SocketAddr {
+    let remote: SocketAddr = remote.parse().unwrap();
+    let local_ip = if remote.is_ipv4() {
+        "10.0.0.1"
+    } else {
+        "fd00::1"
+    };
+    let local = host_candidate(local_ip, 5000, 1000).await;
+    let remote_candidate = host_candidate(&remote.ip().to_string(), remote.port(), priority).await;
+    agent.internal.add_pair(local, remote_candidate).await;
+    remote
+}
+
+// checks returns the destinations and times of the checks sent since the
+// last call
+async fn checks(agent: &Agent) -> Vec<(SocketAddr, Instant)> {
+    let mut pending_binding_requests = agent.internal.pending_binding_requests.lock().await;
+    pending_binding_requests
+        .drain(..)
+        .map(|r| (r.destination, r.timestamp))
+        .collect()
+}
+
+async fn destinations(agent: &Agent) -> Vec<SocketAddr> {
+    checks(agent).await.into_iter().map(|(d, _)| d).collect()
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_other_family_waits_for_head_start() {
+    let agent = dual_stack_agent().await;
+    agent.internal.start().await;
+    let ipv6 = add_pair(&agent, "[fd00::2]:5000", 100).await;
+    let ipv4 = add_pair(&agent, "10.0.0.2:5000", 200).await;
+
+    agent.internal.ping_all_candidates().await;
+    assert_eq!(destinations(&agent).await, vec![ipv6]);
+
+    tokio::time::sleep(HEAD_START).await;
+    agent.internal.ping_all_candidates().await;
+    assert_eq!(destinations(&agent).await, vec![ipv6, ipv4]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_checks_alternate_families() {
+    let agent = dual_stack_agent().await;
+    agent.internal.start().await;
+    tokio::time::sleep(HEAD_START).await;
+
+    let ipv4_low = add_pair(&agent, "10.0.0.2:5001", 100).await;
+    let ipv6_low = add_pair(&agent, "[fd00::2]:5001", 300).await;
+    let ipv4_high = add_pair(&agent, "10.0.0.2:5000", 500).await;
+    let ipv4_lowest = add_pair(&agent, "10.0.0.2:5002", 50).await;
+    let ipv6_high = add_pair(&agent, "[fd00::2]:5000", 400).await;
+
+    agent.internal.ping_all_candidates().await;
+    let checks = checks(&agent).await;
+    let destinations: Vec<SocketAddr> = checks.iter().map(|(d, _)| *d).collect();
+    assert_eq!(
+        destinations,
+        vec![ipv6_high, ipv4_high, ipv6_low, ipv4_low, ipv4_lowest]
+    );
+
+    // Five checks do not fit into the 200ms check interval at the full pacing
+    for w in checks.windows(2) {
+        assert_eq!(w[1].1 - w[0].1, Duration::from_millis(40));
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_late_pair_of_other_family_gets_no_head_start() {
+    let agent = dual_stack_agent().await;
+    agent.internal.start().await;
+    let ipv6 = add_pair(&agent, "[fd00::2]:5000", 100).await;
+    agent.internal.ping_all_candidates().await;
+    assert_eq!(destinations(&agent).await, vec![ipv6]);
+
+    // The head start counts from the start of the checks, not from the pair
+    tokio::time::sleep(HEAD_START).await;
+    let ipv4 = add_pair(&agent, "10.0.0.2:5000", 200).await;
+    agent.internal.ping_all_candidates().await;
+    assert_eq!(destinations(&agent).await, vec![ipv6, ipv4]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_restart_starts_a_new_head_start() {
+    let agent = dual_stack_agent().await;
+    agent.internal.start().await;
+    tokio::time::sleep(HEAD_START).await;
+
+    agent.restart(String::new(), String::new()).await.unwrap();
+    let ipv6 = add_pair(&agent, "[fd00::2]:5000", 100).await;
+    let ipv4 = add_pair(&agent, "10.0.0.2:5000", 200).await;
+
+    agent.internal.ping_all_candidates().await;
+    assert_eq!(destinations(&agent).await, vec![ipv6]);
+
+    tokio::time::sleep(HEAD_START).await;
+    agent.internal.ping_all_candidates().await;
+    assert_eq!(destinations(&agent).await, vec![ipv6, ipv4]);
+}
@@ -1,11 +1,15 @@
+use crate::webrtc::ice::agent::agent_config::*;
 use crate::webrtc::ice::agent::agent_internal::*;
+use crate::webrtc::ice::candidate::candidate_base::CandidateBaseConfig;
+use crate::webrtc::ice::candidate::candidate_peer_reflexive::CandidatePeerReflexiveConfig;
 use crate::webrtc::ice::candidate::*;
 use crate::webrtc::ice::control::*;
+use crate::webrtc::ice::nomination::*;
 use crate::webrtc::ice::priority::*;
 use crate::webrtc::ice::use_candidate::*;
 
 use crate::webrtc::stun::{
-    agent::*, attributes::*, fingerprint::*, integrity::*, message::*, textattrs::*,
+    agent::*, attributes::*, fingerprint::*, integrity::*, message::*, textattrs::*, xoraddr::*,
 };
 
 use async_trait::async_trait;
@@ -73,6 +77,27 @@ impl AgentInternal {
                     .as_nanos()
                     > self.host_acceptance_min_wait.as_nanos()
             }
+            CandidateType::ServerReflexive => {
+                Instant::now()
+                    .checked_duration_since(*start_time)
+                    .unwrap_or_else(|| Duration::from_secs(0))
+                    .as_nanos()
+                    > self.srflx_acceptance_min_wait.as_nanos()
+            }
+            CandidateType::PeerReflexive => {
+                Instant::now()
+                    .checked_duration_since(*start_time)
+                    .unwrap_or_else(|| Duration::from_secs(0))
+                    .as_nanos()
+                    > self.prflx_acceptance_min_wait.as_nanos()
+            }
+            CandidateType::Relay => {
+                Instant::now()
+                    .checked_duration_since(*start_time)
+                    .unwrap_or_else(|| Duration::from_secs(0))
+                    .as_nanos()
+                    > self.relay_acceptance_min_wait.as_nanos()
+            }
             _ => {
                 log::error!(
                     "is_nominatable invalid candidate type {}",
@@ -83,6 +108,211 @@ impl AgentInternal {
         }
     }
 
+    // valid_pair returns the pair validated by a successful check from local to
+    // remote. When the mapped address in the response is not the address of
+    // local, a NAT between the agents assigned a new one: it becomes a local
+    // peer reflexive candidate with local as its base, and the valid pair is
+    // built from it.
+    // https://www.rfc-editor.org/rfc/rfc8445#section-7.2.5.3.1
+    async fn valid_pair(
+        &self,
+        m: &Message,
+        local: &Arc<dyn Candidate + Send + Sync>,
+        remote: &Arc<dyn Candidate + Send + Sync>,
+    ) -> Option<Arc<CandidatePair>> {
+        let checked = self.find_pair(local, remote).await;
+
+        // An active TCP candidate opens a connection from a new port for every
+        // remote, the mapped address never matches its signaled port
+        if local.network_type().is_tcp() {
+            return checked;
+        }
+
+        let mut mapped = XorMappedAddress::default();
+        if mapped.get_from(m).is_err() {
+            return checked;
+        }
+        let (mapped_addr, base_addr) =
+            (SocketAddr::new(mapped.ip, mapped.port), local.addr().await);
+        if mapped_addr == base_addr {
+            return checked;
+        }
+
+        if let Some(p) = &checked {
+            p.state
+                .store(CandidatePairState::Succeeded as u8, Ordering::SeqCst);
+        }
+
+        // Reuse the candidate learned by an earlier check
+        {
+            let checklist = self.agent_conn.checklist.lock().await;
+            for p in &*checklist {
+                if p.local.candidate_type() == CandidateType::PeerReflexive
+                    && p.remote.equal(&**remote)
+                    && p.local.addr().await == mapped_addr
+                {
+                    return Some(Arc::clone(p));
+                }
+            }
+        }
+
+        let prflx_config = CandidatePeerReflexiveConfig {
+            base_config: CandidateBaseConfig {
+                network: local.network_type().network_short(),
+                address: mapped.ip.to_string(),
+                port: mapped.port,
+                component: local.component(),
+                priority: peer_reflexive_priority(local.component()),
+                conn: local.get_conn().cloned(),
+                ..CandidateBaseConfig::default()
+            },
+            rel_addr: base_addr.ip().to_string(),
+            rel_port: base_addr.port(),
+        };
+        let prflx: Arc<dyn Candidate + Send + Sync> =
+            match prflx_config.new_candidate_peer_reflexive().await {
+                Ok(prflx) => Arc::new(prflx),
+                Err(err) => {
+                    log::error!(
+                        "[{}]: Failed to create new local prflx candidate ({})",
+                        self.get_name(),
+                        err
+                    );
+                    return checked;
+                }
+            };
+
+        log::debug!(
+            "[{}]: adding a new local peer-reflexive candidate: {}",
+            self.get_name(),
+            prflx
+        );
+
+        // It shares the socket of its base, whose receive loop handles its
+        // traffic, and is neither signaled nor paired with other remote
+        // candidates, so it stays out of the local candidates
+        self.add_pair(Arc::clone(&prflx), Arc::clone(remote)).await;
+        self.find_pair(&prflx, remote).await
+    }
+
+    // check_renomination measures the round trip time of the valid pairs
+    // besides the selected one, and nominates one that is clearly better than
+    // the selected pair, e.g. once a faster interface came up
+    // https://datatracker.ietf.org/doc/html/draft-thatcher-ice-renomination-01
+    async fn check_renomination(&self, selected_pair: &Arc<CandidatePair>) {
+        // Without the NOMINATION attribute the controlled agent keeps the pair
+        // it selected first
+        if !self.remote_renomination.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let now = Instant::now();
+        {
+            let mut next_rtt_probe = self.next_rtt_probe.lock().await;
+            if now < *next_rtt_probe {
+                return;
+            }
+            *next_rtt_probe = now + RTT_PROBE_INTERVAL;
+        }
+
+        let valid_pairs: Vec<Arc<CandidatePair>> = {
+            let checklist = self.agent_conn.checklist.lock().await;
+            checklist
+                .iter()
+                .filter(|p| {
+                    p.state.load(Ordering::SeqCst) == CandidatePairState::Succeeded as u8
+                        && !Arc::ptr_eq(p, selected_pair)
+                })
+                .cloned()
+                .collect()
+        };
+        for p in &valid_pairs {
+            self.ping_candidate(&p.local, &p.remote).await;
+        }
+
+        let last_nomination = *self.last_nomination.lock().await;
+        if now.saturating_duration_since(last_nomination) < RENOMINATION_HOLD_TIME {
+            return;
+        }
+        let selected_score = match selected_pair.score() {
+            Some(selected_score) => selected_score,
+            None => return,
+        };
+        let best = valid_pairs
+            .iter()
+            .filter(|p| p.responses_received.load(Ordering::SeqCst) >= RENOMINATION_MIN_SAMPLES)
+            .filter_map(|p| p.score().map(|score| (p, score)))
+            .min_by_key(|(_, score)| *score);
+
+        // Only switch for a gain that outweighs the disruption: a quarter
+        // of the round trip time and no less than RENOMINATION_MIN_GAIN
+        if let Some((p, score)) = best {
+            if score < selected_score.mul_f64(0.75)
+                && selected_score - score >= RENOMINATION_MIN_GAIN
+            {
+                log::info!(
+                    "[{}]: renominating {} ({:?}) in place of {} ({:?})",
+                    self.get_name(),
+                    p,
+                    score,
+                    selected_pair,
+                    selected_score
+                );
+                self.set_nominated_pair(Arc::clone(p)).await;
+                *self.last_nomination.lock().await = now;
+                self.nominate_pair().await;
+            }
+        }
+    }
+
+    // is_renominated returns whether p is the pair nominated in place of the
+    // selected one
+    async fn is_renominated(&self, p: &Arc<CandidatePair>) -> bool {
+        if let Some(selected_pair) = self.agent_conn.get_selected_pair().await {
+            if Arc::ptr_eq(&selected_pair, p) {
+                return false;
+            }
+        }
+
+        let nominated_pair = self.nominated_pair.lock().await;
+        match &*nominated_pair {
+            Some(nominated_pair) => **nominated_pair == **p,
+            None => false,
+        }
+    }
+
+    // set_nominated_pair makes p the pair to nominate, with the next
+    // NOMINATION value
+    async fn set_nominated_pair(&self, p: Arc<CandidatePair>) {
+        let mut nominated_pair = self.nominated_pair.lock().await;
+        *nominated_pair = Some(p);
+        self.nomination.fetch_add(1, Ordering::SeqCst);
+    }
+
+    // accept_nomination returns whether the controlled agent selects p, which
+    // the controlling agent nominated with the NOMINATION value nomination.
+    // Renominated pairs are followed only for a value above the last one.
+    async fn accept_nomination(&self, p: &Arc<CandidatePair>, nomination: Option<u32>) -> bool {
+        let selected_pair = self.agent_conn.get_selected_pair().await;
+        if let Some(selected_pair) = &selected_pair {
+            if Arc::ptr_eq(selected_pair, p) {
+                return false;
+            }
+        }
+
+        match nomination {
+            Some(nomination) => {
+                let last = self.remote_nomination.load(Ordering::SeqCst);
+                if selected_pair.is_some() && nomination <= last {
+                    return false;
+                }
+                self.remote_nomination.store(nomination, Ordering::SeqCst);
+                true
+            }
+            None => selected_pair.is_none(),
+        }
+    }
+
     async fn nominate_pair(&self) {
         let result = {
             let nominated_pair = self.nominated_pair.lock().await;
@@ -96,19 +326,27 @@ impl AgentInternal {
                     let ufrag_pwd = self.ufrag_pwd.lock().await;
                     let username =
                         ufrag_pwd.remote_ufrag.clone() + ":" + ufrag_pwd.local_ufrag.as_str();
-                    let mut msg = Message::new();
-                    let result = msg.build(&[
+                    let mut setters: Vec<Box<dyn Setter>> = vec![
                         Box::new(BINDING_REQUEST),
                         Box::new(TransactionId::new()),
                         Box::new(Username::new(ATTR_USERNAME, username)),
                         Box::new(UseCandidateAttr::default()),
                         Box::new(AttrControlling(self.tie_breaker.load(Ordering::SeqCst))),
-                        Box::new(PriorityAttr(pair.local.priority())),
-                        Box::new(MessageIntegrity::new_short_term_integrity(
-                            ufrag_pwd.remote_pwd.clone(),
-                        )),
-                        Box::new(FINGERPRINT),
-                    ]);
+                        Box::new(PriorityAttr(peer_reflexive_priority(
+                            pair.local.component(),
+                        ))),
+                    ];
+                    if self.remote_renomination.load(Ordering::SeqCst) {
+                        setters.push(Box::new(NominationAttr(
+                            self.nomination.load(Ordering::SeqCst),
+                        )));
+                    }
+                    setters.push(Box::new(MessageIntegrity::new_short_term_integrity(
+                        ufrag_pwd.remote_pwd.clone(),
+                    )));
+                    setters.push(Box::new(FINGERPRINT));
+                    let mut msg = Message::new();
+                    let result = msg.build(&setters);
                     (msg, result)
                 };
 
@@ -136,6 +374,7 @@ impl AgentInternal {
     }
 
     pub(crate) async fn start(&self) {
+        *self.checks_started_at.lock().await = Instant::now();
         if self.is_controlling.load(Ordering::SeqCst) {
             ControllingSelector::start(self).await;
         } else {
@@ -198,6 +437,7 @@ impl ControllingSelector for AgentInternal {
             let mut nominated_pair = self.nominated_pair.lock().await;
             *nominated_pair = None;
         }
+        self.nomination.store(0, Ordering::SeqCst);
         {
             let mut start_time = self.start_time.lock().await;
             *start_time = Instant::now();
@@ -216,10 +456,11 @@ impl ControllingSelector for AgentInternal {
             nominated_pair.is_some()
         };
 
-        if self.agent_conn.get_selected_pair().await.is_some() {
-            if self.validate_selected_pair().await {
+        if let Some(selected_pair) = self.agent_conn.get_selected_pair().await {
+            if self.validate_selected_pair().await && self.check_consent().await {
                 log::trace!("[{}]: checking keepalive", self.get_name());
                 self.check_keepalive().await;
+                self.check_renomination(&selected_pair).await;
             }
         } else if nominated_pair_is_some {
             self.nominate_pair().await;
@@ -239,10 +480,7 @@ impl ControllingSelector for AgentInternal {
                         p.remote.to_string()
                     );
                     p.nominated.store(true, Ordering::SeqCst);
-                    {
-                        let mut nominated_pair = self.nominated_pair.lock().await;
-                        *nominated_pair = Some(p);
-                    }
+                    self.set_nominated_pair(p).await;
                 }
 
                 self.nominate_pair().await;
@@ -266,7 +504,7 @@ impl ControllingSelector for AgentInternal {
                 Box::new(TransactionId::new()),
                 Box::new(Username::new(ATTR_USERNAME, username)),
                 Box::new(AttrControlling(self.tie_breaker.load(Ordering::SeqCst))),
-                Box::new(PriorityAttr(local.priority())),
+                Box::new(PriorityAttr(peer_reflexive_priority(local.component()))),
                 Box::new(MessageIntegrity::new_short_term_integrity(
                     ufrag_pwd.remote_pwd.clone(),
                 )),
@@ -306,9 +544,11 @@ impl ControllingSelector for AgentInternal {
             );
             let selected_pair_is_none = self.agent_conn.get_selected_pair().await.is_none();
 
-            if let Some(p) = self.find_pair(local, remote).await {
+            if let Some(p) = self.valid_pair(m, local, remote).await {
                 p.state
                     .store(CandidatePairState::Succeeded as u8, Ordering::SeqCst);
+                p.record_response(pending_request.timestamp.elapsed());
+                self.refresh_consent(&p).await;
                 log::trace!(
                     "Found valid candidate pair: {}, p.state: {}, isUseCandidate: {}, {}",
                     p,
@@ -316,7 +556,9 @@ impl ControllingSelector for AgentInternal {
                     pending_request.is_use_candidate,
                     selected_pair_is_none
                 );
-                if pending_request.is_use_candidate && selected_pair_is_none {
+                if pending_request.is_use_candidate
+                    && (selected_pair_is_none || self.is_renominated(&p).await)
+                {
                     self.set_selected_pair(Some(Arc::clone(&p))).await;
                 }
             } else {
@@ -369,10 +611,7 @@ impl ControllingSelector for AgentInternal {
                     {
                         log::trace!("The candidate ({}, {}) is the best candidate available, marking it as nominated",
                             p.local, p.remote);
-                        {
-                            let mut nominated_pair = self.nominated_pair.lock().await;
-                            *nominated_pair = Some(p);
-                        }
+                        self.set_nominated_pair(p).await;
                         self.nominate_pair().await;
                     }
                 } else {
@@ -388,14 +627,16 @@ impl ControllingSelector for AgentInternal {
 
 #[async_trait]
 impl ControlledSelector for AgentInternal {
-    async fn start(&self) {}
+    async fn start(&self) {
+        self.remote_nomination.store(0, Ordering::SeqCst);
+    }
 
     async fn contact_candidates(&self) {
         // A lite selector should not contact candidates
         if self.lite.load(Ordering::SeqCst) {
             self.validate_selected_pair().await;
         } else if self.agent_conn.get_selected_pair().await.is_some() {
-            if self.validate_selected_pair().await {
+            if self.validate_selected_pair().await && self.check_consent().await {
                 log::trace!("[{}]: checking keepalive", self.get_name());
                 self.check_keepalive().await;
             }
@@ -418,7 +659,7 @@ impl ControlledSelector for AgentInternal {
                 Box::new(TransactionId::new()),
                 Box::new(Username::new(ATTR_USERNAME, username)),
                 Box::new(AttrControlled(self.tie_breaker.load(Ordering::SeqCst))),
-                Box::new(PriorityAttr(local.priority())),
+                Box::new(PriorityAttr(peer_reflexive_priority(local.component()))),
                 Box::new(MessageIntegrity::new_short_term_integrity(
                     ufrag_pwd.remote_pwd.clone(),
                 )),
@@ -463,9 +704,11 @@ impl ControlledSelector for AgentInternal {
                 local
             );
 
-            if let Some(p) = self.find_pair(local, remote).await {
+            if let Some(p) = self.valid_pair(m, local, remote).await {
                 p.state
                     .store(CandidatePairState::Succeeded as u8, Ordering::SeqCst);
+                p.record_response(pending_request.timestamp.elapsed());
+                self.refresh_consent(&p).await;
                 log::trace!("Found valid candidate pair: {}", p);
             } else {
                 // This shouldn't happen
@@ -500,7 +743,12 @@ impl ControlledSelector for AgentInternal {
                     // previously sent by this pair produced a successful response and
                     // generated a valid pair (Section 7.2.5.3.2).  The agent sets the
                     // nominated flag value of the valid pair to true.
-                    if self.agent_conn.get_selected_pair().await.is_none() {
+                    // A controlling agent that renominates sends a higher
+                    // NOMINATION value with the pair it switches to
+                    // https://datatracker.ietf.org/doc/html/draft-thatcher-ice-renomination-01#section-3
+                    let mut nomination = NominationAttr::default();
+                    let nomination = nomination.get_from(m).ok().map(|_| nomination.0);
+                    if self.accept_nomination(&p, nomination).await {
                         self.set_selected_pair(Some(Arc::clone(&p))).await;
                     }
                     self.send_binding_success(m, local, remote).await;
@@ -0,0 +1,219 @@
+use super::agent_config::AgentConfig;
+use super::Agent;
+use crate::webrtc::ice::candidate::CandidatePairState;
+use crate::webrtc::ice::control::AttrControlling;
+use crate::webrtc::ice::network_type::NetworkType;
+use crate::webrtc::ice::nomination::NominationAttr;
+use crate::webrtc::ice::priority::PriorityAttr;
+use crate::webrtc::ice::use_candidate::UseCandidateAttr;
+use crate::webrtc::stun::{
+    agent::TransactionId, attributes::ATTR_USERNAME, fingerprint::FINGERPRINT,
+    integrity::MessageIntegrity, message::*, textattrs::Username, xoraddr::XorMappedAddress,
+};
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::time::Duration;
+
+const PEER_UFRAG: &str = "peerufrag";
+const PEER_PWD: &str = "peerpasswordpeerpassword";
+
+// The controlling peer of the test, checking from one socket per pair
+struct ControllingPeer {
+    sockets: Vec<Arc<UdpSocket>>,
+    agent_addr: SocketAddr,
+    agent_ufrag: String,
+    agent_pwd: String,
+}
+
+impl ControllingPeer {
+    async fn new(agent: &Agent, agent_addr: SocketAddr, pairs: usize) -> Self {
+        let (agent_ufrag, agent_pwd) = agent.get_local_user_credentials().await;
+        let mut sockets = vec![];
+        for _ in 0..pairs {
+            let socket = Arc::new(UdpSocket::bind("0.0.0.0:0").await.unwrap());
+            tokio::spawn(answer_checks(Arc::clone(&socket)));
+            sockets.push(socket);
+        }
+        ControllingPeer {
+            sockets,
+            agent_addr,
+            agent_ufrag,
+            agent_pwd,
+        }
+    }
+
+    fn addr(&self, pair: usize) -> SocketAddr {
+        let port = self.sockets[pair].local_addr().unwrap().port();
+        SocketAddr::new(self.agent_addr.ip(), port)
+    }
+
+    // check sends a binding request on the pair, nominating it with
+    // USE-CANDIDATE and the NOMINATION value when given
+    async fn check(&self, pair: usize, use_candidate: bool, nomination: Option<u32>) {
+        let mut setters: Vec<Box<dyn Setter>> = vec![
+            Box::new(BINDING_REQUEST),
+            Box::new(TransactionId::new()),
+            Box::new(Username::new(
+                ATTR_USERNAME,
+                format!("{}:{}", self.agent_ufrag, PEER_UFRAG),
+            )),
+            Box::new(AttrControlling(1)),
+            Box::new(PriorityAttr(1)),
+        ];
+        if use_candidate {
+            setters.push(Box::new(UseCandidateAttr::default()));
+        }
+        if let Some(nomination) = nomination {
+            setters.push(Box::new(NominationAttr(nomination)));
+        }
+        setters.push(Box::new(MessageIntegrity::new_short_term_integrity(
+            self.agent_pwd.clone(),
+        )));
+        setters.push(Box::new(FINGERPRINT));
+
+        let mut m = Message::new();
+        m.build(&setters).unwrap();
+        self.sockets[pair]
+            .send_to(&m.raw, self.agent_addr)
+            .await
+            .unwrap();
+    }
+}
+
+// answer_checks answers the checks of the agent, so that its pairs succeed
+async fn answer_checks(socket: Arc<UdpSocket>) {
+    let mut buf = vec![0u8; 1500];
+    while let Ok((n, from)) = socket.recv_from(&mut buf).await {
+        let mut m = Message::new();
+        m.raw = buf[..n].to_vec();
+        if m.decode().is_err() || m.typ != BINDING_REQUEST {
+            continue;
+        }
+
+        let mut out = Message::new();
+        out.build(&[
+            Box::new(m),
+            Box::new(BINDING_SUCCESS),
+            Box::new(XorMappedAddress {
+                ip: from.ip(),
+                port: from.port(),
+            }),
+            Box::new(MessageIntegrity::new_short_term_integrity(
+                PEER_PWD.to_owned(),
+            )),
+            Box::new(FINGERPRINT),
+        ])
+        .unwrap();
+        let _ = socket.send_to(&out.raw, from).await;
+    }
+}
+
+async fn wait_for<F, Fut>(mut condition: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = bool>,
+{
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !condition().await {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("timed out");
+}
+
+async fn selected_remote(agent: &Agent) -> Option<SocketAddr> {
+    let pair = agent.get_selected_candidate_pair().await?;
+    Some(pair.remote.addr().await)
+}
+
+async fn succeeded_pairs(agent: &Agent) -> usize {
+    let checklist = agent.internal.agent_conn.checklist.lock().await;
+    checklist
+        .iter()
+        .filter(|p| p.state.load(Ordering::SeqCst) == CandidatePairState::Succeeded as u8)
+        .count()
+}
+
+// A controlled agent starts checking a peer that checks it from two sockets,
+// the test returns the agent and that peer once both pairs succeeded
+async fn controlled_agent_with_two_pairs() -> (Arc<Agent>, ControllingPeer) {
+    let agent = Arc::new(
+        Agent::new(AgentConfig {
+            network_types: vec![NetworkType::Udp4],
+            ..Default::default()
+        })
+        .await
+        .unwrap(),
+    );
+    let (candidate_tx, mut candidate_rx) = mpsc::unbounded_channel();
+    agent
+        .on_candidate(Box::new(move |candidate| {
+            if let Some(candidate) = candidate {
+                let _ = candidate_tx.send(candidate);
+            }
+            Box::pin(async {})
+        }))
+        .await;
+    agent.gather_candidates().await.unwrap();
+    let agent_addr = candidate_rx.recv().await.unwrap().addr().await;
+
+    let accepting_agent = Arc::clone(&agent);
+    tokio::spawn(async move {
+        let (_cancel_tx, cancel_rx) = mpsc::channel(1);
+        let _ = accepting_agent
+            .accept(cancel_rx, PEER_UFRAG.to_owned(), PEER_PWD.to_owned())
+            .await;
+    });
+
+    let peer = ControllingPeer::new(&agent, agent_addr, 2).await;
+    wait_for(|| async {
+        for pair in 0..2 {
+            peer.check(pair, false, None).await;
+        }
+        succeeded_pairs(&agent).await == 2
+    })
+    .await;
+
+    (agent, peer)
+}
+
+#[tokio::test]
+async fn test_controlled_follows_renomination() {
+    let (agent, peer) = controlled_agent_with_two_pairs().await;
+
+    peer.check(0, true, Some(1)).await;
+    wait_for(|| async { selected_remote(&agent).await == Some(peer.addr(0)) }).await;
+
+    // The controlling peer switches to the other pair
+    peer.check(1, true, Some(2)).await;
+    wait_for(|| async { selected_remote(&agent).await == Some(peer.addr(1)) }).await;
+
+    // A late nomination with a lower value, or one without a value, does not
+    // switch back
+    peer.check(0, true, Some(1)).await;
+    peer.check(0, true, None).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(selected_remote(&agent).await, Some(peer.addr(1)));
+
+    agent.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_controlled_keeps_first_nomination_without_renomination() {
+    let (agent, peer) = controlled_agent_with_two_pairs().await;
+
+    peer.check(0, true, None).await;
+    wait_for(|| async { selected_remote(&agent).await == Some(peer.addr(0)) }).await;
+
+    peer.check(1, true, None).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(selected_remote(&agent).await, Some(peer.addr(0)));
+
+    agent.close().await.unwrap();
+}
@@ -0,0 +1,593 @@
+use super::agent_config::AgentConfig;
+use super::Agent;
+use crate::webrtc::ice::candidate::candidate_base::unmarshal_candidate;
+use crate::webrtc::ice::candidate::{peer_reflexive_priority, Candidate, CandidateType};
+use crate::webrtc::ice::error::Result;
+use crate::webrtc::ice::network_type::NetworkType;
+use crate::webrtc::ice::state::ConnectionState;
+use crate::webrtc::ice::url::Url;
+use crate::webrtc::stun::message::{Message, BINDING_REQUEST, BINDING_SUCCESS};
+use crate::webrtc::stun::xoraddr::XorMappedAddress;
+use crate::webrtc::util::ifaces::monitor::{InterfaceEvent, InterfaceMonitor};
+use crate::webrtc::util::vnet::nat::{EndpointDependencyType, NatType};
+use crate::webrtc::util::vnet::net::{Net, NetConfig};
+use crate::webrtc::util::vnet::router::{Nic, Router, RouterConfig};
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant};
+
+const STUN_SERVER_IP: Ipv4Addr = Ipv4Addr::new(1, 2, 3, 4);
+const STUN_SERVER_PORT: u16 = 3478;
+const NAT_MAPPED_IP: Ipv4Addr = Ipv4Addr::new(1, 2, 3, 100);
+const WAN_PEER_IP: Ipv4Addr = Ipv4Addr::new(1, 2, 3, 5);
+
+// A LAN behind an endpoint independent NAT on a WAN that runs a STUN server
+// and hosts a peer
+struct VirtualInternet {
+    wan: Arc<Mutex<Router>>,
+    lan_net: Arc<Net>,
+    wan_net: Arc<Net>,
+}
+
+impl VirtualInternet {
+    async fn new() -> Self {
+        let wan = Arc::new(Mutex::new(
+            Router::new(RouterConfig {
+                cidr: "1.2.3.0/24".to_owned(),
+                ..Default::default()
+            })
+            .unwrap(),
+        ));
+        let lan = Arc::new(Mutex::new(
+            Router::new(RouterConfig {
+                cidr: "192.168.0.0/24".to_owned(),
+                static_ips: vec![NAT_MAPPED_IP.to_string()],
+                nat_type: Some(NatType {
+                    mapping_behavior: EndpointDependencyType::EndpointIndependent,
+                    filtering_behavior: EndpointDependencyType::EndpointIndependent,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .unwrap(),
+        ));
+        wan.lock().await.add_router(Arc::clone(&lan)).await.unwrap();
+        lan.lock().await.set_router(Arc::clone(&wan)).await.unwrap();
+
+        let stun_net = Net::new(Some(NetConfig {
+            static_ips: vec![STUN_SERVER_IP.to_string()],
+            ..Default::default()
+        }));
+        connect(&wan, &stun_net).await;
+        start_stun_server(&stun_net).await;
+
+        let lan_net = Net::new(Some(NetConfig::default()));
+        connect(&lan, &lan_net).await;
+
+        let wan_net = Net::new(Some(NetConfig {
+            static_ips: vec![WAN_PEER_IP.to_string()],
+            ..Default::default()
+        }));
+        connect(&wan, &wan_net).await;
+
+        wan.lock().await.start().await.unwrap();
+
+        VirtualInternet {
+            wan,
+            lan_net: Arc::new(lan_net),
+            wan_net: Arc::new(wan_net),
+        }
+    }
+
+    async fn stop(&self) {
+        self.wan.lock().await.stop().await.unwrap();
+    }
+}
+
+async fn connect(router: &Arc<Mutex<Router>>, net: &Net) {
+    let nic = net.get_nic().unwrap();
+    router.lock().await.add_net(Arc::clone(&nic)).await.unwrap();
+    nic.lock()
+        .await
+        .set_router(Arc::clone(router))
+        .await
+        .unwrap();
+}
+
+// Answers Binding requests with the source address they came from
+async fn start_stun_server(net: &Net) {
+    let conn = net
+        .bind(SocketAddr::new(STUN_SERVER_IP.into(), STUN_SERVER_PORT))
+        .await
+        .unwrap();
+    tokio::spawn(async move {
+        let mut buf = vec![0u8; 1500];
+        while let Ok((n, from)) = conn.recv_from(&mut buf).await {
+            let mut request = Message::new();
+            request.raw = buf[..n].to_vec();
+            if request.decode().is_err() || request.typ != BINDING_REQUEST {
+                continue;
+            }
+
+            let mut response = Message::new();
+            response
+                .build(&[
+                    Box::new(request),
+                    Box::new(BINDING_SUCCESS),
+                    Box::new(XorMappedAddress {
+                        ip: from.ip(),
+                        port: from.port(),
+                    }),
+                ])
+                .unwrap();
+            let _ = conn.send_to(&response.raw, from).await;
+        }
+    });
+}
+
+// Gathers the candidates of the agent until gathering completes
+async fn gather(agent: &Agent) -> Vec<Arc<dyn Candidate + Send + Sync>> {
+    let (candidate_tx, mut candidate_rx) = mpsc::unbounded_channel();
+    agent
+        .on_candidate(Box::new(move |candidate| {
+            let _ = candidate_tx.send(candidate);
+            Box::pin(async {})
+        }))
+        .await;
+    agent.gather_candidates().await.unwrap();
+
+    let mut candidates = vec![];
+    while let Some(Some(candidate)) = candidate_rx.recv().await {
+        candidates.push(candidate);
+    }
+    candidates
+}
+
+// Host candidates only, on the given network
+fn host_config(net: &Arc<Net>) -> AgentConfig {
+    AgentConfig {
+        network_types: vec![NetworkType::Udp4],
+        candidate_types: vec![CandidateType::Host],
+        net: Some(Arc::clone(net)),
+        ..Default::default()
+    }
+}
+
+async fn host_agent(net: &Arc<Net>) -> Agent {
+    Agent::new(host_config(net)).await.unwrap()
+}
+
+// Starts the checks of both agents, the dialing one controlling, and returns
+// the tasks that finish once each agent selected a pair
+async fn start_checks(
+    dialing: &Arc<Agent>,
+    accepting: &Arc<Agent>,
+) -> (JoinHandle<Result<()>>, JoinHandle<Result<()>>) {
+    let (dialing_ufrag, dialing_pwd) = dialing.get_local_user_credentials().await;
+    let (accepting_ufrag, accepting_pwd) = accepting.get_local_user_credentials().await;
+
+    let agent = Arc::clone(dialing);
+    let dialed = tokio::spawn(async move {
+        let (_cancel_tx, cancel_rx) = mpsc::channel(1);
+        agent
+            .dial(cancel_rx, accepting_ufrag, accepting_pwd)
+            .await
+            .map(|_| ())
+    });
+    let agent = Arc::clone(accepting);
+    let accepted = tokio::spawn(async move {
+        let (_cancel_tx, cancel_rx) = mpsc::channel(1);
+        agent
+            .accept(cancel_rx, dialing_ufrag, dialing_pwd)
+            .await
+            .map(|_| ())
+    });
+    (dialed, accepted)
+}
+
+// A host candidate on the WAN that nothing answers on
+async fn unreachable_candidate(port: u16) -> Arc<dyn Candidate + Send + Sync> {
+    Arc::new(
+        unmarshal_candidate(&format!("1 1 udp 2130706431 1.2.3.200 {} typ host", port))
+            .await
+            .unwrap(),
+    )
+}
+
+// Hands the candidates of one agent to the other, as signaling would
+async fn signal_candidates(from: &[Arc<dyn Candidate + Send + Sync>], to: &Agent) {
+    for candidate in from {
+        let candidate: Arc<dyn Candidate + Send + Sync> =
+            Arc::new(unmarshal_candidate(&candidate.marshal()).await.unwrap());
+        to.add_remote_candidate(&candidate).await.unwrap();
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_gather_srflx_behind_nat() {
+    let internet = VirtualInternet::new().await;
+
+    let agent = Agent::new(AgentConfig {
+        urls: vec![
+            Url::parse_url(&format!("stun:{}:{}", STUN_SERVER_IP, STUN_SERVER_PORT)).unwrap(),
+        ],
+        network_types: vec![NetworkType::Udp4],
+        candidate_types: vec![CandidateType::Host, CandidateType::ServerReflexive],
+        net: Some(Arc::clone(&internet.lan_net)),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    let candidates = gather(&agent).await;
+
+    let host = candidates
+        .iter()
+        .find(|c| c.candidate_type() == CandidateType::Host)
+        .expect("no host candidate");
+    assert!(host.address().starts_with("192.168.0."), "{}", host);
+
+    let srflx: Vec<_> = candidates
+        .iter()
+        .filter(|c| c.candidate_type() == CandidateType::ServerReflexive)
+        .collect();
+    assert_eq!(srflx.len(), 1);
+    // The NAT maps the socket the binding was sent from to its public address,
+    // the related address names that socket, bound on the host address
+    assert_eq!(srflx[0].address(), NAT_MAPPED_IP.to_string());
+    assert_ne!(srflx[0].port(), 0);
+    let related_address = srflx[0].related_address().expect("no related address");
+    assert_eq!(related_address.address, host.address());
+    assert_ne!(related_address.port, 0);
+
+    agent.close().await.unwrap();
+    internet.stop().await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_peer_reflexive_from_unsignaled_address() {
+    let internet = VirtualInternet::new().await;
+
+    // The agent behind the NAT signals its private host address only, its
+    // checks reach the peer from the NAT's public address
+    let lan_agent = Arc::new(host_agent(&internet.lan_net).await);
+    let wan_agent = Arc::new(host_agent(&internet.wan_net).await);
+    let lan_candidates = gather(&lan_agent).await;
+    let wan_candidates = gather(&wan_agent).await;
+    signal_candidates(&lan_candidates, &wan_agent).await;
+    signal_candidates(&wan_candidates, &lan_agent).await;
+
+    let (dialed, accepted) = start_checks(&lan_agent, &wan_agent).await;
+    dialed.await.unwrap().unwrap();
+    accepted.await.unwrap().unwrap();
+
+    // The peer learned a remote prflx candidate carrying the PRIORITY of the check
+    // https://www.rfc-editor.org/rfc/rfc8445#section-7.3.1.3
+    let prflx = {
+        let remote_candidates = wan_agent.internal.remote_candidates.lock().await;
+        remote_candidates
+            .values()
+            .flatten()
+            .find(|c| c.candidate_type() == CandidateType::PeerReflexive)
+            .cloned()
+            .expect("no remote prflx candidate")
+    };
+    assert_eq!(prflx.address(), NAT_MAPPED_IP.to_string());
+    assert_eq!(prflx.priority(), peer_reflexive_priority(1));
+    let selected = wan_agent.get_selected_candidate_pair().await.unwrap();
+    assert!(selected.remote.equal(&*prflx));
+
+    // The agent behind the NAT learned its public address from XOR-MAPPED-ADDRESS
+    // https://www.rfc-editor.org/rfc/rfc8445#section-7.2.5.3.1
+    let host = &lan_candidates[0];
+    let local_prflx = {
+        let checklist = lan_agent.internal.agent_conn.checklist.lock().await;
+        checklist
+            .iter()
+            .map(|p| Arc::clone(&p.local))
+            .find(|c| c.candidate_type() == CandidateType::PeerReflexive)
+            .expect("no local prflx candidate")
+    };
+    assert_eq!(local_prflx.address(), NAT_MAPPED_IP.to_string());
+    assert_eq!(local_prflx.port(), prflx.port());
+    let related_address = local_prflx.related_address().unwrap();
+    assert_eq!(related_address.address, host.address());
+    assert_eq!(related_address.port, host.port());
+
+    lan_agent.close().await.unwrap();
+    wan_agent.close().await.unwrap();
+    internet.stop().await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_mdns_candidate_dropped_without_mdns() {
+    let internet = VirtualInternet::new().await;
+
+    // The virtual network runs no mDNS, the name can not be resolved
+    let agent = host_agent(&internet.lan_net).await;
+    assert!(agent.mdns_conn.is_none());
+    let candidate: Arc<dyn Candidate + Send + Sync> = Arc::new(
+        unmarshal_candidate(
+            "1 1 udp 2130706431 7a3c0d5e-7d6b-4e0a-9a43-0a1f5d0c7a11.local 5000 typ host",
+        )
+        .await
+        .unwrap(),
+    );
+    agent.add_remote_candidate(&candidate).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(agent
+        .internal
+        .remote_candidates
+        .lock()
+        .await
+        .values()
+        .all(|candidates| candidates.is_empty()));
+
+    agent.close().await.unwrap();
+    internet.stop().await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_late_candidates_connect() {
+    let internet = VirtualInternet::new().await;
+
+    let config = |net| AgentConfig {
+        disconnected_timeout: Some(Duration::from_secs(1)),
+        failed_timeout: Some(Duration::from_secs(2)),
+        ..host_config(net)
+    };
+    let lan_agent = Arc::new(Agent::new(config(&internet.lan_net)).await.unwrap());
+    let wan_agent = Arc::new(Agent::new(config(&internet.wan_net)).await.unwrap());
+    let lan_candidates = gather(&lan_agent).await;
+    let wan_candidates = gather(&wan_agent).await;
+
+    // The checks start with the offer. A first trickled pair restarts the
+    // disconnected and failed timeouts, the candidates that connect arrive
+    // after they ran out counted from the offer
+    let (dialed, accepted) = start_checks(&lan_agent, &wan_agent).await;
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    for agent in [&lan_agent, &wan_agent] {
+        agent
+            .add_remote_candidate(&unreachable_candidate(5000).await)
+            .await
+            .unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(2000)).await;
+    signal_candidates(&lan_candidates, &wan_agent).await;
+    signal_candidates(&wan_candidates, &lan_agent).await;
+
+    tokio::time::timeout(Duration::from_secs(2), async {
+        dialed.await.unwrap().unwrap();
+        accepted.await.unwrap().unwrap();
+    })
+    .await
+    .expect("late candidates did not connect");
+    assert!(lan_agent.get_selected_candidate_pair().await.is_some());
+
+    lan_agent.close().await.unwrap();
+    wan_agent.close().await.unwrap();
+    internet.stop().await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_trickled_pairs_do_not_hold_off_failed() {
+    let internet = VirtualInternet::new().await;
+
+    let (disconnected_timeout, failed_timeout) = (Duration::from_secs(1), Duration::from_secs(2));
+    let agent = Arc::new(
+        Agent::new(AgentConfig {
+            disconnected_timeout: Some(disconnected_timeout),
+            failed_timeout: Some(failed_timeout),
+            ..host_config(&internet.wan_net)
+        })
+        .await
+        .unwrap(),
+    );
+    let (failed_tx, mut failed_rx) = mpsc::unbounded_channel();
+    agent
+        .on_connection_state_change(Box::new(move |state| {
+            if state == ConnectionState::Failed {
+                let _ = failed_tx.send(Instant::now());
+            }
+            Box::pin(async {})
+        }))
+        .await;
+    let peer = Arc::new(host_agent(&internet.lan_net).await);
+    gather(&agent).await;
+
+    // A peer that keeps trickling candidates nothing answers on
+    let started = Instant::now();
+    let (_dialed, _accepted) = start_checks(&peer, &agent).await;
+    let trickling = Arc::clone(&agent);
+    let trickle = tokio::spawn(async move {
+        for port in 5000..5040 {
+            trickling
+                .add_remote_candidate(&unreachable_candidate(port).await)
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    });
+
+    // The wait restarts with each pair until failed_timeout after checking began
+    let failed_at = tokio::time::timeout(Duration::from_secs(15), failed_rx.recv())
+        .await
+        .expect("trickled pairs held off failed")
+        .unwrap();
+    let deadline = failed_timeout + disconnected_timeout + failed_timeout;
+    assert!(
+        failed_at.duration_since(started) <= deadline + Duration::from_secs(1),
+        "{:?}",
+        failed_at.duration_since(started)
+    );
+
+    trickle.abort();
+    agent.close().await.unwrap();
+    peer.close().await.unwrap();
+    internet.stop().await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_interface_changes_update_host_candidates() {
+    let internet = VirtualInternet::new().await;
+
+    let lan_agent = Arc::new(host_agent(&internet.lan_net).await);
+    let wan_agent = Arc::new(host_agent(&internet.wan_net).await);
+    let lan_candidates = gather(&lan_agent).await;
+    let wan_candidates = gather(&wan_agent).await;
+    signal_candidates(&lan_candidates, &wan_agent).await;
+    signal_candidates(&wan_candidates, &lan_agent).await;
+    let (dialed, accepted) = start_checks(&lan_agent, &wan_agent).await;
+    dialed.await.unwrap().unwrap();
+    accepted.await.unwrap().unwrap();
+
+    // The virtual network has no monitor of its own, the test reports the changes
+    let monitor = InterfaceMonitor::manual();
+    wan_agent.watch_interfaces(monitor.subscribe().await.unwrap());
+    let (change_tx, mut change_rx) = mpsc::unbounded_channel();
+    wan_agent
+        .on_network_change(Box::new(move |event| {
+            let _ = change_tx.send(event);
+            Box::pin(async {})
+        }))
+        .await;
+    let (candidate_tx, mut candidate_rx) = mpsc::unbounded_channel();
+    wan_agent
+        .on_candidate(Box::new(move |candidate| {
+            let _ = candidate_tx.send(candidate);
+            Box::pin(async {})
+        }))
+        .await;
+
+    let host = &wan_candidates[0];
+    let ip: IpAddr = host.address().parse().unwrap();
+    assert_eq!(ip, IpAddr::from(WAN_PEER_IP));
+
+    // The address goes away: its host candidate, the pairs on it and the
+    // selected pair go with it
+    let removed = InterfaceEvent::AddrRemoved {
+        name: "eth0".to_owned(),
+        addr: ip,
+    };
+    monitor.emit(removed.clone()).await;
+    assert_eq!(change_rx.recv().await, Some(removed));
+    assert!(wan_agent.get_local_candidates().await.unwrap().is_empty());
+    assert!(wan_agent
+        .internal
+        .agent_conn
+        .checklist
+        .lock()
+        .await
+        .is_empty());
+    assert!(wan_agent.get_selected_candidate_pair().await.is_none());
+
+    // The address comes back: a new host candidate is gathered on it and paired
+    let added = InterfaceEvent::AddrAdded {
+        name: "eth0".to_owned(),
+        addr: ip,
+    };
+    monitor.emit(added.clone()).await;
+    assert_eq!(change_rx.recv().await, Some(added));
+    let candidate = candidate_rx.recv().await.unwrap().unwrap();
+    assert_eq!(candidate.candidate_type(), CandidateType::Host);
+    assert_eq!(candidate.address(), ip.to_string());
+    let local_candidates = wan_agent.get_local_candidates().await.unwrap();
+    assert_eq!(local_candidates.len(), 1);
+    assert!(local_candidates[0].equal(&*candidate));
+    assert!(wan_agent
+        .internal
+        .agent_conn
+        .checklist
+        .lock()
+        .await
+        .iter()
+        .any(|p| p.local.equal(&*candidate)));
+
+    monitor.close().await;
+    lan_agent.close().await.unwrap();
+    wan_agent.close().await.unwrap();
+    internet.stop().await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_consent_expires_when_peer_goes_silent() {
+    let internet = VirtualInternet::new().await;
+
+    let lan_agent = Arc::new(host_agent(&internet.lan_net).await);
+    let wan_agent = Arc::new(host_agent(&internet.wan_net).await);
+    let lan_candidates = gather(&lan_agent).await;
+    let wan_candidates = gather(&wan_agent).await;
+    signal_candidates(&lan_candidates, &wan_agent).await;
+    signal_candidates(&wan_candidates, &lan_agent).await;
+    let (dialed, accepted) = start_checks(&lan_agent, &wan_agent).await;
+    dialed.await.unwrap().unwrap();
+    accepted.await.unwrap().unwrap();
+
+    let (state_tx, mut state_rx) = mpsc::unbounded_channel();
+    lan_agent
+        .on_connection_state_change(Box::new(move |state| {
+            let _ = state_tx.send((state, Instant::now()));
+            Box::pin(async {})
+        }))
+        .await;
+
+    // The peer goes away after selection, a socket on its address records the
+    // consent checks without answering them
+    let selected = lan_agent.get_selected_candidate_pair().await.unwrap();
+    let peer_addr = SocketAddr::new(
+        selected.remote.address().parse().unwrap(),
+        selected.remote.port(),
+    );
+    wan_agent.close().await.unwrap();
+    let silent_at = Instant::now();
+    let silent = internet.wan_net.bind(peer_addr).await.unwrap();
+    let (check_tx, mut check_rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let mut buf = vec![0u8; 1500];
+        while let Ok((n, _)) = silent.recv_from(&mut buf).await {
+            let mut m = Message::new();
+            m.raw = buf[..n].to_vec();
+            if m.decode().is_ok() && m.typ == BINDING_REQUEST {
+                let _ = check_tx.send(Instant::now());
+            }
+        }
+    });
+
+    let failed_at = tokio::time::timeout(Duration::from_secs(60), async {
+        loop {
+            if let Some((ConnectionState::Failed, at)) = state_rx.recv().await {
+                return at;
+            }
+        }
+    })
+    .await
+    .expect("consent did not expire");
+    assert!(lan_agent.consent_expired());
+    // https://www.rfc-editor.org/rfc/rfc7675#section-5.1
+    let expired_after = failed_at.duration_since(silent_at);
+    assert!(
+        expired_after >= Duration::from_secs(24) && expired_after <= Duration::from_secs(31),
+        "{:?}",
+        expired_after
+    );
+
+    // Consent checks went out every 4 to 6 seconds until then
+    let mut checks = vec![];
+    while let Ok(at) = check_rx.try_recv() {
+        checks.push(at);
+    }
+    assert!(checks.len() >= 4, "{} checks", checks.len());
+    for pair in checks.windows(2) {
+        let interval = pair[1].duration_since(pair[0]);
+        assert!(
+            interval >= Duration::from_secs(4) && interval <= Duration::from_secs(6),
+            "{:?}",
+            interval
+        );
+    }
+
+    lan_agent.close().await.unwrap();
+    internet.stop().await;
+}
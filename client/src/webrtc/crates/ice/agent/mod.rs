@@ -1,8 +1,14 @@
 pub(crate) mod agent_config;
 pub(crate) mod agent_gather;
 pub(crate) mod agent_internal;
+#[cfg(test)]
+mod agent_internal_test;
 pub(crate) mod agent_selector;
+#[cfg(test)]
+mod agent_selector_test;
 pub(crate) mod agent_transport;
+#[cfg(test)]
+mod agent_vnet_test;
 
 use crate::webrtc::ice::candidate::*;
 use crate::webrtc::ice::error::*;
@@ -10,12 +16,15 @@ use crate::webrtc::ice::external_ip_mapper::*;
 use crate::webrtc::ice::mdns::*;
 use crate::webrtc::ice::network_type::*;
 use crate::webrtc::ice::state::*;
+use crate::webrtc::ice::url::*;
+use crate::webrtc::mdns::conn::DnsConn;
 use agent_config::*;
 use agent_internal::*;
 
 use crate::webrtc::stun::{
     agent::*, attributes::*, fingerprint::*, integrity::*, message::*, xoraddr::*,
 };
+use crate::webrtc::util::ifaces::monitor::{InterfaceEvent, InterfaceMonitor};
 use crate::webrtc::util::{vnet::net::*, Buffer};
 use std::collections::HashMap;
 use std::net::{Ipv4Addr, SocketAddr};
@@ -36,6 +45,8 @@ pub(crate) struct BindingRequest {
     pub(crate) transaction_id: TransactionId,
     pub(crate) destination: SocketAddr,
     pub(crate) is_use_candidate: bool,
+    // The pair checked, it records the round trip time or the loss
+    pub(crate) pair: Option<Arc<CandidatePair>>,
 }
 
 impl Default for BindingRequest {
@@ -45,6 +56,7 @@ impl Default for BindingRequest {
             transaction_id: TransactionId::default(),
             destination: SocketAddr::new(Ipv4Addr::new(0, 0, 0, 0).into(), 0),
             is_use_candidate: false,
+            pair: None,
         }
     }
 }
@@ -69,6 +81,9 @@ pub(crate) type OnCandidateHdlrFn = Box<
         + Send
         + Sync,
 >;
+pub(crate) type OnNetworkChangeHdlrFn = Box<
+    dyn (FnMut(InterfaceEvent) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>) + Send + Sync,
+>;
 pub(crate) type GatherCandidateCancelFn = Box<dyn Fn() + Send + Sync>;
 
 pub(crate) struct ChanReceivers {
@@ -82,8 +97,14 @@ pub(crate) struct Agent {
     pub(crate) internal: Arc<AgentInternal>,
 
     pub(crate) interface_filter: Arc<Option<InterfaceFilterFn>>,
+    pub(crate) ip_filter: Arc<Option<IpFilterFn>>,
+    pub(crate) port_min: u16,
+    pub(crate) port_max: u16,
+    pub(crate) urls: Vec<Url>,
     pub(crate) mdns_mode: MulticastDnsMode,
     pub(crate) mdns_name: String,
+    pub(crate) mdns_conn: Option<Arc<DnsConn>>,
+    pub(crate) interface_monitor: Option<Arc<InterfaceMonitor>>,
     pub(crate) net: Arc<Net>,
 
     // 1:1 D-NAT IP address mapping
@@ -133,10 +154,17 @@ impl Agent {
             return Err(Error::ErrLiteUsingNonHostCandidates);
         }
 
-        if !config.urls.is_empty() {
+        if !config.urls.is_empty()
+            && !candidate_types.contains(&CandidateType::ServerReflexive)
+            && !candidate_types.contains(&CandidateType::Relay)
+        {
             return Err(Error::ErrUselessUrlsProvided);
         }
 
+        if config.port_max != 0 && config.port_min > config.port_max {
+            return Err(Error::ErrInvalidPortRange);
+        }
+
         let ext_ip_mapper = match config.init_ext_ip_mapping(mdns_mode, &candidate_types) {
             Ok(ext_ip_mapper) => ext_ip_mapper,
             Err(err) => {
@@ -157,14 +185,41 @@ impl Agent {
             Arc::new(Net::new(None))
         };
 
+        let mdns_conn = if net.is_virtual() {
+            None
+        } else {
+            match create_multicast_dns(mdns_mode, &mdns_name) {
+                Ok(mdns_conn) => mdns_conn,
+                Err(err) => {
+                    // Opportunistic mDNS: connections through IPs and STUN still work
+                    log::warn!("Failed to initialize mDNS {}: {}", mdns_name, err);
+                    None
+                }
+            }
+        };
+
+        // The virtual network's interfaces only change through its router
+        let interface_monitor = if net.is_virtual() {
+            None
+        } else {
+            Some(Arc::new(InterfaceMonitor::new()))
+        };
+
+        let gathering_state = Arc::clone(&ai.gathering_state);
         let agent = Self {
             internal: Arc::new(ai),
             interface_filter: Arc::clone(&config.interface_filter),
+            ip_filter: Arc::clone(&config.ip_filter),
+            port_min: config.port_min,
+            port_max: config.port_max,
+            urls: config.urls.clone(),
             mdns_mode,
             mdns_name,
+            mdns_conn,
+            interface_monitor,
             net,
             ext_ip_mapper: Arc::new(ext_ip_mapper),
-            gathering_state: Arc::new(AtomicU8::new(0)), //GatheringState::New,
+            gathering_state,
             candidate_types,
             network_types: config.network_types.clone(),
 
@@ -180,6 +235,12 @@ impl Agent {
             )
             .await;
 
+        if let Some(interface_monitor) = &agent.interface_monitor {
+            if let Some(events_rx) = interface_monitor.subscribe().await {
+                agent.watch_interfaces(events_rx);
+            }
+        }
+
         // Restart is also used to initialize the agent for the first time
         if let Err(err) = agent.restart(config.local_ufrag, config.local_pwd).await {
             let _ = agent.close().await;
@@ -196,7 +257,13 @@ impl Agent {
         *on_connection_state_change_hdlr = Some(f);
     }
 
-    /// Sets a handler that is fired when the final candidate pair is selected.
+    /// Returns the selected candidate pair, if one has been selected.
+    pub(crate) async fn get_selected_candidate_pair(&self) -> Option<Arc<CandidatePair>> {
+        self.internal.agent_conn.get_selected_pair().await
+    }
+
+    /// Sets a handler that is fired when a candidate pair is selected, and again each time
+    /// another pair is nominated in its place.
     pub(crate) async fn on_selected_candidate_pair_change(
         &self,
         f: OnSelectedCandidatePairChangeHdlrFn,
@@ -216,6 +283,13 @@ impl Agent {
         *on_candidate_hdlr = Some(f);
     }
 
+    /// Sets a handler that is fired when an address appears on or disappears from an
+    /// interface of the host, after the agent gathered candidates on it or deleted them.
+    pub(crate) async fn on_network_change(&self, f: OnNetworkChangeHdlrFn) {
+        let mut on_network_change_hdlr = self.internal.on_network_change_hdlr.lock().await;
+        *on_network_change_hdlr = Some(f);
+    }
+
     /// Adds a new remote candidate.
     pub(crate) async fn add_remote_candidate(
         &self,
@@ -234,6 +308,37 @@ impl Agent {
             if c.candidate_type() != CandidateType::Host {
                 return Err(Error::ErrAddressParseFailed);
             }
+
+            if let Some(mdns_conn) = &self.mdns_conn {
+                let (mdns_conn, ai) = (Arc::clone(mdns_conn), Arc::clone(&self.internal));
+                let candidate = Arc::clone(c);
+                tokio::spawn(async move {
+                    match mdns_conn.query(&candidate.address()).await {
+                        Ok(ip) => {
+                            if let Err(err) = candidate.set_ip(&ip).await {
+                                log::warn!(
+                                    "Failed to set the IP of remote mDNS candidate {}: {}",
+                                    candidate.address(),
+                                    err
+                                );
+                                return;
+                            }
+                            ai.add_remote_candidate(&candidate).await;
+                        }
+                        Err(err) => log::warn!(
+                            "Failed to resolve remote mDNS candidate {}: {}",
+                            candidate.address(),
+                            err
+                        ),
+                    }
+                });
+            } else {
+                // mDNS failed to start or the agent runs on a virtual network
+                log::warn!(
+                    "remote mDNS candidate added, but mDNS is unavailable: ({})",
+                    c.address()
+                );
+            }
         } else {
             let ai = Arc::clone(&self.internal);
             let candidate = Arc::clone(c);
@@ -245,6 +350,20 @@ impl Agent {
         Ok(())
     }
 
+    /// Records that the peer signaled end-of-candidates, connectivity checks fail as soon as
+    /// every pair failed instead of waiting for more candidates to trickle in.
+    pub(crate) fn set_remote_candidates_complete(&self) {
+        self.internal
+            .remote_candidates_complete
+            .store(true, Ordering::SeqCst);
+    }
+
+    /// Returns whether the agent failed because the peer stopped answering consent checks on the
+    /// selected pair.
+    pub(crate) fn consent_expired(&self) -> bool {
+        self.internal.consent_expired.load(Ordering::SeqCst)
+    }
+
     /// Returns the local candidates.
     pub(crate) async fn get_local_candidates(
         &self,
@@ -275,6 +394,16 @@ impl Agent {
             gather_candidate_cancel();
         }
 
+        if let Some(mdns_conn) = &self.mdns_conn {
+            if let Err(err) = mdns_conn.close().await {
+                log::warn!("Failed to close mDNS: {}", err);
+            }
+        }
+
+        if let Some(interface_monitor) = &self.interface_monitor {
+            interface_monitor.close().await;
+        }
+
         //FIXME: deadlock here
         self.internal.close().await
     }
@@ -331,6 +460,10 @@ impl Agent {
             let mut checklist = self.internal.agent_conn.checklist.lock().await;
             *checklist = vec![];
         }
+        self.internal
+            .remote_candidates_complete
+            .store(false, Ordering::SeqCst);
+        self.internal.consent_expired.store(false, Ordering::SeqCst);
 
         self.internal.set_selected_pair(None).await;
         self.internal.delete_all_candidates().await;
@@ -347,6 +480,26 @@ impl Agent {
         Ok(())
     }
 
+    /// Sets the credentials the remote agent signaled, e.g. the new ones it picked for an
+    /// ICE restart.
+    pub(crate) async fn set_remote_credentials(
+        &self,
+        remote_ufrag: String,
+        remote_pwd: String,
+    ) -> Result<()> {
+        self.internal
+            .set_remote_credentials(remote_ufrag, remote_pwd)
+            .await
+    }
+
+    /// Sets whether the remote agent signaled ice-options:renomination, only then a
+    /// controlling agent nominates a better pair in place of the selected one.
+    pub(crate) fn set_remote_renomination(&self, renomination: bool) {
+        self.internal
+            .remote_renomination
+            .store(renomination, Ordering::SeqCst);
+    }
+
     /// Initiates the trickle based gathering process.
     pub(crate) async fn gather_candidates(&self) -> Result<()> {
         if self.gathering_state.load(Ordering::SeqCst) != GatheringState::New as u8 {
@@ -368,11 +521,15 @@ impl Agent {
 
         let params = GatherCandidatesInternalParams {
             candidate_types: self.candidate_types.clone(),
+            urls: self.urls.clone(),
             network_types: self.network_types.clone(),
             mdns_mode: self.mdns_mode,
             mdns_name: self.mdns_name.clone(),
             net: Arc::clone(&self.net),
             interface_filter: self.interface_filter.clone(),
+            ip_filter: self.ip_filter.clone(),
+            port_min: self.port_min,
+            port_max: self.port_max,
             ext_ip_mapper: Arc::clone(&self.ext_ip_mapper),
             agent_internal: Arc::clone(&self.internal),
             gathering_state: Arc::clone(&self.gathering_state),
@@ -1,6 +1,10 @@
 use super::*;
 use crate::webrtc::ice::candidate::candidate_host::CandidateHostConfig;
+use crate::webrtc::ice::candidate::candidate_peer_reflexive::CandidatePeerReflexiveConfig;
+use crate::webrtc::ice::candidate::candidate_relay::CandidateRelayConfig;
+use crate::webrtc::ice::candidate::candidate_server_reflexive::CandidateServerReflexiveConfig;
 use crate::webrtc::ice::error::*;
+use crate::webrtc::ice::tcp_type::TcpType;
 use crate::webrtc::ice::util::*;
 
 use async_trait::async_trait;
@@ -21,6 +25,7 @@ pub(crate) struct CandidateBaseConfig {
     pub(crate) component: u16,
     pub(crate) priority: u32,
     pub(crate) foundation: String,
+    pub(crate) tcp_type: TcpType,
     pub(crate) conn: Option<Arc<dyn crate::webrtc::util::Conn + Send + Sync>>,
 }
 
@@ -28,6 +33,7 @@ pub(crate) struct CandidateBase {
     pub(crate) id: String,
     pub(crate) network_type: AtomicU8,
     pub(crate) candidate_type: CandidateType,
+    pub(crate) tcp_type: TcpType,
 
     pub(crate) component: AtomicU16,
     pub(crate) address: String,
@@ -55,6 +61,7 @@ impl Default for CandidateBase {
             id: String::new(),
             network_type: AtomicU8::new(0),
             candidate_type: CandidateType::default(),
+            tcp_type: TcpType::default(),
 
             component: AtomicU16::new(0),
             address: String::new(),
@@ -150,6 +157,11 @@ impl Candidate for CandidateBase {
         NetworkType::from(self.network_type.load(Ordering::SeqCst))
     }
 
+    /// Returns candidate TcpType.
+    fn tcp_type(&self) -> TcpType {
+        self.tcp_type
+    }
+
     /// Returns Candidate Address.
     fn address(&self) -> String {
         self.address.clone()
@@ -208,6 +220,10 @@ impl Candidate for CandidateBase {
             .as_str();
         }
 
+        if self.tcp_type != TcpType::Unspecified {
+            val += format!(" tcptype {}", self.tcp_type).as_str();
+        }
+
         val
     }
 
@@ -263,6 +279,7 @@ impl Candidate for CandidateBase {
             && self.address() == other.address()
             && self.port() == other.port()
             && self.related_address() == other.related_address()
+            && self.tcp_type() == other.tcp_type()
     }
 
     async fn set_ip(&self, ip: &IpAddr) -> Result<()> {
@@ -298,9 +315,35 @@ impl CandidateBase {
         self.last_sent.store(d.as_nanos() as u64, Ordering::SeqCst);
     }
 
-    /// Returns the local preference for this candidate.
+    /// Returns the local preference for this candidate. TCP candidates rank
+    /// below UDP ones, and by the direction they open connections in.
+    ///
+    /// <https://www.rfc-editor.org/rfc/rfc6544#section-4.2>
     pub(crate) fn local_preference(&self) -> u16 {
-        DEFAULT_LOCAL_PREFERENCE
+        if !self.network_type().is_tcp() {
+            return DEFAULT_LOCAL_PREFERENCE;
+        }
+
+        let other_pref: u16 = 8191;
+        let direction_pref: u16 = match self.candidate_type() {
+            CandidateType::Host | CandidateType::Relay => match self.tcp_type() {
+                TcpType::Active => 6,
+                TcpType::Passive => 4,
+                TcpType::SimultaneousOpen => 2,
+                TcpType::Unspecified => 0,
+            },
+            CandidateType::PeerReflexive | CandidateType::ServerReflexive => {
+                match self.tcp_type() {
+                    TcpType::SimultaneousOpen => 6,
+                    TcpType::Active => 4,
+                    TcpType::Passive => 2,
+                    TcpType::Unspecified => 0,
+                }
+            }
+            CandidateType::Unspecified => 0,
+        };
+
+        (1 << 13) * direction_pref + other_pref
     }
 }
 
@@ -335,9 +378,21 @@ pub(crate) async fn unmarshal_candidate(raw: &str) -> Result<impl Candidate> {
 
     let typ = split[7];
 
+    let mut rel_addr = String::new();
+    let mut rel_port = 0;
+    let mut tcp_type = TcpType::Unspecified;
+
     if split.len() > 8 {
         let split2 = &split[8..];
 
+        // https://www.rfc-editor.org/rfc/rfc6544#section-4.5
+        if let Some(i) = split2.iter().position(|s| *s == "tcptype") {
+            match split2.get(i + 1) {
+                Some(typ) => tcp_type = TcpType::from(*typ),
+                None => return Err(Error::ErrParseTcpType),
+            }
+        }
+
         if split2[0] == "raddr" {
             if split2.len() < 4 {
                 return Err(Error::Other(format!(
@@ -345,6 +400,12 @@ pub(crate) async fn unmarshal_candidate(raw: &str) -> Result<impl Candidate> {
                     Error::ErrParseRelatedAddr
                 )));
             }
+
+            // RelatedAddress
+            rel_addr = split2[1].to_owned();
+
+            // RelatedPort
+            rel_port = split2[3].parse()?;
         }
     }
 
@@ -358,11 +419,63 @@ pub(crate) async fn unmarshal_candidate(raw: &str) -> Result<impl Candidate> {
                     component,
                     priority,
                     foundation,
+                    tcp_type,
                     ..CandidateBaseConfig::default()
                 },
             };
             config.new_candidate_host().await
         }
+        "srflx" => {
+            let config = CandidateServerReflexiveConfig {
+                base_config: CandidateBaseConfig {
+                    network,
+                    address,
+                    port,
+                    component,
+                    priority,
+                    foundation,
+                    tcp_type,
+                    ..CandidateBaseConfig::default()
+                },
+                rel_addr,
+                rel_port,
+            };
+            config.new_candidate_server_reflexive().await
+        }
+        "prflx" => {
+            let config = CandidatePeerReflexiveConfig {
+                base_config: CandidateBaseConfig {
+                    network,
+                    address,
+                    port,
+                    component,
+                    priority,
+                    foundation,
+                    tcp_type,
+                    ..CandidateBaseConfig::default()
+                },
+                rel_addr,
+                rel_port,
+            };
+            config.new_candidate_peer_reflexive().await
+        }
+        "relay" => {
+            let config = CandidateRelayConfig {
+                base_config: CandidateBaseConfig {
+                    network,
+                    address,
+                    port,
+                    component,
+                    priority,
+                    foundation,
+                    tcp_type,
+                    ..CandidateBaseConfig::default()
+                },
+                rel_addr,
+                rel_port,
+            };
+            config.new_candidate_relay().await
+        }
         _ => Err(Error::Other(format!(
             "{:?} ({})",
             Error::ErrUnknownCandidateType,
@@ -27,6 +27,7 @@ impl CandidateHostConfig {
             foundation_override: self.base_config.foundation,
             priority_override: self.base_config.priority,
             network: self.base_config.network,
+            tcp_type: self.base_config.tcp_type,
             network_type: AtomicU8::new(NetworkType::Udp4 as u8),
             conn: self.base_config.conn,
             ..CandidateBase::default()
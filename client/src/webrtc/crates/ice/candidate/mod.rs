@@ -1,21 +1,28 @@
 pub(crate) mod candidate_base;
 pub(crate) mod candidate_host;
+pub(crate) mod candidate_peer_reflexive;
+pub(crate) mod candidate_relay;
+pub(crate) mod candidate_server_reflexive;
 
 use crate::webrtc::ice::error::Result;
 use crate::webrtc::ice::network_type::*;
+use crate::webrtc::ice::tcp_type::TcpType;
 use candidate_base::*;
 
 use async_trait::async_trait;
 use std::fmt;
 use std::net::{IpAddr, SocketAddr};
-use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU8, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU32, AtomicU64, AtomicU8, Ordering};
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 use tokio::sync::{broadcast, Mutex};
 
 pub(crate) const RECEIVE_MTU: usize = 8192;
 pub(crate) const DEFAULT_LOCAL_PREFERENCE: u16 = 65535;
 
+// Loss of a pair is kept in thousandths
+const LOSS_SCALE: u16 = 1000;
+
 /// Indicates that the candidate is used for RTP.
 pub(crate) const COMPONENT_RTP: u16 = 1;
 
@@ -44,6 +51,10 @@ pub(crate) trait Candidate: fmt::Display {
     fn last_sent(&self) -> SystemTime;
 
     fn network_type(&self) -> NetworkType;
+
+    /// How a TCP candidate opens its connections.
+    fn tcp_type(&self) -> TcpType;
+
     fn address(&self) -> String;
     fn port(&self) -> u16;
 
@@ -74,6 +85,9 @@ pub(crate) trait Candidate: fmt::Display {
 pub(crate) enum CandidateType {
     Unspecified,
     Host,
+    ServerReflexive,
+    PeerReflexive,
+    Relay,
 }
 
 // String makes CandidateType printable
@@ -81,6 +95,9 @@ impl fmt::Display for CandidateType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = match *self {
             CandidateType::Host => "host",
+            CandidateType::ServerReflexive => "srflx",
+            CandidateType::PeerReflexive => "prflx",
+            CandidateType::Relay => "relay",
             CandidateType::Unspecified => "Unknown candidate type",
         };
         write!(f, "{}", s)
@@ -104,11 +121,24 @@ impl CandidateType {
     pub(crate) const fn preference(self) -> u16 {
         match self {
             Self::Host => 126,
-            CandidateType::Unspecified => 0,
+            Self::ServerReflexive => 100,
+            Self::PeerReflexive => 110,
+            CandidateType::Relay | CandidateType::Unspecified => 0,
         }
     }
 }
 
+/// Returns the priority of a peer reflexive candidate for `component`. Connectivity checks carry it
+/// in the PRIORITY attribute so a peer reflexive candidate learned from the check gets it.
+///
+/// <https://www.rfc-editor.org/rfc/rfc8445#section-7.1.1>
+#[must_use]
+pub(crate) fn peer_reflexive_priority(component: u16) -> u32 {
+    (1 << 24) * u32::from(CandidateType::PeerReflexive.preference())
+        + (1 << 8) * u32::from(DEFAULT_LOCAL_PREFERENCE)
+        + (256 - u32::from(component))
+}
+
 /// Convey transport addresses related to the candidate, useful for diagnostics and other purposes.
 #[derive(PartialEq, Debug, Clone)]
 pub(crate) struct CandidateRelatedAddress {
@@ -182,6 +212,13 @@ pub(crate) struct CandidatePair {
     pub(crate) binding_request_count: AtomicU16,
     pub(crate) state: AtomicU8, // convert it to CandidatePairState,
     pub(crate) nominated: AtomicBool,
+    // Smoothed round trip time of the checks on this pair in microseconds,
+    // 0 until the first response
+    pub(crate) round_trip_time: AtomicU64,
+    // Smoothed share of the checks on this pair that got no response, in
+    // thousandths
+    pub(crate) loss: AtomicU16,
+    pub(crate) responses_received: AtomicU32,
 }
 
 impl Default for CandidatePair {
@@ -193,6 +230,9 @@ impl Default for CandidatePair {
             state: AtomicU8::new(CandidatePairState::Waiting as u8),
             binding_request_count: AtomicU16::new(0),
             nominated: AtomicBool::new(false),
+            round_trip_time: AtomicU64::new(0),
+            loss: AtomicU16::new(0),
+            responses_received: AtomicU32::new(0),
         }
     }
 }
@@ -245,6 +285,9 @@ impl CandidatePair {
             state: AtomicU8::new(CandidatePairState::Waiting as u8),
             binding_request_count: AtomicU16::new(0),
             nominated: AtomicBool::new(false),
+            round_trip_time: AtomicU64::new(0),
+            loss: AtomicU16::new(0),
+            responses_received: AtomicU32::new(0),
         }
     }
 
@@ -267,6 +310,57 @@ impl CandidatePair {
             + if g > d { 1 } else { 0 }
     }
 
+    /// Records a check on this pair that got a response after rtt. Both the round trip time and
+    /// the loss are smoothed with a gain of 1/8, as TCP does for its round trip time.
+    /// <https://www.rfc-editor.org/rfc/rfc6298#section-2>
+    pub(crate) fn record_response(&self, rtt: Duration) {
+        let rtt = u64::try_from(rtt.as_micros()).unwrap_or(u64::MAX).max(1);
+        let _ = self
+            .round_trip_time
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |srtt| {
+                Some(if srtt == 0 {
+                    rtt
+                } else {
+                    srtt - srtt / 8 + rtt / 8
+                })
+            });
+        let _ = self
+            .loss
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |loss| {
+                Some(loss - loss / 8)
+            });
+        self.responses_received.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Records a check on this pair that timed out without a response.
+    pub(crate) fn record_timeout(&self) {
+        let _ = self
+            .loss
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |loss| {
+                Some(loss - loss / 8 + LOSS_SCALE / 8)
+            });
+    }
+
+    /// Returns the smoothed round trip time of the checks on this pair, if any got a response.
+    pub(crate) fn round_trip_time(&self) -> Option<Duration> {
+        match self.round_trip_time.load(Ordering::SeqCst) {
+            0 => None,
+            rtt => Some(Duration::from_micros(rtt)),
+        }
+    }
+
+    /// Returns the smoothed share of the checks on this pair that got no response, from 0 to 1.
+    pub(crate) fn loss(&self) -> f64 {
+        f64::from(self.loss.load(Ordering::SeqCst)) / f64::from(LOSS_SCALE)
+    }
+
+    /// Returns the round trip time penalized by loss, lower is better. Every lost check costs
+    /// at least one retransmission, so a pair with 25% loss ranks as twice its round trip time.
+    pub(crate) fn score(&self) -> Option<Duration> {
+        self.round_trip_time()
+            .map(|rtt| rtt.mul_f64(1.0 + 4.0 * self.loss()))
+    }
+
     pub(crate) async fn write(&self, b: &[u8]) -> Result<usize> {
         self.local.write_to(b, &*self.remote).await
     }
@@ -68,6 +68,14 @@ pub(crate) enum Error {
     #[error("invalid 1:1 NAT IP mapping")]
     ErrInvalidNat1to1IpMapping,
 
+    /// Indicates the minimum of the port range is above its maximum.
+    #[error("port range minimum is above its maximum")]
+    ErrInvalidPortRange,
+
+    /// Indicates every port of the port range is in use.
+    #[error("no free port in the port range")]
+    ErrPortRangeExhausted,
+
     /// IPNotFound in NAT1To1IPMapping.
     #[error("external mapped IP not found")]
     ErrExternalMappedIpNotFound,
@@ -82,6 +90,11 @@ pub(crate) enum Error {
     #[error("1:1 NAT IP mapping for host candidate ineffective")]
     ErrIneffectiveNat1to1IpMappingHost,
 
+    /// Indicates that 1:1 NAT IP mapping for srflx candidate is requested, but the srflx candidate
+    /// type is disabled.
+    #[error("1:1 NAT IP mapping for srflx candidate ineffective")]
+    ErrIneffectiveNat1to1IpMappingSrflx,
+
     /// Indicates an invalid MulticastDNSHostName.
     #[error("invalid mDNS HostName, must end with .local and can only contain a single '.'")]
     ErrInvalidMulticastDnshostName,
@@ -90,10 +103,40 @@ pub(crate) enum Error {
     #[error("ICE Agent can not be restarted when gathering")]
     ErrRestartWhenGathering,
 
+    /// Indicates the url is not a valid STUN or TURN url.
+    #[error("invalid url")]
+    ErrInvalidUrl,
+
+    /// Indicates the scheme type could not be parsed.
+    #[error("unknown scheme type")]
+    ErrSchemeType,
+
+    /// Indicates query arguments are provided in a STUN URL.
+    #[error("queries not supported in stun address")]
+    ErrStunQuery,
+
+    /// Indicates an malformed query is provided.
+    #[error("invalid query")]
+    ErrInvalidQuery,
+
+    /// Indicates malformed hostname is provided.
+    #[error("invalid hostname")]
+    ErrHost,
+
+    /// Indicates an unsupported transport type was provided.
+    #[error("invalid transport protocol type")]
+    ErrProtoType,
+
+    /// Indicates the STUN server did not answer a Binding request in time.
+    #[error("no response to STUN binding request")]
+    ErrStunTimeout,
+
     #[error("attribute not long enough to be ICE candidate")]
     ErrAttributeTooShortIceCandidate,
     #[error("could not parse related addresses")]
     ErrParseRelatedAddr,
+    #[error("could not parse tcptype")]
+    ErrParseTcpType,
     #[error("unknown candidate type")]
     ErrUnknownCandidateType,
     #[error("unable to determine networkType")]
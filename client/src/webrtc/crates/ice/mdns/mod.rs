@@ -1,3 +1,6 @@
+use crate::webrtc::mdns::conn::DnsConn;
+
+use std::sync::Arc;
 use uuid::Uuid;
 
 /// Represents the different Multicast modes that ICE can run.
@@ -27,3 +30,18 @@ pub(crate) fn generate_multicast_dns_name() -> String {
     let u = Uuid::new_v4();
     format!("{}.local", u)
 }
+
+/// Starts the mDNS host for `mdns_mode`. It resolves remote `.local` candidates and, when gathering
+/// with mDNS, answers queries for `mdns_name`.
+pub(crate) fn create_multicast_dns(
+    mdns_mode: MulticastDnsMode,
+    mdns_name: &str,
+) -> Result<Option<Arc<DnsConn>>, crate::webrtc::mdns::Error> {
+    let local_names = match mdns_mode {
+        MulticastDnsMode::QueryOnly => vec![],
+        MulticastDnsMode::QueryAndGather => vec![mdns_name.to_owned()],
+        MulticastDnsMode::Disabled | MulticastDnsMode::Unspecified => return Ok(None),
+    };
+
+    Ok(Some(Arc::new(DnsConn::server(local_names)?)))
+}
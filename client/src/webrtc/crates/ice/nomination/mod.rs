@@ -0,0 +1,29 @@
+use crate::webrtc::stun::attributes::ATTR_NOMINATION;
+use crate::webrtc::stun::checks::*;
+use crate::webrtc::stun::message::*;
+
+pub(crate) const NOMINATION_SIZE: usize = 4; // 24 bit value in 32 bit
+
+/// Represents NOMINATION attribute, sent with USE-CANDIDATE by a controlling agent
+/// that renominates. The controlled agent selects the pair of the highest value.
+/// <https://datatracker.ietf.org/doc/html/draft-thatcher-ice-renomination-01#section-3>
+#[derive(Default, PartialEq, Debug, Copy, Clone)]
+pub(crate) struct NominationAttr(pub(crate) u32);
+
+impl Setter for NominationAttr {
+    /// Adds NOMINATION to message.
+    fn add_to(&self, m: &mut Message) -> Result<(), crate::webrtc::stun::Error> {
+        m.add(ATTR_NOMINATION, &(self.0 & 0x00FF_FFFF).to_be_bytes());
+        Ok(())
+    }
+}
+
+impl Getter for NominationAttr {
+    /// Decodes NOMINATION from message.
+    fn get_from(&mut self, m: &Message) -> Result<(), crate::webrtc::stun::Error> {
+        let v = m.get(ATTR_NOMINATION)?;
+        check_size(ATTR_NOMINATION, v.len(), NOMINATION_SIZE)?;
+        self.0 = u32::from_be_bytes([0, v[1], v[2], v[3]]);
+        Ok(())
+    }
+}
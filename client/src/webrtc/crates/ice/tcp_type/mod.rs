@@ -0,0 +1,43 @@
+use std::fmt;
+
+/// Represents the role of a TCP candidate in opening its connections.
+///
+/// <https://www.rfc-editor.org/rfc/rfc6544#section-4.5>
+#[derive(Default, PartialEq, Debug, Copy, Clone, Eq)]
+pub(crate) enum TcpType {
+    /// Indicates a UDP candidate, or a TCP candidate without a tcptype.
+    #[default]
+    Unspecified,
+
+    /// Indicates a candidate that opens outgoing connections but never accepts one.
+    Active,
+
+    /// Indicates a candidate that accepts incoming connections but never opens one.
+    Passive,
+
+    /// Indicates a candidate that attempts a simultaneous open with its peer.
+    SimultaneousOpen,
+}
+
+impl From<&str> for TcpType {
+    fn from(raw: &str) -> Self {
+        match raw {
+            "active" => Self::Active,
+            "passive" => Self::Passive,
+            "so" => Self::SimultaneousOpen,
+            _ => Self::Unspecified,
+        }
+    }
+}
+
+impl fmt::Display for TcpType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match *self {
+            Self::Active => "active",
+            Self::Passive => "passive",
+            Self::SimultaneousOpen => "so",
+            Self::Unspecified => "unspecified",
+        };
+        write!(f, "{}", s)
+    }
+}
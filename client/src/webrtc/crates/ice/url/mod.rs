@@ -1,3 +1,6 @@
+use crate::webrtc::ice::error::*;
+
+use std::borrow::Cow;
 use std::convert::From;
 use std::fmt;
 
@@ -103,6 +106,9 @@ pub(crate) struct Url {
     pub(crate) host: String,
     pub(crate) port: u16,
     pub(crate) proto: ProtoType,
+    /// Long-term credentials for a TURN server
+    pub(crate) username: String,
+    pub(crate) password: String,
 }
 
 impl fmt::Display for Url {
@@ -123,3 +129,108 @@ impl fmt::Display for Url {
         }
     }
 }
+
+impl Url {
+    /// Parses a STUN or TURN url following the ABNF syntax described in
+    /// [IETF rfc-7064](https://tools.ietf.org/html/rfc7064) and
+    /// [IETF rfc-7065](https://tools.ietf.org/html/rfc7065) respectively.
+    pub(crate) fn parse_url(raw: &str) -> Result<Self> {
+        // work around for url crate
+        if raw.contains("//") {
+            return Err(Error::ErrInvalidUrl);
+        }
+
+        let mut s = raw.to_string();
+        let pos = raw.find(':');
+        if let Some(p) = pos {
+            s.replace_range(p..=p, "://");
+        } else {
+            return Err(Error::ErrSchemeType);
+        }
+
+        let raw_parts = url::Url::parse(&s)?;
+
+        let scheme = raw_parts.scheme().into();
+
+        let host = if let Some(host) = raw_parts.host_str() {
+            host.trim()
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_owned()
+        } else {
+            return Err(Error::ErrHost);
+        };
+
+        let port = if let Some(port) = raw_parts.port() {
+            port
+        } else if scheme == SchemeType::Stun || scheme == SchemeType::Turn {
+            3478
+        } else {
+            5349
+        };
+
+        let mut q_args = raw_parts.query_pairs();
+        let proto = match scheme {
+            SchemeType::Stun => {
+                if q_args.count() > 0 {
+                    return Err(Error::ErrStunQuery);
+                }
+                ProtoType::Udp
+            }
+            SchemeType::Stuns => {
+                if q_args.count() > 0 {
+                    return Err(Error::ErrStunQuery);
+                }
+                ProtoType::Tcp
+            }
+            SchemeType::Turn => {
+                if q_args.count() > 1 {
+                    return Err(Error::ErrInvalidQuery);
+                }
+                if let Some((key, value)) = q_args.next() {
+                    if key == Cow::Borrowed("transport") {
+                        let proto: ProtoType = value.as_ref().into();
+                        if proto == ProtoType::Unknown {
+                            return Err(Error::ErrProtoType);
+                        }
+                        proto
+                    } else {
+                        return Err(Error::ErrInvalidQuery);
+                    }
+                } else {
+                    ProtoType::Udp
+                }
+            }
+            SchemeType::Turns => {
+                if q_args.count() > 1 {
+                    return Err(Error::ErrInvalidQuery);
+                }
+                if let Some((key, value)) = q_args.next() {
+                    if key == Cow::Borrowed("transport") {
+                        let proto: ProtoType = value.as_ref().into();
+                        if proto == ProtoType::Unknown {
+                            return Err(Error::ErrProtoType);
+                        }
+                        proto
+                    } else {
+                        return Err(Error::ErrInvalidQuery);
+                    }
+                } else {
+                    ProtoType::Tcp
+                }
+            }
+            SchemeType::Unknown => {
+                return Err(Error::ErrSchemeType);
+            }
+        };
+
+        Ok(Self {
+            scheme,
+            host,
+            port,
+            proto,
+            username: String::new(),
+            password: String::new(),
+        })
+    }
+}
@@ -1,12 +1,18 @@
-use crate::webrtc::ice::agent::agent_config::InterfaceFilterFn;
+use crate::webrtc::ice::agent::agent_config::{InterfaceFilterFn, IpFilterFn};
 use crate::webrtc::ice::error::*;
 use crate::webrtc::ice::network_type::*;
 
-use crate::webrtc::stun::{attributes::*, integrity::*, message::*, textattrs::*};
-use crate::webrtc::util::{vnet::net::*, Conn};
+use crate::webrtc::stun::{
+    agent::*, attributes::*, client::round_trip, credentials::*, error_code::*, integrity::*,
+    message::*, textattrs::*, xoraddr::*,
+};
+use crate::webrtc::util::{self, vnet::net::*, Conn};
+use rand::Rng;
 use std::collections::HashSet;
+use std::io;
 use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use tokio::time::{Duration, Instant};
 
 pub(crate) fn create_addr(_network: NetworkType, ip: IpAddr, port: u16) -> SocketAddr {
     /*if network.is_tcp(){
@@ -41,11 +47,51 @@ pub(crate) fn assert_inbound_message_integrity(m: &mut Message, key: &[u8]) -> R
 pub(crate) async fn local_interfaces(
     vnet: &Arc<Net>,
     interface_filter: &Option<InterfaceFilterFn>,
+    ip_filter: &Option<IpFilterFn>,
     network_types: &[NetworkType],
 ) -> HashSet<IpAddr> {
     let mut ips = HashSet::new();
     let interfaces = vnet.get_interfaces().await;
 
+    for iface in interfaces {
+        for ipnet in iface.addrs() {
+            let ipaddr = ipnet.addr();
+            if is_usable_interface_addr(
+                iface.name(),
+                ipaddr,
+                interface_filter,
+                ip_filter,
+                network_types,
+            ) {
+                ips.insert(ipaddr);
+            }
+        }
+    }
+
+    ips
+}
+
+/// Returns whether candidates are gathered on ipaddr of the interface name: the filters
+/// accept the interface and the address, the address is not a loopback and its family is
+/// requested.
+pub(crate) fn is_usable_interface_addr(
+    name: &str,
+    ipaddr: IpAddr,
+    interface_filter: &Option<InterfaceFilterFn>,
+    ip_filter: &Option<IpFilterFn>,
+    network_types: &[NetworkType],
+) -> bool {
+    if let Some(filter) = interface_filter {
+        if !filter(name) {
+            return false;
+        }
+    }
+    if let Some(filter) = ip_filter {
+        if !filter(ipaddr) {
+            return false;
+        }
+    }
+
     let (mut ipv4requested, mut ipv6requested) = (false, false);
     for typ in network_types {
         if typ.is_ipv4() {
@@ -56,29 +102,129 @@ pub(crate) async fn local_interfaces(
         }
     }
 
-    for iface in interfaces {
-        if let Some(filter) = interface_filter {
-            if !filter(iface.name()) {
-                continue;
+    !ipaddr.is_loopback()
+        && ((ipv4requested && ipaddr.is_ipv4()) || (ipv6requested && ipaddr.is_ipv6()))
+}
+
+/// Binds a UDP socket on the IP of laddr to a port between port_min and port_max, skipping the
+/// ports in use. The search starts at a random port, so agents on one host spread over the
+/// range. A bound of 0 leaves that end of the range open, with both bounds 0 or a port in laddr
+/// the socket is bound to laddr as is.
+pub(crate) async fn listen_udp_in_port_range(
+    vnet: &Arc<Net>,
+    port_max: u16,
+    port_min: u16,
+    laddr: SocketAddr,
+) -> Result<Arc<dyn Conn + Send + Sync>> {
+    if laddr.port() != 0 || (port_min == 0 && port_max == 0) {
+        return Ok(vnet.bind(laddr).await?);
+    }
+
+    let i = if port_min == 0 { 1 } else { port_min };
+    let j = if port_max == 0 { u16::MAX } else { port_max };
+    if i > j {
+        return Err(Error::ErrInvalidPortRange);
+    }
+
+    let port_start = rand::thread_rng().gen_range(i..=j);
+    let mut port_current = port_start;
+    loop {
+        let laddr = SocketAddr::new(laddr.ip(), port_current);
+        match vnet.bind(laddr).await {
+            Ok(conn) => return Ok(conn),
+            Err(err) if is_addr_in_use(&err) => {
+                log::debug!("failed to listen {}: {}", laddr, err);
             }
+            Err(err) => return Err(err.into()),
         }
 
-        for ipnet in iface.addrs() {
-            let ipaddr = ipnet.addr();
-            if !ipaddr.is_loopback()
-                && ((ipv4requested && ipaddr.is_ipv4()) || (ipv6requested && ipaddr.is_ipv6()))
-            {
-                ips.insert(ipaddr);
-            }
+        port_current = if port_current == j {
+            i
+        } else {
+            port_current + 1
+        };
+        if port_current == port_start {
+            return Err(Error::ErrPortRangeExhausted);
         }
     }
+}
 
-    ips
+// is_addr_in_use returns whether binding failed because another socket holds
+// the port
+fn is_addr_in_use(err: &util::Error) -> bool {
+    match err {
+        util::Error::ErrAddressAlreadyInUse => true,
+        util::Error::Io(err) => err.0.kind() == io::ErrorKind::AddrInUse,
+        _ => false,
+    }
 }
 
-pub(crate) async fn listen_udp_in_port_range(
-    vnet: &Arc<Net>,
-    laddr: SocketAddr,
-) -> Result<Arc<dyn Conn + Send + Sync>> {
-    return Ok(vnet.bind(laddr).await?);
+/// Sends a STUN Binding request to `server_addr` and returns the mapped address
+/// from the response, retransmitting with backoff until `deadline` passes. With
+/// `credentials`, a server asking for long-term credentials gets them.
+pub(crate) async fn get_xormapped_addr(
+    conn: &Arc<dyn Conn + Send + Sync>,
+    server_addr: SocketAddr,
+    credentials: Option<&mut LongTermCredentials>,
+    deadline: Duration,
+) -> Result<XorMappedAddress> {
+    let resp = stun_request(conn, server_addr, credentials, deadline).await?;
+    let mut addr = XorMappedAddress::default();
+    addr.get_from(&resp)?;
+    Ok(addr)
+}
+
+// Challenges answered per request: the first 401 and a stale nonce
+const MAX_STUN_CHALLENGES: usize = 2;
+
+async fn stun_request(
+    conn: &Arc<dyn Conn + Send + Sync>,
+    server_addr: SocketAddr,
+    mut credentials: Option<&mut LongTermCredentials>,
+    deadline: Duration,
+) -> Result<Message> {
+    let deadline = Instant::now() + deadline;
+    let mut challenges = 0;
+    loop {
+        let authenticated = credentials.as_ref().is_some_and(|c| c.is_challenged());
+        let request = {
+            let mut setters: Vec<Box<dyn Setter>> =
+                vec![Box::new(BINDING_REQUEST), Box::new(TransactionId::new())];
+            if let Some(credentials) = credentials.as_ref().filter(|_| authenticated) {
+                setters.extend(credentials.setters());
+            }
+            let mut request = Message::new();
+            request.build(&setters)?;
+            request
+        };
+
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let mut resp = match round_trip(conn, server_addr, &request, timeout).await? {
+            Some((resp, _)) => resp,
+            None => return Err(Error::ErrStunTimeout),
+        };
+        if resp.typ == BINDING_SUCCESS {
+            if let Some(credentials) = credentials.as_ref().filter(|_| authenticated) {
+                credentials.check(&mut resp)?;
+            }
+            return Ok(resp);
+        }
+
+        // https://www.rfc-editor.org/rfc/rfc8489#section-9.2.5
+        if let Some(credentials) = credentials.as_deref_mut() {
+            if challenges < MAX_STUN_CHALLENGES {
+                if let Some(challenge) = Challenge::from_error_response(&resp)? {
+                    if credentials.accept_challenge(challenge, authenticated)? {
+                        challenges += 1;
+                        continue;
+                    }
+                }
+            }
+        }
+
+        return Err(Error::Other(format!(
+            "unexpected STUN response {}",
+            resp.typ
+        )));
+    }
 }
@@ -0,0 +1,197 @@
+use super::error::{Error, Result};
+use super::message::*;
+
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::Duration;
+
+// https://www.rfc-editor.org/rfc/rfc6762#section-3
+const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+
+// https://www.rfc-editor.org/rfc/rfc6762#section-17
+const MAX_MESSAGE_SIZE: usize = 9000;
+
+const QUERY_INTERVAL: Duration = Duration::from_secs(1);
+const QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Query {
+    name: String,
+    answer_tx: mpsc::Sender<IpAddr>,
+}
+
+/// DnsConn is an mDNS host (RFC 6762) on the IPv4 multicast group. It
+/// resolves `.local` names by querying the group, and answers queries for
+/// its own names with the address of the interface the query came from.
+pub(crate) struct DnsConn {
+    socket: Arc<UdpSocket>,
+    dst_addr: SocketAddr,
+    queries: Arc<Mutex<Vec<Query>>>,
+    close_tx: Mutex<Option<mpsc::Sender<()>>>,
+}
+
+impl DnsConn {
+    /// Joins the mDNS group and answers queries for local_names until closed.
+    pub(crate) fn server(local_names: Vec<String>) -> Result<Self> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        // Other responders on the host share the port
+        socket.set_reuse_address(true)?;
+        #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+        socket.set_reuse_port(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&SockAddr::from(SocketAddr::new(
+            Ipv4Addr::UNSPECIFIED.into(),
+            MDNS_PORT,
+        )))?;
+        socket.join_multicast_v4(&MDNS_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+        socket.set_multicast_loop_v4(true)?;
+
+        let socket = Arc::new(UdpSocket::from_std(socket.into())?);
+        let dst_addr = SocketAddr::new(MDNS_GROUP.into(), MDNS_PORT);
+        let queries = Arc::new(Mutex::new(vec![]));
+        let (close_tx, close_rx) = mpsc::channel(1);
+
+        let (socket2, queries2) = (Arc::clone(&socket), Arc::clone(&queries));
+        tokio::spawn(async move {
+            DnsConn::read_loop(socket2, dst_addr, queries2, local_names, close_rx).await;
+        });
+
+        Ok(DnsConn {
+            socket,
+            dst_addr,
+            queries,
+            close_tx: Mutex::new(Some(close_tx)),
+        })
+    }
+
+    /// Resolves name, repeating the query every second until it is answered
+    /// or times out.
+    pub(crate) async fn query(&self, name: &str) -> Result<IpAddr> {
+        if self.close_tx.lock().await.is_none() {
+            return Err(Error::ErrConnectionClosed);
+        }
+
+        let raw = Message::query(name).marshal()?;
+        let (answer_tx, mut answer_rx) = mpsc::channel(1);
+        {
+            let mut queries = self.queries.lock().await;
+            queries.push(Query {
+                name: name.to_owned(),
+                answer_tx,
+            });
+        }
+
+        let mut ticker = tokio::time::interval(QUERY_INTERVAL);
+        let timeout = tokio::time::sleep(QUERY_TIMEOUT);
+        tokio::pin!(timeout);
+
+        let result = loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    if let Err(err) = self.socket.send_to(&raw, self.dst_addr).await {
+                        log::warn!("mdns: failed to send query for {}: {}", name, err);
+                    }
+                }
+                addr = answer_rx.recv() => break addr.ok_or(Error::ErrConnectionClosed),
+                _ = &mut timeout => break Err(Error::ErrQueryTimeout),
+            }
+        };
+
+        drop(answer_rx);
+        let mut queries = self.queries.lock().await;
+        queries.retain(|q| !q.answer_tx.is_closed());
+
+        result
+    }
+
+    pub(crate) async fn close(&self) -> Result<()> {
+        {
+            let mut close_tx = self.close_tx.lock().await;
+            if close_tx.take().is_none() {
+                return Err(Error::ErrConnectionClosed);
+            }
+        }
+
+        // Pending queries fail once their channels are dropped
+        let mut queries = self.queries.lock().await;
+        queries.clear();
+
+        Ok(())
+    }
+
+    async fn read_loop(
+        socket: Arc<UdpSocket>,
+        dst_addr: SocketAddr,
+        queries: Arc<Mutex<Vec<Query>>>,
+        local_names: Vec<String>,
+        mut close_rx: mpsc::Receiver<()>,
+    ) {
+        let mut buf = vec![0_u8; MAX_MESSAGE_SIZE];
+        loop {
+            let (n, src) = tokio::select! {
+                result = socket.recv_from(&mut buf) => match result {
+                    Ok(result) => result,
+                    Err(err) => {
+                        log::warn!("mdns: failed to read: {}", err);
+                        break;
+                    }
+                },
+                _ = close_rx.recv() => break,
+            };
+
+            let msg = match Message::unmarshal(&buf[..n]) {
+                Ok(msg) => msg,
+                Err(err) => {
+                    log::trace!("mdns: discard message from {}: {}", src, err);
+                    continue;
+                }
+            };
+
+            if msg.response {
+                let queries = queries.lock().await;
+                for a in &msg.answers {
+                    for q in queries.iter().filter(|q| names_equal(&q.name, &a.name)) {
+                        let _ = q.answer_tx.try_send(a.addr);
+                    }
+                }
+                continue;
+            }
+
+            for q in &msg.questions {
+                if !q.wants_ipv4() || !local_names.iter().any(|n| names_equal(n, &q.name)) {
+                    continue;
+                }
+
+                let addr = match DnsConn::interface_for_remote(src).await {
+                    Ok(addr) => addr,
+                    Err(err) => {
+                        log::warn!("mdns: failed to find interface for {}: {}", src, err);
+                        continue;
+                    }
+                };
+
+                let raw = match Message::answer(&q.name, addr).marshal() {
+                    Ok(raw) => raw,
+                    Err(err) => {
+                        log::warn!("mdns: failed to build answer for {}: {}", q.name, err);
+                        continue;
+                    }
+                };
+                if let Err(err) = socket.send_to(&raw, dst_addr).await {
+                    log::warn!("mdns: failed to send answer for {}: {}", q.name, err);
+                }
+            }
+        }
+    }
+
+    // interface_for_remote returns the local address the host routes to
+    // remote from
+    async fn interface_for_remote(remote: SocketAddr) -> Result<IpAddr> {
+        let socket = UdpSocket::bind(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0)).await?;
+        socket.connect(remote).await?;
+        Ok(socket.local_addr()?.ip())
+    }
+}
@@ -0,0 +1,20 @@
+use super::conn::*;
+use super::error::Error;
+
+#[tokio::test]
+async fn test_dns_conn_query_and_answer() {
+    let name = "a5f0d1c2-mdns-test.local";
+    let responder = DnsConn::server(vec![name.to_owned()]).unwrap();
+    let querier = DnsConn::server(vec![]).unwrap();
+
+    // Answered with the address of the interface the query came from
+    let addr = querier.query(name).await.unwrap();
+    assert!(addr.is_ipv4(), "{}", addr);
+
+    querier.close().await.unwrap();
+    assert!(matches!(
+        querier.query(name).await,
+        Err(Error::ErrConnectionClosed)
+    ));
+    responder.close().await.unwrap();
+}
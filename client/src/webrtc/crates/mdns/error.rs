@@ -0,0 +1,21 @@
+use std::io;
+use thiserror::Error;
+
+pub(crate) type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub(crate) enum Error {
+    #[error("mDNS: connection is closed")]
+    ErrConnectionClosed,
+    #[error("mDNS: query timed out")]
+    ErrQueryTimeout,
+    #[error("mDNS: message is too short")]
+    ErrShortBuffer,
+    #[error("mDNS: invalid label")]
+    ErrInvalidLabel,
+    #[error("mDNS: too many compression pointers")]
+    ErrTooManyPointers,
+    #[error("{0}")]
+    Io(#[from] io::Error),
+}
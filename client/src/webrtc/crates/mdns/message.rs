@@ -0,0 +1,239 @@
+use super::error::{Error, Result};
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+// https://www.rfc-editor.org/rfc/rfc1035#section-4.1.1
+const HEADER_SIZE: usize = 12;
+const FLAG_RESPONSE: u16 = 1 << 15;
+const FLAG_AUTHORITATIVE: u16 = 1 << 10;
+
+// https://www.rfc-editor.org/rfc/rfc1035#section-3.2.2
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const TYPE_ANY: u16 = 255;
+const CLASS_INET: u16 = 1;
+
+// The top bit of the class is the unicast-response bit in questions and the
+// cache-flush bit in answers
+// https://www.rfc-editor.org/rfc/rfc6762#section-10.2
+const CLASS_MASK: u16 = 0x7FFF;
+const CLASS_CACHE_FLUSH: u16 = 1 << 15;
+
+// https://www.rfc-editor.org/rfc/rfc6762#section-10
+const ANSWER_TTL: u32 = 120;
+
+// https://www.rfc-editor.org/rfc/rfc1035#section-4.1.4
+const POINTER_MASK: u8 = 0xC0;
+const MAX_LABEL_LENGTH: usize = 63;
+const MAX_POINTERS: usize = 16;
+
+/// A question for the address records of a name.
+pub(crate) struct Question {
+    pub(crate) name: String,
+    pub(crate) typ: u16,
+}
+
+impl Question {
+    /// Returns whether the question asks for an IPv4 address.
+    pub(crate) fn wants_ipv4(&self) -> bool {
+        self.typ == TYPE_A || self.typ == TYPE_ANY
+    }
+}
+
+/// An A or AAAA record, records of other types are skipped when parsing.
+pub(crate) struct Answer {
+    pub(crate) name: String,
+    pub(crate) addr: IpAddr,
+}
+
+/// The subset of a DNS message mDNS hosts need to resolve each other's
+/// names.
+pub(crate) struct Message {
+    pub(crate) response: bool,
+    pub(crate) questions: Vec<Question>,
+    pub(crate) answers: Vec<Answer>,
+}
+
+impl Message {
+    /// Creates a query for the IPv4 address of name.
+    pub(crate) fn query(name: &str) -> Self {
+        Message {
+            response: false,
+            questions: vec![Question {
+                name: name.to_owned(),
+                typ: TYPE_A,
+            }],
+            answers: vec![],
+        }
+    }
+
+    /// Creates an authoritative answer that name has addr.
+    pub(crate) fn answer(name: &str, addr: IpAddr) -> Self {
+        Message {
+            response: true,
+            questions: vec![],
+            answers: vec![Answer {
+                name: name.to_owned(),
+                addr,
+            }],
+        }
+    }
+
+    pub(crate) fn marshal(&self) -> Result<Vec<u8>> {
+        let flags = if self.response {
+            FLAG_RESPONSE | FLAG_AUTHORITATIVE
+        } else {
+            0
+        };
+
+        // Message ID is zero in multicast messages
+        // https://www.rfc-editor.org/rfc/rfc6762#section-18.1
+        let mut buf = Vec::with_capacity(HEADER_SIZE);
+        buf.extend_from_slice(&0_u16.to_be_bytes());
+        buf.extend_from_slice(&flags.to_be_bytes());
+        buf.extend_from_slice(&(self.questions.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&(self.answers.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&[0; 4]);
+
+        for q in &self.questions {
+            write_name(&mut buf, &q.name)?;
+            buf.extend_from_slice(&q.typ.to_be_bytes());
+            buf.extend_from_slice(&CLASS_INET.to_be_bytes());
+        }
+
+        for a in &self.answers {
+            write_name(&mut buf, &a.name)?;
+            let (typ, data) = match a.addr {
+                IpAddr::V4(ip) => (TYPE_A, ip.octets().to_vec()),
+                IpAddr::V6(ip) => (TYPE_AAAA, ip.octets().to_vec()),
+            };
+            buf.extend_from_slice(&typ.to_be_bytes());
+            buf.extend_from_slice(&(CLASS_INET | CLASS_CACHE_FLUSH).to_be_bytes());
+            buf.extend_from_slice(&ANSWER_TTL.to_be_bytes());
+            buf.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            buf.extend_from_slice(&data);
+        }
+
+        Ok(buf)
+    }
+
+    pub(crate) fn unmarshal(buf: &[u8]) -> Result<Self> {
+        if buf.len() < HEADER_SIZE {
+            return Err(Error::ErrShortBuffer);
+        }
+        let flags = read_u16(buf, 2)?;
+        let question_count = read_u16(buf, 4)?;
+        // Answers may also be sent in the authority and additional sections
+        let record_count = u32::from(read_u16(buf, 6)?)
+            + u32::from(read_u16(buf, 8)?)
+            + u32::from(read_u16(buf, 10)?);
+
+        let mut off = HEADER_SIZE;
+
+        let mut questions = vec![];
+        for _ in 0..question_count {
+            let (name, next) = read_name(buf, off)?;
+            let typ = read_u16(buf, next)?;
+            off = next + 4;
+            questions.push(Question { name, typ });
+        }
+
+        let mut answers = vec![];
+        for _ in 0..record_count {
+            let (name, next) = read_name(buf, off)?;
+            let typ = read_u16(buf, next)?;
+            let class = read_u16(buf, next + 2)? & CLASS_MASK;
+            let data_len = usize::from(read_u16(buf, next + 8)?);
+            let data_off = next + 10;
+            let data = buf
+                .get(data_off..data_off + data_len)
+                .ok_or(Error::ErrShortBuffer)?;
+            off = data_off + data_len;
+
+            if class != CLASS_INET {
+                continue;
+            }
+            let addr = match (typ, data.len()) {
+                (TYPE_A, 4) => IpAddr::V4(Ipv4Addr::new(data[0], data[1], data[2], data[3])),
+                (TYPE_AAAA, 16) => {
+                    let mut octets = [0_u8; 16];
+                    octets.copy_from_slice(data);
+                    IpAddr::V6(Ipv6Addr::from(octets))
+                }
+                _ => continue,
+            };
+            answers.push(Answer { name, addr });
+        }
+
+        Ok(Message {
+            response: flags & FLAG_RESPONSE != 0,
+            questions,
+            answers,
+        })
+    }
+}
+
+/// Returns whether two names are the same, names are case insensitive and
+/// may end with the root label.
+pub(crate) fn names_equal(a: &str, b: &str) -> bool {
+    a.trim_end_matches('.')
+        .eq_ignore_ascii_case(b.trim_end_matches('.'))
+}
+
+fn read_u16(buf: &[u8], off: usize) -> Result<u16> {
+    match buf.get(off..off + 2) {
+        Some(b) => Ok(u16::from_be_bytes([b[0], b[1]])),
+        None => Err(Error::ErrShortBuffer),
+    }
+}
+
+fn write_name(buf: &mut Vec<u8>, name: &str) -> Result<()> {
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > MAX_LABEL_LENGTH {
+            return Err(Error::ErrInvalidLabel);
+        }
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label.as_bytes());
+    }
+    buf.push(0);
+    Ok(())
+}
+
+// read_name returns the name at off, following compression pointers, and the
+// offset right after it
+fn read_name(buf: &[u8], mut off: usize) -> Result<(String, usize)> {
+    let mut labels = vec![];
+    let mut end = None;
+    let mut pointers = 0;
+
+    loop {
+        let len = *buf.get(off).ok_or(Error::ErrShortBuffer)?;
+        if len & POINTER_MASK == POINTER_MASK {
+            let low = *buf.get(off + 1).ok_or(Error::ErrShortBuffer)?;
+            if end.is_none() {
+                end = Some(off + 2);
+            }
+            pointers += 1;
+            if pointers > MAX_POINTERS {
+                return Err(Error::ErrTooManyPointers);
+            }
+            off = usize::from(u16::from_be_bytes([len & !POINTER_MASK, low]));
+            continue;
+        }
+
+        let len = usize::from(len);
+        if len > MAX_LABEL_LENGTH {
+            return Err(Error::ErrInvalidLabel);
+        }
+        off += 1;
+        if len == 0 {
+            break;
+        }
+
+        let label = buf.get(off..off + len).ok_or(Error::ErrShortBuffer)?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        off += len;
+    }
+
+    Ok((labels.join("."), end.unwrap_or(off)))
+}
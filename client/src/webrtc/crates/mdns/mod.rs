@@ -0,0 +1,8 @@
+pub(crate) mod conn;
+mod error;
+mod message;
+
+pub(crate) use error::Error;
+
+#[cfg(test)]
+mod conn_test;
@@ -15,6 +15,7 @@ pub(crate) const ATTR_KEY_GROUP: &str = "group";
 pub(crate) const ATTR_KEY_CONNECTION_SETUP: &str = "setup";
 pub(crate) const ATTR_KEY_MID: &str = "mid";
 pub(crate) const ATTR_KEY_ICELITE: &str = "ice-lite";
+pub(crate) const ATTR_KEY_ICE_OPTIONS: &str = "ice-options";
 
 /// Version describes the value provided by the "v=" field which gives
 /// the version of the Session Description Protocol.
@@ -250,7 +251,12 @@ impl SessionDescription {
             }],
             time_zones: vec![],
             encryption_key: None,
-            attributes: vec![], // TODO: implement trickle ICE
+            // Candidates are sent as they are gathered
+            // https://www.rfc-editor.org/rfc/rfc8840#section-4.1.1
+            attributes: vec![Attribute::new(
+                "ice-options".to_owned(),
+                Some("trickle".to_owned()),
+            )],
             media_descriptions: vec![],
         };
 
@@ -58,6 +58,33 @@ impl Getter for MappedAddress {
     }
 }
 
+/// OtherAddress represents OTHER-ADDRESS attribute, the alternate address
+/// and port of a server supporting NAT behavior discovery.
+///
+/// RFC 5780 Section 7.4
+#[derive(Default)]
+pub(crate) struct OtherAddress(pub(crate) MappedAddress);
+
+impl fmt::Display for OtherAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Setter for OtherAddress {
+    /// add_to adds OTHER-ADDRESS to message.
+    fn add_to(&self, m: &mut Message) -> Result<()> {
+        self.0.add_to_as(m, ATTR_OTHER_ADDRESS)
+    }
+}
+
+impl Getter for OtherAddress {
+    /// get_from decodes OTHER-ADDRESS from message.
+    fn get_from(&mut self, m: &Message) -> Result<()> {
+        self.0.get_from_as(m, ATTR_OTHER_ADDRESS)
+    }
+}
+
 impl MappedAddress {
     /// get_from_as decodes MAPPED-ADDRESS value in message m as an attribute of type t.
     pub(crate) fn get_from_as(&mut self, m: &Message, t: AttrType) -> Result<()> {
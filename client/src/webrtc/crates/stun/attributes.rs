@@ -47,6 +47,7 @@ impl fmt::Display for AttrType {
             ATTR_USE_CANDIDATE => "USE-CANDIDATE",
             ATTR_ICE_CONTROLLED => "ICE-CONTROLLED",
             ATTR_ICE_CONTROLLING => "ICE-CONTROLLING",
+            ATTR_NOMINATION => "NOMINATION",
             ATTR_CHANNEL_NUMBER => "CHANNEL-NUMBER",
             ATTR_LIFETIME => "LIFETIME",
             ATTR_XOR_PEER_ADDRESS => "XOR-PEER-ADDRESS",
@@ -63,6 +64,9 @@ impl fmt::Display for AttrType {
             ATTR_USER_HASH => "USERHASH",
             ATTR_PASSWORD_ALGORITHMS => "PASSWORD-ALGORITHMS",
             ATTR_ALTERNATE_DOMAIN => "ALTERNATE-DOMAIN",
+            ATTR_CHANGE_REQUEST => "CHANGE-REQUEST",
+            ATTR_RESPONSE_ORIGIN => "RESPONSE-ORIGIN",
+            ATTR_OTHER_ADDRESS => "OTHER-ADDRESS",
             _ => other.as_str(),
         };
 
@@ -98,6 +102,9 @@ pub(crate) const ATTR_USE_CANDIDATE: AttrType = AttrType(0x0025); // USE-CANDIDA
 pub(crate) const ATTR_ICE_CONTROLLED: AttrType = AttrType(0x8029); // ICE-CONTROLLED
 pub(crate) const ATTR_ICE_CONTROLLING: AttrType = AttrType(0x802A); // ICE-CONTROLLING
 
+/// Attributes from draft-thatcher-ice-renomination.
+pub(crate) const ATTR_NOMINATION: AttrType = AttrType(0xC001); // NOMINATION
+
 /// Attributes from RFC 5766 TURN.
 pub(crate) const ATTR_CHANNEL_NUMBER: AttrType = AttrType(0x000C); // CHANNEL-NUMBER
 pub(crate) const ATTR_LIFETIME: AttrType = AttrType(0x000D); // LIFETIME
@@ -122,6 +129,11 @@ pub(crate) const ATTR_USER_HASH: AttrType = AttrType(0x001E); // USER-HASH
 pub(crate) const ATTR_PASSWORD_ALGORITHMS: AttrType = AttrType(0x8002); // PASSWORD-ALGORITHMS
 pub(crate) const ATTR_ALTERNATE_DOMAIN: AttrType = AttrType(0x8003); // ALTERNATE-DOMAIN
 
+/// Attributes from RFC 5780 NAT Behavior Discovery.
+pub(crate) const ATTR_CHANGE_REQUEST: AttrType = AttrType(0x0003); // CHANGE-REQUEST
+pub(crate) const ATTR_RESPONSE_ORIGIN: AttrType = AttrType(0x802B); // RESPONSE-ORIGIN
+pub(crate) const ATTR_OTHER_ADDRESS: AttrType = AttrType(0x802C); // OTHER-ADDRESS
+
 /// RawAttribute is a Type-Length-Value (TLV) object that
 /// can be added to a STUN message. Attributes are divided into two
 /// types: comprehension-required and comprehension-optional.  STUN
@@ -0,0 +1,46 @@
+use crate::webrtc::stun::attributes::*;
+use crate::webrtc::stun::checks::*;
+use crate::webrtc::stun::error::*;
+use crate::webrtc::stun::message::*;
+
+const CHANGE_REQUEST_SIZE: usize = 4;
+
+// Flags in the last byte of the CHANGE-REQUEST value
+const CHANGE_IP: u8 = 0x04;
+const CHANGE_PORT: u8 = 0x02;
+
+/// ChangeRequest represents CHANGE-REQUEST attribute. It asks the server to send
+/// the response from its alternate IP address, port or both.
+///
+/// RFC 5780 Section 7.2
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ChangeRequest {
+    pub(crate) change_ip: bool,
+    pub(crate) change_port: bool,
+}
+
+impl Setter for ChangeRequest {
+    /// add_to adds CHANGE-REQUEST to message.
+    fn add_to(&self, m: &mut Message) -> Result<()> {
+        let mut v = [0u8; CHANGE_REQUEST_SIZE];
+        if self.change_ip {
+            v[3] |= CHANGE_IP;
+        }
+        if self.change_port {
+            v[3] |= CHANGE_PORT;
+        }
+        m.add(ATTR_CHANGE_REQUEST, &v);
+        Ok(())
+    }
+}
+
+impl Getter for ChangeRequest {
+    /// get_from decodes CHANGE-REQUEST from message.
+    fn get_from(&mut self, m: &Message) -> Result<()> {
+        let v = m.get(ATTR_CHANGE_REQUEST)?;
+        check_size(ATTR_CHANGE_REQUEST, v.len(), CHANGE_REQUEST_SIZE)?;
+        self.change_ip = v[3] & CHANGE_IP != 0;
+        self.change_port = v[3] & CHANGE_PORT != 0;
+        Ok(())
+    }
+}
@@ -1,10 +1,54 @@
 use crate::webrtc::stun::agent::*;
 use crate::webrtc::stun::error::*;
+use crate::webrtc::stun::message::*;
+use crate::webrtc::util::Conn;
 
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::sync::mpsc;
 use tokio::time::{self, Duration, Instant};
 
+// Largest STUN response read by round_trip
+const MAX_MESSAGE_SIZE: usize = 1280;
+
+// Initial retransmission timeout of a STUN request, RFC 5389 Section 7.2.1
+const STUN_RTO: Duration = Duration::from_millis(500);
+
+/// Sends request to server_addr and waits for a message with the same transaction id,
+/// retransmitting with backoff until timeout passes. Returns the message with the
+/// address it came from, or None if nothing arrived in time.
+pub(crate) async fn round_trip(
+    conn: &Arc<dyn Conn + Send + Sync>,
+    server_addr: SocketAddr,
+    request: &Message,
+    timeout: Duration,
+) -> Result<Option<(Message, SocketAddr)>> {
+    let deadline = Instant::now() + timeout;
+    let mut rto = STUN_RTO;
+    let mut bs = vec![0_u8; MAX_MESSAGE_SIZE];
+    loop {
+        conn.send_to(&request.raw, server_addr).await?;
+
+        let retransmit_at = std::cmp::min(Instant::now() + rto, deadline);
+        rto *= 2;
+        while let Ok(result) = time::timeout_at(retransmit_at, conn.recv_from(&mut bs)).await {
+            let (n, from) = result?;
+
+            let mut resp = Message::new();
+            resp.raw = bs[..n].to_vec();
+            if resp.decode().is_err() || resp.transaction_id != request.transaction_id {
+                // Not the answer to our request
+                continue;
+            }
+            return Ok(Some((resp, from)));
+        }
+
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+    }
+}
+
 /// Collector calls function f with constant rate.
 ///
 /// The simple Collector is ticker which calls function on each tick.
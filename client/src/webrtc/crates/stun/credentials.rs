@@ -0,0 +1,164 @@
+use crate::webrtc::stun::attributes::*;
+use crate::webrtc::stun::error::*;
+use crate::webrtc::stun::error_code::*;
+use crate::webrtc::stun::integrity::*;
+use crate::webrtc::stun::message::*;
+use crate::webrtc::stun::password_algorithm::*;
+use crate::webrtc::stun::textattrs::*;
+
+// Nonces of RFC 8489 servers start with this cookie, followed by 4 base64
+// characters encoding 24 bits of security features
+// https://www.rfc-editor.org/rfc/rfc8489#section-9.2
+const NONCE_COOKIE: &str = "obMatJos2";
+const NONCE_FEATURES_LEN: usize = 4;
+
+// Security feature bit 0, the server sends PASSWORD-ALGORITHMS
+const FEATURE_PASSWORD_ALGORITHMS: u8 = 0x80;
+
+// LongTermCredentials authenticates requests to one server with the long-term
+// credential mechanism. The realm and nonce of the last challenge are cached,
+// so only the first request and one with a stale nonce cost an extra round trip.
+//
+// RFC 8489 Section 9.2
+#[derive(Clone)]
+pub(crate) struct LongTermCredentials {
+    username: String,
+    password: String,
+    realm: String,
+    nonce: String,
+    // Echoed back to RFC 8489 servers, None for RFC 5389 servers
+    password_algorithms: Option<PasswordAlgorithms>,
+    password_algorithm: Option<PasswordAlgorithm>,
+    key: Vec<u8>,
+}
+
+impl LongTermCredentials {
+    pub(crate) fn new(username: String, password: String) -> Self {
+        LongTermCredentials {
+            username,
+            password,
+            realm: String::new(),
+            nonce: String::new(),
+            password_algorithms: None,
+            password_algorithm: None,
+            key: vec![],
+        }
+    }
+
+    // is_challenged returns whether a challenge provided the realm and nonce,
+    // requests carry credentials from then on
+    pub(crate) fn is_challenged(&self) -> bool {
+        !self.nonce.is_empty()
+    }
+
+    // accept_challenge takes the realm, nonce and password algorithm from
+    // challenge and returns whether to send the request again with them. A 401
+    // to a request that carried credentials for the same realm means they are wrong.
+    pub(crate) fn accept_challenge(
+        &mut self,
+        challenge: Challenge,
+        authenticated: bool,
+    ) -> Result<bool> {
+        if challenge.code == CODE_UNAUTHORIZED
+            && authenticated
+            && challenge.realm.as_deref() == Some(self.realm.as_str())
+        {
+            return Ok(false);
+        }
+
+        // A nonce announcing PASSWORD-ALGORITHMS without the attribute, or a
+        // list changing between challenges, is an attempt to bid down to MD5
+        // https://www.rfc-editor.org/rfc/rfc8489#section-9.2.5
+        if nonce_has_password_algorithms(&challenge.nonce)
+            && challenge.password_algorithms.is_none()
+        {
+            return Err(Error::ErrPasswordAlgorithmsMismatch);
+        }
+        if self.is_challenged() && challenge.password_algorithms != self.password_algorithms {
+            return Err(Error::ErrPasswordAlgorithmsMismatch);
+        }
+
+        if let Some(realm) = challenge.realm {
+            self.realm = realm;
+        }
+        if self.realm.is_empty() {
+            return Ok(false);
+        }
+        self.nonce = challenge.nonce;
+
+        // The first algorithm of the server's preference order that we support
+        self.password_algorithm = match &challenge.password_algorithms {
+            Some(algorithms) => Some(
+                algorithms
+                    .0
+                    .iter()
+                    .find(|algorithm| algorithm.is_supported())
+                    .cloned()
+                    .ok_or(Error::ErrNoSupportedPasswordAlgorithm)?,
+            ),
+            None => None,
+        };
+        self.password_algorithms = challenge.password_algorithms;
+
+        let md5 = PasswordAlgorithm {
+            algorithm: PASSWORD_ALGORITHM_MD5,
+            params: vec![],
+        };
+        self.key = long_term_key(
+            &self.username,
+            &self.realm,
+            &self.password,
+            self.password_algorithm.as_ref().unwrap_or(&md5),
+        );
+
+        Ok(true)
+    }
+
+    // setters returns the attributes authenticating a request, the integrity
+    // last. RFC 8489 servers get MESSAGE-INTEGRITY-SHA256, older ones
+    // MESSAGE-INTEGRITY.
+    pub(crate) fn setters(&self) -> Vec<Box<dyn Setter>> {
+        let mut setters: Vec<Box<dyn Setter>> = vec![
+            Box::new(Username::new(ATTR_USERNAME, self.username.clone())),
+            Box::new(TextAttribute::new(ATTR_REALM, self.realm.clone())),
+            Box::new(TextAttribute::new(ATTR_NONCE, self.nonce.clone())),
+        ];
+        match (&self.password_algorithms, &self.password_algorithm) {
+            (Some(algorithms), Some(algorithm)) => {
+                setters.push(Box::new(algorithms.clone()));
+                setters.push(Box::new(algorithm.clone()));
+                setters.push(Box::new(MessageIntegritySha256(self.key.clone())));
+            }
+            _ => setters.push(Box::new(MessageIntegrity(self.key.clone()))),
+        }
+        setters
+    }
+
+    // check verifies the integrity of a success response to an authenticated
+    // request
+    pub(crate) fn check(&self, m: &mut Message) -> Result<()> {
+        if m.contains(ATTR_MESSAGE_INTEGRITY_SHA256) {
+            MessageIntegritySha256(self.key.clone()).check(m)
+        } else {
+            MessageIntegrity(self.key.clone()).check(m)
+        }
+    }
+}
+
+// nonce_has_password_algorithms returns whether the security features in the
+// nonce cookie promise a PASSWORD-ALGORITHMS attribute
+fn nonce_has_password_algorithms(nonce: &str) -> bool {
+    let features = match nonce
+        .strip_prefix(NONCE_COOKIE)
+        .and_then(|rest| rest.get(..NONCE_FEATURES_LEN))
+    {
+        Some(features) => features,
+        None => return false,
+    };
+    match base64::decode(features) {
+        Ok(bits) => bits
+            .first()
+            .is_some_and(|bits| bits & FEATURE_PASSWORD_ALGORITHMS != 0),
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,181 @@
+use super::attributes::*;
+use super::credentials::*;
+use super::error::*;
+use super::error_code::*;
+use super::message::*;
+use super::password_algorithm::*;
+
+const REALM: &str = "example.org";
+
+// A nonce of an RFC 8489 server, its security features announce
+// PASSWORD-ALGORITHMS
+const NONCE_WITH_PASSWORD_ALGORITHMS: &str = "obMatJos2gAAAf//499k954d6OL34oL9FSTvy64sA";
+
+// A nonce of an RFC 8489 server that announces no security features
+const NONCE_WITHOUT_FEATURES: &str = "obMatJos2AAAAf//499k954d6OL34oL9FSTvy64sA";
+
+fn sha256_and_md5() -> PasswordAlgorithms {
+    PasswordAlgorithms(vec![
+        PasswordAlgorithm {
+            algorithm: PASSWORD_ALGORITHM_SHA256,
+            params: vec![],
+        },
+        PasswordAlgorithm {
+            algorithm: PASSWORD_ALGORITHM_MD5,
+            params: vec![],
+        },
+    ])
+}
+
+fn challenge(
+    code: ErrorCode,
+    nonce: &str,
+    password_algorithms: Option<PasswordAlgorithms>,
+) -> Challenge {
+    Challenge {
+        code,
+        realm: Some(REALM.to_owned()),
+        nonce: nonce.to_owned(),
+        password_algorithms,
+    }
+}
+
+fn credentials() -> LongTermCredentials {
+    LongTermCredentials::new("user".to_owned(), "pass".to_owned())
+}
+
+fn authenticated_request(credentials: &LongTermCredentials) -> Message {
+    let mut setters: Vec<Box<dyn Setter>> = vec![Box::new(BINDING_REQUEST)];
+    setters.extend(credentials.setters());
+    let mut m = Message::new();
+    m.build(&setters).unwrap();
+    m
+}
+
+#[test]
+fn test_accept_challenge_uses_sha256() {
+    let mut credentials = credentials();
+    assert!(!credentials.is_challenged());
+    assert_eq!(
+        credentials.accept_challenge(
+            challenge(
+                CODE_UNAUTHORIZED,
+                NONCE_WITH_PASSWORD_ALGORITHMS,
+                Some(sha256_and_md5())
+            ),
+            false,
+        ),
+        Ok(true)
+    );
+    assert!(credentials.is_challenged());
+
+    let m = authenticated_request(&credentials);
+    assert!(m.contains(ATTR_PASSWORD_ALGORITHMS));
+    assert!(m.contains(ATTR_MESSAGE_INTEGRITY_SHA256));
+    assert!(!m.contains(ATTR_MESSAGE_INTEGRITY));
+    let mut algorithm = PasswordAlgorithm::default();
+    algorithm.get_from(&m).unwrap();
+    assert_eq!(algorithm.algorithm, PASSWORD_ALGORITHM_SHA256);
+}
+
+#[test]
+fn test_accept_challenge_rejects_missing_password_algorithms() {
+    // The nonce promises PASSWORD-ALGORITHMS, a challenge without it is an
+    // attempt to bid down to MD5
+    let mut credentials = credentials();
+    assert_eq!(
+        credentials.accept_challenge(
+            challenge(CODE_UNAUTHORIZED, NONCE_WITH_PASSWORD_ALGORITHMS, None),
+            false,
+        ),
+        Err(Error::ErrPasswordAlgorithmsMismatch)
+    );
+    assert!(!credentials.is_challenged());
+
+    // Without the feature bit the server is free to leave it out
+    let mut credentials = self::credentials();
+    assert_eq!(
+        credentials.accept_challenge(
+            challenge(CODE_UNAUTHORIZED, NONCE_WITHOUT_FEATURES, None),
+            false,
+        ),
+        Ok(true)
+    );
+    let m = authenticated_request(&credentials);
+    assert!(m.contains(ATTR_MESSAGE_INTEGRITY));
+    assert!(!m.contains(ATTR_MESSAGE_INTEGRITY_SHA256));
+}
+
+#[test]
+fn test_accept_challenge_rejects_changed_password_algorithms() {
+    let mut credentials = credentials();
+    assert_eq!(
+        credentials.accept_challenge(
+            challenge(
+                CODE_UNAUTHORIZED,
+                NONCE_WITH_PASSWORD_ALGORITHMS,
+                Some(sha256_and_md5())
+            ),
+            false,
+        ),
+        Ok(true)
+    );
+
+    let md5_only = PasswordAlgorithms(vec![PasswordAlgorithm {
+        algorithm: PASSWORD_ALGORITHM_MD5,
+        params: vec![],
+    }]);
+    assert_eq!(
+        credentials.accept_challenge(
+            challenge(
+                CODE_STALE_NONCE,
+                NONCE_WITH_PASSWORD_ALGORITHMS,
+                Some(md5_only)
+            ),
+            true,
+        ),
+        Err(Error::ErrPasswordAlgorithmsMismatch)
+    );
+}
+
+#[test]
+fn test_accept_challenge_unauthorized_after_credentials() {
+    let mut credentials = credentials();
+    assert_eq!(
+        credentials.accept_challenge(
+            challenge(
+                CODE_UNAUTHORIZED,
+                NONCE_WITH_PASSWORD_ALGORITHMS,
+                Some(sha256_and_md5())
+            ),
+            false,
+        ),
+        Ok(true)
+    );
+
+    // A stale nonce only asks for the request again with the new one
+    assert_eq!(
+        credentials.accept_challenge(
+            challenge(
+                CODE_STALE_NONCE,
+                NONCE_WITH_PASSWORD_ALGORITHMS,
+                Some(sha256_and_md5())
+            ),
+            true,
+        ),
+        Ok(true)
+    );
+
+    // A 401 for the realm the credentials were sent for means they are wrong
+    assert_eq!(
+        credentials.accept_challenge(
+            challenge(
+                CODE_UNAUTHORIZED,
+                NONCE_WITH_PASSWORD_ALGORITHMS,
+                Some(sha256_and_md5())
+            ),
+            true,
+        ),
+        Ok(false)
+    );
+}
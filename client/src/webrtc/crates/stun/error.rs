@@ -29,6 +29,10 @@ pub(crate) enum Error {
     ErrBadUnknownAttrsSize,
     #[error("collector is closed")]
     ErrCollectorClosed,
+    #[error("no supported password algorithm")]
+    ErrNoSupportedPasswordAlgorithm,
+    #[error("server offered password algorithms that do not match its nonce")]
+    ErrPasswordAlgorithmsMismatch,
     #[error("{0}")]
     Other(String),
     #[error("url parse: {0}")]
@@ -2,6 +2,8 @@ use crate::webrtc::stun::attributes::*;
 use crate::webrtc::stun::checks::*;
 use crate::webrtc::stun::error::*;
 use crate::webrtc::stun::message::*;
+use crate::webrtc::stun::password_algorithm::*;
+use crate::webrtc::stun::textattrs::*;
 
 use std::collections::HashMap;
 use std::fmt;
@@ -156,3 +158,52 @@ lazy_static! {
         ].iter().cloned().collect();
 
 }
+
+// Challenge is what a server sends with a 401 Unauthorized or 438 Stale Nonce
+// error response to ask for long-term credentials: the realm, a fresh nonce and,
+// for RFC 8489 servers, the password algorithms it accepts.
+//
+// RFC 8489 Section 9.2.5
+#[derive(Default)]
+pub(crate) struct Challenge {
+    pub(crate) code: ErrorCode,
+    pub(crate) realm: Option<String>,
+    pub(crate) nonce: String,
+    pub(crate) password_algorithms: Option<PasswordAlgorithms>,
+}
+
+impl Challenge {
+    // from_error_response returns the challenge in m, None if m is not a 401 or
+    // 438 error response.
+    pub(crate) fn from_error_response(m: &Message) -> Result<Option<Self>> {
+        if m.typ.class != CLASS_ERROR_RESPONSE {
+            return Ok(None);
+        }
+        let mut code = ErrorCodeAttribute::default();
+        code.get_from(m)?;
+        if code.code != CODE_UNAUTHORIZED && code.code != CODE_STALE_NONCE {
+            return Ok(None);
+        }
+
+        // A 438 may leave out the realm, the one of the last challenge stays valid
+        let realm = match TextAttribute::get_from_as(m, ATTR_REALM) {
+            Ok(realm) => Some(realm.text),
+            Err(Error::ErrAttributeNotFound) if code.code == CODE_STALE_NONCE => None,
+            Err(err) => return Err(err),
+        };
+        let nonce = TextAttribute::get_from_as(m, ATTR_NONCE)?.text;
+        let mut password_algorithms = PasswordAlgorithms::default();
+        let password_algorithms = match password_algorithms.get_from(m) {
+            Ok(()) => Some(password_algorithms),
+            Err(Error::ErrAttributeNotFound) => None,
+            Err(err) => return Err(err),
+        };
+
+        Ok(Some(Challenge {
+            code: code.code,
+            realm,
+            nonce,
+            password_algorithms,
+        }))
+    }
+}
@@ -2,8 +2,11 @@ use crate::webrtc::stun::attributes::*;
 use crate::webrtc::stun::checks::*;
 use crate::webrtc::stun::error::*;
 use crate::webrtc::stun::message::*;
+use crate::webrtc::stun::password_algorithm::*;
 
+use md5::Md5;
 use ring::hmac;
+use sha2::{Digest, Sha256};
 use std::fmt;
 
 // MessageIntegrity represents MESSAGE-INTEGRITY attribute.
@@ -15,11 +18,99 @@ use std::fmt;
 #[derive(Default, Clone)]
 pub(crate) struct MessageIntegrity(pub(crate) Vec<u8>);
 
-fn new_hmac(key: &[u8], message: &[u8]) -> Vec<u8> {
-    let mac = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, key);
+fn new_hmac(algorithm: hmac::Algorithm, key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mac = hmac::Key::new(algorithm, key);
     hmac::sign(&mac, message).as_ref().to_vec()
 }
 
+// long_term_key returns the key for long-term credentials, hashed with the
+// password algorithm. Each parameter must be SASL-prepared.
+// https://www.rfc-editor.org/rfc/rfc8489#section-9.2.2
+pub(crate) fn long_term_key(
+    username: &str,
+    realm: &str,
+    password: &str,
+    algorithm: &PasswordAlgorithm,
+) -> Vec<u8> {
+    let s = [username, realm, password].join(":");
+    if algorithm.algorithm == PASSWORD_ALGORITHM_SHA256 {
+        Sha256::digest(s.as_bytes()).to_vec()
+    } else {
+        Md5::digest(s.as_bytes()).to_vec()
+    }
+}
+
+// add_hmac adds the attribute t with the HMAC over the message up to and
+// including the attribute preceding it.
+fn add_hmac(
+    m: &mut Message,
+    t: AttrType,
+    size: usize,
+    algorithm: hmac::Algorithm,
+    key: &[u8],
+) -> Result<()> {
+    for a in &m.attributes.0 {
+        // Message should not contain FINGERPRINT attribute
+        // before MESSAGE-INTEGRITY.
+        if a.typ == ATTR_FINGERPRINT {
+            return Err(Error::ErrFingerprintBeforeIntegrity);
+        }
+    }
+    // The text used as input to HMAC is the STUN message,
+    // including the header, up to and including the attribute preceding the
+    // MESSAGE-INTEGRITY attribute.
+    let length = m.length;
+    // Adjusting m.Length to contain MESSAGE-INTEGRITY TLV.
+    m.length += (size + ATTRIBUTE_HEADER_SIZE) as u32;
+    m.write_length(); // writing length to m.Raw
+    let mut v = new_hmac(algorithm, key, &m.raw); // calculating HMAC for adjusted m.Raw
+    v.truncate(size);
+    m.length = length; // changing m.Length back
+
+    m.add(t, &v);
+
+    Ok(())
+}
+
+// check_hmac_attr checks the HMAC in attribute t, which may be truncated to
+// its first bytes.
+fn check_hmac_attr(
+    m: &mut Message,
+    t: AttrType,
+    algorithm: hmac::Algorithm,
+    key: &[u8],
+) -> Result<()> {
+    let v = m.get(t)?;
+
+    // Adjusting length in header to match m.Raw that was
+    // used when computing HMAC.
+
+    let length = m.length as usize;
+    let mut after_integrity = false;
+    let mut size_reduced = 0;
+
+    for a in &m.attributes.0 {
+        if after_integrity {
+            size_reduced += nearest_padded_value_length(a.length as usize);
+            size_reduced += ATTRIBUTE_HEADER_SIZE;
+        }
+        if a.typ == t {
+            after_integrity = true;
+        }
+    }
+    m.length -= size_reduced as u32;
+    m.write_length();
+    // start_of_hmac should be first byte of integrity attribute.
+    let start_of_hmac = MESSAGE_HEADER_SIZE + m.length as usize
+        - (ATTRIBUTE_HEADER_SIZE + nearest_padded_value_length(v.len()));
+    let b = &m.raw[..start_of_hmac]; // data before integrity attribute
+    let mut expected = new_hmac(algorithm, key, b);
+    expected.truncate(v.len());
+    m.length = length as u32;
+    m.write_length(); // writing length back
+    check_hmac(&v, &expected)
+}
+
 impl fmt::Display for MessageIntegrity {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "KEY: 0x{:x?}", self.0)
@@ -31,26 +122,13 @@ impl Setter for MessageIntegrity {
     //
     // CPU costly, see BenchmarkMessageIntegrity_AddTo.
     fn add_to(&self, m: &mut Message) -> Result<()> {
-        for a in &m.attributes.0 {
-            // Message should not contain FINGERPRINT attribute
-            // before MESSAGE-INTEGRITY.
-            if a.typ == ATTR_FINGERPRINT {
-                return Err(Error::ErrFingerprintBeforeIntegrity);
-            }
-        }
-        // The text used as input to HMAC is the STUN message,
-        // including the header, up to and including the attribute preceding the
-        // MESSAGE-INTEGRITY attribute.
-        let length = m.length;
-        // Adjusting m.Length to contain MESSAGE-INTEGRITY TLV.
-        m.length += (MESSAGE_INTEGRITY_SIZE + ATTRIBUTE_HEADER_SIZE) as u32;
-        m.write_length(); // writing length to m.Raw
-        let v = new_hmac(&self.0, &m.raw); // calculating HMAC for adjusted m.Raw
-        m.length = length; // changing m.Length back
-
-        m.add(ATTR_MESSAGE_INTEGRITY, &v);
-
-        Ok(())
+        add_hmac(
+            m,
+            ATTR_MESSAGE_INTEGRITY,
+            MESSAGE_INTEGRITY_SIZE,
+            hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY,
+            &self.0,
+        )
     }
 }
 
@@ -67,33 +145,61 @@ impl MessageIntegrity {
     //
     // CPU costly, see BenchmarkMessageIntegrity_Check.
     pub(crate) fn check(&self, m: &mut Message) -> Result<()> {
-        let v = m.get(ATTR_MESSAGE_INTEGRITY)?;
-
-        // Adjusting length in header to match m.Raw that was
-        // used when computing HMAC.
-
-        let length = m.length as usize;
-        let mut after_integrity = false;
-        let mut size_reduced = 0;
-
-        for a in &m.attributes.0 {
-            if after_integrity {
-                size_reduced += nearest_padded_value_length(a.length as usize);
-                size_reduced += ATTRIBUTE_HEADER_SIZE;
-            }
-            if a.typ == ATTR_MESSAGE_INTEGRITY {
-                after_integrity = true;
-            }
+        let size = m.get(ATTR_MESSAGE_INTEGRITY)?.len();
+        check_size(ATTR_MESSAGE_INTEGRITY, size, MESSAGE_INTEGRITY_SIZE)?;
+        check_hmac_attr(
+            m,
+            ATTR_MESSAGE_INTEGRITY,
+            hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY,
+            &self.0,
+        )
+    }
+}
+
+// MessageIntegritySha256 represents MESSAGE-INTEGRITY-SHA256 attribute, the
+// HMAC-SHA256 of the message keyed like MESSAGE-INTEGRITY.
+//
+// RFC 8489 Section 14.6
+#[derive(Default, Clone)]
+pub(crate) struct MessageIntegritySha256(pub(crate) Vec<u8>);
+
+impl fmt::Display for MessageIntegritySha256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "KEY: 0x{:x?}", self.0)
+    }
+}
+
+pub(crate) const MESSAGE_INTEGRITY_SHA256_SIZE: usize = 32;
+
+// A truncated MESSAGE-INTEGRITY-SHA256 keeps at least 16 bytes, in steps of 4
+const MESSAGE_INTEGRITY_SHA256_MIN_SIZE: usize = 16;
+
+impl Setter for MessageIntegritySha256 {
+    // add_to adds MESSAGE-INTEGRITY-SHA256 attribute to message.
+    fn add_to(&self, m: &mut Message) -> Result<()> {
+        add_hmac(
+            m,
+            ATTR_MESSAGE_INTEGRITY_SHA256,
+            MESSAGE_INTEGRITY_SHA256_SIZE,
+            hmac::HMAC_SHA256,
+            &self.0,
+        )
+    }
+}
+
+impl MessageIntegritySha256 {
+    // check checks MESSAGE-INTEGRITY-SHA256 attribute, which the sender may
+    // have truncated.
+    pub(crate) fn check(&self, m: &mut Message) -> Result<()> {
+        let size = m.get(ATTR_MESSAGE_INTEGRITY_SHA256)?.len();
+        if size < MESSAGE_INTEGRITY_SHA256_MIN_SIZE || size % PADDING != 0 {
+            return Err(Error::ErrAttributeSizeInvalid);
         }
-        m.length -= size_reduced as u32;
-        m.write_length();
-        // start_of_hmac should be first byte of integrity attribute.
-        let start_of_hmac = MESSAGE_HEADER_SIZE + m.length as usize
-            - (ATTRIBUTE_HEADER_SIZE + MESSAGE_INTEGRITY_SIZE);
-        let b = &m.raw[..start_of_hmac]; // data before integrity attribute
-        let expected = new_hmac(&self.0, b);
-        m.length = length as u32;
-        m.write_length(); // writing length back
-        check_hmac(&v, &expected)
+        check_overflow(
+            ATTR_MESSAGE_INTEGRITY_SHA256,
+            size,
+            MESSAGE_INTEGRITY_SHA256_SIZE,
+        )?;
+        check_hmac_attr(m, ATTR_MESSAGE_INTEGRITY_SHA256, hmac::HMAC_SHA256, &self.0)
     }
 }
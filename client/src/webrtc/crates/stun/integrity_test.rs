@@ -0,0 +1,175 @@
+use super::agent::TransactionId;
+use super::attributes::*;
+use super::error::*;
+use super::integrity::*;
+use super::message::*;
+use super::password_algorithm::*;
+use super::textattrs::*;
+
+use ring::hmac;
+
+// The sample request of RFC 8489 Appendix B.1, with USERHASH. The header of
+// the printed message gives a length of 0x009c, 12 bytes more than its
+// attributes take; its MESSAGE-INTEGRITY-SHA256 is over the length 0x0090.
+// https://www.rfc-editor.org/rfc/rfc8489#appendix-B.1
+const SAMPLE_REQUEST: &[u8] = &[
+    0x00, 0x01, 0x00, 0x90, // Request type and message length
+    0x21, 0x12, 0xa4, 0x42, // Magic cookie
+    0x78, 0xad, 0x34, 0x33, // Transaction ID
+    0xc6, 0xad, 0x72, 0xc0, //
+    0x29, 0xda, 0x41, 0x2e, //
+    0x00, 0x1e, 0x00, 0x20, // USERHASH attribute header
+    0x4a, 0x3c, 0xf3, 0x8f, // Userhash value (32 bytes)
+    0xef, 0x69, 0x92, 0xbd, //
+    0xa9, 0x52, 0xc6, 0x78, //
+    0x04, 0x17, 0xda, 0x0f, //
+    0x24, 0x81, 0x94, 0x15, //
+    0x56, 0x9e, 0x60, 0xb2, //
+    0x05, 0xc4, 0x6e, 0x41, //
+    0x40, 0x7f, 0x17, 0x04, //
+    0x00, 0x15, 0x00, 0x29, // NONCE attribute header
+    0x6f, 0x62, 0x4d, 0x61, // Nonce value and padding (3 bytes)
+    0x74, 0x4a, 0x6f, 0x73, //
+    0x32, 0x41, 0x41, 0x41, //
+    0x43, 0x66, 0x2f, 0x2f, //
+    0x34, 0x39, 0x39, 0x6b, //
+    0x39, 0x35, 0x34, 0x64, //
+    0x36, 0x4f, 0x4c, 0x33, //
+    0x34, 0x6f, 0x4c, 0x39, //
+    0x46, 0x53, 0x54, 0x76, //
+    0x79, 0x36, 0x34, 0x73, //
+    0x41, 0x00, 0x00, 0x00, //
+    0x00, 0x14, 0x00, 0x0b, // REALM attribute header
+    0x65, 0x78, 0x61, 0x6d, // Realm value (11 bytes) and padding (1 byte)
+    0x70, 0x6c, 0x65, 0x2e, //
+    0x6f, 0x72, 0x67, 0x00, //
+    0x00, 0x1d, 0x00, 0x04, // PASSWORD-ALGORITHM attribute header
+    0x00, 0x02, 0x00, 0x00, // PASSWORD-ALGORITHM value (4 bytes)
+    0x00, 0x1c, 0x00, 0x20, // MESSAGE-INTEGRITY-SHA256 attribute header
+    0xb5, 0xc7, 0xbf, 0x00, // HMAC-SHA256 value
+    0x5b, 0x6c, 0x52, 0xa2, //
+    0x1c, 0x51, 0xc5, 0xe8, //
+    0x92, 0xf8, 0x19, 0x24, //
+    0x13, 0x62, 0x96, 0xcb, //
+    0x92, 0x7c, 0x43, 0x14, //
+    0x93, 0x09, 0x27, 0x8c, //
+    0xc6, 0x51, 0x8e, 0x65, //
+];
+
+fn sample_key() -> Vec<u8> {
+    // The password "The<U+00AD>M<U+00AA>tr<U+2168>" after SASLprep
+    long_term_key(
+        "\u{30DE}\u{30C8}\u{30EA}\u{30C3}\u{30AF}\u{30B9}",
+        "example.org",
+        "TheMatrIX",
+        &PasswordAlgorithm {
+            algorithm: PASSWORD_ALGORITHM_SHA256,
+            params: vec![],
+        },
+    )
+}
+
+fn decoded(raw: &[u8]) -> Message {
+    let mut m = Message::new();
+    m.raw = raw.to_vec();
+    m.decode().unwrap();
+    m
+}
+
+// request_with_integrity returns a request with a MESSAGE-INTEGRITY-SHA256
+// truncated to size bytes, as a sender may send it
+fn request_with_integrity(key: &[u8], size: usize) -> Message {
+    let mut m = Message::new();
+    m.build(&[
+        Box::new(BINDING_REQUEST),
+        Box::new(TransactionId::new()),
+        Box::new(Username::new(ATTR_USERNAME, "user".to_owned())),
+    ])
+    .unwrap();
+
+    let length = m.length;
+    m.length += (ATTRIBUTE_HEADER_SIZE + size) as u32;
+    m.write_length();
+    let mac = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), &m.raw);
+    m.length = length;
+    m.add(ATTR_MESSAGE_INTEGRITY_SHA256, &mac.as_ref()[..size]);
+
+    decoded(&m.raw)
+}
+
+#[test]
+fn test_message_integrity_sha256_rfc8489_sample() {
+    let mut m = decoded(SAMPLE_REQUEST);
+    assert_eq!(MessageIntegritySha256(sample_key()).check(&mut m), Ok(()));
+
+    let mut algorithm = PasswordAlgorithm::default();
+    algorithm.get_from(&m).unwrap();
+    assert_eq!(algorithm.algorithm, PASSWORD_ALGORITHM_SHA256);
+
+    // A different password does not verify
+    let wrong_key = long_term_key(
+        "\u{30DE}\u{30C8}\u{30EA}\u{30C3}\u{30AF}\u{30B9}",
+        "example.org",
+        "TheMatrix",
+        &algorithm,
+    );
+    assert_eq!(
+        MessageIntegritySha256(wrong_key).check(&mut m),
+        Err(Error::ErrIntegrityMismatch)
+    );
+}
+
+#[test]
+fn test_message_integrity_sha256_round_trip() {
+    let key = sample_key();
+    let mut m = Message::new();
+    m.build(&[
+        Box::new(BINDING_REQUEST),
+        Box::new(TransactionId::new()),
+        Box::new(Username::new(ATTR_USERNAME, "user".to_owned())),
+        Box::new(MessageIntegritySha256(key.clone())),
+    ])
+    .unwrap();
+
+    let mut m = decoded(&m.raw);
+    assert_eq!(
+        m.get(ATTR_MESSAGE_INTEGRITY_SHA256).unwrap().len(),
+        MESSAGE_INTEGRITY_SHA256_SIZE
+    );
+    assert_eq!(MessageIntegritySha256(key).check(&mut m), Ok(()));
+}
+
+#[test]
+fn test_message_integrity_sha256_truncated() {
+    let key = sample_key();
+    for size in [16, 20, 28] {
+        let mut m = request_with_integrity(&key, size);
+        assert_eq!(
+            MessageIntegritySha256(key.clone()).check(&mut m),
+            Ok(()),
+            "truncated to {}",
+            size
+        );
+    }
+
+    // Less than 16 bytes, or not in steps of 4, is not a valid truncation
+    for size in [12, 18] {
+        let mut m = request_with_integrity(&key, size);
+        assert_eq!(
+            MessageIntegritySha256(key.clone()).check(&mut m),
+            Err(Error::ErrAttributeSizeInvalid),
+            "truncated to {}",
+            size
+        );
+    }
+
+    // A truncated HMAC still has to match
+    let mut m = request_with_integrity(&key, 16);
+    let last = m.raw.len() - 1;
+    m.raw[last] ^= 0xff;
+    let mut m = decoded(&m.raw);
+    assert_eq!(
+        MessageIntegritySha256(key).check(&mut m),
+        Err(Error::ErrIntegrityMismatch)
+    );
+}
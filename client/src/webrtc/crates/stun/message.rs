@@ -463,6 +463,11 @@ impl Setter for MessageType {
 }
 
 impl MessageType {
+    // new returns a new message type.
+    pub(crate) const fn new(method: Method, class: MessageClass) -> Self {
+        MessageType { method, class }
+    }
+
     // Value returns bit representation of messageType.
     pub(crate) fn value(&self) -> u16 {
         //	 0                 1
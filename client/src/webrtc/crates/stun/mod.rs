@@ -1,13 +1,20 @@
 pub(crate) mod addr;
 pub(crate) mod agent;
 pub(crate) mod attributes;
+pub(crate) mod change_request;
 pub(crate) mod checks;
 pub(crate) mod client;
+pub(crate) mod credentials;
+#[cfg(test)]
+mod credentials_test;
 mod error;
 pub(crate) mod error_code;
 pub(crate) mod fingerprint;
 pub(crate) mod integrity;
+#[cfg(test)]
+mod integrity_test;
 pub(crate) mod message;
+pub(crate) mod password_algorithm;
 pub(crate) mod textattrs;
 pub(crate) mod uattrs;
 pub(crate) mod uri;
@@ -0,0 +1,106 @@
+use crate::webrtc::stun::attributes::*;
+use crate::webrtc::stun::error::*;
+use crate::webrtc::stun::message::*;
+
+// Password algorithms registered by RFC 8489 Section 18.5
+pub(crate) const PASSWORD_ALGORITHM_MD5: u16 = 0x0001;
+pub(crate) const PASSWORD_ALGORITHM_SHA256: u16 = 0x0002;
+
+// Algorithm and parameters length fields
+const PASSWORD_ALGORITHM_HEADER_SIZE: usize = 4;
+
+// PasswordAlgorithm represents PASSWORD-ALGORITHM attribute, the algorithm the
+// client hashes the long-term password with.
+//
+// RFC 8489 Section 14.12
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PasswordAlgorithm {
+    pub(crate) algorithm: u16,
+    pub(crate) params: Vec<u8>,
+}
+
+impl PasswordAlgorithm {
+    // is_supported returns whether long_term_key can hash with the algorithm
+    pub(crate) fn is_supported(&self) -> bool {
+        self.algorithm == PASSWORD_ALGORITHM_MD5 || self.algorithm == PASSWORD_ALGORITHM_SHA256
+    }
+
+    fn encode(&self, v: &mut Vec<u8>) {
+        v.extend_from_slice(&self.algorithm.to_be_bytes());
+        v.extend_from_slice(&(self.params.len() as u16).to_be_bytes());
+        v.extend_from_slice(&self.params);
+        v.resize(nearest_padded_value_length(v.len()), 0);
+    }
+
+    // decode reads one algorithm from the start of v and returns the bytes used
+    fn decode(v: &[u8]) -> Result<(Self, usize)> {
+        if v.len() < PASSWORD_ALGORITHM_HEADER_SIZE {
+            return Err(Error::ErrUnexpectedEof);
+        }
+        let algorithm = u16::from_be_bytes([v[0], v[1]]);
+        let params_len = u16::from_be_bytes([v[2], v[3]]) as usize;
+        let end = PASSWORD_ALGORITHM_HEADER_SIZE + params_len;
+        if v.len() < end {
+            return Err(Error::ErrUnexpectedEof);
+        }
+        let params = v[PASSWORD_ALGORITHM_HEADER_SIZE..end].to_vec();
+        Ok((
+            PasswordAlgorithm { algorithm, params },
+            std::cmp::min(nearest_padded_value_length(end), v.len()),
+        ))
+    }
+}
+
+impl Setter for PasswordAlgorithm {
+    // add_to adds PASSWORD-ALGORITHM attribute to message.
+    fn add_to(&self, m: &mut Message) -> Result<()> {
+        let mut v = vec![];
+        self.encode(&mut v);
+        m.add(ATTR_PASSWORD_ALGORITHM, &v);
+        Ok(())
+    }
+}
+
+impl Getter for PasswordAlgorithm {
+    // get_from decodes PASSWORD-ALGORITHM from message.
+    fn get_from(&mut self, m: &Message) -> Result<()> {
+        let v = m.get(ATTR_PASSWORD_ALGORITHM)?;
+        let (algorithm, _) = PasswordAlgorithm::decode(&v)?;
+        *self = algorithm;
+        Ok(())
+    }
+}
+
+// PasswordAlgorithms represents PASSWORD-ALGORITHMS attribute, the algorithms
+// a server accepts in order of its preference.
+//
+// RFC 8489 Section 14.11
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PasswordAlgorithms(pub(crate) Vec<PasswordAlgorithm>);
+
+impl Setter for PasswordAlgorithms {
+    // add_to adds PASSWORD-ALGORITHMS attribute to message.
+    fn add_to(&self, m: &mut Message) -> Result<()> {
+        let mut v = vec![];
+        for algorithm in &self.0 {
+            algorithm.encode(&mut v);
+        }
+        m.add(ATTR_PASSWORD_ALGORITHMS, &v);
+        Ok(())
+    }
+}
+
+impl Getter for PasswordAlgorithms {
+    // get_from decodes PASSWORD-ALGORITHMS from message.
+    fn get_from(&mut self, m: &Message) -> Result<()> {
+        let v = m.get(ATTR_PASSWORD_ALGORITHMS)?;
+        self.0.clear();
+        let mut first = 0;
+        while first < v.len() {
+            let (algorithm, n) = PasswordAlgorithm::decode(&v[first..])?;
+            self.0.push(algorithm);
+            first += n;
+        }
+        Ok(())
+    }
+}
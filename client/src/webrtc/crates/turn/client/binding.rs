@@ -0,0 +1,86 @@
+use crate::webrtc::turn::error::*;
+use crate::webrtc::turn::proto::channum::*;
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+// Channel binding state
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum BindingState {
+    Idle,
+    Request,
+    Ready,
+}
+
+// A channel number bound to a peer address
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Binding {
+    pub(crate) number: ChannelNumber,
+    pub(crate) addr: SocketAddr,
+    pub(crate) state: BindingState,
+}
+
+// BindingManager tracks the channel bindings of an allocation, by channel
+// number for inbound ChannelData and by peer address for outbound data
+#[derive(Default)]
+pub(crate) struct BindingManager {
+    chan_map: HashMap<u16, SocketAddr>,
+    addr_map: HashMap<SocketAddr, Binding>,
+    next: u16,
+}
+
+impl BindingManager {
+    // assign_channel_number returns the next unused channel number
+    fn assign_channel_number(&mut self) -> Result<ChannelNumber> {
+        let count = (MAX_CHANNEL_NUMBER - MIN_CHANNEL_NUMBER) as usize + 1;
+        if self.chan_map.len() >= count {
+            return Err(Error::ErrChannelNumbersExhausted);
+        }
+
+        loop {
+            let number = MIN_CHANNEL_NUMBER + self.next;
+            self.next = (self.next + 1) % count as u16;
+            if !self.chan_map.contains_key(&number) {
+                return Ok(ChannelNumber(number));
+            }
+        }
+    }
+
+    // create returns a new idle binding for addr
+    pub(crate) fn create(&mut self, addr: SocketAddr) -> Result<Binding> {
+        let b = Binding {
+            number: self.assign_channel_number()?,
+            addr,
+            state: BindingState::Idle,
+        };
+
+        self.chan_map.insert(b.number.0, addr);
+        self.addr_map.insert(addr, b);
+        Ok(b)
+    }
+
+    pub(crate) fn find_by_addr(&self, addr: &SocketAddr) -> Option<Binding> {
+        self.addr_map.get(addr).copied()
+    }
+
+    pub(crate) fn find_addr_by_number(&self, number: u16) -> Option<SocketAddr> {
+        self.chan_map.get(&number).copied()
+    }
+
+    pub(crate) fn set_state(&mut self, addr: &SocketAddr, state: BindingState) {
+        if let Some(b) = self.addr_map.get_mut(addr) {
+            b.state = state;
+        }
+    }
+
+    // delete_by_addr drops the binding, the channel number becomes reusable
+    pub(crate) fn delete_by_addr(&mut self, addr: &SocketAddr) {
+        if let Some(b) = self.addr_map.remove(addr) {
+            self.chan_map.remove(&b.number.0);
+        }
+    }
+
+    pub(crate) fn bindings(&self) -> Vec<Binding> {
+        self.addr_map.values().copied().collect()
+    }
+}
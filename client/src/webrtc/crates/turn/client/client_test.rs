@@ -0,0 +1,450 @@
+use super::*;
+use crate::webrtc::stun::attributes::*;
+use crate::webrtc::stun::integrity::*;
+use crate::webrtc::stun::password_algorithm::*;
+use crate::webrtc::stun::textattrs::*;
+use crate::webrtc::util::vnet::net::{Net, NetConfig};
+
+use std::collections::HashSet;
+use std::net::IpAddr;
+
+const USERNAME: &str = "user";
+const PASSWORD: &str = "pass";
+const REALM: &str = "example.org";
+
+const TURN_SERVER_ADDR: &str = "127.0.0.1:3478";
+
+// TurnServerState is what the stand-in server saw and allowed
+#[derive(Default)]
+struct TurnServerState {
+    nonce: String,
+    // Answer the next authenticated request with 438 and a new nonce
+    stale_next_request: bool,
+    client: Option<SocketAddr>,
+    methods: Vec<Method>,
+    unauthenticated: usize,
+    lifetimes: Vec<Duration>,
+    permissions: HashSet<IpAddr>,
+    channels: HashMap<u16, SocketAddr>,
+    send_indications: usize,
+    channel_data: usize,
+}
+
+impl TurnServerState {
+    fn count(&self, method: Method) -> usize {
+        self.methods.iter().filter(|m| **m == method).count()
+    }
+}
+
+// TurnServer is an in-process TURN server stand-in on a virtual network. It
+// challenges for long-term credentials, allocates a relayed address on the
+// same network and relays through Send indications and channels.
+struct TurnServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<TurnServerState>>,
+}
+
+impl TurnServer {
+    async fn new(net: &Net, lifetime: Duration) -> Self {
+        let addr: SocketAddr = TURN_SERVER_ADDR.parse().unwrap();
+        let conn = net.bind(addr).await.unwrap();
+        let relay = net.bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let state = Arc::new(Mutex::new(TurnServerState {
+            nonce: "nonce-0".to_owned(),
+            ..Default::default()
+        }));
+
+        let (conn2, relay2, state2) = (Arc::clone(&conn), Arc::clone(&relay), Arc::clone(&state));
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; MAX_DATA_BUFFER_SIZE];
+            while let Ok((n, from)) = conn2.recv_from(&mut buf).await {
+                TurnServer::handle(&conn2, &relay2, &state2, lifetime, &buf[..n], from).await;
+            }
+        });
+
+        let state2 = Arc::clone(&state);
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; MAX_DATA_BUFFER_SIZE];
+            while let Ok((n, from)) = relay.recv_from(&mut buf).await {
+                TurnServer::relay_to_client(&conn, &state2, &buf[..n], from).await;
+            }
+        });
+
+        TurnServer { addr, state }
+    }
+
+    async fn handle(
+        conn: &Arc<dyn Conn + Send + Sync>,
+        relay: &Arc<dyn Conn + Send + Sync>,
+        state: &Mutex<TurnServerState>,
+        lifetime: Duration,
+        buf: &[u8],
+        from: SocketAddr,
+    ) {
+        if ChannelData::is_channel_data(buf) {
+            let ch = ChannelData::decode(buf).unwrap();
+            let peer = {
+                let mut state = state.lock().await;
+                state.channel_data += 1;
+                state.channels.get(&ch.number.0).copied()
+            };
+            if let Some(peer) = peer {
+                relay.send_to(&ch.data, peer).await.unwrap();
+            }
+            return;
+        }
+
+        let mut m = Message::new();
+        m.raw = buf.to_vec();
+        m.decode().unwrap();
+
+        if m.typ == MessageType::new(METHOD_SEND, CLASS_INDICATION) {
+            let mut peer = PeerAddress::default();
+            peer.get_from(&m).unwrap();
+            let mut data = Data::default();
+            data.get_from(&m).unwrap();
+            let allowed = {
+                let mut state = state.lock().await;
+                state.send_indications += 1;
+                state.permissions.contains(&peer.ip)
+            };
+            if allowed {
+                relay.send_to(&data.0, peer.into()).await.unwrap();
+            }
+            return;
+        }
+
+        let key = long_term_key(
+            USERNAME,
+            REALM,
+            PASSWORD,
+            &PasswordAlgorithm {
+                algorithm: PASSWORD_ALGORITHM_MD5,
+                params: vec![],
+            },
+        );
+        let relayed = relay.local_addr().await.unwrap();
+        let mut state = state.lock().await;
+        // Setters are not Send, the response is built without awaiting
+        let resp = {
+            let setters: Vec<Box<dyn Setter>> = if !m.contains(ATTR_MESSAGE_INTEGRITY) {
+                state.unauthenticated += 1;
+                challenge(&m, CODE_UNAUTHORIZED, &state.nonce)
+            } else if MessageIntegrity(key.clone()).check(&mut m).is_err() {
+                challenge(&m, CODE_UNAUTHORIZED, &state.nonce)
+            } else if state.stale_next_request
+                || TextAttribute::get_from_as(&m, ATTR_NONCE).unwrap().text != state.nonce
+            {
+                state.stale_next_request = false;
+                state.nonce = format!("nonce-{}", state.methods.len() + 1);
+                challenge(&m, CODE_STALE_NONCE, &state.nonce)
+            } else {
+                state.methods.push(m.typ.method);
+                let mut setters: Vec<Box<dyn Setter>> = vec![
+                    Box::new(m.clone()),
+                    Box::new(MessageType::new(m.typ.method, CLASS_SUCCESS_RESPONSE)),
+                ];
+                match m.typ.method {
+                    METHOD_ALLOCATE => {
+                        state.client = Some(from);
+                        setters.push(Box::new(RelayedAddress {
+                            ip: relayed.ip(),
+                            port: relayed.port(),
+                        }));
+                        setters.push(Box::new(XorMappedAddress {
+                            ip: from.ip(),
+                            port: from.port(),
+                        }));
+                        setters.push(Box::new(Lifetime(lifetime)));
+                    }
+                    METHOD_REFRESH => {
+                        let mut requested = Lifetime::default();
+                        requested.get_from(&m).unwrap();
+                        state.lifetimes.push(requested.0);
+                        setters.push(Box::new(Lifetime(std::cmp::min(requested.0, lifetime))));
+                    }
+                    METHOD_CREATE_PERMISSION => {
+                        let mut peer = PeerAddress::default();
+                        peer.get_from(&m).unwrap();
+                        state.permissions.insert(peer.ip);
+                    }
+                    METHOD_CHANNEL_BIND => {
+                        let v = m.get(ATTR_CHANNEL_NUMBER).unwrap();
+                        let number = u16::from_be_bytes([v[0], v[1]]);
+                        let mut peer = PeerAddress::default();
+                        peer.get_from(&m).unwrap();
+                        state.permissions.insert(peer.ip);
+                        state.channels.insert(number, peer.into());
+                    }
+                    _ => {}
+                }
+                setters.push(Box::new(MessageIntegrity(key)));
+                setters
+            };
+            let mut resp = Message::new();
+            resp.build(&setters).unwrap();
+            resp
+        };
+        drop(state);
+
+        conn.send_to(&resp.raw, from).await.unwrap();
+    }
+
+    // relay_to_client hands data from a permitted peer to the client, over
+    // the channel bound to the peer if there is one
+    async fn relay_to_client(
+        conn: &Arc<dyn Conn + Send + Sync>,
+        state: &Mutex<TurnServerState>,
+        data: &[u8],
+        from: SocketAddr,
+    ) {
+        let (client, number) = {
+            let state = state.lock().await;
+            if !state.permissions.contains(&from.ip()) {
+                return;
+            }
+            let number = state
+                .channels
+                .iter()
+                .find(|(_, peer)| **peer == from)
+                .map(|(number, _)| *number);
+            (state.client.unwrap(), number)
+        };
+
+        let raw = match number {
+            Some(number) => ChannelData {
+                number: ChannelNumber(number),
+                data: data.to_vec(),
+            }
+            .encode(),
+            None => {
+                let mut m = Message::new();
+                m.build(&[
+                    Box::new(MessageType::new(METHOD_DATA, CLASS_INDICATION)),
+                    Box::new(TransactionId::new()),
+                    Box::new(PeerAddress::from(from)),
+                    Box::new(Data(data.to_vec())),
+                ])
+                .unwrap();
+                m.raw
+            }
+        };
+        conn.send_to(&raw, client).await.unwrap();
+    }
+}
+
+// challenge builds the error response asking for credentials or a fresh nonce
+fn challenge(m: &Message, code: ErrorCode, nonce: &str) -> Vec<Box<dyn Setter>> {
+    vec![
+        Box::new(m.clone()),
+        Box::new(MessageType::new(m.typ.method, CLASS_ERROR_RESPONSE)),
+        Box::new(code),
+        Box::new(TextAttribute::new(ATTR_REALM, REALM.to_owned())),
+        Box::new(TextAttribute::new(ATTR_NONCE, nonce.to_owned())),
+    ]
+}
+
+async fn new_client(net: &Net, server: &TurnServer, password: &str) -> Arc<Client> {
+    Client::new(ClientConfig {
+        turn_serv_addr: server.addr,
+        username: USERNAME.to_owned(),
+        password: password.to_owned(),
+        conn: net.bind("127.0.0.1:0".parse().unwrap()).await.unwrap(),
+    })
+    .await
+}
+
+fn new_net() -> Net {
+    Net::new(Some(NetConfig::default()))
+}
+
+// wait_for polls the server state until f holds
+async fn wait_for<F>(server: &TurnServer, f: F)
+where
+    F: Fn(&TurnServerState) -> bool,
+{
+    for _ in 0..100 {
+        if f(&*server.state.lock().await) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("turn server state not reached");
+}
+
+#[tokio::test]
+async fn test_client_allocate_with_challenge() {
+    let net = new_net();
+    let server = TurnServer::new(&net, DEFAULT_LIFETIME).await;
+    let client = new_client(&net, &server, PASSWORD).await;
+
+    let relay_conn = client.allocate().await.unwrap();
+    {
+        let state = server.state.lock().await;
+        // The first Allocate carries no credentials and gets the 401
+        assert_eq!(state.unauthenticated, 1);
+        assert_eq!(state.methods, vec![METHOD_ALLOCATE]);
+        assert_eq!(relay_conn.mapped_addr(), state.client.unwrap());
+    }
+    assert_eq!(
+        relay_conn.local_addr().await.unwrap().ip(),
+        "127.0.0.1".parse::<IpAddr>().unwrap()
+    );
+    assert_eq!(
+        client.allocate().await.err(),
+        Some(Error::ErrAlreadyAllocated)
+    );
+
+    relay_conn.close().await.unwrap();
+    let state = server.state.lock().await;
+    assert_eq!(state.lifetimes, vec![Duration::from_secs(0)]);
+}
+
+#[tokio::test]
+async fn test_client_stale_nonce() {
+    let net = new_net();
+    let server = TurnServer::new(&net, DEFAULT_LIFETIME).await;
+    let client = new_client(&net, &server, PASSWORD).await;
+    let relay_conn = client.allocate().await.unwrap();
+
+    // The 438 hands out a new nonce, the request is sent again with it
+    server.state.lock().await.stale_next_request = true;
+    let lifetime = client.refresh_allocation(DEFAULT_LIFETIME).await.unwrap();
+    assert_eq!(lifetime, DEFAULT_LIFETIME);
+    {
+        let state = server.state.lock().await;
+        assert!(!state.stale_next_request);
+        assert_eq!(state.unauthenticated, 1);
+        assert_eq!(state.methods, vec![METHOD_ALLOCATE, METHOD_REFRESH]);
+    }
+
+    relay_conn.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_client_wrong_credentials() {
+    let net = new_net();
+    let server = TurnServer::new(&net, DEFAULT_LIFETIME).await;
+    let client = new_client(&net, &server, "wrong").await;
+
+    // The second 401, to a request with credentials for the same realm, is final
+    match client.allocate().await {
+        Err(Error::ErrErrorResponse(method, code)) => {
+            assert_eq!(method, METHOD_ALLOCATE.to_string());
+            assert!(code.starts_with("401"), "{}", code);
+        }
+        result => panic!("expected a 401, got {:?}", result.map(|_| ())),
+    }
+    assert!(server.state.lock().await.methods.is_empty());
+
+    client.close().await;
+}
+
+#[tokio::test]
+async fn test_client_relay_permission_and_channel() {
+    let net = new_net();
+    let server = TurnServer::new(&net, DEFAULT_LIFETIME).await;
+    let client = new_client(&net, &server, PASSWORD).await;
+    let relay_conn = client.allocate().await.unwrap();
+    let relayed_addr = relay_conn.local_addr().await.unwrap();
+
+    let peer = net.bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+    let peer_addr = peer.local_addr().await.unwrap();
+    let mut buf = vec![0u8; 1500];
+
+    // The first datagram installs the permission and goes out as a Send
+    // indication while the channel is bound
+    relay_conn.send_to(b"first", peer_addr).await.unwrap();
+    let (n, from) = peer.recv_from(&mut buf).await.unwrap();
+    assert_eq!(&buf[..n], b"first");
+    assert_eq!(from, relayed_addr);
+    wait_for(&server, |state| !state.channels.is_empty()).await;
+    {
+        let state = server.state.lock().await;
+        assert_eq!(state.count(METHOD_CREATE_PERMISSION), 1);
+        assert!(state.permissions.contains(&peer_addr.ip()));
+        assert_eq!(state.send_indications, 1);
+        assert_eq!(state.channel_data, 0);
+    }
+    wait_for_binding_ready(&client, peer_addr).await;
+
+    // Once bound, data flows over the channel both ways
+    relay_conn.send_to(b"second", peer_addr).await.unwrap();
+    let (n, _) = peer.recv_from(&mut buf).await.unwrap();
+    assert_eq!(&buf[..n], b"second");
+    peer.send_to(b"reply", relayed_addr).await.unwrap();
+    let (n, from) = relay_conn.recv_from(&mut buf).await.unwrap();
+    assert_eq!(&buf[..n], b"reply");
+    assert_eq!(from, peer_addr);
+    {
+        let state = server.state.lock().await;
+        assert_eq!(state.count(METHOD_CREATE_PERMISSION), 1);
+        assert_eq!(state.count(METHOD_CHANNEL_BIND), 1);
+        assert_eq!(state.send_indications, 1);
+        assert_eq!(state.channel_data, 1);
+    }
+
+    relay_conn.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_client_data_indication() {
+    let net = new_net();
+    let server = TurnServer::new(&net, DEFAULT_LIFETIME).await;
+    let client = new_client(&net, &server, PASSWORD).await;
+    let relay_conn = client.allocate().await.unwrap();
+    let relayed_addr = relay_conn.local_addr().await.unwrap();
+
+    let peer = net.bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+    let peer_addr = peer.local_addr().await.unwrap();
+
+    // Before a channel is bound the server relays through Data indications
+    client.create_permission(&[peer_addr]).await.unwrap();
+    peer.send_to(b"data", relayed_addr).await.unwrap();
+    let mut buf = vec![0u8; 1500];
+    let (n, from) = relay_conn.recv_from(&mut buf).await.unwrap();
+    assert_eq!(&buf[..n], b"data");
+    assert_eq!(from, peer_addr);
+
+    relay_conn.close().await.unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_client_refresh_timers() {
+    let net = new_net();
+    let lifetime = Duration::from_secs(120);
+    let server = TurnServer::new(&net, lifetime).await;
+    let client = new_client(&net, &server, PASSWORD).await;
+    let relay_conn = client.allocate().await.unwrap();
+
+    let peer = net.bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+    let peer_addr = peer.local_addr().await.unwrap();
+    relay_conn.send_to(b"hello", peer_addr).await.unwrap();
+    wait_for_binding_ready(&client, peer_addr).await;
+
+    // The allocation is refreshed at half its lifetime, every minute, the
+    // permission after 4 and the channel after 5 minutes
+    tokio::time::sleep(Duration::from_secs(5 * 60 + 1)).await;
+    {
+        let state = server.state.lock().await;
+        assert_eq!(state.count(METHOD_REFRESH), 5);
+        assert!(state.lifetimes.iter().all(|l| *l == DEFAULT_LIFETIME));
+        assert_eq!(state.count(METHOD_CREATE_PERMISSION), 2);
+        assert_eq!(state.count(METHOD_CHANNEL_BIND), 2);
+    }
+
+    relay_conn.close().await.unwrap();
+    let state = server.state.lock().await;
+    assert_eq!(state.lifetimes.last(), Some(&Duration::from_secs(0)));
+}
+
+async fn wait_for_binding_ready(client: &Client, peer: SocketAddr) {
+    for _ in 0..100 {
+        let b = client.bindings.lock().await.find_by_addr(&peer);
+        if b.map(|b| b.state) == Some(BindingState::Ready) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("channel to {} not bound", peer);
+}
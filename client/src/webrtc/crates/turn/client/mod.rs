@@ -0,0 +1,394 @@
+pub(crate) mod binding;
+pub(crate) mod relay_conn;
+
+#[cfg(test)]
+mod client_test;
+
+use binding::*;
+use relay_conn::*;
+
+use crate::webrtc::stun::agent::*;
+use crate::webrtc::stun::credentials::*;
+use crate::webrtc::stun::error_code::*;
+use crate::webrtc::stun::message::*;
+use crate::webrtc::stun::xoraddr::*;
+use crate::webrtc::turn::error::*;
+use crate::webrtc::turn::proto::{
+    chandata::*, channum::*, data::*, lifetime::*, peeraddr::*, relayaddr::*, reqtrans::*,
+};
+use crate::webrtc::util::Conn;
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc, oneshot, Mutex};
+use tokio::time::{Duration, Instant};
+
+// Initial retransmission timeout of a request, doubled on every retransmission
+// https://www.rfc-editor.org/rfc/rfc8489#section-6.2.1
+const DEFAULT_RTO: Duration = Duration::from_millis(500);
+
+// Give up on a request that got no response within this time
+const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Largest datagram read from the server
+const MAX_DATA_BUFFER_SIZE: usize = u16::MAX as usize;
+
+// Relayed datagrams queued for the reader before new ones are dropped
+const MAX_READ_QUEUE_SIZE: usize = 1024;
+
+// Challenges answered per request: the first 401 and a stale nonce
+const MAX_CHALLENGES: usize = 2;
+
+// A datagram received from a peer through the allocation
+pub(crate) type RelayedData = (Vec<u8>, SocketAddr);
+
+/// The config required to create a new `Client`.
+pub(crate) struct ClientConfig {
+    pub(crate) turn_serv_addr: SocketAddr,
+    pub(crate) username: String,
+    pub(crate) password: String,
+    pub(crate) conn: Arc<dyn Conn + Send + Sync>,
+}
+
+/// A TURN client (RFC 8656) speaking to one server over UDP. It owns the
+/// socket to the server, matches responses to requests and hands relayed
+/// data to the `RelayConn` returned by `allocate`.
+pub(crate) struct Client {
+    conn: Arc<dyn Conn + Send + Sync>,
+    turn_serv_addr: SocketAddr,
+    credentials: Mutex<LongTermCredentials>,
+    transactions: Mutex<HashMap<TransactionId, oneshot::Sender<Message>>>,
+    bindings: Mutex<BindingManager>,
+    read_tx: Mutex<Option<mpsc::Sender<RelayedData>>>,
+    read_rx: Mutex<Option<mpsc::Receiver<RelayedData>>>,
+    close_tx: Mutex<Option<broadcast::Sender<()>>>,
+}
+
+impl Client {
+    /// Creates a client and starts reading from `config.conn`.
+    pub(crate) async fn new(config: ClientConfig) -> Arc<Self> {
+        let (read_tx, read_rx) = mpsc::channel(MAX_READ_QUEUE_SIZE);
+        let (close_tx, close_rx) = broadcast::channel(1);
+
+        let c = Arc::new(Client {
+            conn: config.conn,
+            turn_serv_addr: config.turn_serv_addr,
+            credentials: Mutex::new(LongTermCredentials::new(config.username, config.password)),
+            transactions: Mutex::new(HashMap::new()),
+            bindings: Mutex::new(BindingManager::default()),
+            read_tx: Mutex::new(Some(read_tx)),
+            read_rx: Mutex::new(Some(read_rx)),
+            close_tx: Mutex::new(Some(close_tx)),
+        });
+
+        let c2 = Arc::clone(&c);
+        tokio::spawn(async move {
+            c2.read_loop(close_rx).await;
+        });
+
+        c
+    }
+
+    async fn read_loop(&self, mut close_rx: broadcast::Receiver<()>) {
+        let mut buf = vec![0u8; MAX_DATA_BUFFER_SIZE];
+        loop {
+            let (n, from) = tokio::select! {
+                result = self.conn.recv_from(&mut buf) => match result {
+                    Ok(result) => result,
+                    Err(err) => {
+                        log::debug!("turn: exiting read loop: {}", err);
+                        break;
+                    }
+                },
+                _ = close_rx.recv() => break,
+            };
+
+            if from != self.turn_serv_addr {
+                log::debug!("turn: dropping datagram from unknown source {}", from);
+                continue;
+            }
+
+            if let Err(err) = self.handle_inbound(&buf[..n]).await {
+                log::debug!("turn: failed to handle inbound message: {}", err);
+            }
+        }
+    }
+
+    async fn handle_inbound(&self, buf: &[u8]) -> Result<()> {
+        if ChannelData::is_channel_data(buf) {
+            let ch = ChannelData::decode(buf)?;
+            let peer = {
+                let bindings = self.bindings.lock().await;
+                bindings.find_addr_by_number(ch.number.0)
+            };
+            if let Some(peer) = peer {
+                self.deliver(ch.data, peer).await;
+            } else {
+                log::debug!("turn: no binding for channel {}", ch.number);
+            }
+            return Ok(());
+        }
+
+        if !is_message(buf) {
+            return Ok(());
+        }
+
+        let mut msg = Message::new();
+        msg.raw = buf.to_vec();
+        msg.decode()?;
+
+        if msg.typ.class == CLASS_INDICATION {
+            if msg.typ.method == METHOD_DATA {
+                let mut peer = PeerAddress::default();
+                peer.get_from(&msg)?;
+                let mut data = Data::default();
+                data.get_from(&msg)?;
+                self.deliver(data.0, peer.into()).await;
+            }
+            return Ok(());
+        }
+
+        if msg.typ.class == CLASS_SUCCESS_RESPONSE || msg.typ.class == CLASS_ERROR_RESPONSE {
+            let tx = {
+                let mut transactions = self.transactions.lock().await;
+                transactions.remove(&msg.transaction_id)
+            };
+            if let Some(tx) = tx {
+                let _ = tx.send(msg);
+            }
+        }
+
+        Ok(())
+    }
+
+    // deliver queues relayed data for the RelayConn, dropping it like an
+    // overflowing socket buffer when the reader falls behind
+    async fn deliver(&self, data: Vec<u8>, from: SocketAddr) {
+        let read_tx = self.read_tx.lock().await;
+        if let Some(tx) = &*read_tx {
+            if tx.try_send((data, from)).is_err() {
+                log::debug!("turn: read queue full, dropping datagram from {}", from);
+            }
+        }
+    }
+
+    // perform_transaction sends a request and waits for the matching response,
+    // retransmitting with backoff
+    // https://www.rfc-editor.org/rfc/rfc8489#section-6.2.1
+    async fn perform_transaction(&self, msg: &Message) -> Result<Message> {
+        let (tx, mut rx) = oneshot::channel();
+        {
+            let mut transactions = self.transactions.lock().await;
+            transactions.insert(msg.transaction_id, tx);
+        }
+
+        let deadline = Instant::now() + TRANSACTION_TIMEOUT;
+        let mut rto = DEFAULT_RTO;
+        let result = loop {
+            if let Err(err) = self.conn.send_to(&msg.raw, self.turn_serv_addr).await {
+                break Err(err.into());
+            }
+
+            let retransmit_at = std::cmp::min(Instant::now() + rto, deadline);
+            rto *= 2;
+            match tokio::time::timeout_at(retransmit_at, &mut rx).await {
+                Ok(Ok(resp)) => break Ok(resp),
+                Ok(Err(_)) => break Err(Error::ErrClientClosed),
+                Err(_) => {
+                    if Instant::now() >= deadline {
+                        break Err(Error::ErrTransactionTimeout);
+                    }
+                }
+            }
+        };
+
+        let mut transactions = self.transactions.lock().await;
+        transactions.remove(&msg.transaction_id);
+
+        result
+    }
+
+    // request sends a request authenticated with the long-term credentials.
+    // The server's first 401 provides them, a 438 a fresh nonce, and the
+    // request is sent again after either.
+    // https://www.rfc-editor.org/rfc/rfc8489#section-9.2.4
+    async fn request<F>(&self, typ: MessageType, attrs: F) -> Result<Message>
+    where
+        F: Fn() -> Vec<Box<dyn Setter>>,
+    {
+        let mut challenges = 0;
+        loop {
+            let (msg, authenticated) = {
+                let credentials = self.credentials.lock().await;
+                let mut setters: Vec<Box<dyn Setter>> =
+                    vec![Box::new(typ), Box::new(TransactionId::new())];
+                setters.extend(attrs());
+                // Until challenged, the server may accept unauthenticated requests
+                let authenticated = credentials.is_challenged();
+                if authenticated {
+                    setters.extend(credentials.setters());
+                }
+
+                let mut msg = Message::new();
+                msg.build(&setters)?;
+                (msg, authenticated)
+            };
+
+            let mut resp = self.perform_transaction(&msg).await?;
+            if resp.typ.class != CLASS_ERROR_RESPONSE {
+                if authenticated {
+                    let credentials = self.credentials.lock().await;
+                    credentials.check(&mut resp)?;
+                }
+                return Ok(resp);
+            }
+
+            if challenges < MAX_CHALLENGES {
+                if let Some(challenge) = Challenge::from_error_response(&resp)? {
+                    let mut credentials = self.credentials.lock().await;
+                    if credentials.accept_challenge(challenge, authenticated)? {
+                        challenges += 1;
+                        continue;
+                    }
+                }
+            }
+
+            let mut code = ErrorCodeAttribute::default();
+            let _ = code.get_from(&resp);
+            return Err(Error::ErrErrorResponse(
+                typ.method.to_string(),
+                error_code_to_string(&code),
+            ));
+        }
+    }
+
+    /// Allocates a relayed transport address on the server, authenticating with
+    /// the long-term credentials the server asks for.
+    /// https://www.rfc-editor.org/rfc/rfc8656#section-7.1
+    pub(crate) async fn allocate(self: &Arc<Self>) -> Result<RelayConn> {
+        let read_rx = {
+            let mut read_rx = self.read_rx.lock().await;
+            match read_rx.take() {
+                Some(read_rx) => read_rx,
+                None => return Err(Error::ErrAlreadyAllocated),
+            }
+        };
+
+        let typ = MessageType::new(METHOD_ALLOCATE, CLASS_REQUEST);
+        let transport = RequestedTransport {
+            protocol: PROTO_UDP,
+        };
+
+        // The first request carries no credentials, the server answers with
+        // the realm and nonce to use
+        let resp = self.request(typ, || vec![Box::new(transport)]).await?;
+
+        if resp.typ != MessageType::new(METHOD_ALLOCATE, CLASS_SUCCESS_RESPONSE) {
+            return Err(Error::ErrUnexpectedResponse(resp.typ.to_string()));
+        }
+
+        let mut relayed = RelayedAddress::default();
+        relayed.get_from(&resp)?;
+        let mut mapped = XorMappedAddress::default();
+        mapped.get_from(&resp)?;
+        let mut lifetime = Lifetime::default();
+        lifetime.get_from(&resp)?;
+
+        Ok(RelayConn::new(
+            Arc::clone(self),
+            relayed.into(),
+            SocketAddr::new(mapped.ip, mapped.port),
+            lifetime.0,
+            read_rx,
+        ))
+    }
+
+    // refresh_allocation asks the server to keep the allocation for lifetime,
+    // a zero lifetime deletes it
+    // https://www.rfc-editor.org/rfc/rfc8656#section-7.2
+    async fn refresh_allocation(&self, lifetime: Duration) -> Result<Duration> {
+        let resp = self
+            .request(MessageType::new(METHOD_REFRESH, CLASS_REQUEST), || {
+                vec![Box::new(Lifetime(lifetime))]
+            })
+            .await?;
+
+        let mut updated = Lifetime::default();
+        updated.get_from(&resp)?;
+        Ok(updated.0)
+    }
+
+    // create_permission installs or refreshes the permissions for the peers
+    // https://www.rfc-editor.org/rfc/rfc8656#section-9
+    async fn create_permission(&self, peers: &[SocketAddr]) -> Result<()> {
+        self.request(
+            MessageType::new(METHOD_CREATE_PERMISSION, CLASS_REQUEST),
+            || {
+                peers
+                    .iter()
+                    .map(|peer| Box::new(PeerAddress::from(*peer)) as Box<dyn Setter>)
+                    .collect()
+            },
+        )
+        .await?;
+        Ok(())
+    }
+
+    // channel_bind binds or rebinds a channel number to the peer
+    // https://www.rfc-editor.org/rfc/rfc8656#section-12
+    async fn channel_bind(&self, number: ChannelNumber, peer: SocketAddr) -> Result<()> {
+        self.request(MessageType::new(METHOD_CHANNEL_BIND, CLASS_REQUEST), || {
+            vec![Box::new(number), Box::new(PeerAddress::from(peer))]
+        })
+        .await?;
+        Ok(())
+    }
+
+    // send_indication relays data to the peer without a channel binding
+    // https://www.rfc-editor.org/rfc/rfc8656#section-10
+    async fn send_indication(&self, data: &[u8], peer: SocketAddr) -> Result<()> {
+        let mut msg = Message::new();
+        msg.build(&[
+            Box::new(MessageType::new(METHOD_SEND, CLASS_INDICATION)),
+            Box::new(TransactionId::new()),
+            Box::new(PeerAddress::from(peer)),
+            Box::new(Data(data.to_vec())),
+        ])?;
+        self.conn.send_to(&msg.raw, self.turn_serv_addr).await?;
+        Ok(())
+    }
+
+    // send_channel_data relays data to the peer bound to the channel
+    async fn send_channel_data(&self, data: &[u8], number: ChannelNumber) -> Result<()> {
+        let ch = ChannelData {
+            number,
+            data: data.to_vec(),
+        };
+        self.conn.send_to(&ch.encode(), self.turn_serv_addr).await?;
+        Ok(())
+    }
+
+    /// Stops the read loop and fails pending transactions. The socket is
+    /// closed as well since the client owns it.
+    pub(crate) async fn close(&self) {
+        {
+            let mut close_tx = self.close_tx.lock().await;
+            close_tx.take();
+        }
+        {
+            let mut read_tx = self.read_tx.lock().await;
+            read_tx.take();
+        }
+        {
+            let mut transactions = self.transactions.lock().await;
+            transactions.clear();
+        }
+
+        let _ = self.conn.close().await;
+    }
+}
+
+fn error_code_to_string(code: &ErrorCodeAttribute) -> String {
+    format!("{} {}", code.code.0, String::from_utf8_lossy(&code.reason))
+}
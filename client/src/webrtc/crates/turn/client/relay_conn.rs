@@ -0,0 +1,250 @@
+use super::binding::*;
+use super::{Client, RelayedData};
+use crate::webrtc::turn::error::Error;
+use crate::webrtc::turn::proto::lifetime::DEFAULT_LIFETIME;
+use crate::webrtc::util::{self, Conn};
+
+use async_trait::async_trait;
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::{Duration, Instant};
+
+type UtilResult<T> = std::result::Result<T, util::Error>;
+
+// Permissions expire after 5 minutes, refresh them a minute early
+// https://www.rfc-editor.org/rfc/rfc8656#section-9
+const PERMISSION_REFRESH_INTERVAL: Duration = Duration::from_secs(4 * 60);
+
+// Channel bindings expire after 10 minutes
+// https://www.rfc-editor.org/rfc/rfc8656#section-12
+const BINDING_REFRESH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+// Wait before retrying a failed allocation refresh
+const REFRESH_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// RelayConn is the relayed transport address of a TURN allocation. Data sent
+/// to a peer goes through the server, over a channel once one is bound, and
+/// the allocation, permissions and channel bindings are refreshed until it is
+/// closed.
+pub(crate) struct RelayConn {
+    client: Arc<Client>,
+    relayed_addr: SocketAddr,
+    mapped_addr: SocketAddr,
+    permissions: Arc<Mutex<HashSet<IpAddr>>>,
+    read_rx: Mutex<mpsc::Receiver<RelayedData>>,
+    refresh_close_tx: Mutex<Option<mpsc::Sender<()>>>,
+}
+
+impl RelayConn {
+    pub(crate) fn new(
+        client: Arc<Client>,
+        relayed_addr: SocketAddr,
+        mapped_addr: SocketAddr,
+        lifetime: Duration,
+        read_rx: mpsc::Receiver<RelayedData>,
+    ) -> Self {
+        let permissions = Arc::new(Mutex::new(HashSet::new()));
+        let (refresh_close_tx, refresh_close_rx) = mpsc::channel(1);
+
+        let (client2, permissions2) = (Arc::clone(&client), Arc::clone(&permissions));
+        tokio::spawn(async move {
+            RelayConn::refresh_loop(client2, permissions2, lifetime, refresh_close_rx).await;
+        });
+
+        RelayConn {
+            client,
+            relayed_addr,
+            mapped_addr,
+            permissions,
+            read_rx: Mutex::new(read_rx),
+            refresh_close_tx: Mutex::new(Some(refresh_close_tx)),
+        }
+    }
+
+    /// The client's address as seen by the TURN server.
+    pub(crate) fn mapped_addr(&self) -> SocketAddr {
+        self.mapped_addr
+    }
+
+    async fn refresh_loop(
+        client: Arc<Client>,
+        permissions: Arc<Mutex<HashSet<IpAddr>>>,
+        lifetime: Duration,
+        mut close_rx: mpsc::Receiver<()>,
+    ) {
+        let mut allocation_at = Instant::now() + lifetime / 2;
+        let mut permission_ticker = tokio::time::interval_at(
+            Instant::now() + PERMISSION_REFRESH_INTERVAL,
+            PERMISSION_REFRESH_INTERVAL,
+        );
+        let mut binding_ticker = tokio::time::interval_at(
+            Instant::now() + BINDING_REFRESH_INTERVAL,
+            BINDING_REFRESH_INTERVAL,
+        );
+
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep_until(allocation_at) => {
+                    match client.refresh_allocation(DEFAULT_LIFETIME).await {
+                        Ok(lifetime) => allocation_at = Instant::now() + lifetime / 2,
+                        Err(err) => {
+                            log::warn!("turn: failed to refresh allocation: {}", err);
+                            allocation_at = Instant::now() + REFRESH_RETRY_INTERVAL;
+                        }
+                    }
+                }
+                _ = permission_ticker.tick() => {
+                    let peers: Vec<SocketAddr> = {
+                        let permissions = permissions.lock().await;
+                        permissions.iter().map(|ip| SocketAddr::new(*ip, 0)).collect()
+                    };
+                    if peers.is_empty() {
+                        continue;
+                    }
+                    if let Err(err) = client.create_permission(&peers).await {
+                        log::warn!("turn: failed to refresh permissions: {}", err);
+                    }
+                }
+                _ = binding_ticker.tick() => {
+                    let bindings = {
+                        let bindings = client.bindings.lock().await;
+                        bindings.bindings()
+                    };
+                    for b in bindings {
+                        if b.state != BindingState::Ready {
+                            continue;
+                        }
+                        if let Err(err) = client.channel_bind(b.number, b.addr).await {
+                            log::warn!("turn: failed to refresh channel {} to {}: {}", b.number, b.addr, err);
+                        }
+                    }
+                }
+                _ = close_rx.recv() => break,
+            }
+        }
+    }
+
+    // bind_channel binds the channel in the background, data keeps flowing
+    // through Send indications until it is ready
+    fn bind_channel(&self, b: Binding) {
+        let client = Arc::clone(&self.client);
+        tokio::spawn(async move {
+            let result = client.channel_bind(b.number, b.addr).await;
+
+            let mut bindings = client.bindings.lock().await;
+            match result {
+                Ok(_) => bindings.set_state(&b.addr, BindingState::Ready),
+                Err(err) => {
+                    log::warn!(
+                        "turn: failed to bind channel {} to {}: {}",
+                        b.number,
+                        b.addr,
+                        err
+                    );
+                    // Try again with the next datagram
+                    bindings.delete_by_addr(&b.addr);
+                }
+            }
+        });
+    }
+
+    async fn write_to(&self, buf: &[u8], target: SocketAddr) -> Result<usize, Error> {
+        if target.is_ipv4() != self.relayed_addr.is_ipv4() {
+            return Err(Error::ErrPeerAddressFamilyMismatch);
+        }
+
+        let has_permission = {
+            let permissions = self.permissions.lock().await;
+            permissions.contains(&target.ip())
+        };
+        if !has_permission {
+            self.client.create_permission(&[target]).await?;
+            let mut permissions = self.permissions.lock().await;
+            permissions.insert(target.ip());
+        }
+
+        let b = {
+            let mut bindings = self.client.bindings.lock().await;
+            let b = match bindings.find_by_addr(&target) {
+                Some(b) => b,
+                None => bindings.create(target)?,
+            };
+            if b.state == BindingState::Idle {
+                bindings.set_state(&target, BindingState::Request);
+            }
+            b
+        };
+
+        match b.state {
+            BindingState::Ready => self.client.send_channel_data(buf, b.number).await?,
+            BindingState::Idle => {
+                self.client.send_indication(buf, target).await?;
+                self.bind_channel(b);
+            }
+            BindingState::Request => self.client.send_indication(buf, target).await?,
+        }
+
+        Ok(buf.len())
+    }
+}
+
+#[async_trait]
+impl Conn for RelayConn {
+    async fn connect(&self, _addr: SocketAddr) -> UtilResult<()> {
+        Err(util::Error::Other("Not applicable".to_owned()))
+    }
+
+    async fn recv(&self, buf: &mut [u8]) -> UtilResult<usize> {
+        let (n, _) = self.recv_from(buf).await?;
+        Ok(n)
+    }
+
+    async fn recv_from(&self, buf: &mut [u8]) -> UtilResult<(usize, SocketAddr)> {
+        let mut read_rx = self.read_rx.lock().await;
+        match read_rx.recv().await {
+            Some((data, from)) => {
+                let n = std::cmp::min(buf.len(), data.len());
+                buf[..n].copy_from_slice(&data[..n]);
+                Ok((n, from))
+            }
+            None => Err(util::Error::ErrUseClosedNetworkConn),
+        }
+    }
+
+    async fn send(&self, _buf: &[u8]) -> UtilResult<usize> {
+        Err(util::Error::ErrNoRemAddr)
+    }
+
+    async fn send_to(&self, buf: &[u8], target: SocketAddr) -> UtilResult<usize> {
+        self.write_to(buf, target)
+            .await
+            .map_err(util::Error::from_std)
+    }
+
+    async fn local_addr(&self) -> UtilResult<SocketAddr> {
+        Ok(self.relayed_addr)
+    }
+
+    async fn remote_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    // close deletes the allocation on the server and closes the client
+    async fn close(&self) -> UtilResult<()> {
+        {
+            let mut refresh_close_tx = self.refresh_close_tx.lock().await;
+            if refresh_close_tx.take().is_none() {
+                return Err(util::Error::ErrAlreadyClosed);
+            }
+        }
+
+        if let Err(err) = self.client.refresh_allocation(Duration::from_secs(0)).await {
+            log::debug!("turn: failed to delete allocation: {}", err);
+        }
+        self.client.close().await;
+
+        Ok(())
+    }
+}
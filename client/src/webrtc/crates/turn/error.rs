@@ -0,0 +1,32 @@
+use thiserror::Error;
+
+pub(crate) type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error, PartialEq)]
+#[non_exhaustive]
+pub(crate) enum Error {
+    #[error("turn: transaction timed out")]
+    ErrTransactionTimeout,
+    #[error("turn: allocation already exists")]
+    ErrAlreadyAllocated,
+    #[error("turn: client is closed")]
+    ErrClientClosed,
+    #[error("turn: unexpected response type {0}")]
+    ErrUnexpectedResponse(String),
+    #[error("turn: {0} failed: {1}")]
+    ErrErrorResponse(String, String),
+    #[error("turn: channel data is too short")]
+    ErrShortChannelData,
+    #[error("turn: channel data length does not match")]
+    ErrBadChannelDataLength,
+    #[error("turn: invalid channel number")]
+    ErrInvalidChannelNumber,
+    #[error("turn: no channel number is available")]
+    ErrChannelNumbersExhausted,
+    #[error("turn: relayed address family does not match the peer")]
+    ErrPeerAddressFamilyMismatch,
+    #[error("{0}")]
+    Util(#[from] crate::webrtc::util::Error),
+    #[error("{0}")]
+    Stun(#[from] crate::webrtc::stun::Error),
+}
@@ -0,0 +1,3 @@
+pub(crate) mod client;
+mod error;
+pub(crate) mod proto;
@@ -0,0 +1,75 @@
+use super::channum::*;
+use crate::webrtc::turn::error::*;
+
+const CHANNEL_DATA_LENGTH_SIZE: usize = 2;
+const CHANNEL_DATA_NUMBER_SIZE: usize = CHANNEL_DATA_LENGTH_SIZE;
+const CHANNEL_DATA_HEADER_SIZE: usize = CHANNEL_DATA_LENGTH_SIZE + CHANNEL_DATA_NUMBER_SIZE;
+const PADDING: usize = 4;
+
+// ChannelData represents the ChannelData Message.
+//
+// The ChannelData message is used to carry application data between the
+// client and the server over a bound channel, with a 4 byte header
+// instead of the 36 bytes of a Send or Data indication.
+//
+// https://www.rfc-editor.org/rfc/rfc8656#section-12.4
+#[derive(Default, Debug, PartialEq)]
+pub(crate) struct ChannelData {
+    pub(crate) number: ChannelNumber,
+    pub(crate) data: Vec<u8>,
+}
+
+fn nearest_padded_value_length(l: usize) -> usize {
+    let mut n = PADDING * (l / PADDING);
+    if n < l {
+        n += PADDING;
+    }
+    n
+}
+
+impl ChannelData {
+    // is_channel_data returns true if buf looks like the ChannelData Message.
+    pub(crate) fn is_channel_data(buf: &[u8]) -> bool {
+        if buf.len() < CHANNEL_DATA_HEADER_SIZE {
+            return false;
+        }
+
+        // The first two bits of a channel number are 0b01, STUN messages
+        // start with 0b00
+        ChannelNumber(u16::from_be_bytes([buf[0], buf[1]])).is_valid()
+    }
+
+    // encode returns the ChannelData Message with its header, padded to a
+    // multiple of four bytes as required over UDP.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let padded = nearest_padded_value_length(self.data.len());
+        let mut raw = Vec::with_capacity(CHANNEL_DATA_HEADER_SIZE + padded);
+        raw.extend_from_slice(&self.number.0.to_be_bytes());
+        raw.extend_from_slice(&(self.data.len() as u16).to_be_bytes());
+        raw.extend_from_slice(&self.data);
+        raw.resize(CHANNEL_DATA_HEADER_SIZE + padded, 0);
+        raw
+    }
+
+    // decode parses the ChannelData Message from buf, ignoring the padding.
+    pub(crate) fn decode(buf: &[u8]) -> Result<Self> {
+        if buf.len() < CHANNEL_DATA_HEADER_SIZE {
+            return Err(Error::ErrShortChannelData);
+        }
+
+        let number = ChannelNumber(u16::from_be_bytes([buf[0], buf[1]]));
+        if !number.is_valid() {
+            return Err(Error::ErrInvalidChannelNumber);
+        }
+
+        let l = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+        if l > buf[CHANNEL_DATA_HEADER_SIZE..].len() {
+            return Err(Error::ErrBadChannelDataLength);
+        }
+
+        Ok(ChannelData {
+            number,
+            data: buf[CHANNEL_DATA_HEADER_SIZE..CHANNEL_DATA_HEADER_SIZE + l].to_vec(),
+        })
+    }
+}
@@ -0,0 +1,46 @@
+use crate::webrtc::stun::attributes::*;
+use crate::webrtc::stun::message::*;
+use crate::webrtc::stun::Error;
+
+use std::fmt;
+
+// 16 bits of uint + 16 bits of RFFU = 0.
+const CHANNEL_NUMBER_SIZE: usize = 4;
+
+/// First channel number a client may bind.
+pub(crate) const MIN_CHANNEL_NUMBER: u16 = 0x4000;
+
+/// Last channel number a client may bind.
+pub(crate) const MAX_CHANNEL_NUMBER: u16 = 0x4FFF;
+
+// ChannelNumber represents CHANNEL-NUMBER attribute.
+//
+// The CHANNEL-NUMBER attribute contains the number of the channel.
+//
+// https://www.rfc-editor.org/rfc/rfc8656#section-18.1
+#[derive(Default, Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub(crate) struct ChannelNumber(pub(crate) u16);
+
+impl fmt::Display for ChannelNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Setter for ChannelNumber {
+    // add_to adds CHANNEL-NUMBER to message.
+    fn add_to(&self, m: &mut Message) -> Result<(), Error> {
+        let mut v = vec![0; CHANNEL_NUMBER_SIZE];
+        v[..2].copy_from_slice(&self.0.to_be_bytes());
+        // v[2:4] are zeroes (RFFU = 0)
+        m.add(ATTR_CHANNEL_NUMBER, &v);
+        Ok(())
+    }
+}
+
+impl ChannelNumber {
+    // is_valid returns true if c is in the range a client may bind.
+    pub(crate) fn is_valid(&self) -> bool {
+        (MIN_CHANNEL_NUMBER..=MAX_CHANNEL_NUMBER).contains(&self.0)
+    }
+}
@@ -0,0 +1,31 @@
+use crate::webrtc::stun::attributes::*;
+use crate::webrtc::stun::message::*;
+use crate::webrtc::stun::Error;
+
+// Data represents DATA attribute.
+//
+// The DATA attribute is present in all Send and Data indications. The
+// value portion of this attribute is variable length and consists of
+// the application data (that is, the data that would immediately follow
+// the UDP header if the data was been sent directly between the client
+// and the peer).
+//
+// https://www.rfc-editor.org/rfc/rfc8656#section-18.4
+#[derive(Default, Debug, PartialEq, Clone)]
+pub(crate) struct Data(pub(crate) Vec<u8>);
+
+impl Setter for Data {
+    // add_to adds DATA to message.
+    fn add_to(&self, m: &mut Message) -> Result<(), Error> {
+        m.add(ATTR_DATA, &self.0);
+        Ok(())
+    }
+}
+
+impl Getter for Data {
+    // get_from decodes DATA from message.
+    fn get_from(&mut self, m: &Message) -> Result<(), Error> {
+        self.0 = m.get(ATTR_DATA)?;
+        Ok(())
+    }
+}
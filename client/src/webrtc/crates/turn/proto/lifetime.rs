@@ -0,0 +1,49 @@
+use crate::webrtc::stun::attributes::*;
+use crate::webrtc::stun::checks::*;
+use crate::webrtc::stun::message::*;
+use crate::webrtc::stun::Error;
+
+use std::time::Duration;
+
+// DEFAULT_LIFETIME in RFC 8656 is 10 minutes.
+//
+// https://www.rfc-editor.org/rfc/rfc8656#section-3.8
+pub(crate) const DEFAULT_LIFETIME: Duration = Duration::from_secs(10 * 60);
+
+// uint32 seconds
+const LIFETIME_SIZE: usize = 4; // 4 bytes, 32 bits
+
+// Lifetime represents LIFETIME attribute.
+//
+// The LIFETIME attribute represents the duration for which the server
+// will maintain an allocation in the absence of a refresh. The value
+// portion of this attribute is 4-bytes long and consists of a 32-bit
+// unsigned integral value representing the number of seconds remaining
+// until expiration.
+//
+// https://www.rfc-editor.org/rfc/rfc8656#section-18.2
+#[derive(Default, Debug, PartialEq, Copy, Clone)]
+pub(crate) struct Lifetime(pub(crate) Duration);
+
+impl Setter for Lifetime {
+    // add_to adds LIFETIME to message.
+    fn add_to(&self, m: &mut Message) -> Result<(), Error> {
+        let v = (self.0.as_secs() as u32).to_be_bytes();
+        m.add(ATTR_LIFETIME, &v);
+        Ok(())
+    }
+}
+
+impl Getter for Lifetime {
+    // get_from decodes LIFETIME from message.
+    fn get_from(&mut self, m: &Message) -> Result<(), Error> {
+        let v = m.get(ATTR_LIFETIME)?;
+
+        check_size(ATTR_LIFETIME, v.len(), LIFETIME_SIZE)?;
+
+        let seconds = u32::from_be_bytes([v[0], v[1], v[2], v[3]]);
+        self.0 = Duration::from_secs(seconds as u64);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,7 @@
+pub(crate) mod chandata;
+pub(crate) mod channum;
+pub(crate) mod data;
+pub(crate) mod lifetime;
+pub(crate) mod peeraddr;
+pub(crate) mod relayaddr;
+pub(crate) mod reqtrans;
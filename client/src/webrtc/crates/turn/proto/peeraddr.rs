@@ -0,0 +1,65 @@
+use crate::webrtc::stun::attributes::*;
+use crate::webrtc::stun::message::*;
+use crate::webrtc::stun::xoraddr::*;
+use crate::webrtc::stun::Error;
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+// PeerAddress implements XOR-PEER-ADDRESS attribute.
+//
+// The XOR-PEER-ADDRESS specifies the address and port of the peer as
+// seen from the TURN server. (For example, the peer's server-reflexive
+// transport address if the peer is behind a NAT.)
+//
+// https://www.rfc-editor.org/rfc/rfc8656#section-18.3
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub(crate) struct PeerAddress {
+    pub(crate) ip: IpAddr,
+    pub(crate) port: u16,
+}
+
+impl Default for PeerAddress {
+    fn default() -> Self {
+        PeerAddress {
+            ip: IpAddr::V4(Ipv4Addr::from(0)),
+            port: 0,
+        }
+    }
+}
+
+impl From<SocketAddr> for PeerAddress {
+    fn from(addr: SocketAddr) -> Self {
+        PeerAddress {
+            ip: addr.ip(),
+            port: addr.port(),
+        }
+    }
+}
+
+impl From<PeerAddress> for SocketAddr {
+    fn from(addr: PeerAddress) -> Self {
+        SocketAddr::new(addr.ip, addr.port)
+    }
+}
+
+impl Setter for PeerAddress {
+    // add_to adds XOR-PEER-ADDRESS to message.
+    fn add_to(&self, m: &mut Message) -> Result<(), Error> {
+        let a = XorMappedAddress {
+            ip: self.ip,
+            port: self.port,
+        };
+        a.add_to_as(m, ATTR_XOR_PEER_ADDRESS)
+    }
+}
+
+impl Getter for PeerAddress {
+    // get_from decodes XOR-PEER-ADDRESS from message.
+    fn get_from(&mut self, m: &Message) -> Result<(), Error> {
+        let mut a = XorMappedAddress::default();
+        a.get_from_as(m, ATTR_XOR_PEER_ADDRESS)?;
+        self.ip = a.ip;
+        self.port = a.port;
+        Ok(())
+    }
+}
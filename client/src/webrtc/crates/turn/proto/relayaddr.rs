@@ -0,0 +1,55 @@
+use crate::webrtc::stun::attributes::*;
+use crate::webrtc::stun::message::*;
+use crate::webrtc::stun::xoraddr::*;
+use crate::webrtc::stun::Error;
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+// RelayedAddress implements XOR-RELAYED-ADDRESS attribute.
+//
+// It specifies the address and port that the server allocated to the
+// client. It is encoded in the same way as XOR-MAPPED-ADDRESS.
+//
+// https://www.rfc-editor.org/rfc/rfc8656#section-18.5
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub(crate) struct RelayedAddress {
+    pub(crate) ip: IpAddr,
+    pub(crate) port: u16,
+}
+
+impl Default for RelayedAddress {
+    fn default() -> Self {
+        RelayedAddress {
+            ip: IpAddr::V4(Ipv4Addr::from(0)),
+            port: 0,
+        }
+    }
+}
+
+impl From<RelayedAddress> for SocketAddr {
+    fn from(addr: RelayedAddress) -> Self {
+        SocketAddr::new(addr.ip, addr.port)
+    }
+}
+
+impl Setter for RelayedAddress {
+    // add_to adds XOR-RELAYED-ADDRESS to message.
+    fn add_to(&self, m: &mut Message) -> Result<(), Error> {
+        let a = XorMappedAddress {
+            ip: self.ip,
+            port: self.port,
+        };
+        a.add_to_as(m, ATTR_XOR_RELAYED_ADDRESS)
+    }
+}
+
+impl Getter for RelayedAddress {
+    // get_from decodes XOR-RELAYED-ADDRESS from message.
+    fn get_from(&mut self, m: &Message) -> Result<(), Error> {
+        let mut a = XorMappedAddress::default();
+        a.get_from_as(m, ATTR_XOR_RELAYED_ADDRESS)?;
+        self.ip = a.ip;
+        self.port = a.port;
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+use crate::webrtc::stun::attributes::*;
+use crate::webrtc::stun::message::*;
+use crate::webrtc::stun::Error;
+
+// PROTO_UDP is IANA assigned protocol number for UDP.
+pub(crate) const PROTO_UDP: u8 = 17;
+
+const REQUESTED_TRANSPORT_SIZE: usize = 4;
+
+// RequestedTransport represents REQUESTED-TRANSPORT attribute.
+//
+// This attribute is used by the client to request a specific transport
+// protocol for the allocated transport address.
+//
+// https://www.rfc-editor.org/rfc/rfc8656#section-18.11
+#[derive(Default, Debug, PartialEq, Copy, Clone)]
+pub(crate) struct RequestedTransport {
+    pub(crate) protocol: u8,
+}
+
+impl Setter for RequestedTransport {
+    // add_to adds REQUESTED-TRANSPORT to message.
+    fn add_to(&self, m: &mut Message) -> Result<(), Error> {
+        let mut v = vec![0; REQUESTED_TRANSPORT_SIZE];
+        v[0] = self.protocol;
+        // v[1:4] is RFFU = 0.
+        // The RFFU field MUST be set to zero on transmission and MUST be
+        // ignored on reception. It is reserved for future uses.
+        m.add(ATTR_REQUESTED_TRANSPORT, &v);
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 pub(crate) mod ffi;
+pub(crate) mod monitor;
 pub(crate) use ffi::ifaces;
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,173 @@
+#[cfg(target_os = "linux")]
+mod netlink;
+
+use super::ifaces;
+
+use std::collections::HashSet;
+use std::net::IpAddr;
+use tokio::sync::{broadcast, mpsc, Mutex};
+use tokio::time::{Duration, Interval};
+
+// How often the interfaces are enumerated where the OS doesn't notify of
+// address changes
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_EVENT_QUEUE_SIZE: usize = 16;
+
+/// An address that appeared on or disappeared from an interface of the host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum InterfaceEvent {
+    AddrAdded { name: String, addr: IpAddr },
+    AddrRemoved { name: String, addr: IpAddr },
+}
+
+/// InterfaceMonitor watches the addresses of the host's interfaces. Each time
+/// they may have changed it enumerates them again and reports the difference.
+/// On Linux netlink tells when that is, elsewhere, or if netlink can't be
+/// opened, the interfaces are polled.
+pub(crate) struct InterfaceMonitor {
+    events_tx: Mutex<Option<broadcast::Sender<InterfaceEvent>>>,
+    close_tx: Mutex<Option<mpsc::Sender<()>>>,
+}
+
+impl InterfaceMonitor {
+    pub(crate) fn new() -> Self {
+        let (events_tx, _) = broadcast::channel(MAX_EVENT_QUEUE_SIZE);
+        let (close_tx, close_rx) = mpsc::channel(1);
+
+        let events_tx2 = events_tx.clone();
+        tokio::spawn(async move {
+            InterfaceMonitor::watch_loop(events_tx2, close_rx).await;
+        });
+
+        InterfaceMonitor {
+            events_tx: Mutex::new(Some(events_tx)),
+            close_tx: Mutex::new(Some(close_tx)),
+        }
+    }
+
+    /// A monitor that only reports the events passed to [`InterfaceMonitor::emit`],
+    /// for tests.
+    #[cfg(test)]
+    pub(crate) fn manual() -> Self {
+        let (events_tx, _) = broadcast::channel(MAX_EVENT_QUEUE_SIZE);
+
+        InterfaceMonitor {
+            events_tx: Mutex::new(Some(events_tx)),
+            close_tx: Mutex::new(None),
+        }
+    }
+
+    /// Reports the event as if the interfaces changed.
+    #[cfg(test)]
+    pub(crate) async fn emit(&self, event: InterfaceEvent) {
+        if let Some(events_tx) = &*self.events_tx.lock().await {
+            let _ = events_tx.send(event);
+        }
+    }
+
+    /// Returns a receiver of the events from now on, or None once closed.
+    pub(crate) async fn subscribe(&self) -> Option<broadcast::Receiver<InterfaceEvent>> {
+        let events_tx = self.events_tx.lock().await;
+        events_tx.as_ref().map(|tx| tx.subscribe())
+    }
+
+    /// Stops watching, receivers see the channel close.
+    pub(crate) async fn close(&self) {
+        self.close_tx.lock().await.take();
+        self.events_tx.lock().await.take();
+    }
+
+    async fn watch_loop(
+        events_tx: broadcast::Sender<InterfaceEvent>,
+        mut close_rx: mpsc::Receiver<()>,
+    ) {
+        let mut trigger = Trigger::new();
+        let mut addrs = host_addrs().unwrap_or_default();
+
+        loop {
+            tokio::select! {
+                _ = trigger.wait() => {}
+                _ = close_rx.recv() => break,
+            }
+
+            // A failed enumeration is not a reason to report every address as gone
+            let current = match host_addrs() {
+                Some(current) => current,
+                None => continue,
+            };
+
+            for (name, addr) in current.difference(&addrs) {
+                log::debug!("ifaces: {} added on {}", addr, name);
+                let _ = events_tx.send(InterfaceEvent::AddrAdded {
+                    name: name.clone(),
+                    addr: *addr,
+                });
+            }
+            for (name, addr) in addrs.difference(&current) {
+                log::debug!("ifaces: {} removed from {}", addr, name);
+                let _ = events_tx.send(InterfaceEvent::AddrRemoved {
+                    name: name.clone(),
+                    addr: *addr,
+                });
+            }
+
+            addrs = current;
+        }
+    }
+}
+
+// Trigger wakes the monitor up when the addresses may have changed
+enum Trigger {
+    #[cfg(target_os = "linux")]
+    Netlink(netlink::AddrNotifier),
+    Poll(Interval),
+}
+
+impl Trigger {
+    fn new() -> Self {
+        #[cfg(target_os = "linux")]
+        match netlink::AddrNotifier::new() {
+            Ok(notifier) => return Trigger::Netlink(notifier),
+            Err(err) => log::debug!("ifaces: netlink is unavailable, polling instead: {}", err),
+        }
+
+        Trigger::poll()
+    }
+
+    fn poll() -> Self {
+        let mut ticker = tokio::time::interval(POLL_INTERVAL);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        Trigger::Poll(ticker)
+    }
+
+    async fn wait(&mut self) {
+        match self {
+            #[cfg(target_os = "linux")]
+            Trigger::Netlink(notifier) => {
+                if let Err(err) = notifier.wait().await {
+                    log::warn!("ifaces: netlink failed, polling instead: {}", err);
+                    *self = Trigger::poll();
+                }
+            }
+            Trigger::Poll(ticker) => {
+                ticker.tick().await;
+            }
+        }
+    }
+}
+
+// host_addrs returns the IP addresses of the host's interfaces with the name
+// of their interface
+fn host_addrs() -> Option<HashSet<(String, IpAddr)>> {
+    match ifaces() {
+        Ok(ifs) => Some(
+            ifs.into_iter()
+                .filter_map(|iface| iface.addr.map(|addr| (iface.name, addr.ip())))
+                .collect(),
+        ),
+        Err(err) => {
+            log::warn!("ifaces: failed to enumerate interfaces: {}", err);
+            None
+        }
+    }
+}
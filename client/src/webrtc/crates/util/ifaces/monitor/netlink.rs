@@ -0,0 +1,76 @@
+use nix::libc;
+use nix::sys::socket::{
+    bind, recv, socket, AddressFamily, MsgFlags, SockAddr, SockFlag, SockProtocol, SockType,
+};
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use tokio::io::unix::AsyncFd;
+
+// Notifications are only counted, never parsed, so a truncated one is fine
+const RECEIVE_BUFFER_SIZE: usize = 4096;
+
+struct NetlinkSocket(RawFd);
+
+impl AsRawFd for NetlinkSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+impl Drop for NetlinkSocket {
+    fn drop(&mut self) {
+        let _ = nix::unistd::close(self.0);
+    }
+}
+
+/// AddrNotifier is a route netlink socket that joined the multicast groups
+/// the kernel announces IPv4 and IPv6 address changes on.
+///
+/// <https://man7.org/linux/man-pages/man7/rtnetlink.7.html>
+pub(super) struct AddrNotifier {
+    socket: AsyncFd<NetlinkSocket>,
+}
+
+impl AddrNotifier {
+    pub(super) fn new() -> io::Result<Self> {
+        let fd = socket(
+            AddressFamily::Netlink,
+            SockType::Raw,
+            SockFlag::SOCK_NONBLOCK | SockFlag::SOCK_CLOEXEC,
+            SockProtocol::NetlinkRoute,
+        )?;
+        let socket = NetlinkSocket(fd);
+
+        let groups = (libc::RTMGRP_IPV4_IFADDR | libc::RTMGRP_IPV6_IFADDR) as u32;
+        bind(socket.as_raw_fd(), &SockAddr::new_netlink(0, groups))?;
+
+        // SAFETY: NetlinkSocket owns the descriptor and only closes it when dropped
+        // along with the AsyncFd
+        let socket = unsafe { AsyncFd::register(socket)? };
+
+        Ok(AddrNotifier { socket })
+    }
+
+    /// Waits until an address changes. A burst of notifications, e.g. from
+    /// an interface going down, is consumed at once.
+    pub(super) async fn wait(&self) -> io::Result<()> {
+        let mut buf = [0_u8; RECEIVE_BUFFER_SIZE];
+        loop {
+            let mut guard = self.socket.readable().await?;
+            let result = guard.try_io(|socket| {
+                recv(socket.as_raw_fd(), &mut buf, MsgFlags::empty()).map_err(io::Error::from)
+            });
+            match result {
+                Ok(Ok(_)) => break,
+                // The kernel dropped notifications, there were changes all the same
+                Ok(Err(err)) if err.raw_os_error() == Some(libc::ENOBUFS) => break,
+                Ok(Err(err)) => return Err(err),
+                Err(_would_block) => continue,
+            }
+        }
+
+        while recv(self.socket.as_raw_fd(), &mut buf, MsgFlags::MSG_DONTWAIT).is_ok() {}
+
+        Ok(())
+    }
+}
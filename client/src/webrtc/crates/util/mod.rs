@@ -29,7 +29,7 @@ pub(crate) trait KeyingMaterialExporter {
 pub(crate) enum KeyingMaterialExporterError {
     #[error("tls handshake is in progress")]
     HandshakeInProgress,
-    #[error("context is not supported for export_keying_material")]
+    #[error("context is too long for export_keying_material")]
     ContextUnsupported,
     #[error("export_keying_material can not be used with a reserved label")]
     ReservedExportKeyingMaterial,
@@ -10,6 +10,13 @@ pub(crate) struct ChunkQueue {
 }
 
 impl ChunkQueue {
+    pub(crate) fn new(max_size: usize) -> Self {
+        ChunkQueue {
+            chunks: RwLock::new(VecDeque::new()),
+            max_size,
+        }
+    }
+
     pub(crate) async fn push(&self, c: Box<dyn Chunk + Send + Sync>) -> bool {
         let mut chunks = self.chunks.write().await;
 
@@ -20,4 +27,9 @@ impl ChunkQueue {
             true
         }
     }
+
+    pub(crate) async fn pop(&self) -> Option<Box<dyn Chunk + Send + Sync>> {
+        let mut chunks = self.chunks.write().await;
+        chunks.pop_front()
+    }
 }
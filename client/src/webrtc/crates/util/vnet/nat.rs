@@ -1,13 +1,22 @@
+// NATs only sit in routers, which only tests build
+#![allow(dead_code)]
+
 use crate::webrtc::util::error::*;
 use crate::webrtc::util::vnet::chunk::Chunk;
 use crate::webrtc::util::vnet::net::UDP_STR;
 
 use std::collections::{HashMap, HashSet};
 use std::net::IpAddr;
+use std::sync::atomic::{AtomicU16, Ordering};
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 use tokio::sync::Mutex;
 
+const DEFAULT_NAT_MAPPING_LIFE_TIME: Duration = Duration::from_secs(30);
+
+// First port of the mappings of a NAPT, mapped ports are handed out from here on
+const NAT_FIRST_MAPPED_PORT: u16 = 0xC000;
+
 // EndpointDependencyType defines a type of behavioral dependendency on the
 // remote endpoint's IP address or port number. This is used for the two
 // kinds of behaviors:
@@ -15,9 +24,14 @@ use tokio::sync::Mutex;
 //  - Filtering behavior
 // See: https://tools.ietf.org/html/rfc4787
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 pub(crate) enum EndpointDependencyType {
     // EndpointIndependent means the behavior is independent of the endpoint's address or port
     EndpointIndependent,
+    // EndpointAddrDependent means the behavior is dependent on the endpoint's address
+    EndpointAddrDependent,
+    // EndpointAddrPortDependent means the behavior is dependent on the endpoint's address and port
+    EndpointAddrPortDependent,
 }
 
 impl Default for EndpointDependencyType {
@@ -48,7 +62,17 @@ impl Default for NatMode {
 #[derive(Default, Debug, Copy, Clone)]
 pub(crate) struct NatType {
     pub(crate) mode: NatMode,
+    pub(crate) mapping_behavior: EndpointDependencyType,
     pub(crate) filtering_behavior: EndpointDependencyType,
+    pub(crate) mapping_life_time: Duration,
+}
+
+#[derive(Default, Debug, Clone)]
+pub(crate) struct NatConfig {
+    pub(crate) name: String,
+    pub(crate) nat_type: NatType,
+    pub(crate) mapped_ips: Vec<IpAddr>, // mapped IPv4
+    pub(crate) local_ips: Vec<IpAddr>,  // local IPv4, required only when the mode is NATModeNAT1To1
 }
 
 #[derive(Debug, Clone)]
@@ -82,9 +106,55 @@ pub(crate) struct NetworkAddressTranslator {
     pub(crate) local_ips: Vec<IpAddr>,  // local IPv4, required only when the mode is NATModeNAT1To1
     pub(crate) outbound_map: Arc<Mutex<HashMap<String, Arc<Mapping>>>>, // key: "<proto>:<local-ip>:<local-port>[:remote-ip[:remote-port]]
     pub(crate) inbound_map: Arc<Mutex<HashMap<String, Arc<Mapping>>>>, // key: "<proto>:<mapped-ip>:<mapped-port>"
+    pub(crate) udp_port_counter: Arc<AtomicU16>,
 }
 
 impl NetworkAddressTranslator {
+    pub(crate) fn new(config: NatConfig) -> Result<Self> {
+        let mut nat_type = config.nat_type;
+
+        if nat_type.mode == NatMode::Nat1To1 {
+            // 1:1 NAT behavior
+            nat_type.mapping_behavior = EndpointDependencyType::EndpointIndependent;
+            nat_type.filtering_behavior = EndpointDependencyType::EndpointIndependent;
+            nat_type.mapping_life_time = Duration::from_secs(0);
+
+            if config.mapped_ips.is_empty() {
+                return Err(Error::ErrNatRequriesMapping);
+            }
+            if config.mapped_ips.len() != config.local_ips.len() {
+                return Err(Error::ErrMismatchLengthIp);
+            }
+        } else {
+            // Normal (NAPT) behavior
+            if config.mapped_ips.is_empty() {
+                return Err(Error::ErrNatRequriesMapping);
+            }
+            if nat_type.mapping_life_time == Duration::from_secs(0) {
+                nat_type.mapping_life_time = DEFAULT_NAT_MAPPING_LIFE_TIME;
+            }
+        }
+
+        Ok(NetworkAddressTranslator {
+            name: config.name,
+            nat_type,
+            mapped_ips: config.mapped_ips,
+            local_ips: config.local_ips,
+            outbound_map: Arc::new(Mutex::new(HashMap::new())),
+            inbound_map: Arc::new(Mutex::new(HashMap::new())),
+            udp_port_counter: Arc::new(AtomicU16::new(0)),
+        })
+    }
+
+    pub(crate) fn get_paired_mapped_ip(&self, loc_ip: &IpAddr) -> Option<&IpAddr> {
+        for (i, ip) in self.local_ips.iter().enumerate() {
+            if ip == loc_ip {
+                return self.mapped_ips.get(i);
+            }
+        }
+        None
+    }
+
     pub(crate) fn get_paired_local_ip(&self, mapped_ip: &IpAddr) -> Option<&IpAddr> {
         for (i, ip) in self.mapped_ips.iter().enumerate() {
             if ip == mapped_ip {
@@ -94,6 +164,93 @@ impl NetworkAddressTranslator {
         None
     }
 
+    pub(crate) async fn translate_outbound(
+        &self,
+        from: &(dyn Chunk + Send + Sync),
+    ) -> Result<Option<Box<dyn Chunk + Send + Sync>>> {
+        let mut to = from.clone_to();
+
+        if from.network() == UDP_STR {
+            if self.nat_type.mode == NatMode::Nat1To1 {
+                // 1:1 NAT behavior
+                let src_addr = from.source_addr();
+                if let Some(src_ip) = self.get_paired_mapped_ip(&src_addr.ip()) {
+                    to.set_source_addr(&format!("{}:{}", src_ip, src_addr.port()))?;
+                } else {
+                    log::debug!("[{}] drop outbound chunk {} with no route", self.name, from);
+                    return Ok(None); // silently discard
+                }
+            } else {
+                // Normal (NAPT) behavior
+                let bound = Self::endpoint_key(self.nat_type.mapping_behavior, from);
+                let filter_key = Self::endpoint_key(self.nat_type.filtering_behavior, from);
+
+                let o_key = format!("udp:{}:{}", from.source_addr(), bound);
+                let mapped = if let Some(m) = self.find_outbound_mapping(&o_key).await {
+                    let mut filters = m.filters.lock().await;
+                    if !filters.contains(&filter_key) {
+                        log::debug!(
+                            "[{}] permit access from {} to {}",
+                            self.name,
+                            filter_key,
+                            m.mapped
+                        );
+                        filters.insert(filter_key);
+                    }
+                    m.mapped.clone()
+                } else {
+                    // Create a new Mapping
+                    let mapped_port = NAT_FIRST_MAPPED_PORT
+                        .wrapping_add(self.udp_port_counter.fetch_add(1, Ordering::SeqCst));
+                    let mut filters = HashSet::new();
+                    filters.insert(filter_key);
+
+                    let m = Arc::new(Mapping {
+                        proto: UDP_STR.to_owned(),
+                        local: from.source_addr().to_string(),
+                        mapped: format!("{}:{}", self.mapped_ips[0], mapped_port),
+                        bound,
+                        filters: Arc::new(Mutex::new(filters)),
+                        expires: Arc::new(Mutex::new(
+                            SystemTime::now() + self.nat_type.mapping_life_time,
+                        )),
+                    });
+
+                    let i_key = NetworkAddressTranslator::get_inbound_map_key(&m);
+                    log::debug!(
+                        "[{}] created a new NAT binding oKey={} iKey={}",
+                        self.name,
+                        o_key,
+                        i_key
+                    );
+                    {
+                        let mut outbound_map = self.outbound_map.lock().await;
+                        outbound_map.insert(o_key, Arc::clone(&m));
+                    }
+                    {
+                        let mut inbound_map = self.inbound_map.lock().await;
+                        inbound_map.insert(i_key, Arc::clone(&m));
+                    }
+
+                    m.mapped.clone()
+                };
+
+                to.set_source_addr(&mapped)?;
+            }
+
+            log::debug!(
+                "[{}] translate outbound chunk from {} to {}",
+                self.name,
+                from,
+                to
+            );
+
+            return Ok(Some(to));
+        }
+
+        Err(Error::ErrNonUdpTranslationNotSupported)
+    }
+
     pub(crate) async fn translate_inbound(
         &self,
         from: &(dyn Chunk + Send + Sync),
@@ -116,8 +273,15 @@ impl NetworkAddressTranslator {
                 }
             } else {
                 // Normal (NAPT) behavior
+                // The remote endpoint of an inbound chunk is its source
                 let filter_key = match self.nat_type.filtering_behavior {
                     EndpointDependencyType::EndpointIndependent => "".to_owned(),
+                    EndpointDependencyType::EndpointAddrDependent => {
+                        from.get_source_ip().to_string()
+                    }
+                    EndpointDependencyType::EndpointAddrPortDependent => {
+                        from.source_addr().to_string()
+                    }
                 };
 
                 let i_key = format!("udp:{}", from.destination_addr());
@@ -165,6 +329,61 @@ impl NetworkAddressTranslator {
         Err(Error::ErrNonUdpTranslationNotSupported)
     }
 
+    // endpoint_key returns the part of the remote endpoint, the destination of an outbound
+    // chunk, a mapping or filter depends on
+    fn endpoint_key(behavior: EndpointDependencyType, from: &(dyn Chunk + Send + Sync)) -> String {
+        match behavior {
+            EndpointDependencyType::EndpointIndependent => "".to_owned(),
+            EndpointDependencyType::EndpointAddrDependent => from.get_destination_ip().to_string(),
+            EndpointDependencyType::EndpointAddrPortDependent => {
+                from.destination_addr().to_string()
+            }
+        }
+    }
+
+    // caller must hold the mutex
+    pub(crate) async fn find_outbound_mapping(&self, o_key: &str) -> Option<Arc<Mapping>> {
+        let mapping_life_time = self.nat_type.mapping_life_time;
+        let mut expired = false;
+        let (in_key, out_key) = {
+            let outbound_map = self.outbound_map.lock().await;
+            if let Some(m) = outbound_map.get(o_key) {
+                let now = SystemTime::now();
+
+                {
+                    let mut expires = m.expires.lock().await;
+                    // check if this Mapping is expired
+                    if now.duration_since(*expires).is_ok() {
+                        expired = true;
+                    } else {
+                        // an outbound chunk refreshes the mapping, RFC 4787 Section 4.3
+                        *expires = now + mapping_life_time;
+                    }
+                }
+                (
+                    NetworkAddressTranslator::get_inbound_map_key(m),
+                    NetworkAddressTranslator::get_outbound_map_key(m),
+                )
+            } else {
+                (String::new(), String::new())
+            }
+        };
+
+        if expired {
+            {
+                let mut inbound_map = self.inbound_map.lock().await;
+                inbound_map.remove(&in_key);
+            }
+            {
+                let mut outbound_map = self.outbound_map.lock().await;
+                outbound_map.remove(&out_key);
+            }
+        }
+
+        let outbound_map = self.outbound_map.lock().await;
+        outbound_map.get(o_key).map(Arc::clone)
+    }
+
     // caller must hold the mutex
     pub(crate) async fn find_inbound_mapping(&self, i_key: &str) -> Option<Arc<Mapping>> {
         let mut expired = false;
@@ -159,6 +159,13 @@ impl Nic for VNet {
     async fn get_static_ips(&self) -> Vec<IpAddr> {
         self.static_ips.clone()
     }
+
+    async fn set_router(&self, r: Arc<Mutex<Router>>) -> Result<()> {
+        let mut vi = self.vi.lock().await;
+        vi.router = Some(r);
+
+        Ok(())
+    }
 }
 
 impl VNet {
@@ -304,7 +311,8 @@ pub(crate) struct NetConfig {
 // up to the transport (UDP / TCP) layer.
 pub(crate) enum Net {
     VNet(Arc<Mutex<VNet>>),
-    Ifs(Vec<Interface>),
+    // The host's interfaces as of the last update_interfaces
+    Ifs(Mutex<Vec<Interface>>),
 }
 
 impl Net {
@@ -350,33 +358,38 @@ impl Net {
 
             Net::VNet(Arc::new(Mutex::new(vnet)))
         } else {
-            let interfaces = match ifaces::ifaces() {
-                Ok(ifs) => ifs,
-                Err(_) => vec![],
-            };
+            Net::Ifs(Mutex::new(Net::host_interfaces()))
+        }
+    }
 
-            let mut m: HashMap<String, Vec<IpNet>> = HashMap::new();
-            for iface in interfaces {
-                if let Some(addrs) = m.get_mut(&iface.name) {
-                    if let Some(addr) = iface.addr {
-                        if let Ok(inet) = Interface::convert(addr, iface.mask) {
-                            addrs.push(inet);
-                        }
-                    }
-                } else if let Some(addr) = iface.addr {
+    // host_interfaces enumerates the host's interfaces and their addresses
+    fn host_interfaces() -> Vec<Interface> {
+        let interfaces = match ifaces::ifaces() {
+            Ok(ifs) => ifs,
+            Err(_) => vec![],
+        };
+
+        let mut m: HashMap<String, Vec<IpNet>> = HashMap::new();
+        for iface in interfaces {
+            if let Some(addrs) = m.get_mut(&iface.name) {
+                if let Some(addr) = iface.addr {
                     if let Ok(inet) = Interface::convert(addr, iface.mask) {
-                        m.insert(iface.name, vec![inet]);
+                        addrs.push(inet);
                     }
                 }
+            } else if let Some(addr) = iface.addr {
+                if let Ok(inet) = Interface::convert(addr, iface.mask) {
+                    m.insert(iface.name, vec![inet]);
+                }
             }
+        }
 
-            let mut ifs = vec![];
-            for (name, addrs) in m.into_iter() {
-                ifs.push(Interface::new(name, addrs));
-            }
-
-            Net::Ifs(ifs)
+        let mut ifs = vec![];
+        for (name, addrs) in m.into_iter() {
+            ifs.push(Interface::new(name, addrs));
         }
+
+        ifs
     }
 
     // Interfaces returns a list of the system's network interfaces.
@@ -386,7 +399,15 @@ impl Net {
                 let net = vnet.lock().await;
                 net.get_interfaces().to_vec()
             }
-            Net::Ifs(ifs) => ifs.clone(),
+            Net::Ifs(ifs) => ifs.lock().await.clone(),
+        }
+    }
+
+    // update_interfaces enumerates the host's interfaces again after they
+    // changed, the virtual network's interfaces only change through its router
+    pub(crate) async fn update_interfaces(&self) {
+        if let Net::Ifs(ifs) = self {
+            *ifs.lock().await = Net::host_interfaces();
         }
     }
 
@@ -398,6 +419,15 @@ impl Net {
         }
     }
 
+    // get_nic returns the NIC of the virtual network to add to a router
+    #[allow(dead_code)]
+    pub(crate) fn get_nic(&self) -> Result<Arc<Mutex<dyn Nic + Send + Sync>>> {
+        match self {
+            Net::VNet(vnet) => Ok(Arc::clone(vnet) as Arc<Mutex<dyn Nic + Send + Sync>>),
+            Net::Ifs(_) => Err(Error::ErrVnetDisabled),
+        }
+    }
+
     pub(crate) async fn bind(&self, addr: SocketAddr) -> Result<Arc<dyn Conn + Send + Sync>> {
         match self {
             Net::VNet(vnet) => {
@@ -1,14 +1,22 @@
+// Routers are only built by tests that put virtual networks behind NATs
+#![allow(dead_code)]
+
 use crate::webrtc::util::error::*;
 use crate::webrtc::util::vnet::chunk::*;
 use crate::webrtc::util::vnet::chunk_queue::*;
 use crate::webrtc::util::vnet::interface::*;
 use crate::webrtc::util::vnet::nat::*;
+use crate::webrtc::util::vnet::net::*;
 
 use async_trait::async_trait;
 use ipnet::*;
-use std::net::IpAddr;
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::pin::Pin;
+use std::str::FromStr;
 use std::sync::atomic::AtomicU64;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 use tokio::sync::{mpsc, Mutex};
 
 lazy_static! {
@@ -22,22 +30,51 @@ pub(crate) trait Nic {
     async fn add_addrs_to_interface(&mut self, ifc_name: &str, addrs: &[IpNet]) -> Result<()>;
     async fn on_inbound_chunk(&self, c: Box<dyn Chunk + Send + Sync>);
     async fn get_static_ips(&self) -> Vec<IpAddr>;
+    async fn set_router(&self, r: Arc<Mutex<Router>>) -> Result<()>;
+}
+
+// RouterConfig ...
+#[derive(Default)]
+pub(crate) struct RouterConfig {
+    // name of router. If not specified, a unique name will be assigned.
+    pub(crate) name: String,
+    // cidr notation, like "192.0.2.0/24"
+    pub(crate) cidr: String,
+    // static_ips is an array of static IP addresses to be assigned for this router.
+    // If no static IP address is given, the router will automatically assign
+    // an IP address.
+    // This will be ignored if this router is the root.
+    // With a 1:1 NAT, each entry is "<mapped-ip>/<local-ip>".
+    pub(crate) static_ips: Vec<String>,
+    // queue_size is the max number of chunks in the queue, 0 for unlimited.
+    pub(crate) queue_size: usize,
+    // nat_type is only valid if parent exists. Without it, the router maps endpoint
+    // independently and filters by address and port.
+    pub(crate) nat_type: Option<NatType>,
 }
 
 #[derive(Default)]
 pub(crate) struct RouterInternal {
-    pub(crate) nat: NetworkAddressTranslator, // read-only
+    pub(crate) nat_type: Option<NatType>,           // read-only
+    pub(crate) ipv4net: IpNet,                      // read-only
+    pub(crate) parent: Option<Weak<Mutex<Router>>>, // read-only
+    pub(crate) last_id: u8, // requires mutex [x], used to assign the last digit of IPv4 address
+    pub(crate) nat: Arc<NetworkAddressTranslator>, // read-only
+    pub(crate) nics: HashMap<String, Arc<Mutex<dyn Nic + Send + Sync>>>, // read-only
 }
 
 // Router ...
 #[derive(Default)]
 pub(crate) struct Router {
-    name: String,                      // read-only
-    queue: Arc<ChunkQueue>,            // read-only
-    interfaces: Vec<Interface>,        // read-only
-    static_ips: Vec<IpAddr>,           // read-only
-    done: Option<mpsc::Sender<()>>,    // requires mutex [x]
-    push_ch: Option<mpsc::Sender<()>>, // writer requires mutex
+    name: String,                              // read-only
+    ipv4net: IpNet,                            // read-only
+    queue: Arc<ChunkQueue>,                    // read-only
+    interfaces: Vec<Interface>,                // read-only
+    static_ips: Vec<IpAddr>,                   // read-only
+    static_local_ips: HashMap<IpAddr, IpAddr>, // read-only
+    children: Vec<Arc<Mutex<Router>>>,         // read-only
+    done: Option<mpsc::Sender<()>>,            // requires mutex [x]
+    push_ch: Option<mpsc::Sender<()>>,         // writer requires mutex
     router_internal: Arc<Mutex<RouterInternal>>,
 }
 
@@ -89,9 +126,185 @@ impl Nic for Router {
     async fn get_static_ips(&self) -> Vec<IpAddr> {
         self.static_ips.clone()
     }
+
+    // set_router sets the parent router of this router and sets up the NAT towards it
+    async fn set_router(&self, parent: Arc<Mutex<Router>>) -> Result<()> {
+        let mut router_internal = self.router_internal.lock().await;
+        router_internal.parent = Some(Arc::downgrade(&parent));
+
+        // The NAT maps to the static IPs, or to the address the parent assigned
+        let mapped_ips = if self.static_ips.is_empty() {
+            let mut mapped_ips = vec![];
+            if let Some(ifc) = self.get_interface("eth0").await {
+                for ipnet in ifc.addrs() {
+                    mapped_ips.push(ipnet.addr());
+                }
+            }
+            if mapped_ips.is_empty() {
+                return Err(Error::ErrNoIpaddrEth0);
+            }
+            mapped_ips
+        } else {
+            self.static_ips.clone()
+        };
+        let local_ips = mapped_ips
+            .iter()
+            .filter_map(|ip| self.static_local_ips.get(ip).copied())
+            .collect();
+
+        let nat_type = router_internal.nat_type.unwrap_or(NatType {
+            mapping_behavior: EndpointDependencyType::EndpointIndependent,
+            filtering_behavior: EndpointDependencyType::EndpointAddrPortDependent,
+            ..Default::default()
+        });
+        router_internal.nat = Arc::new(NetworkAddressTranslator::new(NatConfig {
+            name: self.name.clone(),
+            nat_type,
+            mapped_ips,
+            local_ips,
+        })?);
+
+        Ok(())
+    }
 }
 
 impl Router {
+    pub(crate) fn new(config: RouterConfig) -> Result<Self> {
+        let ipv4net: IpNet = config.cidr.parse()?;
+
+        let name = if config.name.is_empty() {
+            format!(
+                "router{}",
+                ROUTER_ID_CTR.fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+            )
+        } else {
+            config.name.clone()
+        };
+
+        // set up network interface, lo0
+        let mut lo0 = Interface::new(LO0_STR.to_owned(), vec![]);
+        if let Ok(ipnet) = Interface::convert(
+            SocketAddr::new(Ipv4Addr::new(127, 0, 0, 1).into(), 0),
+            Some(SocketAddr::new(Ipv4Addr::new(255, 0, 0, 0).into(), 0)),
+        ) {
+            lo0.add_addr(ipnet);
+        }
+
+        // set up network interface, eth0
+        let eth0 = Interface::new("eth0".to_owned(), vec![]);
+
+        let mut static_ips = vec![];
+        let mut static_local_ips = HashMap::new();
+        for ip_str in &config.static_ips {
+            let (mapped, local) = match ip_str.split_once('/') {
+                Some((mapped, local)) => (mapped, Some(local)),
+                None => (ip_str.as_str(), None),
+            };
+            let ip = IpAddr::from_str(mapped)?;
+            if let Some(local) = local {
+                let loc_ip =
+                    IpAddr::from_str(local).map_err(|_| Error::ErrInvalidLocalIpInStaticIps)?;
+                if !ipv4net.contains(&loc_ip) {
+                    return Err(Error::ErrLocalIpBeyondStaticIpsSubset);
+                }
+                static_local_ips.insert(ip, loc_ip);
+            }
+            static_ips.push(ip);
+        }
+
+        if let Some(nat_type) = &config.nat_type {
+            if nat_type.mode == NatMode::Nat1To1 && static_local_ips.len() != static_ips.len() {
+                return Err(Error::ErrLocalIpNoStaticsIpsAssociated);
+            }
+        }
+
+        Ok(Router {
+            name,
+            ipv4net,
+            queue: Arc::new(ChunkQueue::new(config.queue_size)),
+            interfaces: vec![lo0, eth0],
+            static_ips,
+            static_local_ips,
+            children: vec![],
+            done: None,
+            push_ch: None,
+            router_internal: Arc::new(Mutex::new(RouterInternal {
+                nat_type: config.nat_type,
+                ipv4net,
+                ..Default::default()
+            })),
+        })
+    }
+
+    // start starts routing chunks on this router and its child routers
+    pub(crate) fn start(&mut self) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> {
+        if self.done.is_some() {
+            return Box::pin(async move { Err(Error::ErrRouterAlreadyStarted) });
+        }
+
+        let (done_tx, mut done_rx) = mpsc::channel(1);
+        let (push_ch_tx, mut push_ch_rx) = mpsc::channel(1);
+        self.done = Some(done_tx);
+        self.push_ch = Some(push_ch_tx);
+
+        let name = self.name.clone();
+        let ipv4net = self.ipv4net;
+        let queue = Arc::clone(&self.queue);
+        let router_internal = Arc::clone(&self.router_internal);
+        tokio::spawn(async move {
+            loop {
+                while let Some(c) = queue.pop().await {
+                    Router::route(&name, ipv4net, &router_internal, c).await;
+                }
+
+                tokio::select! {
+                    _ = push_ch_rx.recv() => {},
+                    _ = done_rx.recv() => break,
+                }
+            }
+        });
+
+        let children = self.children.clone();
+        Box::pin(async move {
+            for child in children {
+                let mut child = child.lock().await;
+                child.start().await?;
+            }
+            Ok(())
+        })
+    }
+
+    // stop stops routing chunks on this router and its child routers
+    pub(crate) fn stop(&mut self) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> {
+        if self.done.take().is_none() {
+            return Box::pin(async move { Err(Error::ErrRouterAlreadyStopped) });
+        }
+        self.push_ch = None;
+
+        let children = self.children.clone();
+        Box::pin(async move {
+            for child in children {
+                let mut child = child.lock().await;
+                child.stop().await?;
+            }
+            Ok(())
+        })
+    }
+
+    // add_router adds a child router, which still needs set_router to link it back
+    pub(crate) async fn add_router(&mut self, router: Arc<Mutex<Router>>) -> Result<()> {
+        // Router is a NIC. Add it as a NIC so that packets are routed to this child router.
+        let nic = Arc::clone(&router) as Arc<Mutex<dyn Nic + Send + Sync>>;
+        self.children.push(router);
+        self.add_net(nic).await
+    }
+
+    // add_net adds a NIC, which still needs set_router to link it back
+    pub(crate) async fn add_net(&mut self, nic: Arc<Mutex<dyn Nic + Send + Sync>>) -> Result<()> {
+        let mut router_internal = self.router_internal.lock().await;
+        router_internal.add_nic(nic).await
+    }
+
     pub(crate) async fn push(&self, mut c: Box<dyn Chunk + Send + Sync>) {
         log::debug!("[{}] route {}", self.name, c);
         if self.done.is_some() {
@@ -108,4 +321,106 @@ impl Router {
             log::warn!("router is done");
         }
     }
+
+    // route forwards c to the NIC of its destination in the subnet, or through the NAT to
+    // the parent router
+    async fn route(
+        name: &str,
+        ipv4net: IpNet,
+        router_internal: &Arc<Mutex<RouterInternal>>,
+        c: Box<dyn Chunk + Send + Sync>,
+    ) {
+        let dst_ip = c.get_destination_ip();
+
+        // check if the destination is in our subnet
+        if ipv4net.contains(&dst_ip) {
+            // search for the destination NIC
+            let nic = {
+                let ri = router_internal.lock().await;
+                ri.nics.get(&dst_ip.to_string()).map(Arc::clone)
+            };
+            if let Some(nic) = nic {
+                // found the NIC, forward the chunk to the NIC.
+                let ni = nic.lock().await;
+                ni.on_inbound_chunk(c).await;
+            } else {
+                // NIC not found. drop it.
+                log::debug!("[{}] {} unreachable", name, c);
+            }
+        } else {
+            // the destination is outside of this subnet, is this WAN?
+            let (parent, nat) = {
+                let ri = router_internal.lock().await;
+                (
+                    ri.parent.as_ref().and_then(Weak::upgrade),
+                    Arc::clone(&ri.nat),
+                )
+            };
+            if let Some(parent) = parent {
+                // Pass it to the parent via NAT
+                match nat.translate_outbound(&*c).await {
+                    Ok(Some(to_parent)) => {
+                        let p = parent.lock().await;
+                        p.push(to_parent).await;
+                    }
+                    Ok(None) => {}
+                    Err(err) => log::warn!("[{}] {}", name, err),
+                }
+            } else {
+                // this WAN. No route for this chunk
+                log::debug!("[{}] no route found for {}", name, c);
+            }
+        }
+    }
+}
+
+impl RouterInternal {
+    // caller must hold the mutex
+    pub(crate) async fn add_nic(&mut self, nic: Arc<Mutex<dyn Nic + Send + Sync>>) -> Result<()> {
+        let mut ips = {
+            let ni = nic.lock().await;
+            ni.get_static_ips().await
+        };
+
+        if ips.is_empty() {
+            // assign an IP address
+            let ip = self.assign_ip_address()?;
+            log::debug!("assign_ip_address: {}", ip);
+            ips.push(ip);
+        }
+
+        let mut ipnets = vec![];
+        for ip in &ips {
+            if !self.ipv4net.contains(ip) {
+                return Err(Error::ErrStaticIpIsBeyondSubnet);
+            }
+            self.nics.insert(ip.to_string(), Arc::clone(&nic));
+            ipnets.push(IpNet::from_str(&format!(
+                "{}/{}",
+                ip,
+                self.ipv4net.prefix_len()
+            ))?);
+        }
+
+        let mut ni = nic.lock().await;
+        ni.add_addrs_to_interface("eth0", &ipnets).await
+    }
+
+    // caller must hold the mutex
+    fn assign_ip_address(&mut self) -> Result<IpAddr> {
+        // See: https://stackoverflow.com/questions/14915188/ip-address-ending-with-zero
+        if self.last_id == 0xfe {
+            return Err(Error::ErrAddressSpaceExhausted);
+        }
+
+        match self.ipv4net.network() {
+            IpAddr::V4(network) => {
+                self.last_id += 1;
+                let mut ip = network.octets();
+                ip[3] = self.last_id;
+                Ok(IpAddr::V4(Ipv4Addr::from(ip)))
+            }
+            IpAddr::V6(_) => Err(Error::ErrAddressSpaceExhausted),
+        }
+    }
 }
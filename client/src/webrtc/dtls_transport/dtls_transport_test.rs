@@ -0,0 +1,86 @@
+use std::io::Cursor;
+
+use super::*;
+use crate::webrtc::sdp::description::session::SessionDescription;
+
+fn test_psk() -> DtlsPsk {
+    DtlsPsk {
+        identity_hint: b"client-1".to_vec(),
+        key: vec![0xab; 16],
+    }
+}
+
+#[test]
+fn test_psk_callback_matches_identity() {
+    let callback = psk_callback(&test_psk());
+
+    // The client's identity and the server's hint
+    assert_eq!(callback(b"client-1").unwrap(), vec![0xab; 16]);
+    // A server without a hint
+    assert_eq!(callback(b"").unwrap(), vec![0xab; 16]);
+}
+
+#[test]
+fn test_psk_callback_rejects_other_identity() {
+    let callback = psk_callback(&test_psk());
+
+    assert_eq!(
+        callback(b"client-2"),
+        Err(crate::webrtc::dtls::Error::ErrPskIdentityMismatch)
+    );
+}
+
+// The DTLS role the transport takes for a remote description with the given
+// a=setup line, under the given ICE role
+async fn role_for(setup: Option<&str>, ice_role: RTCIceRole) -> DTLSRole {
+    let mut sdp = "v=0\r\n\
+                   o=- 1 1 IN IP4 0.0.0.0\r\n\
+                   s=-\r\n\
+                   t=0 0\r\n\
+                   m=application 9 UDP/DTLS/SCTP webrtc-datachannel\r\n\
+                   c=IN IP4 0.0.0.0\r\n"
+        .to_owned();
+    if let Some(setup) = setup {
+        sdp += &format!("a=setup:{}\r\n", setup);
+    }
+    let description = SessionDescription::unmarshal(&mut Cursor::new(sdp.as_bytes())).unwrap();
+
+    let ice_transport = Arc::new(RTCIceTransport::default());
+    ice_transport.set_role(ice_role).await;
+    let transport = RTCDtlsTransport::new(
+        ice_transport,
+        vec![],
+        None,
+        DtlsPolicy::default(),
+        DtlsSessionCache::default(),
+    );
+    *transport.remote_parameters.lock().await = DTLSParameters {
+        role: DTLSRole::from(&description),
+        fingerprints: vec![],
+    };
+    transport.role().await
+}
+
+#[tokio::test]
+async fn test_role_inverts_remote_setup() {
+    for ice_role in [RTCIceRole::Controlling, RTCIceRole::Controlled] {
+        // The server connects to us, we accept as the DTLS server
+        assert_eq!(role_for(Some("active"), ice_role).await, DTLSRole::Server);
+        // The server waits for us, we connect as the DTLS client
+        assert_eq!(role_for(Some("passive"), ice_role).await, DTLSRole::Client);
+    }
+}
+
+#[tokio::test]
+async fn test_role_follows_ice_role_without_remote_setup() {
+    for setup in [Some("actpass"), None] {
+        assert_eq!(
+            role_for(setup, RTCIceRole::Controlling).await,
+            DTLSRole::Server
+        );
+        assert_eq!(
+            role_for(setup, RTCIceRole::Controlled).await,
+            DTLSRole::Client
+        );
+    }
+}
@@ -3,7 +3,12 @@ use std::pin::Pin;
 use std::sync::atomic::{AtomicU8, Ordering};
 use std::sync::Arc;
 
-use crate::webrtc::dtls::config::ClientAuthType;
+use crate::socket_config::{DtlsPolicy, DtlsPsk, DtlsSessionCache};
+use crate::webrtc::dtls::alert::{Alert, AlertLevel};
+use crate::webrtc::dtls::config::{
+    random_connection_id_generator, ClientAuthType, ExtendedMasterSecretType, PskCallback,
+    DEFAULT_CONNECTION_ID_LENGTH,
+};
 use crate::webrtc::dtls::conn::DTLSConn;
 use crate::webrtc::util::Conn;
 use tokio::sync::Mutex;
@@ -13,6 +18,7 @@ use dtls_role::*;
 use crate::webrtc::dtls_transport::dtls_parameters::DTLSParameters;
 use crate::webrtc::dtls_transport::dtls_transport_state::RTCDtlsTransportState;
 use crate::webrtc::error::{Error, Result};
+use crate::webrtc::ice_transport::ice_role::RTCIceRole;
 use crate::webrtc::ice_transport::ice_transport_state::RTCIceTransportState;
 use crate::webrtc::ice_transport::RTCIceTransport;
 use crate::webrtc::mux::mux_func::match_dtls;
@@ -23,8 +29,16 @@ pub(crate) mod dtls_parameters;
 pub(crate) mod dtls_role;
 pub(crate) mod dtls_transport_state;
 
+#[cfg(test)]
+mod dtls_transport_test;
+
+/// The alert is the fatal or close_notify alert of the peer that ended the
+/// connection, if any
 pub(crate) type OnDTLSTransportStateChangeHdlrFn = Box<
-    dyn (FnMut(RTCDtlsTransportState) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>)
+    dyn (FnMut(
+            RTCDtlsTransportState,
+            Option<Alert>,
+        ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>)
         + Send
         + Sync,
 >;
@@ -37,22 +51,47 @@ pub(crate) type OnDTLSTransportStateChangeHdlrFn = Box<
 pub(crate) struct RTCDtlsTransport {
     pub(crate) ice_transport: Arc<RTCIceTransport>,
     pub(crate) certificates: Vec<RTCCertificate>,
+    pub(crate) psk: Option<DtlsPsk>,
+    pub(crate) policy: DtlsPolicy,
+    pub(crate) session_cache: DtlsSessionCache,
 
     pub(crate) remote_parameters: Mutex<DTLSParameters>,
-    pub(crate) state: AtomicU8, //DTLSTransportState,
+    pub(crate) state: Arc<AtomicU8>, //DTLSTransportState,
     pub(crate) on_state_change_handler: Arc<Mutex<Option<OnDTLSTransportStateChangeHdlrFn>>>,
+    // Alert of the peer that ended the connection, replayed to a late handler
+    pub(crate) remote_alert: Arc<Mutex<Option<Alert>>>,
     pub(crate) conn: Mutex<Option<Arc<DTLSConn>>>,
 }
 
+// psk_callback returns the key for the identity the peer announced. The DTLS
+// server announces the identity as its hint and the client as its PSK identity,
+// the server may also leave its hint empty (RFC 4279 Section 2).
+fn psk_callback(psk: &DtlsPsk) -> PskCallback {
+    let identity = psk.identity_hint.clone();
+    let key = psk.key.clone();
+    Arc::new(move |hint: &[u8]| {
+        if !hint.is_empty() && hint != identity.as_slice() {
+            return Err(crate::webrtc::dtls::Error::ErrPskIdentityMismatch);
+        }
+        Ok(key.clone())
+    })
+}
+
 impl RTCDtlsTransport {
     pub(crate) fn new(
         ice_transport: Arc<RTCIceTransport>,
         certificates: Vec<RTCCertificate>,
+        psk: Option<DtlsPsk>,
+        policy: DtlsPolicy,
+        session_cache: DtlsSessionCache,
     ) -> Self {
         RTCDtlsTransport {
             ice_transport,
             certificates,
-            state: AtomicU8::new(RTCDtlsTransportState::New as u8),
+            psk,
+            policy,
+            session_cache,
+            state: Arc::new(AtomicU8::new(RTCDtlsTransportState::New as u8)),
             ..Default::default()
         }
     }
@@ -64,13 +103,48 @@ impl RTCDtlsTransport {
 
     /// state_change requires the caller holds the lock
     async fn state_change(&self, state: RTCDtlsTransportState) {
-        self.state.store(state as u8, Ordering::SeqCst);
-        let mut handler = self.on_state_change_handler.lock().await;
+        RTCDtlsTransport::do_state_change(
+            &self.state,
+            &self.on_state_change_handler,
+            &self.remote_alert,
+            state,
+            None,
+        )
+        .await;
+    }
+
+    async fn do_state_change(
+        state: &AtomicU8,
+        on_state_change_handler: &Mutex<Option<OnDTLSTransportStateChangeHdlrFn>>,
+        remote_alert: &Mutex<Option<Alert>>,
+        new_state: RTCDtlsTransportState,
+        alert: Option<Alert>,
+    ) {
+        // Changed under the handler lock, so a handler set meanwhile sees
+        // either the old state or is called with the new one
+        let mut handler = on_state_change_handler.lock().await;
+        state.store(new_state as u8, Ordering::SeqCst);
+        if alert.is_some() {
+            *remote_alert.lock().await = alert;
+        }
         if let Some(f) = &mut *handler {
-            f(state).await;
+            f(new_state, alert).await;
         }
     }
 
+    /// on_state_change sets a handler that is fired when the DTLS transport state
+    /// changes, along with the alert of the peer when it closes the connection.
+    /// A transport that already closed or failed fires it right away.
+    pub(crate) async fn on_state_change(&self, mut f: OnDTLSTransportStateChangeHdlrFn) {
+        let mut on_state_change_handler = self.on_state_change_handler.lock().await;
+        let state = self.state();
+        if state == RTCDtlsTransportState::Closed || state == RTCDtlsTransportState::Failed {
+            let alert = *self.remote_alert.lock().await;
+            f(state, alert).await;
+        }
+        *on_state_change_handler = Some(f);
+    }
+
     /// state returns the current dtls_transport transport state.
     pub(crate) fn state(&self) -> RTCDtlsTransportState {
         self.state.load(Ordering::SeqCst).into()
@@ -86,28 +160,62 @@ impl RTCDtlsTransport {
             return Err(Error::ErrInvalidDTLSStart);
         }
 
+        // A session is only resumed with the peer that owns the certificate
+        let session_key = remote_parameters
+            .fingerprints
+            .first()
+            .map(|fingerprint| format!("{} {}", fingerprint.algorithm, fingerprint.value))
+            .unwrap_or_default()
+            .into_bytes();
+
         {
             let mut rp = self.remote_parameters.lock().await;
             *rp = remote_parameters;
         }
 
-        let certificate = if let Some(cert) = self.certificates.first() {
-            cert.certificate.clone()
+        let mut config = crate::webrtc::dtls::config::Config {
+            cipher_suites: self.policy.cipher_suites.clone(),
+            elliptic_curves: self.policy.curves.clone(),
+            extended_master_secret: if self.policy.require_extended_master_secret {
+                ExtendedMasterSecretType::Require
+            } else {
+                ExtendedMasterSecretType::Request
+            },
+            mtu: self.policy.mtu.unwrap_or_default(),
+            replay_protection_window: self.policy.replay_window.unwrap_or_default(),
+            handshake_timeout: self.policy.handshake_timeout.unwrap_or_default(),
+            max_flight_interval: self.policy.max_flight_interval.unwrap_or_default(),
+            srtp_protection_profiles: vec![],
+            insecure_skip_verify: true,
+            connection_id_generator: Some(random_connection_id_generator(
+                DEFAULT_CONNECTION_ID_LENGTH,
+            )),
+            session_store: Some(self.session_cache.0.clone()),
+            session_key,
+            key_log_path: self
+                .policy
+                .key_log_path
+                .as_ref()
+                .map(|path| path.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            ..Default::default()
+        };
+
+        if let Some(psk) = &self.psk {
+            // PSK-only: the key authenticates both peers, DTLS 1.3 is not
+            // offered since its PSK key exchange is not implemented
+            config.psk = Some(psk_callback(psk));
+            config.psk_identity_hint = Some(psk.identity_hint.clone());
+        } else if let Some(cert) = self.certificates.first() {
+            config.certificates = vec![cert.certificate.clone()];
+            config.client_auth = ClientAuthType::RequireAnyClientCert;
+            config.enable_dtls13 = self.policy.enable_dtls13;
         } else {
             return Err(Error::ErrNonCertificate);
-        };
+        }
         self.state_change(RTCDtlsTransportState::Connecting).await;
 
-        Ok((
-            DTLSRole::Client,
-            crate::webrtc::dtls::config::Config {
-                certificates: vec![certificate],
-                srtp_protection_profiles: vec![],
-                client_auth: ClientAuthType::RequireAnyClientCert,
-                insecure_skip_verify: true,
-                ..Default::default()
-            },
-        ))
+        Ok((self.role().await, config))
     }
 
     /// start DTLS transport negotiation with the parameters of the remote DTLS transport
@@ -115,14 +223,15 @@ impl RTCDtlsTransport {
         let dtls_conn_result = if let Some(dtls_endpoint) =
             self.ice_transport.new_endpoint(Box::new(match_dtls)).await
         {
-            let (_, dtls_config) = self.prepare_transport(remote_parameters).await?;
+            let (role, dtls_config) = self.prepare_transport(remote_parameters).await?;
+            log::trace!("starting DTLS as {}", role);
 
             // Connect as DTLS Client/Server, function is blocking and we
             // must not hold the DTLSTransport lock
             crate::webrtc::dtls::conn::DTLSConn::new(
                 dtls_endpoint as Arc<dyn Conn + Send + Sync>,
                 dtls_config,
-                true,
+                role == DTLSRole::Client,
                 None,
             )
             .await
@@ -133,22 +242,75 @@ impl RTCDtlsTransport {
         };
 
         let dtls_conn = match dtls_conn_result {
-            Ok(dtls_conn) => dtls_conn,
+            Ok(dtls_conn) => Arc::new(dtls_conn),
             Err(err) => {
-                self.state_change(RTCDtlsTransportState::Failed).await;
+                let alert = match &err {
+                    crate::webrtc::dtls::Error::ErrAlertReceived(alert) => Some(*alert),
+                    _ => None,
+                };
+                RTCDtlsTransport::do_state_change(
+                    &self.state,
+                    &self.on_state_change_handler,
+                    &self.remote_alert,
+                    RTCDtlsTransportState::Failed,
+                    alert,
+                )
+                .await;
                 return Err(err.into());
             }
         };
 
         {
             let mut conn = self.conn.lock().await;
-            *conn = Some(Arc::new(dtls_conn));
+            *conn = Some(Arc::clone(&dtls_conn));
         }
         self.state_change(RTCDtlsTransportState::Connected).await;
 
+        // A close_notify of the peer closes the transport, a fatal alert fails it
+        let state = Arc::clone(&self.state);
+        let on_state_change_handler = Arc::clone(&self.on_state_change_handler);
+        let remote_alert = Arc::clone(&self.remote_alert);
+        tokio::spawn(async move {
+            let alert = dtls_conn.remote_alert().await;
+            let new_state = match alert {
+                Some(alert) if alert.alert_level == AlertLevel::Fatal => {
+                    RTCDtlsTransportState::Failed
+                }
+                _ => RTCDtlsTransportState::Closed,
+            };
+            RTCDtlsTransport::do_state_change(
+                &state,
+                &on_state_change_handler,
+                &remote_alert,
+                new_state,
+                alert,
+            )
+            .await;
+        });
+
         Ok(())
     }
 
+    /// role returns the DTLS role to take: the inverse of the role the remote
+    /// description asks for with a=setup (RFC 5763 Section 5), otherwise the ICE
+    /// controlling agent acts as the DTLS server
+    pub(crate) async fn role(&self) -> DTLSRole {
+        {
+            let remote_parameters = self.remote_parameters.lock().await;
+            match remote_parameters.role {
+                DTLSRole::Client => return DTLSRole::Server,
+                DTLSRole::Server => return DTLSRole::Client,
+                _ => {}
+            };
+        }
+
+        if self.ice_transport.role().await == RTCIceRole::Controlling {
+            DTLSRole::Server
+        } else {
+            DTLSRole::Client
+        }
+    }
+
     pub(crate) fn ensure_ice_conn(&self) -> Result<()> {
         if self.ice_transport.state() == RTCIceTransportState::New {
             Err(Error::ErrICEConnectionNotStarted)
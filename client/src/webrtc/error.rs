@@ -92,6 +92,10 @@ pub(crate) enum Error {
     ErrSignalingStateProposedTransitionInvalid,
     #[error("ICETransport can only be called in ICETransportStateNew")]
     ErrICETransportNotInNew,
+    /// ErrRenegotiationDtlsParametersChanged indicates a renegotiation, e.g. the answer to an
+    /// ICE restart, changed the remote fingerprint or DTLS role of the running DTLS session
+    #[error("renegotiation changed the remote DTLS fingerprint or role")]
+    ErrRenegotiationDtlsParametersChanged,
     #[error("SCTP is not established")]
     ErrSCTPNotEstablished,
 
@@ -1,16 +1,19 @@
+use crate::socket_config::{IceNetworkPolicy, IpFamily};
 use crate::webrtc::error::{Error, Result};
 use crate::webrtc::ice_transport::ice_candidate::*;
 use crate::webrtc::ice_transport::ice_gatherer_state::RTCIceGathererState;
 use crate::webrtc::ice_transport::ice_parameters::RTCIceParameters;
 
-use crate::webrtc::ice::agent::Agent;
-use crate::webrtc::ice::candidate::{Candidate, CandidateType};
+use crate::webrtc::ice::agent::{Agent, OnNetworkChangeHdlrFn};
+use crate::webrtc::ice::candidate::Candidate;
 
 use crate::webrtc::ice::mdns::MulticastDnsMode;
+use crate::webrtc::ice::url::Url;
 use std::future::Future;
 use std::pin::Pin;
-use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
 
 pub(crate) type OnLocalCandidateHdlrFn = Box<
@@ -34,10 +37,16 @@ pub(crate) type OnGatheringCompleteHdlrFn =
 /// exchanged in signaling.
 #[derive(Default)]
 pub(crate) struct RTCIceGatherer {
+    pub(crate) validated_servers: Vec<Url>,
+    pub(crate) consent_expiry: Option<Duration>,
+    pub(crate) network_policy: Arc<IceNetworkPolicy>,
+    pub(crate) mdns_mode: MulticastDnsMode,
     pub(crate) state: Arc<AtomicU8>, //ICEGathererState,
     pub(crate) agent: Mutex<Option<Arc<crate::webrtc::ice::agent::Agent>>>,
 
     pub(crate) on_local_candidate_handler: Arc<Mutex<Option<OnLocalCandidateHdlrFn>>>,
+    // Whether the end of candidates went through on_local_candidate_handler
+    pub(crate) end_of_candidates_reported: Arc<AtomicBool>,
     pub(crate) on_state_change_handler: Arc<Mutex<Option<OnICEGathererStateChangeHdlrFn>>>,
 
     // Used for gathering_complete_promise
@@ -45,8 +54,17 @@ pub(crate) struct RTCIceGatherer {
 }
 
 impl RTCIceGatherer {
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(
+        validated_servers: Vec<Url>,
+        consent_expiry: Option<Duration>,
+        network_policy: IceNetworkPolicy,
+        mdns_mode: MulticastDnsMode,
+    ) -> Self {
         RTCIceGatherer {
+            validated_servers,
+            consent_expiry,
+            network_policy: Arc::new(network_policy),
+            mdns_mode,
             state: Arc::new(AtomicU8::new(RTCIceGathererState::New as u8)),
             ..Default::default()
         }
@@ -60,33 +78,57 @@ impl RTCIceGatherer {
             }
         }
 
-        let mut mdns_mode = MulticastDnsMode::Unspecified;
-        if mdns_mode != crate::webrtc::ice::mdns::MulticastDnsMode::Disabled
-            && mdns_mode != crate::webrtc::ice::mdns::MulticastDnsMode::QueryAndGather
-        {
-            // If enum is in state we don't recognized default to MulticastDNSModeQueryOnly
-            mdns_mode = crate::webrtc::ice::mdns::MulticastDnsMode::QueryOnly;
-        }
-
         let mut config = crate::webrtc::ice::agent::agent_config::AgentConfig {
             lite: false,
             disconnected_timeout: None,
             failed_timeout: None,
             keepalive_interval: None,
+            consent_expiry: self.consent_expiry,
             candidate_types: Vec::new(),
             host_acceptance_min_wait: None,
             srflx_acceptance_min_wait: None,
             prflx_acceptance_min_wait: None,
             relay_acceptance_min_wait: None,
-            nat_1to1_ip_candidate_type: CandidateType::Unspecified,
+            nat_1to1_ip_candidate_type: self.network_policy.nat_1to1_candidate_type.into(),
+            nat_1to1_ips: self.network_policy.nat_1to1_ips.clone(),
+            interface_filter: {
+                let network_policy = Arc::clone(&self.network_policy);
+                Arc::new(Some(Box::new(move |name: &str| {
+                    network_policy.allows_interface(name)
+                })))
+            },
+            ip_filter: {
+                let network_policy = Arc::clone(&self.network_policy);
+                Arc::new(Some(Box::new(move |ip| network_policy.allows_ip(ip))))
+            },
+            prefer_ipv4: self.network_policy.prefer_ipv4,
+            family_head_start: self.network_policy.family_head_start,
+            port_min: self
+                .network_policy
+                .udp_port_range
+                .as_ref()
+                .map_or(0, |range| *range.start()),
+            port_max: self
+                .network_policy
+                .udp_port_range
+                .as_ref()
+                .map_or(0, |range| *range.end()),
             net: None,
-            multicast_dns_mode: mdns_mode,
+            multicast_dns_mode: self.mdns_mode,
+            urls: self.validated_servers.clone(),
             //TODO: TCPMux:                 self.setting_engine.iceTCPMux,
             //TODO: ProxyDialer:            self.setting_engine.iceProxyDialer,
             ..Default::default()
         };
 
-        let requested_network_types = crate::webrtc::ice::network_type::supported_network_types();
+        let ip_family = self.network_policy.ip_family;
+        let requested_network_types = crate::webrtc::ice::network_type::supported_network_types()
+            .into_iter()
+            .filter(|typ| match ip_family {
+                IpFamily::Both => true,
+                IpFamily::V4 => typ.is_ipv4(),
+                IpFamily::V6 => typ.is_ipv6(),
+            });
 
         config.network_types.extend(requested_network_types);
 
@@ -104,12 +146,16 @@ impl RTCIceGatherer {
     pub(crate) async fn gather(&self) -> Result<()> {
         self.create_agent().await?;
         self.set_state(RTCIceGathererState::Gathering).await;
+        // An ICE restart gathers again, its end of candidates is reported anew
+        self.end_of_candidates_reported
+            .store(false, Ordering::SeqCst);
 
         if let Some(agent) = self.get_agent().await {
             let state = Arc::clone(&self.state);
             let on_local_candidate_handler = Arc::clone(&self.on_local_candidate_handler);
             let on_state_change_handler = Arc::clone(&self.on_state_change_handler);
             let on_gathering_complete_handler = Arc::clone(&self.on_gathering_complete_handler);
+            let end_of_candidates_reported = Arc::clone(&self.end_of_candidates_reported);
 
             agent
                 .on_candidate(Box::new(
@@ -120,6 +166,8 @@ impl RTCIceGatherer {
                         let on_state_change_handler_clone = Arc::clone(&on_state_change_handler);
                         let on_gathering_complete_handler_clone =
                             Arc::clone(&on_gathering_complete_handler);
+                        let end_of_candidates_reported_clone =
+                            Arc::clone(&end_of_candidates_reported);
 
                         Box::pin(async move {
                             if let Some(cand) = candidate {
@@ -153,6 +201,7 @@ impl RTCIceGatherer {
                                 {
                                     let mut on_local_candidate_handler =
                                         on_local_candidate_handler_clone.lock().await;
+                                    end_of_candidates_reported_clone.store(true, Ordering::SeqCst);
                                     if let Some(handler) = &mut *on_local_candidate_handler {
                                         handler(None).await;
                                     }
@@ -169,6 +218,24 @@ impl RTCIceGatherer {
         Ok(())
     }
 
+    /// Sets a handler that is fired with each local candidate as it is gathered, and with `None`
+    /// once gathering is complete. Candidates gathered before it was set are replayed to it, so a
+    /// candidate gathered meanwhile may be reported twice.
+    pub(crate) async fn on_local_candidate(&self, mut f: OnLocalCandidateHdlrFn) {
+        let mut on_local_candidate_handler = self.on_local_candidate_handler.lock().await;
+        if self.state() != RTCIceGathererState::New {
+            if let Ok(candidates) = self.get_local_candidates().await {
+                for c in candidates {
+                    f(Some(c)).await;
+                }
+            }
+            if self.end_of_candidates_reported.load(Ordering::SeqCst) {
+                f(None).await;
+            }
+        }
+        *on_local_candidate_handler = Some(f);
+    }
+
     /// get_local_parameters returns the ICE parameters of the ICEGatherer.
     pub(crate) async fn get_local_parameters(&self) -> Result<RTCIceParameters> {
         self.create_agent().await?;
@@ -212,6 +279,18 @@ impl RTCIceGatherer {
         }
     }
 
+    /// Sets a handler that is fired when the addresses of the host's interfaces change.
+    pub(crate) async fn on_network_change(&self, f: OnNetworkChangeHdlrFn) -> Result<()> {
+        self.create_agent().await?;
+
+        if let Some(agent) = self.get_agent().await {
+            agent.on_network_change(f).await;
+            Ok(())
+        } else {
+            Err(Error::ErrICEAgentNotExist)
+        }
+    }
+
     pub(crate) async fn get_agent(&self) -> Option<Arc<Agent>> {
         let agent = self.agent.lock().await;
         agent.clone()
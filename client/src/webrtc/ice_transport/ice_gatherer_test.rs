@@ -0,0 +1,175 @@
+use super::ice_candidate::RTCIceCandidate;
+use super::ice_candidate_type::RTCIceCandidateType;
+use super::ice_gatherer::*;
+use super::ice_protocol::RTCIceProtocol;
+use crate::socket_config::{IceNetworkPolicy, IpFamily, Nat1To1CandidateType};
+use crate::webrtc::ice::mdns::MulticastDnsMode;
+use crate::webrtc::ice::url::Url;
+use crate::webrtc::stun::{error_code::CODE_BAD_REQUEST, message::*};
+
+use std::net::IpAddr;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::time::{timeout, Duration};
+
+fn new_gatherer(mdns_mode: MulticastDnsMode) -> RTCIceGatherer {
+    let network_policy = IceNetworkPolicy {
+        ip_family: IpFamily::V4,
+        ..Default::default()
+    };
+    RTCIceGatherer::new(vec![], None, network_policy, mdns_mode)
+}
+
+// gather_candidates gathers until gathering completes and returns the
+// candidates
+async fn gather_candidates(gatherer: &RTCIceGatherer) -> Vec<RTCIceCandidate> {
+    let (candidate_tx, mut candidate_rx) = mpsc::unbounded_channel();
+    gatherer
+        .on_local_candidate(Box::new(move |candidate| {
+            let _ = candidate_tx.send(candidate);
+            Box::pin(async {})
+        }))
+        .await;
+    gatherer.gather().await.unwrap();
+
+    let mut candidates = vec![];
+    while let Some(candidate) = timeout(Duration::from_secs(10), candidate_rx.recv())
+        .await
+        .unwrap()
+        .unwrap()
+    {
+        candidates.push(candidate);
+    }
+    candidates
+}
+
+async fn gather_host_candidates(gatherer: &RTCIceGatherer) -> Vec<RTCIceCandidate> {
+    gather_candidates(gatherer)
+        .await
+        .into_iter()
+        .filter(|candidate| candidate.typ == RTCIceCandidateType::Host)
+        .collect()
+}
+
+#[tokio::test]
+async fn test_ice_gatherer_mdns_query_and_gather() {
+    let gatherer = new_gatherer(MulticastDnsMode::QueryAndGather);
+    let candidates = gather_host_candidates(&gatherer).await;
+
+    let agent = gatherer.get_agent().await.unwrap();
+    assert_eq!(agent.mdns_mode, MulticastDnsMode::QueryAndGather);
+    assert!(agent.mdns_conn.is_some());
+    assert!(!candidates.is_empty());
+    for candidate in &candidates {
+        assert_eq!(candidate.address, agent.mdns_name);
+    }
+
+    // The name is answered on the local network
+    let querier = crate::webrtc::mdns::conn::DnsConn::server(vec![]).unwrap();
+    let addr = querier.query(&agent.mdns_name).await.unwrap();
+    assert!(addr.is_ipv4());
+    querier.close().await.unwrap();
+
+    agent.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_ice_gatherer_mdns_disabled() {
+    let gatherer = new_gatherer(MulticastDnsMode::Disabled);
+    let candidates = gather_host_candidates(&gatherer).await;
+
+    let agent = gatherer.get_agent().await.unwrap();
+    assert_eq!(agent.mdns_mode, MulticastDnsMode::Disabled);
+    assert!(agent.mdns_conn.is_none());
+    for candidate in &candidates {
+        assert!(candidate.address.parse::<std::net::IpAddr>().is_ok());
+    }
+
+    agent.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_ice_gatherer_nat_1to1_srflx() {
+    let network_policy = IceNetworkPolicy {
+        ip_family: IpFamily::V4,
+        nat_1to1_ips: vec!["203.0.113.1".to_owned()],
+        nat_1to1_candidate_type: Nat1To1CandidateType::ServerReflexive,
+        ..Default::default()
+    };
+    let gatherer = RTCIceGatherer::new(vec![], None, network_policy, MulticastDnsMode::Disabled);
+    let candidates = gather_candidates(&gatherer).await;
+
+    // Every UDP host candidate keeps its address and gets a srflx candidate
+    // with the public one
+    let hosts: Vec<_> = candidates
+        .iter()
+        .filter(|c| c.typ == RTCIceCandidateType::Host && c.protocol == RTCIceProtocol::Udp)
+        .collect();
+    let srflxs: Vec<_> = candidates
+        .iter()
+        .filter(|c| c.typ == RTCIceCandidateType::Srflx)
+        .collect();
+    assert!(!hosts.is_empty());
+    assert_eq!(hosts.len(), srflxs.len());
+    for host in &hosts {
+        assert_ne!(host.address, "203.0.113.1");
+        assert!(srflxs
+            .iter()
+            .any(|srflx| srflx.related_address == host.address));
+    }
+    for srflx in &srflxs {
+        assert_eq!(srflx.address, "203.0.113.1");
+        assert_eq!(srflx.port, srflx.related_port);
+    }
+
+    gatherer.get_agent().await.unwrap().close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_ice_gatherer_turn_socket_policy() {
+    // Stand-in TURN server that reports where the allocation came from and
+    // rejects it
+    let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut url = Url::parse_url(&format!("turn:{}", server.local_addr().unwrap())).unwrap();
+    url.username = "user".to_owned();
+    url.password = "pass".to_owned();
+    let (source_tx, mut source_rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let mut buf = vec![0u8; 1500];
+        while let Ok((n, from)) = server.recv_from(&mut buf).await {
+            let mut m = Message::new();
+            m.raw = buf[..n].to_vec();
+            if m.decode().is_err() {
+                continue;
+            }
+            let _ = source_tx.send(from);
+
+            let mut out = Message::new();
+            out.build(&[
+                Box::new(m.clone()),
+                Box::new(MessageType::new(m.typ.method, CLASS_ERROR_RESPONSE)),
+                Box::new(CODE_BAD_REQUEST),
+            ])
+            .unwrap();
+            let _ = server.send_to(&out.raw, from).await;
+        }
+    });
+
+    let network_policy = IceNetworkPolicy {
+        ip_family: IpFamily::V4,
+        udp_port_range: Some(41000..=41099),
+        ..Default::default()
+    };
+    let gatherer = RTCIceGatherer::new(vec![url], None, network_policy, MulticastDnsMode::Disabled);
+    let hosts = gather_host_candidates(&gatherer).await;
+
+    // The allocation is sent from an address the host candidates are
+    // gathered on, within the port range
+    let source = source_rx.recv().await.unwrap();
+    assert!(hosts
+        .iter()
+        .any(|host| host.address.parse::<IpAddr>().unwrap() == source.ip()));
+    assert!((41000..=41099).contains(&source.port()));
+
+    gatherer.get_agent().await.unwrap().close().await.unwrap();
+}
@@ -33,6 +33,9 @@ pub(crate) mod ice_protocol;
 pub(crate) mod ice_role;
 pub(crate) mod ice_transport_state;
 
+#[cfg(test)]
+mod ice_gatherer_test;
+
 pub(crate) type OnConnectionStateChangeHdlrFn = Box<
     dyn (FnMut(RTCIceTransportState) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>)
         + Send
@@ -189,7 +192,19 @@ impl RTCIceTransport {
         *on_connection_state_change_handler = Some(f);
     }
 
-    /// adds a candidate associated with the remote ICETransport.
+    /// on_selected_candidate_pair_change sets a handler that is fired when a
+    /// candidate pair is selected, and again when another pair is nominated.
+    pub(crate) async fn on_selected_candidate_pair_change(
+        &self,
+        f: OnSelectedCandidatePairChangeHdlrFn,
+    ) {
+        let mut on_selected_candidate_pair_change_handler =
+            self.on_selected_candidate_pair_change_handler.lock().await;
+        *on_selected_candidate_pair_change_handler = Some(f);
+    }
+
+    /// adds a candidate associated with the remote ICETransport, `None` signals
+    /// end-of-candidates.
     pub(crate) async fn add_remote_candidate(
         &self,
         remote_candidate: Option<RTCIceCandidate>,
@@ -200,6 +215,8 @@ impl RTCIceTransport {
             if let Some(r) = remote_candidate {
                 let c: Arc<dyn Candidate + Send + Sync> = Arc::new(r.to_ice().await?);
                 agent.add_remote_candidate(&c).await?;
+            } else {
+                agent.set_remote_candidates_complete();
             }
 
             Ok(())
@@ -208,11 +225,62 @@ impl RTCIceTransport {
         }
     }
 
+    /// Restarts ICE with new local credentials and gathers candidates again, RFC 8445
+    /// section 2.4. The conn handed to the DTLS transport stays, it carries traffic again
+    /// once checks with the remote's new credentials select a pair.
+    pub(crate) async fn restart(&self) -> Result<()> {
+        if let Some(agent) = self.gatherer.get_agent().await {
+            agent.restart(String::new(), String::new()).await?;
+        } else {
+            return Err(Error::ErrICEAgentNotExist);
+        }
+
+        self.gatherer.gather().await
+    }
+
+    /// Sets the credentials the remote signaled in a renegotiation.
+    pub(crate) async fn set_remote_credentials(
+        &self,
+        remote_ufrag: String,
+        remote_pwd: String,
+    ) -> Result<()> {
+        if let Some(agent) = self.gatherer.get_agent().await {
+            agent
+                .set_remote_credentials(remote_ufrag, remote_pwd)
+                .await?;
+            Ok(())
+        } else {
+            Err(Error::ErrICEAgentNotExist)
+        }
+    }
+
+    /// Sets whether the remote signaled ice-options:renomination.
+    pub(crate) async fn set_remote_renomination(&self, renomination: bool) -> Result<()> {
+        if let Some(agent) = self.gatherer.get_agent().await {
+            agent.set_remote_renomination(renomination);
+            Ok(())
+        } else {
+            Err(Error::ErrICEAgentNotExist)
+        }
+    }
+
     /// State returns the current ice transport state.
     pub(crate) fn state(&self) -> RTCIceTransportState {
         RTCIceTransportState::from(self.state.load(Ordering::SeqCst))
     }
 
+    /// role indicates the current role of the ICE transport.
+    pub(crate) async fn role(&self) -> RTCIceRole {
+        let internal = self.internal.lock().await;
+        internal.role
+    }
+
+    #[cfg(test)]
+    pub(crate) async fn set_role(&self, role: RTCIceRole) {
+        let mut internal = self.internal.lock().await;
+        internal.role = role;
+    }
+
     pub(crate) async fn new_endpoint(&self, f: MatchFunc) -> Option<Arc<Endpoint>> {
         let internal = self.internal.lock().await;
         if let Some(mux) = &internal.mux {
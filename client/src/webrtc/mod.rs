@@ -3,9 +3,11 @@ mod crates;
 // re-export sub-crates
 pub(crate) use crates::dtls;
 pub(crate) use crates::ice;
+pub(crate) use crates::mdns;
 pub(crate) use crates::sctp;
 pub(crate) use crates::sdp;
 pub(crate) use crates::stun;
+pub(crate) use crates::turn;
 pub(crate) use crates::util;
 pub(crate) use data_channel::internal;
 
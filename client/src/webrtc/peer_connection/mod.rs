@@ -6,6 +6,7 @@ pub(crate) mod policy;
 pub(crate) mod sdp;
 pub(crate) mod signaling_state;
 
+use crate::socket_config::SocketConfig;
 use crate::webrtc::api::API;
 use crate::webrtc::data_channel::data_channel_state::RTCDataChannelState;
 use crate::webrtc::data_channel::RTCDataChannel;
@@ -106,8 +107,8 @@ impl RTCPeerConnection {
     /// If you wish to customize the set of available codecs or the set of
     /// active interceptors, create a MediaEngine and call api.new_peer_connection
     /// instead of this function.
-    pub(crate) async fn new() -> Arc<RTCPeerConnection> {
-        let internal = PeerConnectionInternal::new()
+    pub(crate) async fn new(config: &SocketConfig) -> Arc<RTCPeerConnection> {
+        let internal = PeerConnectionInternal::new(config)
             .await
             .expect("can't create peer connection");
 
@@ -128,6 +129,31 @@ impl RTCPeerConnection {
         }
     }
 
+    /// on_ice_connection_state_change sets an event handler which is called
+    /// when an ICE connection state is changed.
+    pub(crate) async fn on_ice_connection_state_change(&self, f: OnICEConnectionStateChangeHdlrFn) {
+        let mut on_ice_connection_state_change_handler = self
+            .internal
+            .on_ice_connection_state_change_handler
+            .lock()
+            .await;
+        *on_ice_connection_state_change_handler = Some(f);
+    }
+
+    /// on_peer_connection_state_change sets an event handler which is called
+    /// when the PeerConnectionState has changed
+    pub(crate) async fn on_peer_connection_state_change(
+        &self,
+        f: OnPeerConnectionStateChangeHdlrFn,
+    ) {
+        let mut on_peer_connection_state_change_handler = self
+            .internal
+            .on_peer_connection_state_change_handler
+            .lock()
+            .await;
+        *on_peer_connection_state_change_handler = Some(f);
+    }
+
     async fn do_ice_connection_state_change(
         on_ice_connection_state_change_handler: &Arc<
             Mutex<Option<OnICEConnectionStateChangeHdlrFn>>,
@@ -256,6 +282,18 @@ impl RTCPeerConnection {
         Ok(offer)
     }
 
+    /// restart_ice restarts ICE with new local credentials and gathers candidates again,
+    /// the next offer carries the new credentials. The DTLS and SCTP sessions keep running
+    /// and move to the new path once the answer to that offer is applied.
+    /// <https://www.w3.org/TR/webrtc/#dom-rtcpeerconnection-restartice>
+    pub(crate) async fn restart_ice(&self) -> Result<()> {
+        if self.internal.is_closed.load(Ordering::SeqCst) {
+            return Err(Error::ErrConnectionClosed);
+        }
+
+        self.internal.ice_transport.restart().await
+    }
+
     /// Update the PeerConnectionState given the state of relevant transports
     /// <https://www.w3.org/TR/webrtc/#rtcpeerconnectionstate-enum>
     async fn update_connection_state(
@@ -579,6 +617,18 @@ impl RTCPeerConnection {
         }
 
         desc.parsed = Some(desc.unmarshal()?);
+
+        // A renegotiation, e.g. the answer to an ICE restart, keeps the running transports
+        // and only hands the remote's credentials to the agent, before any of its
+        // candidates are checked. The DTLS session stays up, so the remote must keep
+        // its certificate and role.
+        let renegotiation = self.internal.ice_transport.state() != RTCIceTransportState::New;
+        if renegotiation {
+            if let Some(parsed) = &desc.parsed {
+                self.check_dtls_parameters_unchanged(parsed).await?;
+            }
+        }
+
         self.set_description(&desc, StateChangeOp::SetRemote)
             .await?;
 
@@ -586,6 +636,17 @@ impl RTCPeerConnection {
             let we_offer = true;
 
             let (remote_ufrag, remote_pwd, candidates) = extract_ice_details(parsed).await?;
+            self.internal
+                .ice_transport
+                .set_remote_renomination(extract_ice_renomination(parsed))
+                .await?;
+
+            if renegotiation {
+                self.internal
+                    .ice_transport
+                    .set_remote_credentials(remote_ufrag.clone(), remote_pwd.clone())
+                    .await?;
+            }
 
             for candidate in candidates {
                 self.internal
@@ -593,8 +654,21 @@ impl RTCPeerConnection {
                     .add_remote_candidate(Some(candidate))
                     .await?;
             }
+            if parsed
+                .media_descriptions
+                .iter()
+                .any(|m| m.attribute("end-of-candidates").is_some())
+            {
+                self.internal
+                    .ice_transport
+                    .add_remote_candidate(None)
+                    .await?;
+            }
+            if renegotiation {
+                return Ok(());
+            }
 
-            let (fingerprint, fingerprint_hash) = extract_fingerprint(parsed)?;
+            let (fingerprint, fingerprint_hash) = self.extract_remote_fingerprint(parsed)?;
 
             // If one of the agents is lite and the other one is not, the lite agent must be the controlling agent.
             // If both or neither agents are lite the offering agent is controlling.
@@ -634,6 +708,40 @@ impl RTCPeerConnection {
         Ok(())
     }
 
+    // extract_remote_fingerprint returns the fingerprint and its hash function, empty
+    // for a PSK-only peer which has no certificate to fingerprint
+    fn extract_remote_fingerprint(&self, parsed: &SessionDescription) -> Result<(String, String)> {
+        match extract_fingerprint(parsed) {
+            Ok(fingerprint) => Ok(fingerprint),
+            Err(Error::ErrSessionDescriptionNoFingerprint)
+                if self.internal.dtls_transport.psk.is_some() =>
+            {
+                Ok((String::new(), String::new()))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    // check_dtls_parameters_unchanged fails a renegotiation whose remote description
+    // names another certificate or DTLS role than the running DTLS session
+    async fn check_dtls_parameters_unchanged(&self, parsed: &SessionDescription) -> Result<()> {
+        let (fingerprint, fingerprint_hash) = self.extract_remote_fingerprint(parsed)?;
+
+        let remote_parameters = self.internal.dtls_transport.remote_parameters.lock().await;
+        let same_fingerprint = match remote_parameters.fingerprints.first() {
+            Some(f) => {
+                f.algorithm.eq_ignore_ascii_case(&fingerprint_hash)
+                    && f.value.eq_ignore_ascii_case(&fingerprint)
+            }
+            None => fingerprint.is_empty(),
+        };
+        if !same_fingerprint || DTLSRole::from(parsed) != remote_parameters.role {
+            return Err(Error::ErrRenegotiationDtlsParametersChanged);
+        }
+
+        Ok(())
+    }
+
     /// remote_description returns pending_remote_description if it is not null and
     /// otherwise it returns current_remote_description. This property is used to
     /// determine if setRemoteDescription has already been called.
@@ -643,7 +751,8 @@ impl RTCPeerConnection {
     }
 
     /// add_ice_candidate accepts an ICE candidate string and adds it
-    /// to the existing set of candidates.
+    /// to the existing set of candidates. An empty string signals
+    /// end-of-candidates.
     pub(crate) async fn add_ice_candidate(&self, candidate_str: String) -> Result<()> {
         if self.remote_description().await.is_none() {
             return Err(Error::ErrNoRemoteDescription);
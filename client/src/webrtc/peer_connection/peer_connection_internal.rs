@@ -43,7 +43,7 @@ pub(crate) struct PeerConnectionInternal {
 }
 
 impl PeerConnectionInternal {
-    pub(crate) async fn new() -> Result<Arc<Self>> {
+    pub(crate) async fn new(config: &SocketConfig) -> Result<Arc<Self>> {
         let mut pc = PeerConnectionInternal {
             greater_mid: AtomicIsize::new(-1),
             sdp_origin: Mutex::new(Default::default()),
@@ -69,13 +69,16 @@ impl PeerConnectionInternal {
         };
 
         // Create the ice gatherer
-        pc.ice_gatherer = Arc::new(API::new_ice_gatherer()?);
+        pc.ice_gatherer = Arc::new(API::new_ice_gatherer(config)?);
 
         // Create the ice transport
         pc.ice_transport = pc.create_ice_transport().await;
 
         // Create the DTLS transport
-        pc.dtls_transport = Arc::new(API::new_dtls_transport(Arc::clone(&pc.ice_transport))?);
+        pc.dtls_transport = Arc::new(API::new_dtls_transport(
+            Arc::clone(&pc.ice_transport),
+            config,
+        )?);
 
         // Create the SCTP transport
         pc.sctp_transport = Arc::new(API::new_sctp_transport(Arc::clone(&pc.dtls_transport))?);
@@ -97,6 +100,18 @@ impl PeerConnectionInternal {
         Ok(Arc::new(pc))
     }
 
+    /// local_fingerprints returns the fingerprints to advertise in the SDP,
+    /// a PSK-only transport has no certificate and advertises none
+    fn local_fingerprints(&self) -> Result<Vec<RTCDtlsFingerprint>> {
+        if let Some(cert) = self.dtls_transport.certificates.first() {
+            cert.get_fingerprints()
+        } else if self.dtls_transport.psk.is_some() {
+            Ok(vec![])
+        } else {
+            Err(Error::ErrNonCertificate)
+        }
+    }
+
     pub(crate) async fn maybe_start_sctp(
         self: &Arc<Self>,
         remote_desc: Arc<RTCSessionDescription>,
@@ -201,10 +216,14 @@ impl PeerConnectionInternal {
             .dtls_transport
             .start(DTLSParameters {
                 role: dtls_role,
-                fingerprints: vec![RTCDtlsFingerprint {
-                    algorithm: fingerprint_hash,
-                    value: fingerprint,
-                }],
+                fingerprints: if fingerprint.is_empty() {
+                    vec![]
+                } else {
+                    vec![RTCDtlsFingerprint {
+                        algorithm: fingerprint_hash,
+                        value: fingerprint,
+                    }]
+                },
             })
             .await;
         RTCPeerConnection::update_connection_state(
@@ -247,11 +266,7 @@ impl PeerConnectionInternal {
             });
         }
 
-        let dtls_fingerprints = if let Some(cert) = self.dtls_transport.certificates.first() {
-            cert.get_fingerprints()?
-        } else {
-            return Err(Error::ErrNonCertificate);
-        };
+        let dtls_fingerprints = self.local_fingerprints()?;
 
         let params = PopulateSdpParams {
             is_icelite: false,
@@ -319,11 +334,7 @@ impl PeerConnectionInternal {
             }
         }
 
-        let dtls_fingerprints = if let Some(cert) = self.dtls_transport.certificates.first() {
-            cert.get_fingerprints()?
-        } else {
-            return Err(Error::ErrNonCertificate);
-        };
+        let dtls_fingerprints = self.local_fingerprints()?;
 
         let params = PopulateSdpParams {
             is_icelite: false,
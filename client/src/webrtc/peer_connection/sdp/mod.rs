@@ -18,6 +18,8 @@ use crate::webrtc::sdp::util::ConnectionRole;
 use std::convert::From;
 use std::sync::Arc;
 
+pub(crate) const ICE_OPTION_RENOMINATION: &str = "renomination";
+
 /// TrackDetails represents any media source that can be represented in a SDP
 /// This isn't keyed by SSRC because it also needs to support rid based sources
 #[derive(Default, Debug, Clone)]
@@ -230,6 +232,13 @@ pub(crate) async fn populate_sdp(
         d = d.with_value_attribute(ATTR_KEY_ICELITE.to_owned(), ATTR_KEY_ICELITE.to_owned());
     }
 
+    // The agent follows a controlling peer that nominates another pair later
+    // https://datatracker.ietf.org/doc/html/draft-thatcher-ice-renomination-01#section-4
+    d = d.with_value_attribute(
+        ATTR_KEY_ICE_OPTIONS.to_owned(),
+        ICE_OPTION_RENOMINATION.to_owned(),
+    );
+
     Ok(d.with_value_attribute(ATTR_KEY_GROUP.to_owned(), bundle_value))
 }
 
@@ -293,6 +302,24 @@ pub(crate) fn extract_fingerprint(desc: &SessionDescription) -> Result<(String,
     Ok((parts[1].to_owned(), parts[0].to_owned()))
 }
 
+// extract_ice_renomination returns whether the description lists renomination
+// in its ice-options, at session or media level
+pub(crate) fn extract_ice_renomination(desc: &SessionDescription) -> bool {
+    let has_renomination = |options: &str| {
+        options
+            .split_whitespace()
+            .any(|o| o == ICE_OPTION_RENOMINATION)
+    };
+
+    desc.attribute(ATTR_KEY_ICE_OPTIONS)
+        .is_some_and(|options| has_renomination(options))
+        || desc.media_descriptions.iter().any(|m| {
+            m.attribute(ATTR_KEY_ICE_OPTIONS)
+                .flatten()
+                .is_some_and(has_renomination)
+        })
+}
+
 pub(crate) async fn extract_ice_details(
     desc: &SessionDescription,
 ) -> Result<(String, String, Vec<RTCIceCandidate>)> {
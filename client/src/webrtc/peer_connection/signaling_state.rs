@@ -150,6 +150,14 @@ pub(crate) fn check_next_signaling_state(
             }
         }
         RTCSignalingState::HaveLocalOffer => {
+            // have-local-offer->SetLocal(offer)->have-local-offer, e.g. a new offer after
+            // the answer to the previous one was rejected
+            if op == StateChangeOp::SetLocal
+                && sdp_type == RTCSdpType::Offer
+                && next == RTCSignalingState::HaveLocalOffer
+            {
+                return Ok(next);
+            }
             if op == StateChangeOp::SetRemote {
                 match sdp_type {
                     // have-local-offer->SetRemote(answer)->stable
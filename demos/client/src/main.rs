@@ -16,7 +16,7 @@ async fn main() -> Result<()> {
     let server_url = format!("http://{}:14191/rtc_session", server_address);
 
     let (addr_cell, to_server_sender, to_client_receiver) =
-        Socket::connect(server_url.as_str()).await;
+        Socket::connect(server_url.as_str()).await?;
 
     let addr_cell_1 = addr_cell.clone();
     let addr_cell_2 = addr_cell.clone();